@@ -0,0 +1,107 @@
+//! TODO, FIXME and the like, found anywhere in the buffer. Markers are matched as whole words,
+//! in code, comments and strings alike, rilo doesn't know one from the other.
+
+use crate::is_word_byte;
+use crate::rows::Rows;
+
+/// The markers looked for unless the config file lists others
+pub const DEFAULT_MARKERS: &[&str] = &["TODO", "FIXME", "XXX", "HACK"];
+
+/// A marker and what follows it on its row
+pub struct Annotation {
+    pub row: usize,
+    /// Index into the row the marker starts at
+    pub column: usize,
+    pub marker: String,
+    /// The rest of the row after the marker, with any `:` and blanks after it dropped
+    pub text: String,
+}
+
+/// Every annotation in `rows`, in the order they appear. A row with several markers has an
+/// annotation for each.
+pub fn scan(rows: &Rows, markers: &[String]) -> Vec<Annotation> {
+    let mut found = Vec::new();
+    for (row, line) in rows.iter().enumerate() {
+        let mut hits: Vec<(usize, &String)> = markers
+            .iter()
+            .flat_map(|marker| {
+                word_matches(line, marker)
+                    .into_iter()
+                    .map(move |column| (column, marker))
+            })
+            .collect();
+        hits.sort_by_key(|(column, _)| *column);
+
+        for (column, marker) in hits {
+            let text = line[column + marker.len()..]
+                .trim_start_matches(|c: char| c == ':' || c.is_whitespace())
+                .trim_end();
+            found.push(Annotation {
+                row,
+                column,
+                marker: marker.clone(),
+                text: String::from(text),
+            });
+        }
+    }
+
+    found
+}
+
+/// Where `word` appears in `line` with no word characters right before or after it
+fn word_matches(line: &str, word: &str) -> Vec<usize> {
+    let bytes = line.as_bytes();
+    line.match_indices(word)
+        .map(|(start, _)| start)
+        .filter(|start| {
+            let end = start + word.len();
+            (*start == 0 || !is_word_byte(bytes[start - 1]))
+                && bytes.get(end).is_none_or(|b| !is_word_byte(*b))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn markers() -> Vec<String> {
+        DEFAULT_MARKERS.iter().map(|m| String::from(*m)).collect()
+    }
+
+    #[test]
+    fn markers_in_strings_and_comments_count() {
+        let rows = Rows::from(vec![
+            "let s = \"TODO: in a string\";",
+            "// FIXME: in a comment",
+            "TODOS and XXXL aren't markers, HACK is",
+        ]);
+        let found = scan(&rows, &markers());
+        let seen: Vec<_> = found
+            .iter()
+            .map(|a| (a.row, a.column, a.marker.as_str(), a.text.as_str()))
+            .collect();
+        assert_eq!(
+            seen,
+            [
+                (0, 9, "TODO", "in a string\";"),
+                (1, 3, "FIXME", "in a comment"),
+                (2, 31, "HACK", "is"),
+            ]
+        );
+    }
+
+    #[test]
+    fn thousands_of_markers() {
+        let rows: Rows = (0..5000)
+            .map(|n| format!("x = {n} # TODO {n} XXX"))
+            .collect();
+        let found = scan(&rows, &markers());
+        assert_eq!(found.len(), 10_000);
+        assert!(found
+            .windows(2)
+            .all(|w| (w[0].row, w[0].column) < (w[1].row, w[1].column)));
+        assert_eq!(found[9_999].row, 4999);
+        assert_eq!(found[9_998].text, "4999 XXX");
+    }
+}
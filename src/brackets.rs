@@ -0,0 +1,107 @@
+//! Matching brackets, by counting how deep they nest through the rows. The scan is purely
+//! textual, brackets in strings and comments count like any other.
+
+use crate::rows::Rows;
+
+/// Most characters looked at for a bracket's partner, past it the search gives up so a huge
+/// unbalanced file doesn't slow down every key
+const MAX_SCAN: usize = 10_000;
+
+const PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+/// What became of the search for a bracket's partner
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Partner {
+    /// The partner's row and index into it
+    Found(usize, usize),
+    /// The scan reached the end of the buffer without finding it
+    Unmatched,
+    /// The scan gave up after `MAX_SCAN` characters
+    TooFar,
+}
+
+/// The index of the bracket the cursor at `x` is on, or else right after
+pub fn near(row: &str, x: usize) -> Option<usize> {
+    let on = row.get(x..).and_then(|rest| rest.chars().next());
+    if on.is_some_and(is_bracket) {
+        return Some(x);
+    }
+    let before = row.get(..x)?.char_indices().next_back();
+    before.filter(|(_, c)| is_bracket(*c)).map(|(idx, _)| idx)
+}
+
+/// The first bracket at or after `x` in the row
+pub fn next_in_row(row: &str, x: usize) -> Option<usize> {
+    let rest = row.get(x..)?;
+    rest.find(is_bracket).map(|idx| x + idx)
+}
+
+fn is_bracket(c: char) -> bool {
+    PAIRS.iter().any(|(open, close)| c == *open || c == *close)
+}
+
+/// Looks for the partner of the bracket at row `y`, index `x`. `None` when there's no bracket there.
+pub fn partner(rows: &Rows, y: usize, x: usize) -> Option<Partner> {
+    let c = rows.get(y)?.get(x..)?.chars().next()?;
+    let (open, close, forward) = PAIRS.iter().find_map(|(open, close)| {
+        if c == *open {
+            Some((*open, *close, true))
+        } else if c == *close {
+            Some((*open, *close, false))
+        } else {
+            None
+        }
+    })?;
+    // Walking away from the bracket, its own kind nests one level deeper and the other kind closes
+    let (deeper, shallower) = if forward {
+        (open, close)
+    } else {
+        (close, open)
+    };
+
+    let mut depth = 0_usize;
+    let mut scanned = 0;
+    let mut closes = |found: char| {
+        if found == deeper {
+            depth += 1;
+        } else if found == shallower {
+            depth -= 1;
+        }
+        depth == 0
+    };
+
+    if forward {
+        for (row_idx, row) in rows.iter().enumerate().skip(y) {
+            let start = if row_idx == y { x } else { 0 };
+            for (idx, found) in row[start..].char_indices() {
+                scanned += 1;
+                if scanned > MAX_SCAN {
+                    return Some(Partner::TooFar);
+                }
+                if closes(found) {
+                    return Some(Partner::Found(row_idx, start + idx));
+                }
+            }
+        }
+    } else {
+        for row_idx in (0..=y).rev() {
+            let row = &rows[row_idx];
+            let end = if row_idx == y {
+                x + c.len_utf8()
+            } else {
+                row.len()
+            };
+            for (idx, found) in row[..end].char_indices().rev() {
+                scanned += 1;
+                if scanned > MAX_SCAN {
+                    return Some(Partner::TooFar);
+                }
+                if closes(found) {
+                    return Some(Partner::Found(row_idx, idx));
+                }
+            }
+        }
+    }
+
+    Some(Partner::Unmatched)
+}
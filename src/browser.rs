@@ -0,0 +1,119 @@
+//! Listing a directory as rows of a buffer, for browsing to a file. Directories come first and end
+//! in '/', a `../` row on top goes up a level. The paths are kept the way they were typed, so going
+//! into `src` and back up from `.` lists `src` and then `.` again rather than where they lead.
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// The row that stands for the parent directory
+pub const PARENT: &str = "../";
+
+/// The directory a listing buffer shows
+#[derive(Debug)]
+pub struct Listing {
+    pub dir: PathBuf,
+    /// Whether names starting with '.' are listed
+    pub hidden: bool,
+}
+
+impl Listing {
+    /// The rows for the directory's entries: `../` unless it's the root, then the directories and
+    /// then the files, each by name
+    pub fn rows(&self) -> io::Result<Vec<String>> {
+        let mut dirs = Vec::new();
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') && !self.hidden {
+                continue;
+            }
+            // A symlink to a directory is gone into like a directory
+            if entry.path().is_dir() {
+                dirs.push(format!("{name}/"));
+            } else {
+                files.push(name);
+            }
+        }
+        dirs.sort();
+        files.sort();
+
+        let mut rows = Vec::with_capacity(dirs.len() + files.len() + 1);
+        if !self.is_root() {
+            rows.push(String::from(PARENT));
+        }
+        rows.extend(dirs);
+        rows.extend(files);
+        Ok(rows)
+    }
+
+    fn is_root(&self) -> bool {
+        self.dir.has_root() && self.dir.parent().is_none()
+    }
+
+    /// The path of the entry shown as `row`
+    pub fn entry(&self, row: &str) -> PathBuf {
+        if row == PARENT {
+            return self.parent();
+        }
+        let name = row.strip_suffix('/').unwrap_or(row);
+        if self.dir == Path::new(".") {
+            PathBuf::from(name)
+        } else {
+            self.dir.join(name)
+        }
+    }
+
+    /// The directory above this one, `src` goes up to `.` and `.` to `..`
+    pub fn parent(&self) -> PathBuf {
+        match self.dir.components().next_back() {
+            Some(Component::Normal(_)) => match self.dir.parent() {
+                Some(parent) if parent != Path::new("") => parent.to_path_buf(),
+                _ => PathBuf::from("."),
+            },
+            Some(Component::RootDir | Component::Prefix(_)) => self.dir.clone(),
+            _ => self.dir.join(".."),
+        }
+    }
+}
+
+/// The row of a listing showing the entry `path` names, when it's one of them
+pub fn row_of(path: &Path, rows: &[String]) -> Option<usize> {
+    let name = path.file_name()?.to_str()?;
+    rows.iter()
+        .position(|row| row.strip_suffix('/').unwrap_or(row) == name)
+}
+
+/// Completes the last part of a typed path as far as the entries starting with it agree, a
+/// directory gaining its '/'. Names starting with '.' only count once a '.' is typed. What can't be
+/// completed, or can't be listed, comes back as it was.
+pub fn complete(input: &str) -> String {
+    let (dir, name) = match input.rfind('/') {
+        Some(idx) => input.split_at(idx + 1),
+        None => ("", input),
+    };
+    let listing = Listing {
+        dir: PathBuf::from(if dir.is_empty() { "." } else { dir }),
+        hidden: name.starts_with('.'),
+    };
+    let Ok(rows) = listing.rows() else {
+        return String::from(input);
+    };
+
+    let mut matches = rows
+        .iter()
+        .filter(|row| *row != PARENT && row.starts_with(name));
+    let Some(first) = matches.next() else {
+        return String::from(input);
+    };
+    let common = matches.fold(first.as_str(), |common, row| {
+        let len = common
+            .char_indices()
+            .zip(row.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(common.len().min(row.len()), |((idx, _), _)| idx);
+        &common[..len]
+    });
+    format!("{dir}{common}")
+}
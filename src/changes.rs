@@ -0,0 +1,36 @@
+//! Which rows changed since the file was last read or saved. The rows as they were then are kept
+//! as a hash each instead of a copy, and the buffer is diffed against those, so rows that were
+//! only pushed down by a line added above them don't count as changed.
+
+use crate::diff::{self, Edit};
+use crate::rows::Rows;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// The rows as of the last time the buffer matched the file
+#[derive(Default)]
+pub struct Baseline(Vec<u64>);
+
+impl Baseline {
+    pub fn new(rows: &Rows) -> Self {
+        Baseline(rows.iter().map(hash).collect())
+    }
+
+    /// A flag for each of `rows`, set for the ones that were added or edited since
+    pub fn changed(&self, rows: &Rows) -> Vec<bool> {
+        let hashes: Vec<u64> = rows.iter().map(hash).collect();
+        let mut changed = vec![false; rows.len()];
+        for edit in diff::diff(&self.0, &hashes) {
+            if let Edit::Added(idx) = edit {
+                changed[idx] = true;
+            }
+        }
+        changed
+    }
+}
+
+fn hash(row: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    row.hash(&mut hasher);
+    hasher.finish()
+}
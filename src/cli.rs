@@ -0,0 +1,231 @@
+//! Command line argument handling
+
+use std::path::Path;
+use std::str::FromStr;
+
+pub const USAGE: &str = "\
+Usage: rilo [OPTIONS] [FILE[:LINE[:COLUMN[c|r]]]] [+LINE]
+       rilo [OPTIONS] --scratch
+       rilo [OPTIONS] --tutor
+
+Options:
+    --readonly          Open the file in view-only mode
+    --wrap              Soft wrap long lines instead of scrolling horizontally
+    --scratch           Start in the scratch buffer, a notepad that never asks to be saved
+    --tutor             Open a hands-on tutorial on a copy of its text
+    --print-on-exit     Write the buffer to stdout when rilo exits, for use in pipelines
+    --backup            Copy a file's previous contents to FILE~ before saving over it
+    --color <WHEN>      Use colors: 'auto' (the default) unless NO_COLOR is set or stdout
+                        isn't a terminal, 'always' or 'never'
+    --no-color          Same as --color=never
+    --no-alt-screen     Draw over the shell's screen instead of the terminal's alternate one
+    --force             Open a file as text even when it looks like a binary file
+    --tab-size <N>      Number of columns between tab stops (default 4)
+    -h, --help          Print this message and exit
+    -V, --version       Print the version and exit
+
+FILE can be '-' to edit text piped to stdin, text piped in with no FILE is edited the same way.
+COLUMN counts characters, or rendered columns with tabs expanded when it ends with 'r'.
+";
+
+/// What a column number counts, tools disagree once a line has tabs in it
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ColumnKind {
+    /// Characters from the start of the line, a tab is one column
+    #[default]
+    Char,
+    /// Columns on screen, a tab is as wide as it's rendered
+    Render,
+}
+
+/// When the configured colors are used
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ColorMode {
+    /// Unless `NO_COLOR` is set or stdout isn't a terminal
+    #[default]
+    Auto,
+    Always,
+    /// Plain reverse video whatever the config says
+    Never,
+}
+
+impl FromStr for ColorMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            _ => Err(format!(
+                "invalid value '{value}' for --color, it's auto, always or never"
+            )),
+        }
+    }
+}
+
+/// A 1-based line and column to place the cursor at once the file is open
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ColumnKind,
+}
+
+/// Everything the editor needs to know from the command line
+#[derive(Debug, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Options {
+    pub path: Option<String>,
+    pub position: Option<Position>,
+    pub readonly: bool,
+    pub wrap: bool,
+    pub scratch: bool,
+    pub tutor: bool,
+    pub print_on_exit: bool,
+    pub backup: bool,
+    pub color: ColorMode,
+    /// Don't switch to the alternate screen, for terminals that don't have one
+    pub no_alt_screen: bool,
+    /// Open files that look binary as text anyway
+    pub force: bool,
+    /// Overrides the configured tab size
+    pub tab_size: Option<u8>,
+}
+
+/// What the user asked rilo to do
+pub enum Invocation {
+    Edit(Options),
+    Help,
+    Version,
+}
+
+/// Parses the arguments (without the program name) into an `Invocation`.
+/// Errors are returned as a human readable message meant to be printed next to `USAGE`.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Invocation, String> {
+    let mut options = Options::default();
+    let mut line_arg = None;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Invocation::Help),
+            "-V" | "--version" => return Ok(Invocation::Version),
+            "--readonly" => options.readonly = true,
+            "--wrap" => options.wrap = true,
+            "--scratch" => options.scratch = true,
+            "--tutor" => options.tutor = true,
+            "--print-on-exit" => options.print_on_exit = true,
+            "--backup" => options.backup = true,
+            "--no-color" => options.color = ColorMode::Never,
+            "--color" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| String::from("--color requires a value"))?;
+                options.color = value.parse()?;
+            }
+            _ if arg.starts_with("--color=") => options.color = arg["--color=".len()..].parse()?,
+            "--no-alt-screen" => options.no_alt_screen = true,
+            "--force" => options.force = true,
+            "--tab-size" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| String::from("--tab-size requires a value"))?;
+                options.tab_size = Some(parse_number(&value, "--tab-size")?);
+            }
+            _ if arg.starts_with("--tab-size=") => {
+                options.tab_size = Some(parse_number(&arg["--tab-size=".len()..], "--tab-size")?);
+            }
+            _ if arg.starts_with('+') => {
+                line_arg = Some(parse_number(&arg[1..], "+LINE")?);
+            }
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(format!("unknown option '{arg}'"));
+            }
+            _ => {
+                if options.path.is_some() {
+                    return Err(format!("unexpected argument '{arg}'"));
+                }
+                let (path, position) = split_position(&arg);
+                options.path = Some(path);
+                options.position = position;
+            }
+        }
+    }
+
+    if options.tab_size == Some(0) {
+        return Err(String::from("--tab-size must be at least 1"));
+    }
+
+    if options.scratch && options.path.is_some() {
+        return Err(String::from("--scratch can't be combined with a file"));
+    }
+
+    if options.tutor && (options.scratch || options.path.is_some()) {
+        return Err(String::from(
+            "--tutor can't be combined with a file or --scratch",
+        ));
+    }
+
+    if let Some(line) = line_arg {
+        if options.path.is_none() {
+            return Err(String::from("+LINE requires a file to open"));
+        }
+        options.position = Some(Position {
+            line,
+            column: 1,
+            kind: ColumnKind::Char,
+        });
+    }
+
+    Ok(Invocation::Edit(options))
+}
+
+/// Splits a trailing `:LINE` or `:LINE:COLUMN` off a path, the way compilers and grep print locations.
+/// The column can be suffixed with `c` (characters, the default) or `r` (rendered columns). A file
+/// that exists under the whole argument is opened as named, colons and all.
+fn split_position(arg: &str) -> (String, Option<Position>) {
+    if Path::new(arg).exists() {
+        return (String::from(arg), None);
+    }
+    if let Some((rest, last)) = arg.rsplit_once(':') {
+        if let Some((column, kind)) = parse_column(last) {
+            if let Some((path, line)) = rest.rsplit_once(':') {
+                if let Ok(line) = line.parse() {
+                    if !path.is_empty() {
+                        let position = Position { line, column, kind };
+                        return (String::from(path), Some(position));
+                    }
+                }
+            }
+        }
+
+        if let Ok(line) = last.parse() {
+            if !rest.is_empty() {
+                let position = Position {
+                    line,
+                    column: 1,
+                    kind: ColumnKind::Char,
+                };
+                return (String::from(rest), Some(position));
+            }
+        }
+    }
+
+    (String::from(arg), None)
+}
+
+fn parse_column(column: &str) -> Option<(usize, ColumnKind)> {
+    let (column, kind) = if let Some(column) = column.strip_suffix('r') {
+        (column, ColumnKind::Render)
+    } else {
+        (column.strip_suffix('c').unwrap_or(column), ColumnKind::Char)
+    };
+    column.parse().ok().map(|column| (column, kind))
+}
+
+fn parse_number<T: FromStr>(value: &str, name: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{value}' for {name}"))
+}
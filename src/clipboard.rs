@@ -0,0 +1,76 @@
+//! The system clipboard, reached through the terminal with OSC 52 escape sequences
+
+use crate::input::Input;
+use std::convert::TryFrom;
+use std::time::Duration;
+
+/// Some terminals drop OSC 52 sequences longer than this, so bigger copies stay internal
+pub const MAX_PAYLOAD: usize = 74_994;
+
+/// How long to wait for the terminal to answer a clipboard query before giving up on it
+const QUERY_TIMEOUT: Duration = Duration::from_millis(300);
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let group = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                encoded.push(BASE64[(group >> (18 - 6 * idx) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+pub fn base64_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(encoded.len() / 4 * 3);
+    let mut group = 0_u32;
+    let mut bits = 0;
+    for c in encoded
+        .bytes()
+        .filter(|c| !c.is_ascii_whitespace() && *c != b'=')
+    {
+        let value = BASE64.iter().position(|b| *b == c)?;
+        // Only the last few sextets are ever needed, masking keeps the shifts from overflowing
+        group = (group << 6 | u32::try_from(value).ok()?) & 0x00ff_ffff;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push(u8::try_from(group >> bits & 0xff).ok()?);
+        }
+    }
+
+    Some(decoded)
+}
+
+/// Asks the terminal for the clipboard's content, `None` when it doesn't answer in time
+pub fn read(input: &mut Input) -> Option<String> {
+    crate::send_esc_seq(crate::CtrlSeq::QueryClipboard);
+
+    let response = input.reply(QUERY_TIMEOUT, |response| {
+        response.ends_with(b"\x07") || response.ends_with(b"\x1b\\")
+    })?;
+    parse_response(&response)
+}
+
+/// Pulls the payload out of a `ESC ] 52 ; c ; <base64> BEL` answer (or ST terminated)
+fn parse_response(response: &[u8]) -> Option<String> {
+    let response = std::str::from_utf8(response).ok()?;
+    let start = response.find("\x1b]52;")?;
+    let payload = response[start + 5..]
+        .trim_end_matches('\x07')
+        .trim_end_matches("\x1b\\");
+    let (_, encoded) = payload.split_once(';')?;
+    String::from_utf8(base64_decode(encoded)?).ok()
+}
@@ -0,0 +1,100 @@
+//! Where the chars of a row are drawn. Finding the column of an index into a row means going over
+//! the row from its start, since a tab's width depends on everything left of it, and on a row
+//! megabytes long that adds up. `Checkpoints` remembers the column at every so many bytes of such a
+//! row, so a lookup only goes over the row from the closest one.
+
+use crate::{render_width, split_point};
+
+/// Rows shorter than this are quick enough to go over from the start every time
+pub const LONG_ROW: usize = 64 * 1024;
+
+/// Bytes between checkpoints, the most a lookup goes over
+const STRIDE: usize = 16 * 1024;
+
+/// The column `text` ends at when it's drawn from `column` on
+pub fn advance(text: &str, column: usize, tab_size: u8) -> usize {
+    // Printable ASCII takes a column a byte, so runs of it between tabs are measured by their
+    // length. That's most text, and much quicker on long rows than going char by char.
+    let mut rx = column;
+    for (idx, run) in text.split('\t').enumerate() {
+        if idx > 0 {
+            rx += render_width('\t', rx, tab_size);
+        }
+        rx = if is_printable_ascii(run) {
+            rx + run.len()
+        } else {
+            run.chars()
+                .fold(rx, |rx, c| rx + render_width(c, rx, tab_size))
+        };
+    }
+    rx
+}
+
+/// The index into `text` of the char covering column `rx` when `text` is drawn from `column` on,
+/// the end of `text` when it doesn't reach that far
+pub fn index_at(text: &str, column: usize, rx: usize, tab_size: u8) -> usize {
+    let mut current = column;
+    let mut start = 0;
+    for run in text.split_inclusive('\t') {
+        let chars = run.strip_suffix('\t').unwrap_or(run);
+        if is_printable_ascii(chars) {
+            if current + chars.len() > rx {
+                return start + rx.saturating_sub(current);
+            }
+            current += chars.len();
+        } else {
+            for (cx, c) in chars.char_indices() {
+                current += render_width(c, current, tab_size);
+                if current > rx {
+                    return start + cx;
+                }
+            }
+        }
+
+        if chars.len() < run.len() {
+            current += render_width('\t', current, tab_size);
+            if current > rx {
+                return start + chars.len();
+            }
+        }
+        start += run.len();
+    }
+
+    text.len()
+}
+
+fn is_printable_ascii(text: &str) -> bool {
+    text.bytes().all(|byte| (0x20..0x7f).contains(&byte))
+}
+
+/// The index and column of a char every `STRIDE` bytes or so along a row, starting with its start
+pub struct Checkpoints(Vec<(usize, usize)>);
+
+impl Checkpoints {
+    pub fn new(row: &str, tab_size: u8) -> Self {
+        let mut points = vec![(0, 0)];
+        let (mut cx, mut rx) = (0, 0);
+        while cx + STRIDE < row.len() {
+            let next = split_point(row, cx + STRIDE).unwrap_or_else(|clamped| clamped);
+            rx = advance(&row[cx..next], rx, tab_size);
+            cx = next;
+            points.push((cx, rx));
+        }
+        Checkpoints(points)
+    }
+
+    /// `cx_to_rx` for the row these were taken of
+    pub fn cx_to_rx(&self, row: &str, cx: usize, tab_size: u8) -> usize {
+        let cx = split_point(row, cx).unwrap_or_else(|clamped| clamped);
+        let closest = self.0.partition_point(|(start, _)| *start <= cx) - 1;
+        let (start, column) = self.0[closest];
+        advance(&row[start..cx], column, tab_size)
+    }
+
+    /// `rx_to_cx` for the row these were taken of
+    pub fn rx_to_cx(&self, row: &str, rx: usize, tab_size: u8) -> usize {
+        let closest = self.0.partition_point(|(_, column)| *column <= rx) - 1;
+        let (start, column) = self.0[closest];
+        start + index_at(&row[start..], column, rx, tab_size)
+    }
+}
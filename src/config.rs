@@ -0,0 +1,510 @@
+//! The user's configuration file, a list of `key = value` lines.
+//! A line that can't be understood is reported and skipped, its setting keeps the default.
+//! Lines after a `[filetype.NAME]` header set the indentation of that type of file only, `bind`
+//! lines apply wherever they are.
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use crate::filetype::{self, Filetype};
+use crate::keys::Key;
+use crate::render::{TextStyle, Theme};
+use crate::search::CaseMode;
+use crate::CursorShape;
+
+/// A `bind KEY ACTION` line, the action's name is checked once the keymap is built
+#[derive(Debug)]
+pub struct KeyBinding {
+    /// The config file line it came from, for error messages
+    pub line: usize,
+    pub key: Key,
+    pub action: String,
+    /// Where the key and the action start on the line
+    pub key_column: usize,
+    pub action_column: usize,
+}
+
+/// The indentation a `[filetype.NAME]` section sets, what it leaves out is the filetype's own
+#[derive(Debug)]
+pub struct FiletypeSettings {
+    pub name: &'static str,
+    pub tab_size: Option<u8>,
+    pub expand_tab: Option<bool>,
+}
+
+/// Something wrong with the config file, the message quotes the text that's wrong
+#[derive(Clone, Debug)]
+pub struct ConfigError {
+    /// The 1-based line and column the text starts at, `None` when it's about the whole file
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl ConfigError {
+    pub fn at(line: usize, column: usize, message: String) -> Self {
+        ConfigError {
+            location: Some((line, column)),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.location {
+            Some((line, column)) => {
+                write!(f, "config line {line}, column {column}: {}", self.message)
+            }
+            None => write!(f, "config: {}", self.message),
+        }
+    }
+}
+
+/// Why a `key = value` line was skipped
+enum Invalid {
+    Name,
+    /// The value isn't one of these
+    Value(&'static str),
+}
+
+#[derive(Debug)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Config {
+    /// Number of columns between tab stops
+    pub tab_size: u8,
+    /// Insert spaces instead of a tab character when Tab is pressed
+    pub expand_tab: bool,
+    /// Start in soft wrap mode
+    pub wrap: bool,
+    /// Keep the scratch buffer's content between sessions
+    pub persist_scratch: bool,
+    /// Copy killed text to the system clipboard through the terminal (OSC 52)
+    pub osc52: bool,
+    /// Paste from the system clipboard through the terminal, most terminals need this allowed first
+    pub osc52_paste: bool,
+    /// Strip trailing spaces and tabs, and blank lines at the end of the file, when saving
+    pub trim_trailing_whitespace: bool,
+    /// Typing an opening bracket or quote inserts its closer too
+    pub auto_close: bool,
+    /// Reopen files where the cursor was left the last time
+    pub remember_position: bool,
+    /// Start in a listing of the working directory when no file is given
+    pub browse_on_start: bool,
+    /// Rows kept on screen above and below the cursor when scrolling
+    pub scroll_off: usize,
+    /// Pastes bigger than this many megabytes are confirmed before they're inserted
+    pub paste_limit_mb: usize,
+    /// Copy a file's previous contents aside before saving over it
+    pub backup: bool,
+    /// Appended to the file name to name its backup
+    pub backup_suffix: String,
+    /// Keep every backup in this directory instead of next to its file
+    pub backup_dir: Option<PathBuf>,
+    /// The built-in theme the styles not set here come from
+    pub theme: Theme,
+    /// How the status bar, and the questions and lists drawn over it, look
+    pub status_bar_style: Option<TextStyle>,
+    /// Draw a '<' and a '>' at the edges of rows that go on past the window
+    pub clip_markers: bool,
+    /// How the '~' on rows past the end of the file looks
+    pub filler_style: Option<TextStyle>,
+    /// Whether searches tell upper and lower case apart
+    pub search_case: CaseMode,
+    /// How matches of the last search look
+    pub match_style: Option<TextStyle>,
+    /// How the match the search jumped to looks
+    pub current_match_style: Option<TextStyle>,
+    /// How the bracket matching the one at the cursor looks
+    pub bracket_style: Option<TextStyle>,
+    /// How a bracket at the cursor with no match looks
+    pub unmatched_bracket_style: Option<TextStyle>,
+    /// Draw marks for tabs, trailing spaces and line ends
+    pub show_invisibles: bool,
+    /// How those marks look
+    pub invisible_style: Option<TextStyle>,
+    /// How the text between the mark and the cursor looks
+    pub selection_style: Option<TextStyle>,
+    /// Columns to draw the line length guide in, counted from 1
+    pub color_column: Vec<usize>,
+    /// How the guide looks
+    pub color_column_style: Option<TextStyle>,
+    /// Highlight spaces and tabs at the ends of rows, and mark rows indented with both
+    pub whitespace_warnings: bool,
+    /// How the spaces and tabs at the end of a row look
+    pub trailing_whitespace_style: Option<TextStyle>,
+    /// How the mark on a row indented with both tabs and spaces looks
+    pub mixed_indent_style: Option<TextStyle>,
+    /// Mark the rows changed since the file was last read or saved in a gutter left of the text
+    pub change_marks: bool,
+    /// How those marks look
+    pub change_mark_style: Option<TextStyle>,
+    /// Dim the status bar while the terminal window doesn't have focus
+    pub dim_unfocused: bool,
+    /// The cursor's shape while inserting, overwriting always looks different
+    pub cursor_shape: CursorShape,
+    /// Whether the cursor blinks, terminals that can't draw it steady blink anyway
+    pub cursor_blink: bool,
+    /// Words that mark a line as an annotation, like TODO
+    pub annotation_markers: Vec<String>,
+    pub bindings: Vec<KeyBinding>,
+    /// Indentation set for a type of file
+    pub filetypes: Vec<FiletypeSettings>,
+    /// Lines that couldn't be understood
+    pub errors: Vec<ConfigError>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            tab_size: crate::TAB_SIZE,
+            expand_tab: false,
+            wrap: false,
+            persist_scratch: true,
+            osc52: true,
+            osc52_paste: false,
+            trim_trailing_whitespace: false,
+            auto_close: false,
+            remember_position: true,
+            browse_on_start: false,
+            scroll_off: 0,
+            paste_limit_mb: 8,
+            backup: false,
+            backup_suffix: String::from("~"),
+            backup_dir: None,
+            theme: Theme::default(),
+            status_bar_style: None,
+            clip_markers: true,
+            filler_style: None,
+            search_case: CaseMode::Smart,
+            match_style: None,
+            current_match_style: None,
+            bracket_style: None,
+            unmatched_bracket_style: None,
+            show_invisibles: false,
+            invisible_style: None,
+            selection_style: None,
+            color_column: Vec::new(),
+            color_column_style: None,
+            whitespace_warnings: true,
+            trailing_whitespace_style: None,
+            mixed_indent_style: None,
+            change_marks: false,
+            change_mark_style: None,
+            dim_unfocused: true,
+            cursor_shape: CursorShape::Bar,
+            cursor_blink: false,
+            annotation_markers: crate::annotations::DEFAULT_MARKERS
+                .iter()
+                .map(|marker| String::from(*marker))
+                .collect(),
+            bindings: Vec::new(),
+            filetypes: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file, falling back to the defaults for anything missing or unreadable.
+    /// A missing file is how the defaults are asked for, any other failure to read it is reported.
+    pub fn load() -> Self {
+        let Some(path) = path() else {
+            return Config::default();
+        };
+        match fs::read_to_string(&path) {
+            Ok(content) => Config::parse(&content),
+            Err(err) if err.kind() == ErrorKind::NotFound => Config::default(),
+            Err(err) => {
+                let mut config = Config::default();
+                config.errors.push(ConfigError {
+                    location: None,
+                    message: format!("couldn't read {}: {err}", path.display()),
+                });
+                config
+            }
+        }
+    }
+
+    pub fn parse(content: &str) -> Self {
+        let mut config = Config::default();
+        // The `filetypes` entry lines go to while inside a `[filetype.NAME]` section
+        let mut section = None;
+        for (idx, raw) in content.lines().enumerate() {
+            let line = raw.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+                section = match config.start_section(header.trim()) {
+                    Ok(section) => Some(section),
+                    Err(message) => {
+                        let error = ConfigError::at(idx + 1, column(raw, header), message);
+                        config.errors.push(error);
+                        // Its lines are skipped, they'd otherwise change the settings for every file
+                        Some(usize::MAX)
+                    }
+                };
+            // Checked before splitting on '=', a key like `seq:"\x1b="` can have one in it
+            } else if let Some(binding) = line.strip_prefix("bind ") {
+                match parse_binding(idx + 1, raw, binding.trim()) {
+                    Ok(binding) => config.bindings.push(binding),
+                    Err(error) => config.errors.push(error),
+                }
+            } else if let (Some(section), Some((name, value))) = (section, line.split_once('=')) {
+                let (name, value) = (name.trim(), value.trim());
+                let Some(settings) = config.filetypes.get_mut(section) else {
+                    continue;
+                };
+                let error = match settings.set(name, value) {
+                    Ok(()) => continue,
+                    Err(Invalid::Name) => ConfigError::at(
+                        idx + 1,
+                        column(raw, name),
+                        format!(
+                            "'{name}' can't be set for a filetype, only tab_size and expand_tab"
+                        ),
+                    ),
+                    Err(Invalid::Value(expected)) => ConfigError::at(
+                        idx + 1,
+                        column(raw, value),
+                        format!("{name} should be {expected}, not '{value}'"),
+                    ),
+                };
+                config.errors.push(error);
+            } else if let Some((name, value)) = line.split_once('=') {
+                let (name, value) = (name.trim(), value.trim());
+                let error = match config.set(name, value) {
+                    Ok(()) => continue,
+                    Err(Invalid::Name) => ConfigError::at(
+                        idx + 1,
+                        column(raw, name),
+                        format!("unknown setting '{name}'"),
+                    ),
+                    Err(Invalid::Value(expected)) => ConfigError::at(
+                        idx + 1,
+                        column(raw, value),
+                        format!("{name} should be {expected}, not '{value}'"),
+                    ),
+                };
+                config.errors.push(error);
+            } else {
+                let message = format!("'{line}' isn't 'name = value' or 'bind KEY ACTION'");
+                config
+                    .errors
+                    .push(ConfigError::at(idx + 1, column(raw, line), message));
+            }
+        }
+
+        config
+    }
+
+    /// The index into `filetypes` of the section `header` starts, or why it can't start one
+    fn start_section(&mut self, header: &str) -> Result<usize, String> {
+        let Some(name) = header.strip_prefix("filetype.") else {
+            return Err(format!("'[{header}]' isn't '[filetype.NAME]'"));
+        };
+        let filetype = filetype::named(name.trim())
+            .ok_or_else(|| format!("unknown filetype '{}'", name.trim()))?;
+        // The same section twice adds to the settings of the first
+        if let Some(section) = self
+            .filetypes
+            .iter()
+            .position(|settings| settings.name == filetype.name)
+        {
+            return Ok(section);
+        }
+        self.filetypes.push(FiletypeSettings {
+            name: filetype.name,
+            tab_size: None,
+            expand_tab: None,
+        });
+        Ok(self.filetypes.len() - 1)
+    }
+
+    /// The tab size and whether Tab inserts spaces for a file of type `filetype`: what its section
+    /// sets, then what the filetype brings along, then the settings for every file
+    pub fn indent(&self, filetype: Option<&Filetype>) -> (u8, bool) {
+        let Some(filetype) = filetype else {
+            return (self.tab_size, self.expand_tab);
+        };
+        let settings = self
+            .filetypes
+            .iter()
+            .find(|settings| settings.name == filetype.name);
+        (
+            settings
+                .and_then(|settings| settings.tab_size)
+                .or(filetype.tab_size)
+                .unwrap_or(self.tab_size),
+            settings
+                .and_then(|settings| settings.expand_tab)
+                .or(filetype.expand_tab)
+                .unwrap_or(self.expand_tab),
+        )
+    }
+
+    fn set(&mut self, name: &str, value: &str) -> Result<(), Invalid> {
+        match name {
+            "tab_size" => self.tab_size = parse_tab_size(value)?,
+            "expand_tab" => self.expand_tab = parse_bool(value)?,
+            "wrap" => self.wrap = parse_bool(value)?,
+            "persist_scratch" => self.persist_scratch = parse_bool(value)?,
+            "osc52" => self.osc52 = parse_bool(value)?,
+            "osc52_paste" => self.osc52_paste = parse_bool(value)?,
+            "auto_close" => self.auto_close = parse_bool(value)?,
+            "remember_position" => self.remember_position = parse_bool(value)?,
+            "browse_on_start" => self.browse_on_start = parse_bool(value)?,
+            "scroll_off" => {
+                self.scroll_off = value.parse().map_err(|_| Invalid::Value("a number"))?;
+            }
+            "paste_limit_mb" => {
+                self.paste_limit_mb = value.parse().map_err(|_| Invalid::Value("a number"))?;
+            }
+            "trim_trailing_whitespace" => self.trim_trailing_whitespace = parse_bool(value)?,
+            "backup" => self.backup = parse_bool(value)?,
+            // An empty suffix would name the backup after the file itself
+            "backup_suffix" if value.is_empty() => return Err(Invalid::Value("a suffix")),
+            "backup_suffix" => self.backup_suffix = String::from(value),
+            "backup_dir" if value.is_empty() => return Err(Invalid::Value("a directory")),
+            "backup_dir" => self.backup_dir = Some(PathBuf::from(value)),
+            "dim_unfocused" => self.dim_unfocused = parse_bool(value)?,
+            "cursor_shape" => {
+                self.cursor_shape = value
+                    .parse()
+                    .map_err(|()| Invalid::Value("bar, block, underline or default"))?;
+            }
+            "cursor_blink" => self.cursor_blink = parse_bool(value)?,
+            "theme" => {
+                self.theme = Theme::named(value).ok_or(Invalid::Value(THEME_NAMES))?;
+            }
+            "status_bar_style" => self.status_bar_style = Some(parse_style(value)?),
+            "clip_markers" => self.clip_markers = parse_bool(value)?,
+            "filler_style" => self.filler_style = Some(parse_style(value)?),
+            "search_case" => {
+                self.search_case = value
+                    .parse()
+                    .map_err(|()| Invalid::Value("sensitive, insensitive or smart"))?;
+            }
+            "match_style" => self.match_style = Some(parse_style(value)?),
+            "current_match_style" => self.current_match_style = Some(parse_style(value)?),
+            "bracket_style" => self.bracket_style = Some(parse_style(value)?),
+            "unmatched_bracket_style" => self.unmatched_bracket_style = Some(parse_style(value)?),
+            "show_invisibles" => self.show_invisibles = parse_bool(value)?,
+            "invisible_style" => self.invisible_style = Some(parse_style(value)?),
+            "selection_style" => self.selection_style = Some(parse_style(value)?),
+            "color_column" => {
+                self.color_column = parse_columns(value)
+                    .ok_or(Invalid::Value("column numbers like 80,100, or off"))?;
+            }
+            "color_column_style" => self.color_column_style = Some(parse_style(value)?),
+            "whitespace_warnings" => self.whitespace_warnings = parse_bool(value)?,
+            "trailing_whitespace_style" => {
+                self.trailing_whitespace_style = Some(parse_style(value)?);
+            }
+            "mixed_indent_style" => self.mixed_indent_style = Some(parse_style(value)?),
+            "change_marks" => self.change_marks = parse_bool(value)?,
+            "change_mark_style" => self.change_mark_style = Some(parse_style(value)?),
+            "annotation_markers" => {
+                let markers: Vec<String> = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|marker| !marker.is_empty())
+                    .map(String::from)
+                    .collect();
+                if markers.is_empty() {
+                    return Err(Invalid::Value("a comma separated list of words"));
+                }
+                self.annotation_markers = markers;
+            }
+            _ => return Err(Invalid::Name),
+        }
+        Ok(())
+    }
+}
+
+impl FiletypeSettings {
+    fn set(&mut self, name: &str, value: &str) -> Result<(), Invalid> {
+        match name {
+            "tab_size" => self.tab_size = Some(parse_tab_size(value)?),
+            "expand_tab" => self.expand_tab = Some(parse_bool(value)?),
+            _ => return Err(Invalid::Name),
+        }
+        Ok(())
+    }
+}
+
+/// The names `theme` takes, as they're listed in an error
+const THEME_NAMES: &str = "plain, ocean or gruvbox";
+
+/// Where the config file lives, following the XDG base directory spec
+pub fn path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("rilo").join("config"))
+}
+
+/// Reads the `KEY ACTION` part of a `bind` line, the action is the last word
+fn parse_binding(line: usize, raw: &str, binding: &str) -> Result<KeyBinding, ConfigError> {
+    let (key, action) = binding.rsplit_once(char::is_whitespace).ok_or_else(|| {
+        let message = format!("'bind {binding}' isn't 'bind KEY ACTION'");
+        ConfigError::at(line, column(raw, binding), message)
+    })?;
+    let key = key.trim_end();
+    Ok(KeyBinding {
+        line,
+        key: key
+            .parse()
+            .map_err(|message| ConfigError::at(line, column(raw, key), message))?,
+        action: String::from(action),
+        key_column: column(raw, key),
+        action_column: column(raw, action),
+    })
+}
+
+/// The 1-based column `part`, a slice of `line`, starts at
+fn column(line: &str, part: &str) -> usize {
+    let offset = (part.as_ptr() as usize).saturating_sub(line.as_ptr() as usize);
+    line.get(..offset)
+        .map_or(0, |before| before.chars().count())
+        + 1
+}
+
+fn parse_style(value: &str) -> Result<TextStyle, Invalid> {
+    TextStyle::parse(value).map_err(|_| Invalid::Value("style words like 'bold fg:white bg:blue'"))
+}
+
+fn parse_tab_size(value: &str) -> Result<u8, Invalid> {
+    value
+        .parse()
+        .ok()
+        .filter(|size| *size != 0)
+        .ok_or(Invalid::Value("a number from 1 to 255"))
+}
+
+/// Reads comma separated column numbers counted from 1, in any order. `off` is none at all.
+pub fn parse_columns(value: &str) -> Option<Vec<usize>> {
+    if matches!(value, "off" | "none" | "") {
+        return Some(Vec::new());
+    }
+    let mut columns = value
+        .split(',')
+        .map(|column| column.trim().parse().ok().filter(|column| *column > 0))
+        .collect::<Option<Vec<usize>>>()?;
+    columns.sort_unstable();
+    columns.dedup();
+    Some(columns)
+}
+
+fn parse_bool(value: &str) -> Result<bool, Invalid> {
+    match value {
+        "true" | "on" | "yes" => Ok(true),
+        "false" | "off" | "no" => Ok(false),
+        _ => Err(Invalid::Value("true or false")),
+    }
+}
@@ -0,0 +1,109 @@
+//! Questions answered with a single key, drawn over the bottom of the screen. Every yes/no or
+//! multiple choice question goes through here so they all look and behave the same.
+
+use crate::render::{Line, Span, Style};
+
+/// Most screen lines a question covers, including the status bar it's drawn over
+const MAX_LINES: usize = 3;
+
+/// Put between the question and the choices, and between choices
+const SEPARATOR: &str = "  ";
+
+/// An answer to a question and the key that picks it
+pub struct Choice {
+    pub key: char,
+    pub label: &'static str,
+}
+
+pub struct Confirm {
+    question: String,
+    choices: Vec<Choice>,
+    /// Set after a key that isn't one of the choices, reminds the user what the choices are
+    pub flash: bool,
+}
+
+impl Confirm {
+    pub fn new(question: &str, choices: Vec<Choice>) -> Self {
+        Confirm {
+            question: crate::sanitize(question, crate::MAX_MESSAGE_LEN),
+            choices,
+            flash: false,
+        }
+    }
+
+    /// The choice picked by a key, letters match regardless of case
+    pub fn choice(&self, c: char) -> Option<char> {
+        self.choices
+            .iter()
+            .find(|choice| choice.key.eq_ignore_ascii_case(&c))
+            .map(|choice| choice.key)
+    }
+
+    fn question_text(&self) -> String {
+        if self.flash {
+            let keys: Vec<String> = self.choices.iter().map(|c| c.key.to_string()).collect();
+            format!("{} (press {})", self.question, keys.join(", "))
+        } else {
+            self.question.clone()
+        }
+    }
+
+    /// Lays the question out over as few lines as fit in `width`: everything on one line when
+    /// it fits, otherwise the question on its own line and the choices packed under it.
+    /// The first line is where the cursor waits.
+    pub fn lines(&self, width: usize) -> Vec<Line> {
+        let question = self.question_text();
+        let choices: Vec<String> = self
+            .choices
+            .iter()
+            .map(|choice| format!("{} {}", choice.key, choice.label))
+            .collect();
+
+        let one_line = question.len()
+            + choices
+                .iter()
+                .map(|choice| SEPARATOR.len() + choice.len())
+                .sum::<usize>();
+        // Each line's text and the columns of the keys on it
+        let mut lines = vec![(question.into_bytes(), Vec::new())];
+        for text in choices {
+            let (current, _) = lines.last().unwrap();
+            let starts_line = one_line > width
+                && (lines.len() == 1
+                    || (current.len() + SEPARATOR.len() + text.len() > width
+                        && lines.len() < MAX_LINES));
+            if starts_line {
+                lines.push((Vec::new(), Vec::new()));
+            }
+
+            let (line, keys) = lines.last_mut().unwrap();
+            if !line.is_empty() {
+                line.extend(SEPARATOR.as_bytes());
+            }
+            keys.push(line.len());
+            line.extend(text.into_bytes());
+        }
+
+        lines
+            .into_iter()
+            .map(|(mut text, keys)| {
+                text.truncate(width);
+                let mut spans = vec![Span {
+                    start: 0,
+                    end: width,
+                    style: Style::StatusBar,
+                }];
+                spans.extend(keys.into_iter().filter(|key| *key < width).map(|key| Span {
+                    start: key,
+                    end: key + 1,
+                    style: Style::Key,
+                }));
+                Line {
+                    text: String::from_utf8_lossy(&text).chars().collect(),
+                    spans,
+                    combining: Vec::new(),
+                }
+            })
+            .collect()
+    }
+}
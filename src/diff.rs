@@ -0,0 +1,353 @@
+//! Line diffs between the buffer and another text, shown as unified diff hunks.
+//! The lines both sides start and end with are matched up front, only what's left in between is
+//! compared line by line, so two versions of the same file diff quickly however long they are.
+
+/// Most lines compared line by line once the common start and end are set aside, as the product of
+/// both sides' counts. Past it the whole middle is shown as replaced, which is still a correct diff.
+const MAX_MIDDLE_CELLS: usize = 4_000_000;
+
+/// Lines of unchanged text shown around each change
+pub const CONTEXT: usize = 3;
+
+/// What happened to a line going from the old text to the new one, by its index in each
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Edit {
+    Same(usize, usize),
+    Removed(usize),
+    Added(usize),
+}
+
+/// A line of a unified diff and the old text's row it's about, where a jump to it lands
+pub struct DiffLine {
+    pub text: String,
+    pub row: usize,
+}
+
+/// The edits turning `old` into `new`, in order. Lines are anything that can be compared, like
+/// the rows themselves or hashes of them.
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+
+    let mut edits: Vec<Edit> = (0..prefix).map(|idx| Edit::Same(idx, idx)).collect();
+    edits.extend(diff_middle(old, new, prefix..old_end, prefix..new_end));
+    edits.extend((0..suffix).map(|idx| Edit::Same(old_end + idx, new_end + idx)));
+    edits
+}
+
+/// Compares the middle ranges by their longest common subsequence of lines
+fn diff_middle<T: PartialEq>(
+    old: &[T],
+    new: &[T],
+    old_range: std::ops::Range<usize>,
+    new_range: std::ops::Range<usize>,
+) -> Vec<Edit> {
+    let (a, b) = (&old[old_range.clone()], &new[new_range.clone()]);
+    if a.len().saturating_mul(b.len()) > MAX_MIDDLE_CELLS {
+        return old_range
+            .map(Edit::Removed)
+            .chain(new_range.map(Edit::Added))
+            .collect();
+    }
+
+    // lcs[i][j] is the longest common subsequence of a[i..] and b[j..]
+    let width = b.len() + 1;
+    let mut lcs = vec![0_u32; (a.len() + 1) * width];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i * width + j] = if a[i] == b[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut edits = Vec::new();
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            edits.push(Edit::Same(old_range.start + i, new_range.start + j));
+            i += 1;
+            j += 1;
+        } else if j == b.len()
+            || (i < a.len() && lcs[(i + 1) * width + j] >= lcs[i * width + j + 1])
+        {
+            edits.push(Edit::Removed(old_range.start + i));
+            i += 1;
+        } else {
+            edits.push(Edit::Added(new_range.start + j));
+            j += 1;
+        }
+    }
+    edits
+}
+
+/// Lays the edits out as unified diff hunks, `CONTEXT` unchanged lines around each change.
+/// Added lines point at the old row they'd be inserted before.
+pub fn unified(edits: &[Edit], old: &[&str], new: &[&str]) -> Vec<DiffLine> {
+    let changed: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| !matches!(edit, Edit::Same(..)))
+        .map(|(idx, _)| idx)
+        .collect();
+
+    // Changes close enough for their context to touch share a hunk
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for idx in changed {
+        let start = idx.saturating_sub(CONTEXT);
+        let end = (idx + CONTEXT + 1).min(edits.len());
+        match hunks.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    // Where each edit sits in the old and the new text, counting what came before it
+    let mut positions = Vec::with_capacity(edits.len());
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for edit in edits {
+        positions.push((old_pos, new_pos));
+        match edit {
+            Edit::Same(..) => {
+                old_pos += 1;
+                new_pos += 1;
+            }
+            Edit::Removed(_) => old_pos += 1,
+            Edit::Added(_) => new_pos += 1,
+        }
+    }
+
+    let last_row = old.len().saturating_sub(1);
+    let mut lines = Vec::new();
+    for (start, end) in hunks {
+        let hunk = &edits[start..end];
+        let old_len = hunk.iter().filter(|e| !matches!(e, Edit::Added(_))).count();
+        let new_len = hunk
+            .iter()
+            .filter(|e| !matches!(e, Edit::Removed(_)))
+            .count();
+        let (old_start, new_start) = positions[start];
+        lines.push(DiffLine {
+            text: format!(
+                "@@ -{},{old_len} +{},{new_len} @@",
+                hunk_start(old_start, old_len),
+                hunk_start(new_start, new_len)
+            ),
+            row: old_start.min(last_row),
+        });
+
+        for (edit, (old_pos, _)) in hunk.iter().zip(&positions[start..end]) {
+            let (text, row) = match *edit {
+                Edit::Same(old_idx, _) => (format!(" {}", old[old_idx]), old_idx),
+                Edit::Removed(old_idx) => (format!("-{}", old[old_idx]), old_idx),
+                Edit::Added(new_idx) => (format!("+{}", new[new_idx]), *old_pos),
+            };
+            lines.push(DiffLine {
+                text,
+                row: row.min(last_row),
+            });
+        }
+    }
+
+    lines
+}
+
+/// A hunk header's 1-based first line, or the line before it when the side is empty, as in `-0,0`
+fn hunk_start(start: usize, len: usize) -> usize {
+    if len == 0 {
+        start
+    } else {
+        start + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(old: &[&str], new: &[&str]) -> Vec<(String, usize)> {
+        unified(&diff(old, new), old, new)
+            .into_iter()
+            .map(|line| (line.text, line.row))
+            .collect()
+    }
+
+    fn texts(old: &[&str], new: &[&str]) -> Vec<String> {
+        lines(old, new).into_iter().map(|(text, _)| text).collect()
+    }
+
+    #[test]
+    fn empty_sides() {
+        assert_eq!(diff::<&str>(&[], &[]), vec![]);
+        assert!(texts(&[], &[]).is_empty());
+        assert!(texts(&["a", "b"], &["a", "b"]).is_empty());
+
+        assert_eq!(diff(&[], &["a", "b"]), vec![Edit::Added(0), Edit::Added(1)]);
+        assert_eq!(
+            lines(&[], &["a", "b"]),
+            vec![
+                ("@@ -0,0 +1,2 @@".to_string(), 0),
+                ("+a".to_string(), 0),
+                ("+b".to_string(), 0),
+            ]
+        );
+
+        assert_eq!(
+            diff(&["a", "b"], &[]),
+            vec![Edit::Removed(0), Edit::Removed(1)]
+        );
+        assert_eq!(
+            lines(&["a", "b"], &[]),
+            vec![
+                ("@@ -1,2 +0,0 @@".to_string(), 0),
+                ("-a".to_string(), 0),
+                ("-b".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_pure_insert() {
+        let old = ["a", "b", "c"];
+        let new = ["a", "b", "x", "y", "c"];
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                Edit::Same(0, 0),
+                Edit::Same(1, 1),
+                Edit::Added(2),
+                Edit::Added(3),
+                Edit::Same(2, 4),
+            ]
+        );
+        // The added lines point at the row they go before
+        assert_eq!(
+            lines(&old, &new),
+            vec![
+                ("@@ -1,3 +1,5 @@".to_string(), 0),
+                (" a".to_string(), 0),
+                (" b".to_string(), 1),
+                ("+x".to_string(), 2),
+                ("+y".to_string(), 2),
+                (" c".to_string(), 2),
+            ]
+        );
+
+        // Past the last row they point at it
+        assert_eq!(
+            lines(&["a"], &["a", "b"]),
+            vec![
+                ("@@ -1,1 +1,2 @@".to_string(), 0),
+                (" a".to_string(), 0),
+                ("+b".to_string(), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_pure_delete() {
+        let old = ["a", "b", "c", "d"];
+        let new = ["a", "d"];
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                Edit::Same(0, 0),
+                Edit::Removed(1),
+                Edit::Removed(2),
+                Edit::Same(3, 1),
+            ]
+        );
+        assert_eq!(
+            lines(&old, &new),
+            vec![
+                ("@@ -1,4 +1,2 @@".to_string(), 0),
+                (" a".to_string(), 0),
+                ("-b".to_string(), 1),
+                ("-c".to_string(), 2),
+                (" d".to_string(), 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_replaced_line_is_removed_then_added() {
+        assert_eq!(
+            texts(&["a", "b", "c"], &["a", "x", "c"]),
+            ["@@ -1,3 +1,3 @@", " a", "-b", "+x", " c"]
+        );
+    }
+
+    #[test]
+    fn hunks_keep_their_context() {
+        let old: Vec<String> = (1..=20).map(|n| n.to_string()).collect();
+        let old: Vec<&str> = old.iter().map(String::as_str).collect();
+
+        // One change in the middle shows `CONTEXT` lines on each side
+        let mut new = old.clone();
+        new[9] = "ten";
+        assert_eq!(
+            texts(&old, &new),
+            [
+                "@@ -7,7 +7,7 @@",
+                " 7",
+                " 8",
+                " 9",
+                "-10",
+                "+ten",
+                " 11",
+                " 12",
+                " 13"
+            ]
+        );
+
+        // Changes whose context would touch share a hunk
+        let mut new = old.clone();
+        new[4] = "five";
+        new[10] = "eleven";
+        assert_eq!(
+            texts(&old, &new),
+            [
+                "@@ -2,13 +2,13 @@",
+                " 2",
+                " 3",
+                " 4",
+                "-5",
+                "+five",
+                " 6",
+                " 7",
+                " 8",
+                " 9",
+                " 10",
+                "-11",
+                "+eleven",
+                " 12",
+                " 13",
+                " 14",
+            ]
+        );
+
+        // Further apart they're hunks of their own, the header rows pointing at their first line
+        let mut new = old.clone();
+        new[1] = "two";
+        new[17] = "eighteen";
+        let hunks: Vec<_> = lines(&old, &new)
+            .into_iter()
+            .filter(|(text, _)| text.starts_with("@@"))
+            .collect();
+        assert_eq!(
+            hunks,
+            vec![
+                ("@@ -1,5 +1,5 @@".to_string(), 0),
+                ("@@ -15,6 +15,6 @@".to_string(), 14),
+            ]
+        );
+    }
+}
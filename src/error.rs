@@ -0,0 +1,31 @@
+//! Errors the editor can't carry on from. Everything else, like a failed save, is reported in the
+//! status bar where it happened and editing goes on; these end the session instead, and are
+//! printed once the terminal is back to normal.
+
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The terminal couldn't be switched to raw mode
+    Setup(io::Error),
+    /// Writing to the terminal failed, it's most likely gone
+    Output(io::Error),
+    /// Reading keys from the terminal failed
+    Input(io::Error),
+    /// The file, or the tutorial's copy of its text, couldn't be opened at startup
+    Open(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Setup(err) => write!(f, "couldn't set up the terminal: {err}"),
+            Error::Output(err) => write!(f, "couldn't write to the terminal: {err}"),
+            Error::Input(err) => write!(f, "couldn't read from the terminal: {err}"),
+            Error::Open(err) => write!(f, "couldn't open the file: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
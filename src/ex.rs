@@ -0,0 +1,148 @@
+//! The command line Alt-X opens, for small commands typed out in full: `w` and `w path`, `q` and
+//! `q!`, `e path` and `e!`, `set tabsize=8`, `set expandtab=on`, `set colorcolumn=80,100` or
+//! `set filetype=make`, `|sort` to run lines through a shell command and a line number to go to.
+//! Parsing only says what a command asks for, the editor does it.
+
+use crate::filetype::{self, Filetype};
+
+/// A command typed at the prompt
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    /// `w` saves, `w path` writes a copy to `path`
+    Write(Option<String>),
+    /// `q`, with `q!` quitting without asking about unsaved changes
+    Quit { force: bool },
+    /// `e path` edits another file, `e! path` drops unsaved changes to do it
+    Edit { path: String, force: bool },
+    /// `e!` on its own reads the file from disk again
+    Revert,
+    /// `set name=value`
+    Set(Setting),
+    /// A 1-based line number
+    Goto(usize),
+    /// `|command` runs the selected lines, or all of them, through a shell command
+    Filter(String),
+}
+
+/// What `set` can change, for the open file only
+#[derive(Debug, PartialEq)]
+pub enum Setting {
+    TabSize(u8),
+    ExpandTab(bool),
+    /// The columns of the line length guide, none turns it off
+    ColorColumn(Vec<usize>),
+    /// Highlighting and indentation as for another kind of file
+    Filetype(&'static Filetype),
+}
+
+/// What `line` asks for, or the message explaining why it asks for nothing
+pub fn parse(line: &str) -> Result<Command, String> {
+    let line = line.trim();
+    if let Ok(number) = line.parse::<usize>() {
+        return Ok(Command::Goto(number));
+    }
+    if let Some(command) = line.strip_prefix('|') {
+        return match command.trim() {
+            "" => Err(String::from("Run them through what? |command")),
+            command => Ok(Command::Filter(String::from(command))),
+        };
+    }
+
+    let (name, argument) = line
+        .split_once(char::is_whitespace)
+        .map_or((line, ""), |(name, argument)| (name, argument.trim()));
+    let (name, force) = name
+        .strip_suffix('!')
+        .map_or((name, false), |name| (name, true));
+    let path = || (!argument.is_empty()).then(|| String::from(argument));
+
+    match (name, force) {
+        ("w" | "write", false) => Ok(Command::Write(path())),
+        ("q" | "quit", force) if argument.is_empty() => Ok(Command::Quit { force }),
+        ("e" | "edit", true) if argument.is_empty() => Ok(Command::Revert),
+        ("e" | "edit", force) => path()
+            .map(|path| Command::Edit { path, force })
+            .ok_or_else(|| String::from("Edit which file? e path")),
+        ("set", false) => parse_setting(argument).map(Command::Set),
+        _ => Err(format!("Not an editor command: {line}")),
+    }
+}
+
+fn parse_setting(setting: &str) -> Result<Setting, String> {
+    let Some((name, value)) = setting.split_once('=') else {
+        return Err(format!("Set it to what? set {setting}=value"));
+    };
+    match name.trim() {
+        "tabsize" | "ts" => match value.trim().parse::<u8>() {
+            Ok(size) if size > 0 => Ok(Setting::TabSize(size)),
+            _ => Err(format!("Invalid tab size: {}", value.trim())),
+        },
+        "expandtab" | "et" => match value.trim() {
+            "on" | "true" | "yes" => Ok(Setting::ExpandTab(true)),
+            "off" | "false" | "no" => Ok(Setting::ExpandTab(false)),
+            value => Err(format!("expandtab is on or off, not {value}")),
+        },
+        "colorcolumn" | "cc" => crate::config::parse_columns(value.trim())
+            .map(Setting::ColorColumn)
+            .ok_or_else(|| {
+                format!(
+                    "colorcolumn is columns like 80,100 or off, not {}",
+                    value.trim()
+                )
+            }),
+        "filetype" | "ft" => filetype::named(value.trim())
+            .map(Setting::Filetype)
+            .ok_or_else(|| format!("Unknown filetype: {}", value.trim())),
+        name => Err(format!("Unknown option: {name}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands() {
+        let path = |path: &str| String::from(path);
+        let table = [
+            ("w", Ok(Command::Write(None))),
+            ("w notes.txt", Ok(Command::Write(Some(path("notes.txt"))))),
+            ("q", Ok(Command::Quit { force: false })),
+            ("q!", Ok(Command::Quit { force: true })),
+            (
+                "e notes.txt",
+                Ok(Command::Edit {
+                    path: path("notes.txt"),
+                    force: false,
+                }),
+            ),
+            ("e!", Ok(Command::Revert)),
+            (
+                "e! notes.txt",
+                Ok(Command::Edit {
+                    path: path("notes.txt"),
+                    force: true,
+                }),
+            ),
+            ("set ts=0", Err(String::from("Invalid tab size: 0"))),
+            (
+                "set ft=make",
+                Ok(Command::Set(Setting::Filetype(
+                    filetype::named("make").unwrap(),
+                ))),
+            ),
+            ("set ft=cobol", Err(String::from("Unknown filetype: cobol"))),
+            (
+                "set cc=off",
+                Ok(Command::Set(Setting::ColorColumn(Vec::new()))),
+            ),
+            ("|", Err(String::from("Run them through what? |command"))),
+            ("|sort", Ok(Command::Filter(String::from("sort")))),
+            ("42", Ok(Command::Goto(42))),
+            ("w!", Err(String::from("Not an editor command: w!"))),
+        ];
+        for (line, expected) in table {
+            assert_eq!(parse(line), expected, "{line}");
+        }
+    }
+}
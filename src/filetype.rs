@@ -0,0 +1,102 @@
+//! What kind of file is open, told by its name. A filetype brings its own indentation, spaces for
+//! YAML and tabs for Makefiles, which `[filetype.NAME]` sections of the config file can change.
+//! Files of no known type are indented the way the config says for everything.
+
+use std::path::Path;
+
+/// A kind of file, and how it's indented where that differs from the config's defaults
+#[derive(Debug, PartialEq)]
+pub struct Filetype {
+    /// The name shown in the status bar and used in the config file
+    pub name: &'static str,
+    /// File name extensions, without the dot
+    extensions: &'static [&'static str],
+    /// Whole file names, for the files that go without an extension
+    file_names: &'static [&'static str],
+    pub tab_size: Option<u8>,
+    pub expand_tab: Option<bool>,
+}
+
+const fn filetype(
+    name: &'static str,
+    extensions: &'static [&'static str],
+    file_names: &'static [&'static str],
+    indent: Option<(u8, bool)>,
+) -> Filetype {
+    let (tab_size, expand_tab) = match indent {
+        Some((tab_size, expand_tab)) => (Some(tab_size), Some(expand_tab)),
+        None => (None, None),
+    };
+    Filetype {
+        name,
+        extensions,
+        file_names,
+        tab_size,
+        expand_tab,
+    }
+}
+
+pub const FILETYPES: &[Filetype] = &[
+    filetype("c", &["c", "h"], &[], None),
+    filetype("cpp", &["cc", "cpp", "cxx", "hh", "hpp", "hxx"], &[], None),
+    filetype("css", &["css", "scss"], &[], Some((2, true))),
+    filetype(
+        "dockerfile",
+        &["dockerfile"],
+        &["Dockerfile", "Containerfile"],
+        None,
+    ),
+    filetype("go", &["go"], &[], Some((8, false))),
+    filetype("html", &["html", "htm"], &[], Some((2, true))),
+    filetype(
+        "javascript",
+        &["js", "mjs", "cjs", "jsx"],
+        &[],
+        Some((2, true)),
+    ),
+    filetype("json", &["json"], &[], Some((2, true))),
+    // Recipes have to start with a tab, how wide it's drawn is up to the config
+    Filetype {
+        expand_tab: Some(false),
+        ..filetype(
+            "make",
+            &["mk", "mak"],
+            &["Makefile", "makefile", "GNUmakefile"],
+            None,
+        )
+    },
+    filetype("markdown", &["md", "markdown"], &[], None),
+    filetype("python", &["py", "pyi"], &[], Some((4, true))),
+    filetype("ruby", &["rb"], &["Gemfile", "Rakefile"], Some((2, true))),
+    filetype("rust", &["rs"], &[], Some((4, true))),
+    filetype(
+        "shell",
+        &["sh", "bash", "zsh"],
+        &[".bashrc", ".profile", ".zshrc"],
+        None,
+    ),
+    filetype("toml", &["toml"], &["Cargo.lock"], None),
+    filetype("typescript", &["ts", "tsx"], &[], Some((2, true))),
+    filetype("yaml", &["yaml", "yml"], &[], Some((2, true))),
+];
+
+/// The type of the file at `path`, going by its name
+pub fn detect(path: &Path) -> Option<&'static Filetype> {
+    let file_name = path.file_name()?.to_str()?;
+    if let Some(filetype) = FILETYPES
+        .iter()
+        .find(|filetype| filetype.file_names.contains(&file_name))
+    {
+        return Some(filetype);
+    }
+
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    FILETYPES
+        .iter()
+        .find(|filetype| filetype.extensions.contains(&extension.as_str()))
+}
+
+/// The filetype called `name` in the config file
+pub fn named(name: &str) -> Option<&'static Filetype> {
+    FILETYPES.iter().find(|filetype| filetype.name == name)
+}
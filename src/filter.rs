@@ -0,0 +1,102 @@
+//! Running lines of the buffer through a shell command, like `|sort` at the command line. The
+//! command gets the lines on its stdin and what it prints replaces them. It runs with pipes for
+//! all of stdin, stdout and stderr and never gets the terminal, so it can't draw over the editor
+//! or wait for keys the editor is reading. The editor waits for it in between keys, which can
+//! cancel it.
+
+use std::convert::TryFrom;
+use std::io::Write;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use nix::sys::signal::{killpg, Signal};
+use nix::unistd::Pid;
+
+/// What a command run through `start` printed, or why it didn't work
+pub type Output = Result<Vec<String>, String>;
+
+/// A command running in the background, in a process group of its own so whatever it starts can
+/// be stopped along with it
+pub struct Running {
+    group: Pid,
+    receiver: Receiver<Output>,
+}
+
+impl Running {
+    /// What the command printed, waiting up to `timeout` for it to finish. `None` while it's
+    /// still running.
+    pub fn wait(&self, timeout: Duration) -> Option<Output> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(output) => Some(output),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => {
+                Some(Err(String::from("The command's thread stopped")))
+            }
+        }
+    }
+
+    /// Stops the command and everything it started, what it printed is thrown away
+    pub fn cancel(self) {
+        let _ = killpg(self.group, Signal::SIGKILL);
+    }
+}
+
+/// Starts `command` in the background with `input` on its stdin, the lines to filter each with a
+/// line break after it. What comes of it is the lines the command prints. A command that can't be
+/// started, fails or prints something other than UTF-8 gives the message saying so instead, with
+/// the first line it wrote to stderr if there is one.
+pub fn start(command: &str, input: String) -> Result<Running, String> {
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .process_group(0)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("Couldn't run {command}: {err}"))?;
+    let group = Pid::from_raw(i32::try_from(child.id()).unwrap_or(i32::MAX));
+
+    let (sender, receiver) = mpsc::channel();
+    let command = command.to_string();
+    thread::spawn(move || {
+        // Nobody's waiting anymore when the command was cancelled
+        let _ = sender.send(finish(&command, child, input));
+    });
+    Ok(Running { group, receiver })
+}
+
+/// Feeds `input` to the started `child` and waits for what it prints
+fn finish(command: &str, mut child: Child, input: String) -> Output {
+    // Written from another thread, a command printing as it reads would otherwise fill its
+    // stdout pipe and wait for it to be read while rilo waits to write the rest
+    let mut stdin = child.stdin.take();
+    let writer = thread::spawn(move || {
+        // A command that stops reading early, like `head`, is fine, it's the output that counts
+        if let Some(stdin) = stdin.as_mut() {
+            let _ = stdin.write_all(input.as_bytes());
+        }
+    });
+    let output = child
+        .wait_with_output()
+        .map_err(|err| format!("Couldn't run {command}: {err}"))?;
+    let _ = writer.join();
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = match output.status.code() {
+            Some(code) => format!("exited with status {code}"),
+            None => String::from("was killed"),
+        };
+        return Err(match stderr.lines().find(|line| !line.trim().is_empty()) {
+            Some(line) => format!("{command} {reason}: {}", line.trim()),
+            None => format!("{command} {reason}"),
+        });
+    }
+    let stdout = String::from_utf8(output.stdout)
+        .map_err(|_| format!("{command} printed something that isn't UTF-8"))?;
+    Ok(stdout.lines().map(String::from).collect())
+}
@@ -0,0 +1,111 @@
+//! Finding a file under the working directory by typing a few letters of its path. The tree is
+//! listed once, up to a depth and a number of files, and the list is narrowed down to the paths
+//! that have the letters in order. Letters starting the file name or a directory name count the
+//! most, so `lr` finds `src/lib.rs` before `src/color.rs`.
+
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+/// Directories never looked into, they're big and nobody edits what's in them by hand
+const SKIPPED: &[&str] = &[".git", "target"];
+
+/// How many directories deep the listing goes
+const MAX_DEPTH: usize = 8;
+
+/// Most files listed, the rest of a huge tree is left out rather than waited on
+const MAX_FILES: usize = 50_000;
+
+/// The files under `root` as paths relative to it, in no particular order. Directories that can't
+/// be read are left out, and symlinks to directories aren't followed.
+pub fn walk(root: &Path) -> Vec<String> {
+    let mut files = Vec::new();
+    let mut pending = vec![(root.to_path_buf(), String::new(), 0)];
+    while let Some((dir, prefix, depth)) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let path = format!("{prefix}{name}");
+            let Ok(kind) = entry.file_type() else {
+                continue;
+            };
+
+            if kind.is_dir() {
+                if depth + 1 < MAX_DEPTH && !SKIPPED.contains(&name.as_str()) {
+                    pending.push((entry.path(), format!("{path}/"), depth + 1));
+                }
+            } else if kind.is_file() || entry.path().is_file() {
+                files.push(path);
+                if files.len() == MAX_FILES {
+                    return files;
+                }
+            }
+        }
+    }
+    files
+}
+
+/// The paths that match `query`, best first. Ties go to the shorter path.
+pub fn filter<'a>(paths: &'a [String], query: &str) -> Vec<&'a String> {
+    let mut scored: Vec<(i64, &String)> = paths
+        .iter()
+        .filter_map(|path| Some((score(query, path)?, path)))
+        .collect();
+    scored.sort_by(|(score, path), (other_score, other)| {
+        other_score
+            .cmp(score)
+            .then(path.len().cmp(&other.len()))
+            .then(path.cmp(other))
+    });
+    scored.into_iter().map(|(_, path)| path).collect()
+}
+
+/// How well `path` matches `query`, `None` when the query's letters aren't all in it in order.
+/// Case is ignored, as are spaces in the query.
+fn score(query: &str, path: &str) -> Option<i64> {
+    let path: Vec<char> = path.chars().collect();
+    let name_start = path
+        .iter()
+        .rposition(|c| *c == '/')
+        .map_or(0, |slash| slash + 1);
+
+    let mut score = 0;
+    let mut next = 0;
+    let mut last: Option<usize> = None;
+    for wanted in query.chars().filter(|c| !c.is_whitespace()) {
+        let found = (next..path.len()).find(|idx| same_letter(wanted, path[*idx]))?;
+        score += 1;
+        if starts_word(&path, found) {
+            score += if found >= name_start { 8 } else { 5 };
+        }
+        if found >= name_start {
+            score += 2;
+        }
+        match last {
+            Some(last) if last + 1 == found => score += 4,
+            // Letters far apart say little about the path, though a gap is never worse than this
+            Some(last) => score -= i64::try_from((found - last - 1).min(8)).unwrap_or(8),
+            None => {}
+        }
+
+        last = Some(found);
+        next = found + 1;
+    }
+    Some(score)
+}
+
+fn same_letter(wanted: char, c: char) -> bool {
+    wanted == c || wanted.to_lowercase().eq(c.to_lowercase())
+}
+
+/// Whether the character at `idx` starts a path component or a word in one: the first after a
+/// separator, or a capital letter after a lowercase one
+fn starts_word(path: &[char], idx: usize) -> bool {
+    let Some(before) = idx.checked_sub(1).map(|before| path[before]) else {
+        return true;
+    };
+    matches!(before, '/' | '_' | '-' | '.' | ' ')
+        || (before.is_lowercase() && path[idx].is_uppercase())
+}
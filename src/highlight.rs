@@ -0,0 +1,335 @@
+//! Picking out the parts of a row by what they are, a heading or a string or a comment, for the
+//! filetypes that have rules here. The rules are data: markers a part starts with, what ends it
+//! and what kind of part it is, so another simple language is another table rather than more
+//! code. Parts never go past the end of their row, except for blocks like Markdown's fenced code,
+//! which every row is in from the one opening it to the one closing it.
+
+/// What a part of a row is, the theme has a style for each
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    Heading,
+    Strong,
+    Emphasis,
+    Code,
+    /// Punctuation that structures the text, like a list's bullets
+    Marker,
+    Key,
+    String,
+    Comment,
+    /// Numbers and words like `true`
+    Constant,
+}
+
+/// How many kinds there are, the themes' styles for them are in this order
+pub const KINDS: usize = 9;
+
+/// A way a part of a row is recognized. Rules are tried in the order they're listed at every
+/// position of a row, the first that matches takes the part and the next one starts after it.
+enum Rule {
+    /// A row whose text starts with `marker` after its indentation is all one part
+    Line { marker: &'static str, kind: Kind },
+    /// One of `markers` starting the row's text, just the marker is the part
+    Prefix {
+        markers: &'static [&'static str],
+        kind: Kind,
+    },
+    /// A number starting the row's text, with one of `after` and a space right after it
+    Numbered { after: &'static [char], kind: Kind },
+    /// What's before the first `separator` of the row, when it isn't all spaces
+    Key { separator: char, kind: Kind },
+    /// From `marker` to the end of the row
+    ToEnd { marker: &'static str, kind: Kind },
+    /// From `open` to the next `close` on the row, both included. Without a `close` it's no part.
+    Delimited {
+        open: &'static str,
+        close: &'static str,
+        /// A backslash keeps the char after it from closing
+        escape: bool,
+        /// `open` doesn't follow a letter or digit, and what's between doesn't start or end
+        /// with a space, like emphasis in Markdown
+        flanking: bool,
+        kind: Kind,
+    },
+    /// Numbers, and any of `words`, standing on their own
+    Constants {
+        words: &'static [&'static str],
+        kind: Kind,
+    },
+}
+
+/// A block that rows starting with `fence` after their indentation open and close
+struct Block {
+    fence: &'static str,
+    kind: Kind,
+}
+
+/// The rules for a filetype
+pub struct Syntax {
+    /// The name of the filetype they're for
+    filetype: &'static str,
+    rules: &'static [Rule],
+    blocks: &'static [Block],
+}
+
+const SYNTAXES: &[Syntax] = &[
+    Syntax {
+        filetype: "markdown",
+        rules: &[
+            Rule::Line {
+                marker: "#",
+                kind: Kind::Heading,
+            },
+            Rule::Prefix {
+                markers: &["- ", "* ", "+ ", "> "],
+                kind: Kind::Marker,
+            },
+            Rule::Numbered {
+                after: &['.', ')'],
+                kind: Kind::Marker,
+            },
+            Rule::Delimited {
+                open: "`",
+                close: "`",
+                escape: false,
+                flanking: false,
+                kind: Kind::Code,
+            },
+            Rule::Delimited {
+                open: "**",
+                close: "**",
+                escape: false,
+                flanking: true,
+                kind: Kind::Strong,
+            },
+            Rule::Delimited {
+                open: "__",
+                close: "__",
+                escape: false,
+                flanking: true,
+                kind: Kind::Strong,
+            },
+            Rule::Delimited {
+                open: "*",
+                close: "*",
+                escape: false,
+                flanking: true,
+                kind: Kind::Emphasis,
+            },
+            Rule::Delimited {
+                open: "_",
+                close: "_",
+                escape: false,
+                flanking: true,
+                kind: Kind::Emphasis,
+            },
+        ],
+        blocks: &[
+            Block {
+                fence: "```",
+                kind: Kind::Code,
+            },
+            Block {
+                fence: "~~~",
+                kind: Kind::Code,
+            },
+        ],
+    },
+    Syntax {
+        filetype: "toml",
+        rules: &[
+            Rule::Line {
+                marker: "[",
+                kind: Kind::Heading,
+            },
+            Rule::ToEnd {
+                marker: "#",
+                kind: Kind::Comment,
+            },
+            Rule::Key {
+                separator: '=',
+                kind: Kind::Key,
+            },
+            Rule::Delimited {
+                open: "\"",
+                close: "\"",
+                escape: true,
+                flanking: false,
+                kind: Kind::String,
+            },
+            Rule::Delimited {
+                open: "'",
+                close: "'",
+                escape: false,
+                flanking: false,
+                kind: Kind::String,
+            },
+            Rule::Constants {
+                words: &["true", "false", "inf", "nan"],
+                kind: Kind::Constant,
+            },
+        ],
+        blocks: &[],
+    },
+];
+
+/// The rules for the filetype called `filetype`, if it has any
+pub fn for_filetype(filetype: &str) -> Option<&'static Syntax> {
+    SYNTAXES.iter().find(|syntax| syntax.filetype == filetype)
+}
+
+/// A part of a row, as indexes into it
+#[derive(Debug, PartialEq)]
+pub struct Part {
+    pub start: usize,
+    pub end: usize,
+    pub kind: Kind,
+}
+
+impl Syntax {
+    /// Whether any rows can be in a block, only then is there a reason to go over the rows above
+    /// the ones drawn
+    pub fn has_blocks(&self) -> bool {
+        !self.blocks.is_empty()
+    }
+
+    /// The block the row after `row` starts in, given the one `row` starts in, as an index into
+    /// the blocks
+    pub fn block_after(&self, row: &str, block: Option<usize>) -> Option<usize> {
+        let text = row.trim_start();
+        match block {
+            Some(open) if text.starts_with(self.blocks[open].fence) => None,
+            Some(open) => Some(open),
+            None => self
+                .blocks
+                .iter()
+                .position(|block| text.starts_with(block.fence)),
+        }
+    }
+
+    /// The parts of `row`, in order and not overlapping, given the block it starts in
+    pub fn parts(&self, row: &str, block: Option<usize>) -> Vec<Part> {
+        // The rows opening and closing a block belong to it too
+        if let Some(block) = block.or_else(|| self.block_after(row, None)) {
+            return vec![Part {
+                start: 0,
+                end: row.len(),
+                kind: self.blocks[block].kind,
+            }];
+        }
+
+        let indent = row.len() - row.trim_start().len();
+        let mut parts = Vec::new();
+        let mut idx = indent;
+        while idx < row.len() {
+            let found = self.rules.iter().find_map(|rule| {
+                let end = rule.end(row, idx, idx == indent)?;
+                Some((end, rule.kind()))
+            });
+            match found {
+                Some((end, kind)) => {
+                    parts.push(Part {
+                        start: idx,
+                        end,
+                        kind,
+                    });
+                    idx = end;
+                }
+                None => idx += row[idx..].chars().next().map_or(1, char::len_utf8),
+            }
+        }
+        parts
+    }
+}
+
+impl Rule {
+    fn kind(&self) -> Kind {
+        match self {
+            Rule::Line { kind, .. }
+            | Rule::Prefix { kind, .. }
+            | Rule::Numbered { kind, .. }
+            | Rule::Key { kind, .. }
+            | Rule::ToEnd { kind, .. }
+            | Rule::Delimited { kind, .. }
+            | Rule::Constants { kind, .. } => *kind,
+        }
+    }
+
+    /// Where the part this rule finds at `idx` of `row` ends, if it finds one. `first` says `idx`
+    /// is where the row's text starts, the rules about the start of a row only look there.
+    fn end(&self, row: &str, idx: usize, first: bool) -> Option<usize> {
+        let rest = &row[idx..];
+        match *self {
+            Rule::Line { marker, .. } if first && rest.starts_with(marker) => Some(row.len()),
+            Rule::Prefix { markers, .. } if first => markers
+                .iter()
+                .find(|marker| rest.starts_with(**marker))
+                .map(|marker| idx + marker.trim_end().len()),
+            Rule::Numbered { after, .. } if first => {
+                let digits =
+                    rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+                let mut marker = rest[digits..].chars();
+                let ends = marker.next().is_some_and(|c| after.contains(&c));
+                (digits > 0 && ends && marker.next() == Some(' ')).then_some(idx + digits + 1)
+            }
+            Rule::Key { separator, .. } if first => {
+                let key = rest.split_once(separator)?.0.trim_end();
+                (!key.is_empty()).then_some(idx + key.len())
+            }
+            Rule::ToEnd { marker, .. } if rest.starts_with(marker) => Some(row.len()),
+            Rule::Delimited {
+                open,
+                close,
+                escape,
+                flanking,
+                ..
+            } => {
+                let inner = rest.strip_prefix(open)?;
+                if flanking
+                    && row[..idx]
+                        .chars()
+                        .next_back()
+                        .is_some_and(char::is_alphanumeric)
+                {
+                    return None;
+                }
+                let length = closing(inner, close, escape)?;
+                let content = &inner[..length];
+                if content.is_empty()
+                    || (flanking && (content.starts_with(' ') || content.ends_with(' ')))
+                {
+                    return None;
+                }
+                Some(idx + open.len() + length + close.len())
+            }
+            Rule::Constants { words, .. } => {
+                if row[..idx]
+                    .chars()
+                    .next_back()
+                    .is_some_and(|c| c.is_alphanumeric() || c == '_')
+                {
+                    return None;
+                }
+                let word = rest
+                    .split(|c: char| c.is_whitespace() || matches!(c, ',' | ']' | '}' | '#' | '='))
+                    .next()?;
+                let unsigned = word.trim_start_matches(['+', '-']);
+                let number = unsigned.starts_with(|c: char| c.is_ascii_digit());
+                (number || words.contains(&unsigned)).then_some(idx + word.len())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The length of `text` up to the first `close` in it, skipping escaped chars when `escape`
+fn closing(text: &str, close: &str, escape: bool) -> Option<usize> {
+    let mut chars = text.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        if escape && c == '\\' {
+            chars.next();
+        } else if text[idx..].starts_with(close) {
+            return Some(idx);
+        }
+    }
+    None
+}
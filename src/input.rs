@@ -0,0 +1,861 @@
+//! Reading keys from the terminal. Whatever is waiting is read at once into a buffer, and whole
+//! events are decoded from its start: a byte of typing or a control key, a character beyond ASCII
+//! with all of its UTF-8 bytes, or an escape sequence with everything that belongs to it. Decoding
+//! only looks at the buffer, a sequence split between reads is just waited for.
+//!
+//! The bytes come from a `Source`: the terminal, or for driving the editor without one, `Keys`
+//! handed over up front.
+
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::io::{self, Read};
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// How long the rest of an escape sequence is waited for, Escape followed by nothing is Escape
+/// pressed on its own
+const ESCAPE_WAIT: Duration = Duration::from_millis(100);
+
+/// How long a paste that never ended is waited on before what came of it is taken as the paste
+const PASTE_WAIT: Duration = Duration::from_secs(2);
+
+/// Most bytes taken from the terminal with each read
+const READ_SIZE: usize = 64 * 1024;
+
+/// Longest escape sequence read, anything longer is broken and dropped
+const MAX_SEQ_LEN: usize = 32;
+
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// An enum representing a navigation key press
+#[derive(Debug, PartialEq)]
+pub enum NavigationKey {
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    /// Ctrl or Alt with Left, to the start of the previous word
+    WordLeft,
+    /// Ctrl or Alt with Right, to the end of the next word
+    WordRight,
+    /// Ctrl-Up, to the blank line before the previous paragraph
+    ParagraphUp,
+    /// Ctrl-Down, to the blank line after the next paragraph
+    ParagraphDown,
+}
+
+/// A mouse report we act on, coordinates are 0-based screen cells
+pub enum MouseEvent {
+    Click { x: usize, y: usize },
+    WheelUp,
+    WheelDown,
+}
+
+/// What a complete escape sequence decodes to
+pub enum EscapeSequence {
+    Navigation(NavigationKey),
+    Mouse(MouseEvent),
+    /// Alt-Up and Alt-Down, swapping the current row with its neighbour
+    MoveLineUp,
+    MoveLineDown,
+    /// Text pasted while bracketed paste is on, exactly as the terminal sent it
+    Paste(Vec<u8>),
+    /// The Delete key, removing the character under the cursor
+    Delete,
+    /// The Insert key, switching between inserting and overwriting
+    Insert,
+    /// Alt-X, asking for an action to run by name or a command like `w` or `set tabsize=8`
+    RunCommand,
+    /// F1, the other key for help
+    Help,
+    /// F3 and Shift-F3, the next and previous match of the last search
+    FindNext,
+    FindPrevious,
+    /// Shift-Tab, shifting lines left by an indent
+    BackTab,
+    /// Alt-C, the next way for searches to treat case
+    CycleSearchCase,
+    /// Escape pressed on its own, nothing followed it
+    Bare,
+    /// The terminal window gained (true) or lost (false) focus
+    Focus(bool),
+    /// A sequence rilo has no use for, read whole so none of it is taken as typing
+    Unknown,
+}
+
+/// Something read from the terminal
+pub enum Event {
+    /// An ASCII byte, typing or a control key, or a byte that isn't part of any UTF-8 character
+    Byte(u8),
+    /// A character beyond ASCII
+    Char(char),
+    /// Escape and whatever followed it
+    Escape(EscapeSequence),
+}
+
+/// What the start of the buffer decodes to
+enum Decoded {
+    /// An event and the bytes it took
+    Event(Event, usize),
+    /// The start of something whose rest hasn't been read yet, and how long to wait for it
+    Incomplete(Duration),
+    Empty,
+}
+
+/// Where input bytes come from
+pub trait Source {
+    /// Whether there's input to read within `timeout` (forever for `None`), an interrupted wait
+    /// counts as none
+    fn ready(&mut self, timeout: Option<Duration>) -> io::Result<bool>;
+    /// Reads what's waiting into `buff`, 0 means the input ended
+    fn read(&mut self, buff: &mut [u8]) -> io::Result<usize>;
+}
+
+/// The terminal, on stdin
+pub struct Stdin;
+
+impl Stdin {
+    /// Also has a window resize interrupt the wait for input, so it's noticed right away, and
+    /// SIGTSTP ask for a suspend instead of stopping rilo with the terminal still in raw mode
+    pub fn new() -> Self {
+        extern "C" fn wake(_: nix::libc::c_int) {}
+        extern "C" fn request_suspend(_: nix::libc::c_int) {
+            SUSPEND_REQUESTED.store(true, Ordering::SeqCst);
+        }
+
+        // Without SA_RESTART the signal makes `poll` return early, which is all it's for
+        let action = SigAction::new(SigHandler::Handler(wake), SaFlags::empty(), SigSet::empty());
+        // Only resizes lose anything if this fails, they're still picked up on the next event
+        let _ = unsafe { sigaction(Signal::SIGWINCH, &action) };
+        let action = SigAction::new(
+            SigHandler::Handler(request_suspend),
+            SaFlags::empty(),
+            SigSet::empty(),
+        );
+        // If this fails SIGTSTP just stops rilo the default way
+        let _ = unsafe { sigaction(Signal::SIGTSTP, &action) };
+
+        Stdin
+    }
+}
+
+impl Default for Stdin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Source for Stdin {
+    fn ready(&mut self, timeout: Option<Duration>) -> io::Result<bool> {
+        let mut fds = [PollFd::new(io::stdin().as_raw_fd(), PollFlags::POLLIN)];
+        let timeout = timeout.map_or(-1, |timeout| {
+            i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX)
+        });
+        match poll(&mut fds, timeout) {
+            Ok(ready) => Ok(ready > 0),
+            Err(err) if err.as_errno() == Some(Errno::EINTR) => Ok(false),
+            Err(err) => Err(io::Error::other(err)),
+        }
+    }
+
+    fn read(&mut self, buff: &mut [u8]) -> io::Result<usize> {
+        io::stdin().lock().read(buff)
+    }
+}
+
+/// Set by SIGTSTP until the editor gets around to suspending
+static SUSPEND_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether a SIGTSTP came in since this was last asked
+pub fn take_suspend_request() -> bool {
+    SUSPEND_REQUESTED.swap(false, Ordering::SeqCst)
+}
+
+/// Keys given up front, all of them are waiting from the start. Once they run out the input has
+/// ended, like a terminal that went away.
+pub struct Keys(VecDeque<u8>);
+
+impl Keys {
+    pub fn new(bytes: &[u8]) -> Self {
+        Keys(bytes.iter().copied().collect())
+    }
+}
+
+impl Source for Keys {
+    fn ready(&mut self, _: Option<Duration>) -> io::Result<bool> {
+        if self.0.is_empty() {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        Ok(true)
+    }
+
+    fn read(&mut self, buff: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buff)
+    }
+}
+
+/// Keys recorded for a macro, the bytes of each event on their own so they decode the same way
+/// again however they came in
+pub type Macro = Vec<Vec<u8>>;
+
+/// Events taken and then held back with `Input::hold`, each one's bytes with whether it came from
+/// a macro being replayed
+#[derive(Default)]
+pub struct Held(Vec<(Vec<u8>, bool)>);
+
+impl Held {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// The terminal's input, read as it comes
+pub struct Input {
+    source: Box<dyn Source>,
+    buffer: Vec<u8>,
+    /// The events taken while a macro is recorded
+    recording: Option<Macro>,
+    /// Events of a macro being replayed and those put back after being held, taken before
+    /// anything typed. Each one's bytes with whether it came from a macro.
+    replay: VecDeque<(Vec<u8>, bool)>,
+    /// Whether the last event taken came from `replay`
+    replayed: bool,
+    /// The bytes of the last event taken, for looking up the key that sent them
+    last: Vec<u8>,
+}
+
+impl Input {
+    pub fn new(source: Box<dyn Source>) -> Self {
+        Input {
+            source,
+            buffer: Vec::new(),
+            recording: None,
+            replay: VecDeque::new(),
+            replayed: false,
+            last: Vec::new(),
+        }
+    }
+
+    /// The next event, waiting up to `timeout` for one (forever for `None`). `None` when nothing
+    /// came in time or the wait was interrupted, by a resize for one.
+    pub fn next(&mut self, timeout: Option<Duration>) -> io::Result<Option<Event>> {
+        if let Some((bytes, replayed)) = self.replay.pop_front() {
+            self.replayed = replayed;
+            let event = match decode(&bytes) {
+                Decoded::Event(event, _) => event,
+                _ => flush(&bytes).0,
+            };
+            // Typed keys that were held back are recorded when they're taken again
+            if let (false, Some(recording)) = (replayed, &mut self.recording) {
+                recording.push(bytes.clone());
+            }
+            self.last = bytes;
+            return Ok(Some(event));
+        }
+        self.replayed = false;
+
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            match decode(&self.buffer) {
+                Decoded::Event(event, len) => {
+                    self.take(len);
+                    return Ok(Some(event));
+                }
+                Decoded::Incomplete(wait) => {
+                    // The rest of a sequence comes right behind its start, if it comes at all
+                    if !self.fill(Some(wait))? {
+                        let (event, len) = flush(&self.buffer);
+                        self.take(len);
+                        return Ok(Some(event));
+                    }
+                }
+                Decoded::Empty => {
+                    let left =
+                        deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+                    if !self.fill(left)? {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The next key exactly as it's typed, waiting for as long as it takes. Escape is taken on its
+    /// own instead of starting a sequence, whatever followed it is read after it.
+    pub fn next_verbatim(&mut self) -> io::Result<Event> {
+        if self
+            .replay
+            .front()
+            .is_some_and(|(bytes, _)| bytes[..] == [0x1b])
+        {
+            // Taken off the queue the way any event there is
+            self.next(Some(Duration::ZERO))?;
+            return Ok(Event::Byte(0x1b));
+        }
+        loop {
+            match self.buffer.first() {
+                None if self.replay.is_empty() => {
+                    self.fill(None)?;
+                }
+                Some(0x1b) if self.replay.is_empty() => {
+                    self.replayed = false;
+                    self.take(1);
+                    return Ok(Event::Byte(0x1b));
+                }
+                _ => {
+                    if let Some(event) = self.next(Some(Duration::ZERO))? {
+                        return Ok(event);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether more input is already waiting, read or not, so drawing can wait until it's handled
+    pub fn pending(&mut self) -> bool {
+        !self.replay.is_empty()
+            || !self.buffer.is_empty()
+            || self.source.ready(Some(Duration::ZERO)).unwrap_or(false)
+    }
+
+    /// Whether Escape is waiting to be read, without waiting for it. Anything else waiting is
+    /// dropped, this is for long running work that nothing else should be typed into.
+    pub fn escape_pressed(&mut self) -> bool {
+        // A failed read leaves nothing new in the buffer, and Escape can't have been pressed
+        let _ = self.fill(Some(Duration::ZERO));
+        let pressed = self.buffer.first() == Some(&0x1b);
+        self.buffer.clear();
+        pressed
+    }
+
+    /// Waits up to `timeout` for the terminal's answer to a query, until `complete` says all of it
+    /// came. Keys typed in the meantime are taken with it.
+    pub fn reply(
+        &mut self,
+        timeout: Duration,
+        complete: impl Fn(&[u8]) -> bool,
+    ) -> Option<Vec<u8>> {
+        let deadline = Instant::now() + timeout;
+        while !complete(&self.buffer) {
+            let left = deadline.checked_duration_since(Instant::now())?;
+            self.fill(Some(left)).ok()?;
+        }
+        Some(std::mem::take(&mut self.buffer))
+    }
+
+    /// Starts keeping every event taken from here on, until `stop_recording`
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Macro::new());
+    }
+
+    /// The events taken since `start_recording`, without the last one: the key that stopped it.
+    /// `None` when nothing was being recorded.
+    pub fn stop_recording(&mut self) -> Option<Macro> {
+        let mut recorded = self.recording.take()?;
+        recorded.pop();
+        Some(recorded)
+    }
+
+    pub fn recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Has the events of `keys` taken next, ahead of anything typed. They aren't recorded again,
+    /// the key that replayed them already was.
+    pub fn replay(&mut self, keys: &Macro) {
+        for bytes in keys.iter().rev() {
+            self.replay.push_front((bytes.clone(), true));
+        }
+    }
+
+    /// Holds the last event taken back in `held`, as if it hadn't been taken: a macro being
+    /// recorded doesn't have it until `put_back` has it taken again
+    pub fn hold(&mut self, held: &mut Held) {
+        if let (false, Some(recording)) = (self.replayed, &mut self.recording) {
+            recording.pop();
+        }
+        held.0.push((self.last.clone(), self.replayed));
+    }
+
+    /// Has the events `held` back taken next, ahead of anything else, the way they were taken
+    /// the first time
+    pub fn put_back(&mut self, held: Held) {
+        for event in held.0.into_iter().rev() {
+            self.replay.push_front(event);
+        }
+    }
+
+    /// Whether the last event taken came from a macro being replayed
+    pub fn replaying(&self) -> bool {
+        self.replayed
+    }
+
+    /// The bytes the last event was sent as
+    pub fn last_bytes(&self) -> &[u8] {
+        &self.last
+    }
+
+    /// Drops the event of `len` bytes from the start of the buffer, keeping it when recording
+    fn take(&mut self, len: usize) {
+        self.last = self.buffer.drain(..len).collect();
+        if let Some(recording) = &mut self.recording {
+            recording.push(self.last.clone());
+        }
+    }
+
+    /// Reads whatever is waiting once something is, false when nothing came within `timeout`
+    fn fill(&mut self, timeout: Option<Duration>) -> io::Result<bool> {
+        if !self.source.ready(timeout)? {
+            return Ok(false);
+        }
+
+        let start = self.buffer.len();
+        self.buffer.resize(start + READ_SIZE, 0);
+        let read = self.source.read(&mut self.buffer[start..]);
+        self.buffer.truncate(start + *read.as_ref().unwrap_or(&0));
+        // A signal landing mid-read, like coming back from a suspend, is just no input yet
+        if matches!(&read, Err(err) if err.kind() == io::ErrorKind::Interrupted) {
+            return Ok(false);
+        }
+        // Ready with nothing to read is the end of the input, the terminal went away
+        if read? == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        Ok(true)
+    }
+}
+
+fn decode(bytes: &[u8]) -> Decoded {
+    let Some(&first) = bytes.first() else {
+        return Decoded::Empty;
+    };
+    if first == 0x1b {
+        return decode_escape(bytes);
+    }
+    if first.is_ascii() {
+        return Decoded::Event(Event::Byte(first), 1);
+    }
+
+    let len = match first {
+        0xc2..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf4 => 4,
+        // Not the start of a UTF-8 character, it's passed on as it came
+        _ => return Decoded::Event(Event::Byte(first), 1),
+    };
+    if bytes.len() < len {
+        return Decoded::Incomplete(ESCAPE_WAIT);
+    }
+    match std::str::from_utf8(&bytes[..len])
+        .ok()
+        .and_then(|c| c.chars().next())
+    {
+        Some(c) => Decoded::Event(Event::Char(c), len),
+        None => Decoded::Event(Event::Byte(first), 1),
+    }
+}
+
+/// Decodes a buffer starting with Escape
+fn decode_escape(bytes: &[u8]) -> Decoded {
+    let escape = |seq, len| Decoded::Event(Event::Escape(seq), len);
+    match bytes.get(1) {
+        None => Decoded::Incomplete(ESCAPE_WAIT),
+        // Escape pressed twice, the first one was on its own
+        Some(0x1b) => escape(EscapeSequence::Bare, 1),
+        Some(b'[') => decode_csi(bytes),
+        // SS3, what the arrows, Home and End send while the terminal is in application cursor mode
+        Some(b'O') => match bytes.get(2) {
+            None => Decoded::Incomplete(ESCAPE_WAIT),
+            Some(b'P') => escape(EscapeSequence::Help, 3),
+            Some(b'R') => escape(EscapeSequence::FindNext, 3),
+            Some(key) => escape(
+                cursor_key(*key).map_or(EscapeSequence::Unknown, EscapeSequence::Navigation),
+                3,
+            ),
+        },
+        Some(b'x') => escape(EscapeSequence::RunCommand, 2),
+        Some(b'c') => escape(EscapeSequence::CycleSearchCase, 2),
+        // Terminals that send Alt as a leading Escape have Alt-Left and Alt-Right send these
+        Some(b'b') => escape(EscapeSequence::Navigation(NavigationKey::WordLeft), 2),
+        Some(b'f') => escape(EscapeSequence::Navigation(NavigationKey::WordRight), 2),
+        Some(_) => escape(EscapeSequence::Unknown, 2),
+    }
+}
+
+/// Decodes a control sequence, `ESC [` then parameter bytes and a final byte in `@` to `~`
+fn decode_csi(bytes: &[u8]) -> Decoded {
+    let Some(end) = bytes
+        .iter()
+        .skip(2)
+        .position(|b| !(0x20..=0x3f).contains(b))
+        .map(|idx| idx + 2)
+    else {
+        if bytes.len() > MAX_SEQ_LEN {
+            return Decoded::Event(Event::Escape(EscapeSequence::Unknown), bytes.len());
+        }
+        return Decoded::Incomplete(ESCAPE_WAIT);
+    };
+
+    let len = end + 1;
+    if !(0x40..=0x7e).contains(&bytes[end]) {
+        // Broken off by something that can't be in a sequence, which is left to be read on its own
+        return Decoded::Event(Event::Escape(EscapeSequence::Unknown), end);
+    }
+    if &bytes[..len] == PASTE_START {
+        return decode_paste(bytes);
+    }
+
+    let params = &bytes[2..end];
+    let seq = match (params, bytes[end]) {
+        ([b'<', report @ ..], b'M' | b'm') => mouse_event(report, bytes[end] == b'M'),
+        ([], b'I') => Some(EscapeSequence::Focus(true)),
+        ([], b'O') => Some(EscapeSequence::Focus(false)),
+        ([], b'Z') => Some(EscapeSequence::BackTab),
+        ([], key) => cursor_key(key).map(EscapeSequence::Navigation),
+        // Which of these Home and End send depends on the terminal, and on tmux's settings
+        (b"1" | b"7", b'~') => Some(EscapeSequence::Navigation(NavigationKey::Home)),
+        (b"4" | b"8", b'~') => Some(EscapeSequence::Navigation(NavigationKey::End)),
+        (b"2", b'~') => Some(EscapeSequence::Insert),
+        (b"3", b'~') => Some(EscapeSequence::Delete),
+        (b"5", b'~') => Some(EscapeSequence::Navigation(NavigationKey::PageUp)),
+        (b"6", b'~') => Some(EscapeSequence::Navigation(NavigationKey::PageDown)),
+        (b"13", b'~') => Some(EscapeSequence::FindNext),
+        (b"13;2", b'~') => Some(EscapeSequence::FindPrevious),
+        ([b'1', b';', modifier @ ..], key) => modified_key(modifier, key),
+        _ => None,
+    };
+
+    Decoded::Event(Event::Escape(seq.unwrap_or(EscapeSequence::Unknown)), len)
+}
+
+/// A bracketed paste, from `ESC [ 200 ~` to the `ESC [ 201 ~` that ends it
+fn decode_paste(bytes: &[u8]) -> Decoded {
+    let content = &bytes[PASTE_START.len()..];
+    match content
+        .windows(PASTE_END.len())
+        .position(|w| w == PASTE_END)
+    {
+        Some(end) => Decoded::Event(
+            Event::Escape(EscapeSequence::Paste(content[..end].to_vec())),
+            PASTE_START.len() + end + PASTE_END.len(),
+        ),
+        None => Decoded::Incomplete(PASTE_WAIT),
+    }
+}
+
+/// What's buffered when the rest of it never came: Escape on its own, a paste the terminal
+/// stopped sending, which gets what was read of it pasted, or a broken sequence, which is dropped
+fn flush(bytes: &[u8]) -> (Event, usize) {
+    let event = match bytes {
+        [0x1b] => Event::Escape(EscapeSequence::Bare),
+        _ if bytes.starts_with(PASTE_START) => {
+            Event::Escape(EscapeSequence::Paste(bytes[PASTE_START.len()..].to_vec()))
+        }
+        [0x1b, ..] => Event::Escape(EscapeSequence::Unknown),
+        // Only the start of a UTF-8 character, it's passed on as it came
+        _ => return (Event::Byte(bytes[0]), 1),
+    };
+    (event, bytes.len())
+}
+
+/// The arrows, Home and End, by the final byte they're sent with after `ESC [` or `ESC O`
+fn cursor_key(key: u8) -> Option<NavigationKey> {
+    match key {
+        b'A' => Some(NavigationKey::Up),
+        b'B' => Some(NavigationKey::Down),
+        b'C' => Some(NavigationKey::Right),
+        b'D' => Some(NavigationKey::Left),
+        b'H' => Some(NavigationKey::Home),
+        b'F' => Some(NavigationKey::End),
+        _ => None,
+    }
+}
+
+/// A key sent with modifiers, `ESC [ 1 ; modifier key`. Ctrl (5) and Alt (3) make Left and Right
+/// move by words, Ctrl makes Up and Down move by paragraphs and Alt makes them move the current
+/// row, Shift (2) turns F3 around, other modifiers are ignored.
+fn modified_key(modifier: &[u8], key: u8) -> Option<EscapeSequence> {
+    let alt = modifier == b"3";
+    let word = alt || modifier == b"5";
+    let movement = match key {
+        b'R' if modifier == b"2" => return Some(EscapeSequence::FindPrevious),
+        b'A' if alt => return Some(EscapeSequence::MoveLineUp),
+        b'B' if alt => return Some(EscapeSequence::MoveLineDown),
+        b'A' if word => NavigationKey::ParagraphUp,
+        b'B' if word => NavigationKey::ParagraphDown,
+        b'A' => NavigationKey::Up,
+        b'B' => NavigationKey::Down,
+        b'C' if word => NavigationKey::WordRight,
+        b'D' if word => NavigationKey::WordLeft,
+        b'C' => NavigationKey::Right,
+        b'D' => NavigationKey::Left,
+        b'H' => NavigationKey::Home,
+        b'F' => NavigationKey::End,
+        _ => return None,
+    };
+
+    Some(EscapeSequence::Navigation(movement))
+}
+
+/// An SGR mouse report, `button ; x ; y` from `ESC [ < button ; x ; y` followed by `M` (press)
+/// or `m` (release)
+fn mouse_event(report: &[u8], pressed: bool) -> Option<EscapeSequence> {
+    let fields: Vec<usize> = std::str::from_utf8(report)
+        .ok()?
+        .split(';')
+        .filter_map(|field| field.parse().ok())
+        .collect();
+
+    let event = match fields[..] {
+        [0, x, y] if pressed => MouseEvent::Click {
+            x: x.saturating_sub(1),
+            y: y.saturating_sub(1),
+        },
+        [64, _, _] => MouseEvent::WheelUp,
+        [65, _, _] => MouseEvent::WheelDown,
+        _ => return None,
+    };
+    Some(EscapeSequence::Mouse(event))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Input arriving in separate reads, the way a slow terminal sends it. Once they've all been
+    /// read nothing more comes, but the input doesn't end.
+    struct Reads(VecDeque<Vec<u8>>);
+
+    impl Source for Reads {
+        fn ready(&mut self, _: Option<Duration>) -> io::Result<bool> {
+            Ok(!self.0.is_empty())
+        }
+
+        fn read(&mut self, buff: &mut [u8]) -> io::Result<usize> {
+            let read = self.0.pop_front().unwrap_or_default();
+            buff[..read.len()].copy_from_slice(&read);
+            Ok(read.len())
+        }
+    }
+
+    fn input(reads: &[&[u8]]) -> Input {
+        Input::new(Box::new(Reads(
+            reads.iter().map(|read| read.to_vec()).collect(),
+        )))
+    }
+
+    /// Every event the reads decode to, described
+    fn events(reads: &[&[u8]]) -> Vec<String> {
+        let mut input = input(reads);
+        let mut events = Vec::new();
+        while let Some(event) = input.next(Some(Duration::ZERO)).unwrap() {
+            events.push(describe(&event));
+        }
+        events
+    }
+
+    fn describe(event: &Event) -> String {
+        match event {
+            Event::Byte(byte) => format!("byte {byte:#04x}"),
+            Event::Char(c) => format!("char {c}"),
+            Event::Escape(seq) => String::from(match seq {
+                EscapeSequence::Navigation(NavigationKey::Up) => "up",
+                EscapeSequence::Navigation(NavigationKey::Left) => "left",
+                EscapeSequence::Navigation(NavigationKey::Home) => "home",
+                EscapeSequence::Navigation(NavigationKey::End) => "end",
+                EscapeSequence::Navigation(NavigationKey::PageDown) => "pagedown",
+                EscapeSequence::Navigation(NavigationKey::WordRight) => "word right",
+                EscapeSequence::Navigation(NavigationKey::ParagraphDown) => "paragraph down",
+                EscapeSequence::Navigation(_) => "other navigation",
+                EscapeSequence::Mouse(MouseEvent::Click { x: 2, y: 4 }) => "click 2,4",
+                EscapeSequence::Mouse(_) => "other mouse",
+                EscapeSequence::MoveLineUp => "move line up",
+                EscapeSequence::Paste(text) if text == b"a\x1bb" => "paste",
+                EscapeSequence::Paste(_) => "other paste",
+                EscapeSequence::Delete => "delete",
+                EscapeSequence::FindPrevious => "find previous",
+                EscapeSequence::RunCommand => "run command",
+                EscapeSequence::Bare => "escape",
+                EscapeSequence::Focus(true) => "focus",
+                EscapeSequence::Focus(false) => "blur",
+                EscapeSequence::Unknown => "unknown",
+                _ => "other",
+            }),
+        }
+    }
+
+    #[test]
+    fn whole_events_from_one_read() {
+        let read: &[u8] = b"a\x13\xc3\xa9\xe6\xbc\xa2\x1b[A\x1bOD\x1b[1~\x1b[8~\x1b[6~\x1b[3~\
+            \x1b[1;5C\x1b[1;5B\x1b[1;3A\x1b[13;2~\x1b[<0;3;5M\x1b[I\x1b[O\x1bx\x1b[99X\x1b[200~a\x1bb\x1b[201~";
+        assert_eq!(
+            events(&[read]),
+            [
+                "byte 0x61",
+                "byte 0x13",
+                "char é",
+                "char 漢",
+                "up",
+                "left",
+                "home",
+                "end",
+                "pagedown",
+                "delete",
+                "word right",
+                "paragraph down",
+                "move line up",
+                "find previous",
+                "click 2,4",
+                "focus",
+                "blur",
+                "run command",
+                "unknown",
+                "paste",
+            ]
+        );
+    }
+
+    #[test]
+    fn home_end_and_delete_whole_and_byte_by_byte() {
+        let keys: [(&[u8], &str); 9] = [
+            (b"\x1b[1~", "home"),
+            (b"\x1b[7~", "home"),
+            (b"\x1b[H", "home"),
+            (b"\x1bOH", "home"),
+            (b"\x1b[4~", "end"),
+            (b"\x1b[8~", "end"),
+            (b"\x1b[F", "end"),
+            (b"\x1bOF", "end"),
+            (b"\x1b[3~", "delete"),
+        ];
+        for (key, expected) in keys {
+            assert_eq!(events(&[key]), [expected], "{key:?} in one read");
+            let bytes: Vec<&[u8]> = key.chunks(1).collect();
+            assert_eq!(events(&bytes), [expected], "{key:?} a byte at a time");
+        }
+
+        let burst = keys.map(|(key, _)| key).concat();
+        assert_eq!(events(&[&burst]), keys.map(|(_, expected)| expected));
+    }
+
+    #[test]
+    fn events_split_between_reads() {
+        assert_eq!(events(&[b"\x1b", b"[", b"A"]), ["up"]);
+        assert_eq!(events(&[b"\xe6", b"\xbc\xa2x"]), ["char 漢", "byte 0x78"]);
+        assert_eq!(events(&[b"\x1b[200~a", b"\x1bb\x1b[2", b"01~"]), ["paste"]);
+    }
+
+    #[test]
+    fn what_never_finishes() {
+        // Escape with nothing after it is Escape pressed on its own
+        assert_eq!(events(&[b"\x1b"]), ["escape"]);
+        // Escape pressed twice
+        assert_eq!(events(&[b"\x1b\x1b"]), ["escape", "escape"]);
+        // A sequence broken off is dropped whole, what broke it off is read on its own
+        assert_eq!(events(&[b"\x1b[12\x01"]), ["unknown", "byte 0x01"]);
+        // Half a UTF-8 character is passed on as it came
+        assert_eq!(events(&[b"\xe6\xbc"]), ["byte 0xe6", "byte 0xbc"]);
+        // A paste that never ended is pasted as far as it got
+        assert_eq!(events(&[b"\x1b[200~a\x1bb"]), ["paste"]);
+    }
+
+    #[test]
+    fn a_recorded_macro_replays_ahead_of_typing() {
+        let mut input = input(&[b"ab\x1b[A", b"!", b"cd"]);
+        input.start_recording();
+        for _ in 0..4 {
+            input.next(Some(Duration::ZERO)).unwrap();
+        }
+        // The last key is the one that stopped the recording, it isn't part of the macro
+        let recorded = input.stop_recording().unwrap();
+        assert_eq!(recorded, [b"a".to_vec(), b"b".to_vec(), b"\x1b[A".to_vec()]);
+        assert!(!input.recording());
+
+        input.replay(&recorded);
+        let mut replayed = Vec::new();
+        while let Some(event) = input.next(Some(Duration::ZERO)).unwrap() {
+            replayed.push((describe(&event), input.replaying()));
+        }
+        let replayed: Vec<_> = replayed
+            .iter()
+            .map(|(event, replaying)| (event.as_str(), *replaying))
+            .collect();
+        assert_eq!(
+            replayed,
+            [
+                ("byte 0x61", true),
+                ("byte 0x62", true),
+                ("up", true),
+                ("byte 0x63", false),
+                ("byte 0x64", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn held_events_are_taken_again_as_they_came() {
+        let mut input = input(&[b"ab"]);
+        input.start_recording();
+        input.replay(&vec![b"\x1b[A".to_vec()]);
+        let mut held = Held::default();
+        while input.next(Some(Duration::ZERO)).unwrap().is_some() {
+            input.hold(&mut held);
+        }
+        assert_eq!(held.len(), 3);
+        // Nothing held is part of the macro until it's taken again
+        assert!(input.stop_recording().unwrap().is_empty());
+
+        input.start_recording();
+        input.put_back(held);
+        let mut again = Vec::new();
+        while let Some(event) = input.next(Some(Duration::ZERO)).unwrap() {
+            again.push((describe(&event), input.replaying()));
+        }
+        let again: Vec<_> = again
+            .iter()
+            .map(|(event, replaying)| (event.as_str(), *replaying))
+            .collect();
+        assert_eq!(
+            again,
+            [("up", true), ("byte 0x61", false), ("byte 0x62", false)]
+        );
+        // The typed keys are recorded again and the one from the macro isn't, less the last key
+        // taken, which counts as the one that stopped the recording
+        assert_eq!(input.stop_recording().unwrap(), [b"a".to_vec()]);
+    }
+
+    #[test]
+    fn escape_pressed_drops_the_rest() {
+        let mut waiting = input(&[b"\x1bxyz"]);
+        assert!(waiting.escape_pressed());
+        assert!(!waiting.pending());
+
+        let mut typing = input(&[b"xyz\x1b"]);
+        assert!(!typing.escape_pressed());
+        assert!(!typing.pending());
+    }
+
+    #[test]
+    fn the_end_of_keys_is_the_end_of_input() {
+        let mut input = Input::new(Box::new(Keys::new(b"a")));
+        assert!(matches!(
+            input.next(Some(Duration::ZERO)),
+            Ok(Some(Event::Byte(b'a')))
+        ));
+        let ended = input.next(Some(Duration::ZERO)).err().map(|err| err.kind());
+        assert_eq!(ended, Some(io::ErrorKind::UnexpectedEof));
+    }
+}
@@ -0,0 +1,436 @@
+//! Key names as they're written in the config file, like `ctrl-s`, `alt-shift-left` or `f5`.
+//!
+//! A name is any number of `ctrl-`, `alt-` and `shift-` modifiers followed by a key: a single
+//! character, `up`, `down`, `left`, `right`, `home`, `end`, `pageup`, `pagedown`, `insert`,
+//! `delete`, `backspace`, `tab`, `enter`, `escape`, `space` or `f1` to `f12`. Keys a terminal sends
+//! unusually can be written raw instead, as `byte:0x1f` or `seq:"\x1b[15~"`, without modifiers.
+//! Names are read case-insensitively (except for the character keys) and written back in the
+//! canonical form: lowercase, modifiers in the order ctrl, alt, shift.
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyCode {
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    Backspace,
+    Tab,
+    Enter,
+    Escape,
+    Space,
+    F(u8),
+    /// A single raw byte
+    Byte(u8),
+    /// A raw sequence of bytes
+    Seq(Vec<u8>),
+}
+
+/// The names of every key that has one, as they're parsed and formatted
+const NAMES: &[(&str, KeyCode)] = &[
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("insert", KeyCode::Insert),
+    ("delete", KeyCode::Delete),
+    ("backspace", KeyCode::Backspace),
+    ("tab", KeyCode::Tab),
+    ("enter", KeyCode::Enter),
+    ("escape", KeyCode::Escape),
+    ("space", KeyCode::Space),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Key {
+    pub code: KeyCode,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+}
+
+impl Key {
+    pub const fn ctrl(c: char) -> Self {
+        Key {
+            code: KeyCode::Char(c),
+            ctrl: true,
+            alt: false,
+            shift: false,
+        }
+    }
+
+    pub const fn alt(c: char) -> Self {
+        Key {
+            code: KeyCode::Char(c),
+            ctrl: false,
+            alt: true,
+            shift: false,
+        }
+    }
+
+    /// The bytes a terminal sends for the key, in xterm's encoding
+    pub fn encode(&self) -> Vec<u8> {
+        // xterm's modifier parameter, 1 means none
+        let modifier = 1 + u8::from(self.shift) + 2 * u8::from(self.alt) + 4 * u8::from(self.ctrl);
+        let csi = |code: &str, last: char| {
+            if modifier == 1 {
+                format!("\x1b[{code}{last}").into_bytes()
+            } else {
+                let code = if code.is_empty() { "1" } else { code };
+                format!("\x1b[{code};{modifier}{last}").into_bytes()
+            }
+        };
+        let alt = |bytes: Vec<u8>| {
+            if self.alt {
+                [vec![0x1b], bytes].concat()
+            } else {
+                bytes
+            }
+        };
+
+        match &self.code {
+            KeyCode::Char(c) if self.ctrl => alt(vec![*c as u8 & 0x1f]),
+            KeyCode::Char(c) => alt(c.to_string().into_bytes()),
+            KeyCode::Space if self.ctrl => alt(vec![0]),
+            KeyCode::Space => alt(vec![b' ']),
+            KeyCode::Tab if self.shift => b"\x1b[Z".to_vec(),
+            KeyCode::Tab => alt(vec![b'\t']),
+            KeyCode::Enter => alt(vec![b'\r']),
+            KeyCode::Escape => alt(vec![0x1b]),
+            KeyCode::Backspace => alt(vec![0x7f]),
+            KeyCode::Up => csi("", 'A'),
+            KeyCode::Down => csi("", 'B'),
+            KeyCode::Right => csi("", 'C'),
+            KeyCode::Left => csi("", 'D'),
+            KeyCode::Home => csi("", 'H'),
+            KeyCode::End => csi("", 'F'),
+            KeyCode::Insert => csi("2", '~'),
+            KeyCode::Delete => csi("3", '~'),
+            KeyCode::PageUp => csi("5", '~'),
+            KeyCode::PageDown => csi("6", '~'),
+            KeyCode::F(n @ 1..=4) if modifier == 1 => vec![0x1b, b'O', b'P' + n - 1],
+            KeyCode::F(n @ 1..=4) => csi("", char::from(b'P' + n - 1)),
+            KeyCode::F(n) => {
+                let code = match n {
+                    5 => "15",
+                    6 => "17",
+                    7 => "18",
+                    8 => "19",
+                    9 => "20",
+                    10 => "21",
+                    11 => "23",
+                    _ => "24",
+                };
+                csi(code, '~')
+            }
+            KeyCode::Byte(byte) => vec![*byte],
+            KeyCode::Seq(seq) => seq.clone(),
+        }
+    }
+}
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        if let Some(value) = strip_prefix_ignore_case(name, "byte:") {
+            let byte = match strip_prefix_ignore_case(value, "0x") {
+                Some(hex) => u8::from_str_radix(hex, 16),
+                None => value.parse(),
+            };
+            return byte
+                .map(|byte| raw(KeyCode::Byte(byte)))
+                .map_err(|_| format!("'{value}' isn't a byte, write it like 0x1f"));
+        }
+
+        if let Some(value) = strip_prefix_ignore_case(name, "seq:") {
+            let seq = parse_seq(value)?;
+            return Ok(raw(KeyCode::Seq(seq)));
+        }
+
+        let mut key = raw(KeyCode::Space);
+        let mut rest = name;
+        loop {
+            let modifier = if let Some(after) = strip_prefix_ignore_case(rest, "ctrl-") {
+                rest = after;
+                &mut key.ctrl
+            } else if let Some(after) = strip_prefix_ignore_case(rest, "alt-") {
+                rest = after;
+                &mut key.alt
+            } else if let Some(after) = strip_prefix_ignore_case(rest, "shift-") {
+                rest = after;
+                &mut key.shift
+            } else {
+                break;
+            };
+
+            if *modifier {
+                return Err(format!("'{name}' repeats a modifier"));
+            }
+            *modifier = true;
+        }
+
+        key.code = parse_code(rest).ok_or_else(|| format!("unknown key name '{name}'"))?;
+        check_combination(&key).map_err(|reason| format!("'{name}' {reason}"))?;
+        if let KeyCode::Char(c) = &mut key.code {
+            if key.ctrl {
+                *c = c.to_ascii_lowercase();
+            }
+        }
+
+        Ok(key)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.code {
+            KeyCode::Byte(byte) => return write!(f, "byte:0x{byte:02x}"),
+            KeyCode::Seq(seq) => {
+                write!(f, "seq:\"")?;
+                for byte in seq {
+                    match byte {
+                        b'"' | b'\\' => write!(f, "\\{}", char::from(*byte))?,
+                        0x20..=0x7e => write!(f, "{}", char::from(*byte))?,
+                        _ => write!(f, "\\x{byte:02x}")?,
+                    }
+                }
+                return write!(f, "\"");
+            }
+            _ => {}
+        }
+
+        if self.ctrl {
+            write!(f, "ctrl-")?;
+        }
+        if self.alt {
+            write!(f, "alt-")?;
+        }
+        if self.shift {
+            write!(f, "shift-")?;
+        }
+
+        match &self.code {
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::F(n) => write!(f, "f{n}"),
+            code => {
+                let (name, _) = NAMES.iter().find(|(_, named)| named == code).unwrap();
+                write!(f, "{name}")
+            }
+        }
+    }
+}
+
+const fn raw(code: KeyCode) -> Key {
+    Key {
+        code,
+        ctrl: false,
+        alt: false,
+        shift: false,
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(name: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = name.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &name[prefix.len()..])
+}
+
+fn parse_code(name: &str) -> Option<KeyCode> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return (!c.is_control() && c != ' ').then_some(KeyCode::Char(c));
+    }
+
+    let lower = name.to_ascii_lowercase();
+    if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+        return (1..=12).contains(&n).then_some(KeyCode::F(n));
+    }
+
+    NAMES
+        .iter()
+        .find(|(named, _)| *named == lower)
+        .map(|(_, code)| code.clone())
+}
+
+/// Ctrl combinations that send the same byte as a named key. A terminal can't tell them apart, so
+/// the byte is always taken as the named key and the ctrl name can't be bound.
+///
+/// Of the 26 letters only these are reserved: ctrl-h (0x08, what some terminals send for
+/// Backspace), ctrl-i (0x09, Tab) and ctrl-m (0x0d, Enter). Every other letter is bindable,
+/// ctrl-j (0x0a) included since Enter sends 0x0d once the terminal is in raw mode.
+pub const CTRL_ALIASES: &[(char, &str)] = &[
+    ('h', "backspace"),
+    ('i', "tab"),
+    ('m', "enter"),
+    ('[', "escape"),
+    ('?', "backspace"),
+    ('@', "ctrl-space"),
+];
+
+/// Characters besides letters that have a ctrl combination of their own
+const CTRL_PUNCTUATION: &[char] = &['\\', ']', '^', '_'];
+
+/// Rejects modifiers a terminal can't send for a key, or that make it the same as another key
+fn check_combination(key: &Key) -> Result<(), String> {
+    match &key.code {
+        KeyCode::Char(_) if key.shift => Err(String::from(
+            "can't use shift, write the shifted character itself",
+        )),
+        KeyCode::Char(c) if key.ctrl => {
+            if let Some((_, named)) = CTRL_ALIASES
+                .iter()
+                .find(|(alias, _)| alias.eq_ignore_ascii_case(c))
+            {
+                Err(format!(
+                    "is the same key as {named}, terminals send the same byte for both"
+                ))
+            } else if c.is_ascii_alphabetic() || CTRL_PUNCTUATION.contains(c) {
+                Ok(())
+            } else {
+                Err(String::from("can't use ctrl, terminals have no such key"))
+            }
+        }
+        KeyCode::Tab if key.ctrl => Err(String::from("can't be told apart from tab")),
+        KeyCode::Tab if key.shift && key.alt => {
+            Err(String::from("can't be told apart from shift-tab"))
+        }
+        KeyCode::Enter | KeyCode::Escape | KeyCode::Backspace if key.ctrl || key.shift => {
+            Err(String::from("can't be told apart without the modifiers"))
+        }
+        KeyCode::Space if key.shift => Err(String::from("can't be told apart from space")),
+        _ => Ok(()),
+    }
+}
+
+/// Reads the quoted bytes of a `seq:` key, `\xHH`, `\e`, `\t`, `\r`, `\n`, `\\` and `\"` are escapes
+fn parse_seq(value: &str) -> Result<Vec<u8>, String> {
+    let inner = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .filter(|inner| !inner.is_empty())
+        .ok_or_else(|| format!("{value} should be a non-empty quoted string"))?;
+
+    let mut seq = Vec::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buff = [0; 4];
+            seq.extend(c.encode_utf8(&mut buff).as_bytes());
+            continue;
+        }
+
+        let byte = match chars.next() {
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                u8::from_str_radix(&hex, 16).map_err(|_| format!("bad escape '\\x{hex}'"))?
+            }
+            Some('e') => 0x1b,
+            Some('t') => b'\t',
+            Some('r') => b'\r',
+            Some('n') => b'\n',
+            Some('\\') => b'\\',
+            Some('"') => b'"',
+            other => return Err(format!("bad escape '\\{}'", other.unwrap_or(' '))),
+        };
+        seq.push(byte);
+    }
+
+    Ok(seq)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every name, with every combination of modifiers it can take
+    fn every_name() -> Vec<String> {
+        let mut keys: Vec<String> = NAMES.iter().map(|(name, _)| String::from(*name)).collect();
+        keys.extend((1..=12).map(|n| format!("f{n}")));
+        keys.extend(('a'..='z').map(String::from));
+        keys.extend(['1', '/', '[', '\\', ']', '^', '_', 'é'].map(String::from));
+
+        let mut names = Vec::new();
+        for key in keys {
+            for ctrl in ["", "ctrl-"] {
+                for alt in ["", "alt-"] {
+                    for shift in ["", "shift-"] {
+                        names.push(format!("{ctrl}{alt}{shift}{key}"));
+                    }
+                }
+            }
+        }
+        names
+    }
+
+    #[test]
+    fn every_name_round_trips() {
+        let mut parsed = 0;
+        for name in every_name() {
+            // Combinations a terminal can't send are refused, the rest come back as written
+            let Ok(key) = name.parse::<Key>() else {
+                continue;
+            };
+            assert_eq!(key.to_string(), name);
+            assert_eq!(key.to_string().parse::<Key>(), Ok(key));
+            parsed += 1;
+        }
+        assert!(parsed > 300, "only {} names parsed", parsed);
+    }
+
+    #[test]
+    fn names_are_canonical_once_written() {
+        let cases = [
+            ("CTRL-S", "ctrl-s"),
+            ("Shift-Alt-Left", "alt-shift-left"),
+            ("ctrl-X", "ctrl-x"),
+            ("PageUp", "pageup"),
+            ("F12", "f12"),
+            ("byte:31", "byte:0x1f"),
+            ("BYTE:0x7F", "byte:0x7f"),
+            (r#"seq:"\e[15~""#, r#"seq:"\x1b[15~""#),
+            (r#"seq:"a\"b\\c\t""#, r#"seq:"a\"b\\c\x09""#),
+        ];
+        for (name, canonical) in cases {
+            let key: Key = name.parse().unwrap();
+            assert_eq!(key.to_string(), canonical, "{name}");
+            assert_eq!(canonical.parse::<Key>(), Ok(key), "{name}");
+        }
+    }
+
+    #[test]
+    fn bad_names_are_refused() {
+        for name in [
+            "",
+            "ctrl-",
+            "ctrl-ctrl-s",
+            "hyper-s",
+            "f0",
+            "f13",
+            "shift-a",
+            "ctrl-i",
+            "ctrl-m",
+            "ctrl-h",
+            "byte:0x100",
+            "seq:",
+            r#"seq:"""#,
+            r#"seq:"\q""#,
+        ] {
+            assert!(name.parse::<Key>().is_err(), "{:?} parsed", name);
+        }
+    }
+}
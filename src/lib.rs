@@ -0,0 +1,6918 @@
+//! rilo, a small text editor for the terminal. The binary only calls `main`. Everything it draws
+//! goes through one sink that `set_output` can swap out, and `Editor::with_terminal` takes its keys
+//! from an `input::Source`, so the editor can also be driven without a terminal.
+
+#![warn(clippy::all)]
+#![warn(clippy::pedantic)]
+// Only the binary and headless runs use the library, it isn't published for anyone else
+#![allow(
+    clippy::must_use_candidate,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc
+)]
+
+mod annotations;
+mod brackets;
+mod browser;
+mod changes;
+pub mod cli;
+mod clipboard;
+mod columns;
+pub mod config;
+mod confirm;
+mod diff;
+mod error;
+mod ex;
+mod filetype;
+mod filter;
+mod finder;
+mod highlight;
+pub mod input;
+mod keys;
+mod loader;
+mod lock;
+mod render;
+mod rows;
+mod search;
+mod state;
+#[cfg(test)]
+mod tests;
+mod tutor;
+mod width;
+
+use nix::libc::{ioctl, TIOCGWINSZ};
+use nix::sys::signal::{raise, Signal};
+use nix::unistd::{dup, dup2, isatty};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Error, ErrorKind, Read, Write};
+use std::os::raw::c_short;
+use std::os::unix::prelude::*;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::SystemTime;
+use std::time::{Duration, Instant};
+
+use config::{Config, ConfigError};
+use confirm::{Choice, Confirm};
+use input::{EscapeSequence, Event, Held, Input, MouseEvent, NavigationKey, Stdin};
+use keys::{Key, KeyCode};
+use render::{Color, ColorDepth, Frame, Line, Screen, Span, Style, Theme, WIDE_TAIL};
+use rows::Rows;
+use search::CaseMode;
+use state::FileState;
+use termios::{
+    Termios, BRKINT, CS8, ECHO, ICANON, ICRNL, IEXTEN, INPCK, ISIG, ISTRIP, IXON, OPOST, TCSAFLUSH,
+    VMIN, VTIME,
+};
+
+const TAB_SIZE: u8 = 4;
+
+/// The cursor's position relative to the terminal
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CursorPosition {
+    pub x: usize,
+    pub y: usize,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Action {
+    Quit,
+    ForceQuit,
+    Suspend,
+    Save,
+    Delete,
+    DeleteForward,
+    Enter,
+    Cancel,
+    Find,
+    FindNext,
+    FindPrevious,
+    GotoLine,
+    FindFile,
+    CycleSearchCase,
+    ToggleWrap,
+    ToggleReadonly,
+    ToggleOverwrite,
+    InsertVerbatim,
+    RecordMacro,
+    ReplayMacro,
+    Tab,
+    Dedent,
+    KillLine,
+    KillPreviousWord,
+    KillNextWord,
+    Yank,
+    Home,
+    DuplicateLine,
+    JoinLines,
+    Recenter,
+    Redraw,
+    ToggleTrim,
+    ConvertLineEndings,
+    Help,
+    WordCount,
+    ShowConfigErrors,
+    MessageHistory,
+    ReloadConfig,
+    WriteTo,
+    SaveAs,
+    ListAnnotations,
+    RecentFiles,
+    NextChange,
+    PreviousChange,
+    Revert,
+    NextAnnotation,
+    PreviousAnnotation,
+    OtherPane,
+    ClosePane,
+    CompareFile,
+    MatchingBracket,
+    ScrollDown,
+    ScrollUp,
+    ToggleInvisibles,
+    ForgetFileOptions,
+    SetMark,
+    Copy,
+    Cut,
+    Input(char),
+}
+
+/// An action that can be bound to a key or run by name with Alt-X
+struct Binding {
+    /// The key it's bound to by default, if any
+    key: Option<Key>,
+    action: Action,
+    /// What the action is called in `bind` lines of the config file
+    name: &'static str,
+    description: &'static str,
+}
+
+/// Every action with a name, the keymap starts from their default keys and the config file can add
+/// to them
+const BINDINGS: &[Binding] = &[
+    Binding {
+        key: Some(Key::ctrl('q')),
+        action: Action::Quit,
+        name: "quit",
+        description: "Quit",
+    },
+    Binding {
+        key: None,
+        action: Action::Revert,
+        name: "revert",
+        description: "Drop the changes and read the file from disk again",
+    },
+    Binding {
+        key: None,
+        action: Action::ForceQuit,
+        name: "force-quit",
+        description: "Quit without saving, unsaved changes are lost",
+    },
+    Binding {
+        key: Some(Key::ctrl('z')),
+        action: Action::Suspend,
+        name: "suspend",
+        description: "Stop rilo and go back to the shell, fg brings it back",
+    },
+    Binding {
+        key: Some(Key::ctrl('s')),
+        action: Action::Save,
+        name: "save",
+        description: "Save the file",
+    },
+    Binding {
+        key: Some(Key::ctrl('c')),
+        action: Action::Cancel,
+        name: "cancel",
+        description: "Cancel a prompt, or copy the selection",
+    },
+    Binding {
+        key: Some(Key::ctrl('f')),
+        action: Action::Find,
+        name: "find",
+        description: "Find text",
+    },
+    Binding {
+        key: None,
+        action: Action::FindNext,
+        name: "find-next",
+        description: "Go to the next match of the last search, F3 does too",
+    },
+    Binding {
+        key: None,
+        action: Action::FindPrevious,
+        name: "find-previous",
+        description: "Go to the previous match of the last search, Shift-F3 does too",
+    },
+    Binding {
+        key: Some(Key::alt('g')),
+        action: Action::GotoLine,
+        name: "goto-line",
+        description: "Go to a line, or LINE:COLUMN",
+    },
+    Binding {
+        key: Some(Key::alt('p')),
+        action: Action::FindFile,
+        name: "find-file",
+        description: "Open a file under the working directory by typing some of its path",
+    },
+    Binding {
+        key: None,
+        action: Action::CycleSearchCase,
+        name: "search-case",
+        description: "Cycle search between matching case, ignoring it and smart case",
+    },
+    Binding {
+        key: Some(Key::ctrl(']')),
+        action: Action::MatchingBracket,
+        name: "matching-bracket",
+        description: "Go to the bracket matching the one at the cursor",
+    },
+    Binding {
+        key: Some(Key::ctrl('t')),
+        action: Action::ToggleWrap,
+        name: "toggle-wrap",
+        description: "Toggle soft wrap",
+    },
+    Binding {
+        key: Some(Key::ctrl('e')),
+        action: Action::ScrollDown,
+        name: "scroll-down",
+        description: "Scroll the window down a line, the cursor stays where it is",
+    },
+    Binding {
+        key: Some(Key::ctrl('y')),
+        action: Action::ScrollUp,
+        name: "scroll-up",
+        description: "Scroll the window up a line, the cursor stays where it is",
+    },
+    Binding {
+        key: Some(Key::ctrl('g')),
+        action: Action::ToggleInvisibles,
+        name: "toggle-invisibles",
+        description: "Toggle marking tabs, trailing spaces and line ends",
+    },
+    Binding {
+        key: Some(Key::ctrl('r')),
+        action: Action::ToggleReadonly,
+        name: "toggle-readonly",
+        description: "Toggle read-only mode",
+    },
+    Binding {
+        key: None,
+        action: Action::ToggleOverwrite,
+        name: "toggle-overwrite",
+        description: "Toggle overwrite mode, typing replaces the character under the cursor",
+    },
+    Binding {
+        key: Some(Key::ctrl('v')),
+        action: Action::InsertVerbatim,
+        name: "insert-verbatim",
+        description: "Insert the next key as it's typed, control characters and Escape included",
+    },
+    Binding {
+        key: Some(Key::alt('r')),
+        action: Action::RecordMacro,
+        name: "record-macro",
+        description: "Start recording keys as a macro, or stop",
+    },
+    Binding {
+        key: Some(Key::ctrl('\\')),
+        action: Action::ReplayMacro,
+        name: "replay-macro",
+        description: "Replay the keys recorded last, Alt-digits first say how many times",
+    },
+    Binding {
+        key: Some(Key {
+            code: KeyCode::Space,
+            ctrl: true,
+            alt: false,
+            shift: false,
+        }),
+        action: Action::SetMark,
+        name: "set-mark",
+        description: "Start selecting text at the cursor",
+    },
+    Binding {
+        key: None,
+        action: Action::DeleteForward,
+        name: "delete-forward",
+        description: "Delete the character under the cursor, or the selection",
+    },
+    Binding {
+        key: None,
+        action: Action::Copy,
+        name: "copy",
+        description: "Copy the selection",
+    },
+    Binding {
+        key: Some(Key::ctrl('x')),
+        action: Action::Cut,
+        name: "cut",
+        description: "Cut the selection",
+    },
+    Binding {
+        key: Some(Key::ctrl('k')),
+        action: Action::KillLine,
+        name: "kill-line",
+        description: "Cut the current line",
+    },
+    Binding {
+        key: Some(Key::ctrl('w')),
+        action: Action::KillPreviousWord,
+        name: "kill-previous-word",
+        description: "Cut back to the start of the word before the cursor",
+    },
+    Binding {
+        key: Some(Key::alt('d')),
+        action: Action::KillNextWord,
+        name: "kill-next-word",
+        description: "Cut on to the end of the word after the cursor",
+    },
+    Binding {
+        key: None,
+        action: Action::Dedent,
+        name: "dedent",
+        description: "Shift the selected lines, or this one, left an indent, Shift-Tab does too",
+    },
+    Binding {
+        key: Some(Key::ctrl('u')),
+        action: Action::Yank,
+        name: "yank",
+        description: "Paste cut lines",
+    },
+    Binding {
+        key: Some(Key::ctrl('a')),
+        action: Action::Home,
+        name: "home",
+        description: "Go to the start of the line",
+    },
+    Binding {
+        key: Some(Key::ctrl('d')),
+        action: Action::DuplicateLine,
+        name: "duplicate-line",
+        description: "Duplicate the current line",
+    },
+    Binding {
+        key: Some(Key::ctrl('j')),
+        action: Action::JoinLines,
+        name: "join-lines",
+        description: "Join the next line onto this one",
+    },
+    Binding {
+        key: Some(Key::ctrl('l')),
+        action: Action::Recenter,
+        name: "recenter",
+        description: "Scroll the line to the middle, top or bottom and redraw",
+    },
+    Binding {
+        key: None,
+        action: Action::Redraw,
+        name: "redraw",
+        description: "Check the terminal's size and redraw",
+    },
+    Binding {
+        key: Some(Key::ctrl('b')),
+        action: Action::ToggleTrim,
+        name: "toggle-trim",
+        description: "Toggle trimming trailing blanks on save",
+    },
+    Binding {
+        key: Some(Key::ctrl('n')),
+        action: Action::ConvertLineEndings,
+        name: "convert-line-endings",
+        description: "Switch between LF and CRLF line endings",
+    },
+    Binding {
+        // What terminals send for Ctrl-/
+        key: Some(Key::ctrl('_')),
+        action: Action::Help,
+        name: "help",
+        description: "List every key and what it does",
+    },
+    Binding {
+        key: Some(Key::ctrl('o')),
+        action: Action::WriteTo,
+        name: "write-to",
+        description: "Write a copy of the buffer to another file",
+    },
+    Binding {
+        key: None,
+        action: Action::SaveAs,
+        name: "save-as",
+        description: "Save under another name and keep editing that file",
+    },
+    Binding {
+        key: Some(Key::alt('w')),
+        action: Action::OtherPane,
+        name: "other-pane",
+        description: "Split the window, or move to the other pane",
+    },
+    Binding {
+        key: None,
+        action: Action::ClosePane,
+        name: "close-pane",
+        description: "Close the other pane",
+    },
+    Binding {
+        key: None,
+        action: Action::CompareFile,
+        name: "compare-file",
+        description: "Show how the buffer differs from another file",
+    },
+    Binding {
+        key: None,
+        action: Action::ListAnnotations,
+        name: "list-annotations",
+        description: "List the TODOs and FIXMEs in the file and jump to one",
+    },
+    Binding {
+        key: None,
+        action: Action::NextChange,
+        name: "next-change",
+        description: "Go to the next line changed since the file was saved",
+    },
+    Binding {
+        key: None,
+        action: Action::PreviousChange,
+        name: "previous-change",
+        description: "Go to the previous line changed since the file was saved",
+    },
+    Binding {
+        key: None,
+        action: Action::RecentFiles,
+        name: "recent-files",
+        description: "List the files edited lately and open one, 1 to 9 pick the first nine",
+    },
+    Binding {
+        key: None,
+        action: Action::NextAnnotation,
+        name: "next-annotation",
+        description: "Go to the next TODO or FIXME",
+    },
+    Binding {
+        key: None,
+        action: Action::PreviousAnnotation,
+        name: "previous-annotation",
+        description: "Go to the previous TODO or FIXME",
+    },
+    Binding {
+        key: None,
+        action: Action::WordCount,
+        name: "word-count",
+        description: "Count lines, words, characters and bytes, of the selection if there is one",
+    },
+    Binding {
+        key: None,
+        action: Action::ShowConfigErrors,
+        name: "show-config-errors",
+        description: "List the problems with the config file",
+    },
+    Binding {
+        key: None,
+        action: Action::MessageHistory,
+        name: "message-history",
+        description: "List the last messages shown on the status bar, newest first",
+    },
+    Binding {
+        key: None,
+        action: Action::ReloadConfig,
+        name: "reload-config",
+        description: "Read the config file again",
+    },
+    Binding {
+        key: None,
+        action: Action::ForgetFileOptions,
+        name: "forget-file-options",
+        description:
+            "Go back to the config's view options for this file and stop remembering its own",
+    },
+];
+
+/// Builds the keymap from the default bindings and the config file's `bind` lines, a configured key
+/// replaces whatever it was bound to. Returns the keymap and the problems with the config's lines.
+fn build_keymap(config: &Config) -> (Vec<(Key, Action)>, Vec<ConfigError>) {
+    let mut keymap: Vec<(Key, Action)> = BINDINGS
+        .iter()
+        .filter_map(|binding| Some((binding.key.clone()?, binding.action)))
+        .collect();
+    let mut errors = config.errors.clone();
+
+    for bind in &config.bindings {
+        let Some(binding) = BINDINGS.iter().find(|binding| binding.name == bind.action) else {
+            errors.push(ConfigError::at(
+                bind.line,
+                bind.action_column,
+                format!("unknown action '{}'", bind.action),
+            ));
+            continue;
+        };
+        // Escape starts every sequence and cancels whatever is open, it stays what it is
+        if bind.key.encode() == [0x1b] {
+            errors.push(ConfigError::at(
+                bind.line,
+                bind.key_column,
+                format!("'{}' can't be bound, it cancels", bind.key),
+            ));
+            continue;
+        }
+        // The same key bound twice in the file is most likely a mistake, the later line still wins
+        if let Some(earlier) = config
+            .bindings
+            .iter()
+            .find(|earlier| earlier.line < bind.line && earlier.key.encode() == bind.key.encode())
+        {
+            errors.push(ConfigError::at(
+                bind.line,
+                bind.key_column,
+                format!(
+                    "'{}' is already bound to {} on line {}, this line replaces it",
+                    bind.key, earlier.action, earlier.line
+                ),
+            ));
+        }
+
+        keymap.retain(|(key, _)| key.encode() != bind.key.encode());
+        keymap.push((bind.key.clone(), binding.action));
+    }
+
+    // Binding problems are found after the rest, the file is easier to fix top to bottom
+    errors.sort_by_key(|error| error.location);
+    (keymap, errors)
+}
+
+/// What the user is told an action does
+fn describe(action: Action) -> &'static str {
+    BINDINGS
+        .iter()
+        .find(|binding| binding.action == action)
+        .map_or("", |binding| binding.description)
+}
+
+/// Keys outside the keymap, which always do the same thing
+impl From<u8> for Action {
+    fn from(c: u8) -> Self {
+        // Some terminals send Ctrl-H (0x08) for Backspace, see `keys::CTRL_ALIASES`
+        if c == 0x08 || c == 127 {
+            Action::Delete
+        // Ctrl-^, for terminals that send nothing for Ctrl-Space
+        } else if c == 0x1e {
+            Action::SetMark
+        } else if c == b'\r' {
+            Action::Enter
+        } else if c == b'\t' {
+            Action::Tab
+        } else {
+            Action::Input(c as char)
+        }
+    }
+}
+
+/// Various commands we might issue to the terminal
+pub enum CtrlSeq {
+    /// Clears the entire line
+    ClearLine,
+    /// Clears the entire screen, appropriate before either drawing or quitting rilo
+    ClearScreen,
+    /// A shortcut to go the start of the terminal (0, 0)
+    GotoStart,
+    /// Hides the cursor, useful to prevent flashing
+    HideCursor,
+    /// Displays the cursor after we hide it
+    ShowCursor,
+    /// Moves the cursor to a position in the terminal
+    MoveCursor(CursorPosition),
+    InverteColor,
+    NormalColor,
+    Bold,
+    Dim,
+    Underline,
+    Foreground(Color),
+    Background(Color),
+    /// Rings the terminal's bell, or flashes it depending on how it's set up
+    Bell,
+    /// Puts base64 encoded text on the system clipboard (OSC 52)
+    SetClipboard(String),
+    /// Asks the terminal to answer with the system clipboard's content (OSC 52)
+    QueryClipboard,
+    /// Asks the terminal to report clicks and the wheel as SGR (1006) mouse sequences
+    EnableMouse,
+    DisableMouse,
+    /// Asks the terminal to wrap pasted text in `ESC [ 200 ~` and `ESC [ 201 ~`
+    EnableBracketedPaste,
+    DisableBracketedPaste,
+    /// Asks the terminal to send `ESC [ I` and `ESC [ O` when its window gains and loses focus
+    EnableFocusEvents,
+    DisableFocusEvents,
+    /// Switches to the alternate screen (smcup), the shell's screen is kept aside untouched
+    EnterAltScreen,
+    /// Switches back to the shell's screen (rmcup) as it was before
+    LeaveAltScreen,
+    /// Asks the terminal to answer with the cursor's position as `ESC [ rows ; cols R`
+    QueryCursorPosition,
+    /// Sets the cursor's shape (DECSCUSR), blinking or steady. Terminals that don't know the
+    /// sequence ignore it and keep their own.
+    CursorShape(CursorShape, bool),
+    /// Gives the cursor back the shape the terminal is set up with
+    DefaultCursor,
+}
+
+/// A shape the terminal can draw the cursor in
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CursorShape {
+    Block,
+    Underline,
+    Bar,
+    /// Whatever the terminal is set up with, rilo leaves it alone
+    Default,
+}
+
+impl std::str::FromStr for CursorShape {
+    type Err = ();
+
+    /// The names the config file uses
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "block" => Ok(CursorShape::Block),
+            "underline" => Ok(CursorShape::Underline),
+            "bar" => Ok(CursorShape::Bar),
+            "default" => Ok(CursorShape::Default),
+            _ => Err(()),
+        }
+    }
+}
+
+impl From<CtrlSeq> for Vec<u8> {
+    fn from(ctrl: CtrlSeq) -> Self {
+        match ctrl {
+            CtrlSeq::ClearLine => b"\x1b[K".to_vec(),
+            CtrlSeq::ClearScreen => b"\x1b[2J".to_vec(),
+            CtrlSeq::GotoStart => b"\x1b[H".to_vec(),
+            CtrlSeq::HideCursor => b"\x1b[?25l".to_vec(),
+            CtrlSeq::ShowCursor => b"\x1b[?25h".to_vec(),
+            CtrlSeq::MoveCursor(cp) => format!("\x1b[{};{}H", cp.y + 1, cp.x + 1)
+                .as_bytes()
+                .to_vec(),
+            CtrlSeq::InverteColor => b"\x1b[7m".to_vec(),
+            CtrlSeq::NormalColor => b"\x1b[m".to_vec(),
+            CtrlSeq::Bold => b"\x1b[1m".to_vec(),
+            CtrlSeq::Dim => b"\x1b[2m".to_vec(),
+            CtrlSeq::Underline => b"\x1b[4m".to_vec(),
+            CtrlSeq::Foreground(color) => match color {
+                Color::Palette(color @ 0..=7) => format!("\x1b[{}m", 30 + color).into_bytes(),
+                Color::Palette(color @ 8..=15) => format!("\x1b[{}m", 90 + color - 8).into_bytes(),
+                Color::Palette(color) => format!("\x1b[38;5;{color}m").into_bytes(),
+                Color::Rgb(red, green, blue) => {
+                    format!("\x1b[38;2;{red};{green};{blue}m").into_bytes()
+                }
+            },
+            CtrlSeq::Background(color) => match color {
+                Color::Palette(color @ 0..=7) => format!("\x1b[{}m", 40 + color).into_bytes(),
+                Color::Palette(color @ 8..=15) => format!("\x1b[{}m", 100 + color - 8).into_bytes(),
+                Color::Palette(color) => format!("\x1b[48;5;{color}m").into_bytes(),
+                Color::Rgb(red, green, blue) => {
+                    format!("\x1b[48;2;{red};{green};{blue}m").into_bytes()
+                }
+            },
+            CtrlSeq::Bell => b"\x07".to_vec(),
+            CtrlSeq::SetClipboard(encoded) => format!("\x1b]52;c;{encoded}\x07").into_bytes(),
+            CtrlSeq::QueryClipboard => b"\x1b]52;c;?\x07".to_vec(),
+            CtrlSeq::QueryCursorPosition => b"\x1b[6n".to_vec(),
+            CtrlSeq::EnableMouse => b"\x1b[?1000h\x1b[?1006h".to_vec(),
+            CtrlSeq::DisableMouse => b"\x1b[?1006l\x1b[?1000l".to_vec(),
+            CtrlSeq::EnableBracketedPaste => b"\x1b[?2004h".to_vec(),
+            CtrlSeq::DisableBracketedPaste => b"\x1b[?2004l".to_vec(),
+            CtrlSeq::EnableFocusEvents => b"\x1b[?1004h".to_vec(),
+            CtrlSeq::DisableFocusEvents => b"\x1b[?1004l".to_vec(),
+            CtrlSeq::EnterAltScreen => b"\x1b[?1049h".to_vec(),
+            CtrlSeq::LeaveAltScreen => b"\x1b[?1049l".to_vec(),
+            CtrlSeq::CursorShape(shape, blink) => {
+                let steady = match shape {
+                    CursorShape::Block => 2,
+                    CursorShape::Underline => 4,
+                    CursorShape::Bar => 6,
+                    CursorShape::Default => return b"\x1b[0 q".to_vec(),
+                };
+                format!("\x1b[{} q", steady - u8::from(blink)).into_bytes()
+            }
+            CtrlSeq::DefaultCursor => b"\x1b[0 q".to_vec(),
+        }
+    }
+}
+
+/// The colors to draw with, `None` when the configured ones aren't used at all
+fn color_depth(mode: cli::ColorMode) -> Option<ColorDepth> {
+    let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    match mode {
+        cli::ColorMode::Never => None,
+        cli::ColorMode::Auto if no_color => None,
+        _ => Some(ColorDepth::detect(
+            env::var("COLORTERM").ok().as_deref(),
+            env::var("TERM").ok().as_deref(),
+        )),
+    }
+}
+
+/// Points stdin and stdout back at the controlling terminal when they've been redirected,
+/// so rilo can sit in the middle of a pipeline
+fn attach_terminal() -> io::Result<()> {
+    for fd in [io::stdin().as_raw_fd(), io::stdout().as_raw_fd()] {
+        if !isatty(fd).unwrap_or(false) {
+            let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+            dup2(tty.as_raw_fd(), fd).map_err(Error::other)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Where everything drawn goes, and the first write to it that failed. Once one has, the rest are
+/// skipped and the main loop ends the session with it.
+struct Output {
+    /// Stdout unless something else was set to take its place
+    sink: Option<Box<dyn Write + Send>>,
+    failed: Option<Error>,
+}
+
+static OUTPUT: Mutex<Output> = Mutex::new(Output {
+    sink: None,
+    failed: None,
+});
+
+/// Sends everything drawn from now on to `sink` instead of stdout, to see it without a terminal
+pub fn set_output(sink: Box<dyn Write + Send>) {
+    if let Ok(mut output) = OUTPUT.lock() {
+        output.sink = Some(sink);
+    }
+}
+
+fn stdout_write(buff: impl AsRef<[u8]>) {
+    let Ok(mut output) = OUTPUT.lock() else {
+        return;
+    };
+    if output.failed.is_some() {
+        return;
+    }
+
+    let written = if let Some(sink) = &mut output.sink {
+        sink.write_all(buff.as_ref()).and_then(|()| sink.flush())
+    } else {
+        let mut stdout = io::stdout().lock();
+        stdout
+            .write_all(buff.as_ref())
+            .and_then(|()| stdout.flush())
+    };
+    if let Err(err) = written {
+        output.failed = Some(err);
+    }
+}
+
+/// The error that stopped output to the terminal, if one has
+fn output_error() -> Option<Error> {
+    OUTPUT.lock().ok()?.failed.take()
+}
+
+/// The terminal the editor runs in, as far as the editor needs to ask it anything
+pub trait Terminal {
+    /// The size as (rows, cols), clamped to `MAX_WINDOW_SIZE`. An error when it can't be told.
+    fn size(&self) -> io::Result<(usize, usize)>;
+    /// Whether the editor is drawn on the alternate screen, which takes it away on exit
+    fn alt_screen(&self) -> bool;
+    /// Hands the terminal back and stops rilo like the shell's Ctrl-Z, returning once it's been
+    /// continued and has the terminal again
+    fn suspend(&mut self) -> io::Result<()>;
+}
+
+/// A terminal that's always the same size, for running the editor without one
+pub struct FixedTerminal {
+    pub rows: usize,
+    pub cols: usize,
+}
+
+impl Terminal for FixedTerminal {
+    /// A zero size is an error, as from a real terminal
+    fn size(&self) -> io::Result<(usize, usize)> {
+        if self.rows == 0 || self.cols == 0 {
+            return Err(Error::other("FixedTerminal: zero window size"));
+        }
+        Ok((
+            self.rows.min(MAX_WINDOW_SIZE),
+            self.cols.min(MAX_WINDOW_SIZE),
+        ))
+    }
+
+    fn alt_screen(&self) -> bool {
+        false
+    }
+
+    /// There's no shell to go back to
+    fn suspend(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct RawMode {
+    /// The settings the terminal had before, put back whenever it's given up
+    inner: Termios,
+    /// Whether the editor is drawn on the alternate screen, which takes it away on exit
+    alt_screen: bool,
+}
+
+/// Set while the alternate screen is showing. Whichever of a panic or restoring the terminal comes
+/// first leaves it, so a panic's message is printed where it can still be read.
+static ALT_SCREEN_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Shows the cursor again in the shape the terminal is set up with, whatever rilo left it as
+fn restore_cursor() {
+    send_esc_seq(CtrlSeq::DefaultCursor);
+    send_esc_seq(CtrlSeq::ShowCursor);
+}
+
+fn leave_alt_screen() {
+    if ALT_SCREEN_ACTIVE.swap(false, AtomicOrdering::SeqCst) {
+        send_esc_seq(CtrlSeq::LeaveAltScreen);
+    }
+}
+
+impl RawMode {
+    pub fn enable_raw_mode(alt_screen: bool) -> io::Result<Self> {
+        let inner = Termios::from_fd(io::stdin().as_raw_fd())?;
+        let raw_mode = Self { inner, alt_screen };
+        raw_mode.enable()?;
+        Ok(raw_mode)
+    }
+
+    /// Takes the terminal over, again after `disable` when coming back from a suspend
+    fn enable(&self) -> io::Result<()> {
+        let mut term = self.inner;
+        term.c_iflag &= !(BRKINT | ICRNL | INPCK | ISTRIP | IXON);
+        term.c_oflag &= !(OPOST);
+        term.c_cflag |= CS8;
+        term.c_lflag &= !(ECHO | ICANON | IEXTEN | ISIG);
+        term.c_cc[VMIN] = 0;
+        term.c_cc[VTIME] = 1;
+
+        termios::tcsetattr(io::stdin().as_raw_fd(), TCSAFLUSH, &term)?;
+        if self.alt_screen {
+            send_esc_seq(CtrlSeq::EnterAltScreen);
+            ALT_SCREEN_ACTIVE.store(true, AtomicOrdering::SeqCst);
+        }
+        // Terminals without mouse reporting ignore this and just never send mouse sequences
+        send_esc_seq(CtrlSeq::EnableMouse);
+        send_esc_seq(CtrlSeq::EnableBracketedPaste);
+        // Terminals without focus reporting never send the events, and rilo acts as if focused
+        send_esc_seq(CtrlSeq::EnableFocusEvents);
+        Ok(())
+    }
+
+    /// Gives the terminal back the way it was found
+    fn disable(&self) {
+        restore_cursor();
+        send_esc_seq(CtrlSeq::DisableFocusEvents);
+        send_esc_seq(CtrlSeq::DisableBracketedPaste);
+        send_esc_seq(CtrlSeq::DisableMouse);
+        leave_alt_screen();
+        // Nothing more can be done if the terminal is gone
+        let _ = termios::tcsetattr(io::stdin().as_raw_fd(), TCSAFLUSH, &self.inner);
+    }
+}
+
+impl Terminal for RawMode {
+    fn size(&self) -> io::Result<(usize, usize)> {
+        ioctl_window_size()
+    }
+
+    fn alt_screen(&self) -> bool {
+        self.alt_screen
+    }
+
+    fn suspend(&mut self) -> io::Result<()> {
+        self.disable();
+        // SIGSTOP can't be caught or ignored, `raise` returns once the shell continues rilo
+        raise(Signal::SIGSTOP).map_err(io::Error::other)?;
+        self.enable()
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        self.disable();
+    }
+}
+
+#[derive(Default)]
+#[repr(C)]
+#[allow(clippy::struct_field_names)] // Mirrors libc's `struct winsize`
+struct WindowSize {
+    ws_row: c_short,
+    ws_col: c_short,
+    ws_xpixel: c_short,
+    ws_ypxiel: c_short,
+}
+
+/// What typing the first of each pair inserts the second of too, when `auto_close` is on
+const AUTO_PAIRS: [(char, char); 5] =
+    [('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')];
+
+/// Bytes read from a file at a time while opening it
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Files bigger than this are shown once their start is read, the rest is read in the background
+const BACKGROUND_READ_SIZE: u64 = 32 * 1024 * 1024;
+
+/// Rows of a big file read before it's shown, more are waited for if the cursor starts further in
+const FIRST_ROWS: usize = 1024;
+
+/// How often rows read in the background are taken in while no keys come
+const LOAD_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long the buffer stays frozen for work in the background before the status bar says why
+const FREEZE_NOTICE_AFTER: Duration = Duration::from_millis(200);
+
+/// How long the work the buffer is frozen for is waited on before looking for keys again
+const FREEZE_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// The most times a macro is replayed at once, however many digits go into the count
+const MAX_REPLAYS: usize = 9999;
+
+/// Bytes at the start of a file looked at to tell whether it's binary
+const BINARY_SAMPLE: usize = 8 * 1024;
+
+/// Lines inserted between checks for Escape and progress updates while pasting
+const PASTE_CHUNK_LINES: usize = 10_000;
+
+/// Most files the recent files list shows
+const MAX_RECENT_FILES: usize = 50;
+
+/// Drawn in the gutter by rows that changed since the file was last read or saved
+const CHANGE_MARK: char = '\u{2502}';
+
+/// Files bigger than this aren't compared against, a diff that size wouldn't be readable anyway
+const MAX_COMPARE_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Screen rows above the status bar needed for two panes, a text row each and the status line
+/// between them
+const MIN_SPLIT_HEIGHT: usize = 3;
+
+/// Larger sizes than this, in rows or columns, are taken to be a glitch and clamped
+const MAX_WINDOW_SIZE: usize = 1000;
+
+/// Used when the terminal never reports a size, as (rows, cols)
+const DEFAULT_WINDOW_SIZE: (usize, usize) = (24, 80);
+
+/// How many more times to ask for the size on startup before falling back to the default
+const SIZE_RETRIES: usize = 5;
+const SIZE_RETRY_DELAY: Duration = Duration::from_millis(20);
+
+/// How often a size the terminal stopped reporting is looked for again
+const SIZE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long to wait for the cursor position report when measuring the terminal
+const CURSOR_REPORT_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// The terminal's size on startup, as (rows, cols), and where it came from
+#[derive(Copy, Clone)]
+enum TermSize {
+    /// The kernel knows it, and keeps it up to date when the window is resized
+    Reported(usize, usize),
+    /// The kernel didn't know it, the cursor was moved as far as it goes and asked where it ended up
+    Measured(usize, usize),
+    /// Neither worked
+    Unknown,
+}
+
+/// What a fresh look at the terminal's size found
+enum Geometry {
+    Unchanged,
+    Resized,
+    /// The terminal reported a zero size, usually for a moment while a multiplexer attaches
+    Unavailable,
+}
+
+/// What becomes of the keys typed while the buffer is frozen for work in the background
+#[derive(Clone, Copy)]
+enum Freeze {
+    /// They're handled once the work's done, it leaves the buffer as it was
+    Replayed,
+    /// They're dropped, the work changes the buffer from under them. Escape cancels it.
+    Rejected,
+}
+
+/// What a prompt reads, which decides the keys it takes besides typing
+#[derive(Clone, Copy, PartialEq)]
+enum PromptKind {
+    Line,
+    /// A search, Alt-C changes how it treats case
+    Search,
+    /// A file's path, Tab completes it
+    Path,
+}
+
+/// How work the buffer was frozen for ended
+struct Thawed<T> {
+    /// What the work came to, `None` when Escape cancelled it
+    result: Option<T>,
+    /// Keys typed meanwhile that were dropped
+    dropped: usize,
+}
+
+impl<T> Thawed<T> {
+    /// `message`, saying so if any keys were dropped
+    fn report(&self, message: &str) -> String {
+        match self.dropped {
+            0 => message.to_string(),
+            1 => format!("{message}, a key typed meanwhile was dropped"),
+            n => format!("{message}, {n} keys typed meanwhile were dropped"),
+        }
+    }
+}
+
+struct SystemMessage {
+    message: Option<String>,
+    time: Instant,
+}
+
+impl Default for SystemMessage {
+    fn default() -> Self {
+        SystemMessage {
+            message: None,
+            time: Instant::now(),
+        }
+    }
+}
+
+impl SystemMessage {
+    fn new(message: &str) -> Self {
+        SystemMessage {
+            message: Some(sanitize(message, MAX_MESSAGE_LEN)),
+            time: Instant::now(),
+        }
+    }
+}
+
+/// How long a message stays on the status bar
+const MESSAGE_DURATION: Duration = Duration::from_secs(5);
+
+/// Longest message kept, in chars. Nothing longer fits on the status bar anyway.
+const MAX_MESSAGE_LEN: usize = 512;
+
+/// Most messages kept for the message history, older ones are dropped
+const MAX_LOGGED_MESSAGES: usize = 100;
+
+/// How long ago something happened, roughly, like `5s ago` or `2h ago`
+fn format_age(age: Duration) -> String {
+    match age.as_secs() {
+        secs @ 0..60 => format!("{secs}s ago"),
+        secs @ 60..3600 => format!("{}m ago", secs / 60),
+        secs => format!("{}h ago", secs / 3600),
+    }
+}
+
+/// Makes text that didn't come from the keyboard (paths, file content, error output) safe to show:
+/// control characters are escaped so they can't drive the terminal, and anything past `max_chars`
+/// is cut off on a char boundary and marked with an ellipsis. Only the kept prefix is ever read,
+/// so a huge input costs no more than a short one.
+fn sanitize(text: &str, max_chars: usize) -> String {
+    let mut sanitized = String::new();
+    for (count, c) in text.chars().enumerate() {
+        if count == max_chars {
+            sanitized.push_str("...");
+            break;
+        }
+
+        match c {
+            // Caret notation for C0 controls and DEL, like `cat -v`
+            '\0'..='\x1f' => {
+                sanitized.push('^');
+                sanitized.push(char::from(c as u8 + b'@'));
+            }
+            '\x7f' => sanitized.push_str("^?"),
+            _ if c.is_control() => sanitized.push_str(&c.escape_unicode().to_string()),
+            _ => sanitized.push(c),
+        }
+    }
+
+    sanitized
+}
+
+pub type Row = String;
+
+/// Where a view into the buffer is scrolled to and where its cursor is, the editor's fields of the
+/// same names hold the view being edited
+#[derive(Copy, Clone, Default)]
+struct View {
+    cur_pos: CursorPosition,
+    row_offset: usize,
+    col_offset: usize,
+    wrap_skip: usize,
+}
+
+/// The window split into two panes, one above the other, over the same rows or two buffers
+struct Split {
+    /// The pane that isn't being edited
+    other: View,
+    /// The other pane's own buffer, `None` while both panes show the same one
+    buffer: Option<Box<Buffer>>,
+    /// Whether the pane being edited is the top one
+    top_active: bool,
+}
+
+/// Everything about an open file or buffer that goes with its rows. The editor's fields of the
+/// same names hold the buffer being edited, the other pane's is swapped in to draw or edit it.
+#[allow(clippy::struct_excessive_bools)]
+struct Buffer {
+    rows: Rows,
+    file: Option<File>,
+    path: Option<String>,
+    dirty_flag: bool,
+    readonly: bool,
+    lossy: bool,
+    tab_size: u8,
+    expand_tab: bool,
+    filetype: Option<&'static filetype::Filetype>,
+    wrap: bool,
+    show_invisibles: bool,
+    ephemeral: bool,
+    listing: Option<browser::Listing>,
+    loader: Option<loader::Loader>,
+    lock: Option<lock::Lock>,
+    locked_out: bool,
+    baseline: changes::Baseline,
+    edits: u64,
+    changed_rows: Option<(u64, Vec<bool>)>,
+    blocks: Option<(u64, Vec<Option<usize>>)>,
+    checkpoints: Option<(ColumnKey, columns::Checkpoints)>,
+    line_ending: LineEnding,
+    final_newline: bool,
+    disk_stamp: Option<DiskStamp>,
+    search: Option<Search>,
+    mark: Option<(usize, usize)>,
+    auto_closed: Option<(usize, usize)>,
+    goal_column: Option<usize>,
+}
+
+/// The last search, its matches stay highlighted until Escape or an edit clears it
+struct Search {
+    query: String,
+    /// The match the cursor jumped to, as its row and index into the row
+    current: (usize, usize),
+}
+
+#[allow(clippy::struct_excessive_bools)]
+pub struct Editor {
+    terminal: Box<dyn Terminal>,
+    input: Input,
+    screen: Screen,
+    /// Screen rows above the status bar, the text rows of every pane and the status lines between
+    text_height: usize,
+    /// The last text row of the pane being edited
+    term_rows: usize,
+    term_cols: usize,
+    /// The kernel didn't know the size at startup, so the one rilo started with is kept while it
+    /// still doesn't, instead of waiting for it
+    fixed_size: bool,
+    /// Where the cursor is, `y` counted from `row_offset` and `x` an index into its row
+    cur_pos: CursorPosition,
+    /// The rendered column Up and Down aim for, the one the first of them started from. Kept while
+    /// they're pressed one after another, so crossing a short or tab indented row doesn't lose it.
+    /// In soft wrap mode it's the column within a screen row.
+    goal_column: Option<usize>,
+    row_offset: usize,
+    /// The rendered column at the window's left edge, the same one on every row however its tabs
+    /// and wide chars lay it out
+    col_offset: usize,
+    tab_size: u8,
+    file: Option<File>,
+    rows: Rows,
+    message: SystemMessage,
+    /// The question being asked, drawn over the bottom of the screen until it's answered
+    confirm: Option<Confirm>,
+    /// A message raised while a prompt was open, shown once it closes
+    queued_message: Option<SystemMessage>,
+    /// Every message set, oldest first and with when it was, including those a prompt held back
+    message_log: VecDeque<(Instant, String)>,
+    prompt_active: bool,
+    dirty_flag: bool,
+    path: Option<String>,
+    readonly: bool,
+    /// Open files that look binary as text anyway, from `--force`
+    force_text: bool,
+    /// Typing replaces the char under the cursor instead of pushing the rest of the row right
+    overwrite: bool,
+    /// The keys recorded last, replayed by Ctrl-\
+    last_macro: input::Macro,
+    /// How many times the next replay goes, typed as Alt and digits ahead of the replay key
+    replay_count: Option<usize>,
+    /// The file wasn't valid UTF-8 and saving it will replace the bad bytes, asked about first
+    lossy: bool,
+    /// Whether Tab inserts spaces instead of a tab character
+    expand_tab: bool,
+    /// What kind of file is open, going by its name, which decides its indentation
+    filetype: Option<&'static filetype::Filetype>,
+    /// View options set on the command line, they win over anything remembered for a file
+    pinned: FileState,
+    /// Soft wrap mode, long rows are broken into several screen rows instead of scrolling horizontally.
+    /// While it's on `col_offset` is always 0 and `cur_pos.y` is relative to `row_offset` in logical rows.
+    wrap: bool,
+    /// Screen rows of the top row scrolled off the top in soft wrap mode. Only ever set while the
+    /// cursor is on a row taller than the window, it's the only way to see all of such a row.
+    wrap_skip: usize,
+    /// A buffer that isn't backed by a file and is never worth warning about, like the scratch buffer
+    ephemeral: bool,
+    /// The directory the buffer lists instead of a file's rows, it can't be edited and Enter opens
+    /// the entry under the cursor
+    listing: Option<browser::Listing>,
+    /// Reads the rest of a big file while its start is shown. Until it's done the buffer can't be
+    /// edited or saved.
+    loader: Option<loader::Loader>,
+    /// Keeps other rilos from editing the open file while this one is
+    lock: Option<lock::Lock>,
+    /// The file was opened read-only because another process is editing it, saving checks again
+    locked_out: bool,
+    config: Config,
+    /// Rows cut by Ctrl-K, pasted back by Ctrl-U
+    kill_buffer: Vec<Row>,
+    /// Set while consecutive Ctrl-K presses should keep adding to the kill buffer
+    appending_kill: bool,
+    /// How many Ctrl-L presses in a row there were, each puts the line somewhere else
+    recenters: usize,
+    /// The rows as of the last time the file was read or saved, what change marks compare against
+    baseline: changes::Baseline,
+    /// Counts calls to `mark_edited`, so change marks are only worked out again after an edit
+    edits: u64,
+    /// The rows that changed since `baseline`, and the count of edits they were worked out at
+    changed_rows: Option<(u64, Vec<bool>)>,
+    /// The block of the file's syntax each row from the first starts in, as far down as has been
+    /// drawn, and the count of edits they were worked out at
+    blocks: Option<(u64, Vec<Option<usize>>)>,
+    /// Columns along the cursor's row when it's a long one, for what `column_key` gave then
+    checkpoints: Option<(ColumnKey, columns::Checkpoints)>,
+    /// The cursor right after typing an opener that was closed automatically, Backspace there
+    /// takes out the pair. Any other key forgets it.
+    auto_closed: Option<(usize, usize)>,
+    /// The buffer goes to stdout on exit, so there's no need to ask about saving it
+    print_on_exit: bool,
+    /// Which key does what, a key missing from it falls back to `Action::from`
+    keymap: Vec<(Key, Action)>,
+    /// How the file ends its lines, written back the same way
+    line_ending: LineEnding,
+    /// Whether the file's last line ends with a newline
+    final_newline: bool,
+    /// The file as it was on disk when last read or written, to notice other programs changing it
+    disk_stamp: Option<DiskStamp>,
+    trim_on_save: bool,
+    /// Copy the file's previous contents aside before each save
+    backup: bool,
+    /// Problems with the config file and its bindings, flagged in the status bar until it's fixed
+    config_errors: Vec<ConfigError>,
+    /// Lines drawn over the bottom of the screen until a key is pressed
+    overlay: Option<Vec<Line>>,
+    /// Whether the terminal window has focus, as far as its focus events tell
+    focused: bool,
+    /// The colors the terminal can show, `None` to draw in plain reverse video whatever the
+    /// config says, for `--color=never` and `NO_COLOR`
+    colors: Option<ColorDepth>,
+    /// What `--color` asked for, to look at the colors again with
+    color_mode: cli::ColorMode,
+    split: Option<Split>,
+    search: Option<Search>,
+    /// What was searched for last, kept after the highlight goes for finding the next match
+    last_query: Option<String>,
+    search_case: CaseMode,
+    /// Where the selection started, as a row and index into it. The selection runs from here to
+    /// the cursor, moving the cursor extends it and any other key ends it.
+    mark: Option<(usize, usize)>,
+    /// Draw tabs, trailing spaces and line ends with marks
+    show_invisibles: bool,
+    /// Columns counted from 1 the line length guide is drawn in
+    color_column: Vec<usize>,
+    /// Rows of context kept above and below the cursor, where the file has them
+    scroll_off: usize,
+}
+
+impl Editor {
+    /// Takes the terminal over, it's given back when the editor is dropped
+    pub fn new(options: &cli::Options, config: Config) -> io::Result<Self> {
+        let mode = RawMode::enable_raw_mode(!options.no_alt_screen)?;
+        let size = get_window_size();
+        let input = Input::new(Box::new(Stdin::new()));
+        Ok(Self::build(options, config, Box::new(mode), input, size))
+    }
+
+    /// An editor that reads its keys from `input` and draws for `terminal`, leaving the actual
+    /// terminal alone
+    pub fn with_terminal(
+        options: &cli::Options,
+        config: Config,
+        terminal: Box<dyn Terminal>,
+        input: Input,
+    ) -> Self {
+        let size = match terminal.size() {
+            Ok((rows, cols)) => TermSize::Reported(rows, cols),
+            Err(_) => TermSize::Unknown,
+        };
+        Self::build(options, config, terminal, input, size)
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn build(
+        options: &cli::Options,
+        config: Config,
+        terminal: Box<dyn Terminal>,
+        input: Input,
+        size: TermSize,
+    ) -> Self {
+        let fixed_size = !matches!(size, TermSize::Reported(..));
+        let (rows, cols, message) = match size {
+            TermSize::Reported(rows, cols) => (
+                rows,
+                cols,
+                "HELP: Ctrl-S = save | Ctrl-Q = quit | Ctrl-F = find | F1 = keys".to_string(),
+            ),
+            TermSize::Measured(rows, cols) => (
+                rows,
+                cols,
+                format!("The terminal didn't report its size, measured {cols}x{rows}"),
+            ),
+            TermSize::Unknown => (
+                DEFAULT_WINDOW_SIZE.0,
+                DEFAULT_WINDOW_SIZE.1,
+                "Couldn't get the terminal's size, assuming 80x24".to_string(),
+            ),
+        };
+        let (keymap, config_errors) = build_keymap(&config);
+        let message = match config_errors.as_slice() {
+            [] => message,
+            [error] => error.to_string(),
+            [error, rest @ ..] => format!(
+                "{error} (and {} more, Alt-X show-config-errors lists them)",
+                rest.len()
+            ),
+        };
+
+        Editor {
+            terminal,
+            input,
+            screen: Screen::default(),
+            text_height: rows.saturating_sub(1), // -1 to leave a row for the status bar
+            term_rows: rows.saturating_sub(2),
+            term_cols: cols.saturating_sub(1),
+            fixed_size,
+            cur_pos: CursorPosition::default(),
+            goal_column: None,
+            row_offset: 0,
+            col_offset: 0,
+            tab_size: options.tab_size.unwrap_or(config.tab_size),
+            file: Option::default(),
+            rows: Rows::new(),
+            message: SystemMessage::new(&message),
+            queued_message: None,
+            message_log: VecDeque::from([(Instant::now(), sanitize(&message, MAX_MESSAGE_LEN))]),
+            prompt_active: false,
+            confirm: None,
+            dirty_flag: false,
+            path: None,
+            readonly: options.readonly,
+            force_text: options.force,
+            overwrite: false,
+            last_macro: input::Macro::new(),
+            replay_count: None,
+            lossy: false,
+            expand_tab: config.expand_tab,
+            filetype: None,
+            search_case: config.search_case,
+            show_invisibles: config.show_invisibles,
+            color_column: config.color_column.clone(),
+            scroll_off: config.scroll_off,
+            trim_on_save: config.trim_trailing_whitespace,
+            backup: options.backup || config.backup,
+            pinned: FileState {
+                wrap: options.wrap.then_some(true),
+                tab_size: options.tab_size,
+                expand_tab: None,
+                show_invisibles: None,
+                filetype: None,
+                position: None,
+            },
+            wrap: options.wrap || config.wrap,
+            wrap_skip: 0,
+            ephemeral: false,
+            listing: None,
+            loader: None,
+            lock: None,
+            locked_out: false,
+            config,
+            kill_buffer: Vec::new(),
+            appending_kill: false,
+            recenters: 0,
+            baseline: changes::Baseline::default(),
+            edits: 0,
+            changed_rows: None,
+            blocks: None,
+            checkpoints: None,
+            auto_closed: None,
+            print_on_exit: options.print_on_exit,
+            keymap,
+            line_ending: LineEnding::Lf,
+            final_newline: true,
+            disk_stamp: None,
+            config_errors,
+            overlay: None,
+            focused: true,
+            split: None,
+            search: None,
+            last_query: None,
+            mark: None,
+            colors: color_depth(options.color),
+            color_mode: options.color,
+        }
+    }
+
+    /// Takes a fresh look at the terminal's size, keeping the cursor's row on screen when it shrank.
+    /// A size that can't be read keeps the current geometry.
+    fn refresh_size(&mut self) -> Geometry {
+        let Ok((rows, cols)) = self.terminal.size() else {
+            if self.fixed_size {
+                return Geometry::Unchanged;
+            }
+            return Geometry::Unavailable;
+        };
+
+        let (text_height, term_cols) = (rows.saturating_sub(1), cols.saturating_sub(1));
+        if (text_height, term_cols) == (self.text_height, self.term_cols) {
+            return Geometry::Unchanged;
+        }
+
+        self.text_height = text_height;
+        self.term_cols = term_cols;
+        // Panes keep their share of the window, the other pane is fitted when it's next drawn. One
+        // with a buffer of its own is only hidden, it comes back when the window is big enough.
+        if text_height < MIN_SPLIT_HEIGHT {
+            match &self.split {
+                Some(split) if split.buffer.is_none() => {
+                    self.split = None;
+                    self.set_message("Window too small for two panes, the other one was closed");
+                }
+                Some(_) if self.text_height >= MIN_SPLIT_HEIGHT => {
+                    self.set_message("Window too small for two panes, the other one is hidden");
+                }
+                _ => {}
+            }
+        }
+        self.apply_layout();
+        send_esc_seq(CtrlSeq::ClearScreen);
+        self.screen.invalidate();
+        Geometry::Resized
+    }
+
+    /// The configured theme with the styles set one by one over it
+    fn theme(&self) -> Theme {
+        let Some(depth) = self.colors else {
+            return Theme::default();
+        };
+        let theme = self.config.theme;
+        let config = &self.config;
+        Theme {
+            depth,
+            status_bar: config.status_bar_style.unwrap_or(theme.status_bar),
+            filler: config.filler_style.unwrap_or(theme.filler),
+            search_match: config.match_style.unwrap_or(theme.search_match),
+            current_match: config.current_match_style.unwrap_or(theme.current_match),
+            matching_bracket: config.bracket_style.unwrap_or(theme.matching_bracket),
+            unmatched_bracket: config
+                .unmatched_bracket_style
+                .unwrap_or(theme.unmatched_bracket),
+            invisible: config.invisible_style.unwrap_or(theme.invisible),
+            selection: config.selection_style.unwrap_or(theme.selection),
+            change_mark: config.change_mark_style.unwrap_or(theme.change_mark),
+            color_column: config.color_column_style.unwrap_or(theme.color_column),
+            trailing_whitespace: config
+                .trailing_whitespace_style
+                .unwrap_or(theme.trailing_whitespace),
+            mixed_indent: config.mixed_indent_style.unwrap_or(theme.mixed_indent),
+            syntax: theme.syntax,
+        }
+    }
+
+    /// Text rows in the top and bottom panes, the top pane's status line takes the row between them
+    fn pane_heights(&self) -> (usize, usize) {
+        let rows = self.text_height.saturating_sub(1);
+        (rows / 2, rows - rows / 2)
+    }
+
+    /// The split when the window is big enough to show both panes
+    fn shown_split(&self) -> Option<&Split> {
+        self.split
+            .as_ref()
+            .filter(|_| self.text_height >= MIN_SPLIT_HEIGHT)
+    }
+
+    /// Sizes the pane being edited to its share of the window, scrolling to keep its cursor in it
+    fn apply_layout(&mut self) {
+        let height = match self.shown_split() {
+            None => self.text_height,
+            Some(split) if split.top_active => self.pane_heights().0,
+            Some(_) => self.pane_heights().1,
+        };
+        self.term_rows = height.saturating_sub(1);
+        if self.cur_pos.y > self.term_rows {
+            self.row_offset += self.cur_pos.y - self.term_rows;
+            self.cur_pos.y = self.term_rows;
+        }
+    }
+
+    fn view(&self) -> View {
+        View {
+            cur_pos: self.cur_pos,
+            row_offset: self.row_offset,
+            col_offset: self.col_offset,
+            wrap_skip: self.wrap_skip,
+        }
+    }
+
+    fn set_view(&mut self, view: View) {
+        self.cur_pos = view.cur_pos;
+        self.row_offset = view.row_offset;
+        self.col_offset = view.col_offset;
+        self.wrap_skip = view.wrap_skip;
+    }
+
+    /// Makes the other pane the one being edited. Rows may have been removed under it since it was
+    /// last edited, so its cursor is pulled back inside the buffer.
+    fn switch_pane(&mut self) {
+        let current = self.view();
+        let Some(split) = &mut self.split else {
+            return;
+        };
+        let other = std::mem::replace(&mut split.other, current);
+        split.top_active = !split.top_active;
+        if let Some(mut buffer) = split.buffer.take() {
+            self.swap_buffer(&mut buffer);
+            if let Some(split) = &mut self.split {
+                split.buffer = Some(buffer);
+            }
+        }
+
+        self.set_view(other);
+        self.apply_layout();
+        self.clamp_cursor();
+    }
+
+    /// Splits the window into two panes over the same rows, or moves to the other pane
+    fn other_pane(&mut self) {
+        if self.split.is_some() {
+            self.switch_pane();
+            return;
+        }
+        if self.text_height < MIN_SPLIT_HEIGHT {
+            self.set_message("Window too small for two panes");
+            return;
+        }
+
+        // The new pane starts out as a copy of this one, and is the one being edited
+        self.split = Some(Split {
+            other: self.view(),
+            buffer: None,
+            top_active: false,
+        });
+        self.apply_layout();
+        let message = match self.key_name(Action::OtherPane) {
+            Some(key) => format!("Window split, {key} moves between the panes"),
+            None => String::from("Window split"),
+        };
+        self.set_message(&message);
+    }
+
+    /// Goes back to a single pane, the one being edited fills the window. The other pane's own
+    /// buffer is closed with it, unless that would lose unsaved changes.
+    fn close_pane(&mut self) {
+        let Some(split) = &self.split else {
+            self.set_message("There's only one pane");
+            return;
+        };
+        if let Some(buffer) = &split.buffer {
+            if buffer.dirty_flag && !buffer.ephemeral {
+                self.set_message("The other pane has unsaved changes, save them there first");
+                return;
+            }
+            self.switch_pane();
+            self.persist_scratch();
+            self.remember_file_state();
+            self.switch_pane();
+        }
+        self.split = None;
+        self.apply_layout();
+    }
+
+    /// Trades the buffer being edited for `buffer`
+    fn swap_buffer(&mut self, buffer: &mut Buffer) {
+        std::mem::swap(&mut self.rows, &mut buffer.rows);
+        std::mem::swap(&mut self.file, &mut buffer.file);
+        std::mem::swap(&mut self.path, &mut buffer.path);
+        std::mem::swap(&mut self.dirty_flag, &mut buffer.dirty_flag);
+        std::mem::swap(&mut self.readonly, &mut buffer.readonly);
+        std::mem::swap(&mut self.lossy, &mut buffer.lossy);
+        std::mem::swap(&mut self.tab_size, &mut buffer.tab_size);
+        std::mem::swap(&mut self.expand_tab, &mut buffer.expand_tab);
+        std::mem::swap(&mut self.filetype, &mut buffer.filetype);
+        std::mem::swap(&mut self.wrap, &mut buffer.wrap);
+        std::mem::swap(&mut self.show_invisibles, &mut buffer.show_invisibles);
+        std::mem::swap(&mut self.ephemeral, &mut buffer.ephemeral);
+        std::mem::swap(&mut self.listing, &mut buffer.listing);
+        std::mem::swap(&mut self.loader, &mut buffer.loader);
+        std::mem::swap(&mut self.lock, &mut buffer.lock);
+        std::mem::swap(&mut self.locked_out, &mut buffer.locked_out);
+        std::mem::swap(&mut self.baseline, &mut buffer.baseline);
+        std::mem::swap(&mut self.edits, &mut buffer.edits);
+        std::mem::swap(&mut self.changed_rows, &mut buffer.changed_rows);
+        std::mem::swap(&mut self.blocks, &mut buffer.blocks);
+        std::mem::swap(&mut self.checkpoints, &mut buffer.checkpoints);
+        std::mem::swap(&mut self.line_ending, &mut buffer.line_ending);
+        std::mem::swap(&mut self.final_newline, &mut buffer.final_newline);
+        std::mem::swap(&mut self.disk_stamp, &mut buffer.disk_stamp);
+        std::mem::swap(&mut self.search, &mut buffer.search);
+        std::mem::swap(&mut self.mark, &mut buffer.mark);
+        std::mem::swap(&mut self.auto_closed, &mut buffer.auto_closed);
+        std::mem::swap(&mut self.goal_column, &mut buffer.goal_column);
+    }
+
+    /// An empty buffer with the view options of the one being edited, to open another file into
+    fn new_buffer(&self) -> Buffer {
+        Buffer {
+            rows: Rows::default(),
+            file: None,
+            path: None,
+            dirty_flag: false,
+            // Read-only for being locked out of this file doesn't carry over
+            readonly: self.readonly && !self.locked_out,
+            lossy: false,
+            tab_size: self.tab_size,
+            expand_tab: self.expand_tab,
+            filetype: None,
+            wrap: self.wrap,
+            show_invisibles: self.show_invisibles,
+            ephemeral: false,
+            listing: None,
+            loader: None,
+            lock: None,
+            locked_out: false,
+            baseline: changes::Baseline::default(),
+            edits: 0,
+            changed_rows: None,
+            blocks: None,
+            checkpoints: None,
+            line_ending: LineEnding::Lf,
+            final_newline: true,
+            disk_stamp: None,
+            search: None,
+            mark: None,
+            auto_closed: None,
+            goal_column: None,
+        }
+    }
+
+    /// Stops rilo until the shell brings it back with `fg`, then draws everything again for a
+    /// terminal that may have been resized or drawn over in the meantime
+    fn suspend(&mut self) {
+        // Without the alternate screen the shell's prompt comes back under rilo's last screen
+        if !self.terminal.alt_screen() {
+            send_esc_seq(CtrlSeq::ClearScreen);
+            send_esc_seq(CtrlSeq::GotoStart);
+        }
+        if let Err(err) = self.terminal.suspend() {
+            self.set_message(&format!("Couldn't suspend: {err}"));
+        }
+
+        self.apply_cursor_shape();
+        self.refresh_size();
+        send_esc_seq(CtrlSeq::ClearScreen);
+        self.screen.invalidate();
+    }
+
+    /// Follows the terminal window's focus. Coming back is when other programs have most likely
+    /// been at the file or the window was resized, so both are looked at right away.
+    fn set_focus(&mut self, focused: bool) {
+        self.focused = focused;
+        if !focused {
+            return;
+        }
+
+        self.refresh_size();
+        if let (Some(stamp), Some(path)) = (self.disk_stamp, &self.path) {
+            if DiskStamp::read(Path::new(path)) != Some(stamp) {
+                let message = format!("{path} was changed by another program since it was read");
+                self.set_message(&message);
+            }
+        }
+    }
+
+    /// What a key read from the terminal does
+    fn action_for(&self, byte: u8) -> Action {
+        self.bound_action(&[byte])
+            .unwrap_or_else(|| Action::from(byte))
+    }
+
+    /// The action the keymap has for the key sent as `bytes`. The arrows, Home and End are found
+    /// however the terminal's cursor mode sends them.
+    fn bound_action(&self, bytes: &[u8]) -> Option<Action> {
+        let normal;
+        let bytes = match bytes {
+            [0x1b, b'O', key @ (b'A'..=b'D' | b'H' | b'F')] => {
+                normal = [0x1b, b'[', *key];
+                &normal[..]
+            }
+            _ => bytes,
+        };
+        self.keymap
+            .iter()
+            .find(|(key, _)| key.encode() == bytes)
+            .map(|(_, action)| *action)
+    }
+
+    /// The name of the key bound to `action`, as it's written in help texts
+    fn key_name(&self, action: Action) -> Option<String> {
+        self.keymap
+            .iter()
+            .find(|(_, bound)| *bound == action)
+            .map(|(key, _)| key.to_string())
+    }
+
+    /// Moves the cursor within the buffer, scrolling as needed. The terminal's cursor follows on
+    /// the next draw, which is the only place it's moved.
+    #[allow(clippy::too_many_lines)]
+    fn move_cursor(&mut self, ak: &NavigationKey) {
+        if self.wrap {
+            self.move_cursor_wrapped(ak);
+            return;
+        }
+
+        match ak {
+            NavigationKey::Left => {
+                let x = self.cur_pos.x;
+                if x != 0 {
+                    let line = self.current_line().unwrap_or("");
+                    self.cur_pos.x = previous_char_boundary(line, x);
+                } else {
+                    if self.cur_pos.y == 0 && self.row_offset != 0 {
+                        self.row_offset -= 1;
+                    } else if !(self.cur_pos.y == 0 && self.row_offset == 0) {
+                        self.cur_pos.y -= 1;
+                    }
+
+                    if !(self.row_offset == 0 && self.cur_pos.y == 0) {
+                        if let Some(current_line) = self.current_line() {
+                            // scroll_horizontal brings the end of a long row into view
+                            self.cur_pos.x =
+                                previous_char_boundary(current_line, current_line.len());
+                        }
+                    }
+                }
+            }
+            NavigationKey::Right => {
+                if let Some(current_line) = self.current_line() {
+                    let x = self.cur_pos.x;
+                    if x >= current_line.len() {
+                        if self.cur_pos.y + self.row_offset + 1 < self.rows.len() {
+                            self.cur_pos.x = 0;
+
+                            if self.cur_pos.y == self.term_rows {
+                                self.row_offset += 1;
+                            } else {
+                                self.cur_pos.y += 1;
+                            }
+                        }
+                    } else {
+                        self.cur_pos.x = next_char_boundary(current_line, x);
+                    }
+                }
+            }
+            NavigationKey::Up => {
+                let goal = self.goal_column();
+                if self.cur_pos.y != 0 {
+                    self.cur_pos.y -= 1;
+                } else if self.cur_pos.y == 0 && self.row_offset != 0 {
+                    self.row_offset -= 1;
+                }
+                self.move_to_column(goal);
+            }
+            NavigationKey::Down => {
+                // An empty buffer has no row to go down to
+                if self.row_offset + self.cur_pos.y + 1 < self.rows.len() {
+                    let goal = self.goal_column();
+                    if self.cur_pos.y != self.term_rows {
+                        self.cur_pos.y += 1;
+                    } else if self.cur_pos.y == self.term_rows
+                        && self.row_offset + self.term_rows != self.rows.len()
+                    {
+                        self.row_offset += 1;
+                    }
+                    self.move_to_column(goal);
+                }
+            }
+            NavigationKey::Home => {
+                let x = self.cur_pos.x;
+                // scroll_horizontal brings deep indentation back into view
+                self.cur_pos.x = self.current_line().map_or(0, |line| home_column(line, x));
+            }
+            NavigationKey::End => {
+                // scroll_horizontal brings the end into view, however wide the row is on screen
+                self.cur_pos.x = self.current_line().map_or(0, str::len);
+            }
+            NavigationKey::PageUp => {
+                // The top row outside the margin, unless the window already shows the first row
+                self.cur_pos.y = if self.row_offset == 0 {
+                    0
+                } else {
+                    self.scroll_margin()
+                };
+                if let Some(next_line) = self.current_line() {
+                    self.cur_pos.x = split_point(next_line, self.cur_pos.x).unwrap_or_else(|x| x);
+                }
+            }
+            NavigationKey::PageDown => {
+                // The bottom text row outside the margin, or the file's last row when it's on screen
+                let last = self.rows.len().saturating_sub(1);
+                self.cur_pos.y = if last <= self.row_offset + self.term_rows {
+                    last.saturating_sub(self.row_offset)
+                } else {
+                    self.term_rows - self.scroll_margin()
+                };
+                if let Some(next_line) = self.current_line() {
+                    self.cur_pos.x = split_point(next_line, self.cur_pos.x).unwrap_or_else(|x| x);
+                }
+            }
+            NavigationKey::ParagraphUp | NavigationKey::ParagraphDown => {
+                let y = self.row_offset + self.cur_pos.y;
+                let y = paragraph_boundary(&self.rows, y, *ak == NavigationKey::ParagraphDown);
+                let row = self.rows.get(y).unwrap_or("");
+                let x = split_point(row, self.cur_pos.x).unwrap_or_else(|x| x);
+                self.place_cursor(y, x);
+            }
+            NavigationKey::WordLeft | NavigationKey::WordRight => {
+                let x = self.cur_pos.x;
+                let line = self.current_line().unwrap_or("");
+                let target = if *ak == NavigationKey::WordLeft {
+                    (x != 0).then(|| previous_word_boundary(line, x))
+                } else {
+                    (x < line.len()).then(|| next_word_boundary(line, x))
+                };
+
+                match target {
+                    Some(target) => self.cur_pos.x = target,
+                    // At the edge of the line a word move crosses lines just like a plain one
+                    None if *ak == NavigationKey::WordLeft => {
+                        self.move_cursor(&NavigationKey::Left);
+                    }
+                    None => self.move_cursor(&NavigationKey::Right),
+                }
+            }
+        }
+
+        self.scroll_to_row(self.row_offset + self.cur_pos.y);
+        self.scroll_horizontal();
+    }
+
+    /// The soft wrap counterpart of `move_cursor`, Up and Down move by screen rows within a wrapped row
+    fn move_cursor_wrapped(&mut self, ak: &NavigationKey) {
+        let width = self.wrap_width();
+        let mut y = self.row_offset + self.cur_pos.y;
+        let mut x = self.cur_pos.x;
+
+        if let Some(line) = self.rows.get(y) {
+            let (visual_row, visual_col) = self.visual_position(line, x);
+            let visual_col = match ak {
+                NavigationKey::Up | NavigationKey::Down => {
+                    *self.goal_column.get_or_insert(visual_col)
+                }
+                _ => visual_col,
+            };
+            match ak {
+                NavigationKey::Left => {
+                    if x != 0 {
+                        x = previous_char_boundary(line, x);
+                    } else if y != 0 {
+                        y -= 1;
+                        x = self.rows[y].len();
+                    }
+                }
+                NavigationKey::Right => {
+                    if x < line.len() {
+                        x = next_char_boundary(line, x);
+                    } else if y + 1 < self.rows.len() {
+                        y += 1;
+                        x = 0;
+                    }
+                }
+                NavigationKey::WordLeft => {
+                    if x != 0 {
+                        x = previous_word_boundary(line, x);
+                    } else if y != 0 {
+                        y -= 1;
+                        x = self.rows[y].len();
+                    }
+                }
+                NavigationKey::WordRight => {
+                    if x < line.len() {
+                        x = next_word_boundary(line, x);
+                    } else if y + 1 < self.rows.len() {
+                        y += 1;
+                        x = 0;
+                    }
+                }
+                NavigationKey::Up => {
+                    if visual_row != 0 {
+                        x = rx_to_cx(line, (visual_row - 1) * width + visual_col, self.tab_size);
+                    } else if y != 0 {
+                        y -= 1;
+                        let above = &self.rows[y];
+                        let last_row = self.visual_rows(above) - 1;
+                        x = rx_to_cx(above, last_row * width + visual_col, self.tab_size);
+                    }
+                }
+                NavigationKey::Down => {
+                    if visual_row + 1 < self.visual_rows(line) {
+                        x = rx_to_cx(line, (visual_row + 1) * width + visual_col, self.tab_size);
+                    } else if y + 1 < self.rows.len() {
+                        y += 1;
+                        x = rx_to_cx(&self.rows[y], visual_col, self.tab_size);
+                    }
+                }
+                NavigationKey::ParagraphUp | NavigationKey::ParagraphDown => {
+                    y = paragraph_boundary(&self.rows, y, *ak == NavigationKey::ParagraphDown);
+                    x = split_point(&self.rows[y], x).unwrap_or_else(|x| x);
+                }
+                NavigationKey::Home => x = home_column(line, x),
+                NavigationKey::End => x = line.len(),
+                NavigationKey::PageUp => {
+                    y = self.row_offset;
+                    x = x.min(self.rows[y].len());
+                }
+                NavigationKey::PageDown => {
+                    // The last row that starts on screen
+                    let mut used = 0;
+                    y = self.row_offset;
+                    while y + 1 < self.rows.len() {
+                        used += self.visual_rows(&self.rows[y]);
+                        if used > self.term_rows + self.wrap_skip {
+                            break;
+                        }
+                        y += 1;
+                    }
+                    x = x.min(self.rows[y].len());
+                }
+            }
+        }
+
+        self.row_offset = self.row_offset.min(y);
+        self.cur_pos = CursorPosition {
+            x,
+            y: y - self.row_offset,
+        };
+        self.scroll_wrapped();
+    }
+
+    /// Advances `row_offset` until the cursor's screen row fits above the status bar
+    fn scroll_wrapped(&mut self) {
+        let y = self.row_offset + self.cur_pos.y;
+        if y != self.row_offset {
+            self.wrap_skip = 0;
+        }
+        while self.row_offset < y && self.screen_cursor().y > self.term_rows {
+            self.row_offset += 1;
+            self.cur_pos.y -= 1;
+        }
+
+        // A row taller than the window is scrolled through by screen rows instead
+        if self.row_offset == y {
+            let (visual_row, _) = self
+                .rows
+                .get(y)
+                .map_or((0, 0), |line| self.visual_position(line, self.cur_pos.x));
+            self.wrap_skip = self
+                .wrap_skip
+                .min(visual_row)
+                .max(visual_row.saturating_sub(self.term_rows));
+        }
+    }
+
+    fn toggle_wrap(&mut self) {
+        self.wrap = !self.wrap;
+        // The window starts at the left edge and scrolls as far as the cursor needs it to
+        self.col_offset = 0;
+        if self.wrap {
+            self.set_message("Soft wrap on");
+        } else {
+            self.scroll_horizontal();
+            self.set_message("Soft wrap off");
+        }
+    }
+
+    /// Width of a screen row in soft wrap mode
+    fn wrap_width(&self) -> usize {
+        self.layout(0, false).text.max(1)
+    }
+
+    /// How many screen rows a row takes up in soft wrap mode
+    fn visual_rows(&self, line: &str) -> usize {
+        let width = cx_to_rx(line, line.len(), self.tab_size);
+        width.saturating_sub(1) / self.wrap_width() + 1
+    }
+
+    /// Maps a position in a row to (screen row within the row, screen column) in soft wrap mode.
+    /// The end of a row that exactly fills its last screen row stays on that screen row.
+    fn visual_position(&self, line: &str, x: usize) -> (usize, usize) {
+        let width = self.wrap_width();
+        let rx = cx_to_rx(line, x, self.tab_size);
+        if rx != 0 && rx.is_multiple_of(width) && x == line.len() {
+            (rx / width - 1, width)
+        } else {
+            (rx / width, rx % width)
+        }
+    }
+
+    /// Where the cursor should be drawn on the terminal
+    fn screen_cursor(&self) -> CursorPosition {
+        if !self.wrap {
+            return CursorPosition {
+                x: self.rx() + self.left_marker_width(),
+                y: self.cur_pos.y,
+            };
+        }
+
+        let y = self.row_offset + self.cur_pos.y;
+        let rows_above: usize = self
+            .rows
+            .range(self.row_offset..y)
+            .map(|line| self.visual_rows(line))
+            .sum();
+        let (visual_row, visual_col) = self
+            .rows
+            .get(y)
+            .map_or((0, 0), |line| self.visual_position(line, self.cur_pos.x));
+
+        CursorPosition {
+            x: visual_col,
+            y: (rows_above + visual_row).saturating_sub(self.wrap_skip),
+        }
+    }
+
+    /// Open a file to edit/read, optionally placing the cursor at a 1-based line and column
+    fn open(
+        &mut self,
+        filename: impl AsRef<Path> + Clone,
+        position: Option<cli::Position>,
+    ) -> io::Result<()> {
+        if filename.as_ref().is_dir() {
+            let hidden = self.listing.as_ref().is_some_and(|listing| listing.hidden);
+            return self.browse(filename.as_ref().to_path_buf(), hidden, None);
+        }
+        if filename.as_ref().is_file() {
+            let writable = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&filename)
+                .ok();
+            // Still worth looking at, just not worth editing
+            let (file, readonly) = match writable {
+                Some(file) => (file, false),
+                None => (File::open(&filename)?, true),
+            };
+
+            // Nothing changes until the file has been read, one that can't be leaves the buffer be
+            let mut reader = BufReader::with_capacity(READ_BUFFER_SIZE, file.try_clone()?);
+            let start = reader.fill_buf()?;
+            if !self.force_text && looks_binary(&start[..start.len().min(BINARY_SAMPLE)]) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "it looks like a binary file, --force opens it as text",
+                ));
+            }
+            let size = file.metadata().map_or(0, |metadata| metadata.len());
+            let (text, loader) = if size > BACKGROUND_READ_SIZE {
+                let mut lines = LineReader::new(reader);
+                let rows = lines.read_rows(FIRST_ROWS)?;
+                // What the line endings are isn't known until the end, they're settled then
+                let text = ReadText {
+                    rows,
+                    line_ending: LineEnding::Lf,
+                    final_newline: true,
+                    lossy: false,
+                };
+                (text, Some(loader::Loader::start(lines, size)))
+            } else {
+                (read_lines(reader)?, None)
+            };
+
+            let path = filename.as_ref().to_string_lossy().into_owned();
+            // Reading the same file again, like a reload, leaves the cursor where it is
+            let other_file = self.path.as_ref() != Some(&path);
+            // The same file, even by another name, keeps the lock it has
+            let (lock, locked_out) = match &self.lock {
+                Some(lock) if lock.covers(filename.as_ref()) => (self.lock.take(), false),
+                _ if readonly => (None, false),
+                _ => self.claim(filename.as_ref(), &file)?,
+            };
+
+            // Read-only for being locked lasts as long as the file is open
+            self.readonly = readonly || locked_out || (self.readonly && !self.locked_out);
+            if readonly {
+                self.set_message("File isn't writable, opened read-only");
+            }
+            self.lock = lock;
+            self.locked_out = locked_out;
+            self.file = Some(file);
+            self.path = Some(path);
+            self.listing = None;
+            self.loader = loader;
+            if other_file {
+                self.filetype = filetype::detect(filename.as_ref());
+                let (tab_size, expand_tab) = self.config.indent(self.filetype);
+                self.tab_size = self.pinned.tab_size.unwrap_or(tab_size);
+                self.expand_tab = expand_tab;
+            }
+            self.rows = text.rows;
+            self.reset_baseline();
+            self.line_ending = text.line_ending;
+            self.final_newline = text.final_newline;
+            self.lossy = text.lossy;
+            if text.lossy {
+                self.set_message(
+                    "Not valid UTF-8, bad bytes are shown as \u{fffd} and saved that way",
+                );
+            }
+            self.disk_stamp = DiskStamp::read(filename.as_ref());
+
+            let remembered = state::load_file_state(filename.as_ref());
+            if let Some(remembered) = remembered {
+                self.restore_file_state(remembered);
+            }
+
+            if let Some(position) = position {
+                self.load_until(position.line + self.term_rows);
+                let column = match position.kind {
+                    cli::ColumnKind::Char => position.column,
+                    cli::ColumnKind::Render => {
+                        let y = position.line.saturating_sub(1);
+                        let line = self.rows.get(y).or(self.rows.last());
+                        let line = line.unwrap_or("");
+                        let x = rx_to_cx(line, position.column.saturating_sub(1), self.tab_size);
+                        line[..x].chars().count() + 1
+                    }
+                };
+                self.goto(position.line, column);
+            } else if other_file {
+                match remembered.and_then(|remembered| remembered.position) {
+                    Some(saved) if self.config.remember_position => self.restore_position(saved),
+                    _ => self.jump(CursorPosition::default()),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Locks the file at `path` for editing, asking what to do when another process has it. The
+    /// lock is None when it's edited anyway, and when it's opened read-only, which the flag says.
+    /// Choosing not to open it is an error.
+    fn claim(&mut self, path: &Path, file: &File) -> io::Result<(Option<lock::Lock>, bool)> {
+        let owner = match lock::holder(path, file) {
+            lock::Holder::Nobody => return Ok((lock::Lock::acquire(path, file).ok(), false)),
+            lock::Holder::Stale(owner) => {
+                self.set_message(&format!(
+                    "Ignored the lock left on {} by {owner}, it's not running anymore",
+                    path.display()
+                ));
+                return Ok((lock::Lock::acquire(path, file).ok(), false));
+            }
+            lock::Holder::Other(owner) => owner,
+        };
+
+        let who = owner.map_or_else(
+            || String::from("another program"),
+            |owner| owner.to_string(),
+        );
+        let question = format!(
+            "{} is being edited by {who}, open it anyway?",
+            path.display()
+        );
+        let choices = vec![
+            Choice {
+                key: 'r',
+                label: "read-only",
+            },
+            Choice {
+                key: 'e',
+                label: "edit anyway",
+            },
+            Choice {
+                key: 'a',
+                label: "abort",
+            },
+        ];
+        match self.confirm(&question, choices)? {
+            Some('r') => {
+                self.set_message(&format!("Opened read-only, {who} is editing it"));
+                Ok((None, true))
+            }
+            Some('e') => {
+                self.set_message(&format!(
+                    "Editing anyway, saving may overwrite what {who} saves"
+                ));
+                Ok((None, false))
+            }
+            _ => Err(Error::new(
+                ErrorKind::WouldBlock,
+                format!("it's being edited by {who}"),
+            )),
+        }
+    }
+
+    /// Lists the directory `dir` in the buffer, with the cursor on the entry for `select` if it's
+    /// there. A directory that can't be read leaves the buffer as it was.
+    fn browse(&mut self, dir: PathBuf, hidden: bool, select: Option<&Path>) -> io::Result<()> {
+        let listing = browser::Listing { dir, hidden };
+        let rows = listing.rows()?;
+        let y = select
+            .and_then(|select| browser::row_of(select, &rows))
+            .unwrap_or(0);
+
+        self.file = None;
+        self.path = None;
+        self.filetype = None;
+        self.rows = rows.into();
+        self.reset_baseline();
+        self.dirty_flag = false;
+        self.mark = None;
+        self.search = None;
+        self.listing = Some(listing);
+        self.loader = None;
+        self.lock = None;
+        self.locked_out = false;
+        self.col_offset = 0;
+        self.jump(CursorPosition { x: 0, y });
+        // Without a file the message stays up, as the help for the listing
+        self.set_message(if hidden {
+            "Enter opens, Backspace goes up, . hides dotfiles"
+        } else {
+            "Enter opens, Backspace goes up, . shows dotfiles"
+        });
+        Ok(())
+    }
+
+    /// Opens the file or the directory under the cursor in a listing
+    fn open_entry(&mut self) {
+        let Some(listing) = &self.listing else {
+            return;
+        };
+        let Some(row) = self.rows.get(self.row_offset + self.cur_pos.y) else {
+            return;
+        };
+        if row == browser::PARENT {
+            self.browse_parent();
+            return;
+        }
+
+        let path = listing.entry(row);
+        let said = self.message.time;
+        match self.open(&path, None) {
+            Err(err) => self.set_message(&format!("Error opening {}: {err}", path.display())),
+            Ok(()) if self.listing.is_none() && self.message.time == said => self.set_message(
+                &format!("Editing {}, {} lines", path.display(), self.rows.len()),
+            ),
+            Ok(()) => {}
+        }
+    }
+
+    /// Lists the directory above the one listed, with the cursor on the one that was
+    fn browse_parent(&mut self) {
+        let Some(listing) = &self.listing else {
+            return;
+        };
+        let (dir, parent, hidden) = (listing.dir.clone(), listing.parent(), listing.hidden);
+        if let Err(err) = self.browse(parent.clone(), hidden, Some(&dir)) {
+            self.set_message(&format!("Couldn't list {}: {err}", parent.display()));
+        }
+    }
+
+    /// Lists the dotfiles too, or stops listing them, keeping the cursor on its entry
+    fn toggle_dotfiles(&mut self) {
+        let Some(listing) = &self.listing else {
+            return;
+        };
+        let dir = listing.dir.clone();
+        let hidden = !listing.hidden;
+        let select = self
+            .rows
+            .get(self.row_offset + self.cur_pos.y)
+            .map(|row| listing.entry(row));
+        if let Err(err) = self.browse(dir.clone(), hidden, select.as_deref()) {
+            self.set_message(&format!("Couldn't list {}: {err}", dir.display()));
+        }
+    }
+
+    /// Puts the cursor and the window back where they were when the file was last left, as far
+    /// as the file still has the rows and columns for it
+    fn restore_position(&mut self, saved: state::SavedPosition) {
+        self.load_until(saved.y.max(saved.row_offset + self.term_rows) + 1);
+        let y = saved.y.min(self.rows.len().saturating_sub(1));
+        let row = self.rows.get(y).unwrap_or("");
+        let x = split_point(row, saved.x).unwrap_or_else(|x| x);
+
+        self.row_offset = saved.row_offset.min(y);
+        self.scroll_to_row(y);
+        self.col_offset = if self.wrap { 0 } else { saved.col_offset };
+        self.cur_pos.x = x;
+        self.scroll_horizontal();
+    }
+
+    /// Fills the buffer with text piped to stdin, it has no file until it's saved
+    fn open_piped(&mut self, rows: Vec<Row>) {
+        self.rows = rows.into();
+        self.reset_baseline();
+        self.set_message("Read from stdin, Ctrl-S saves it to a file");
+    }
+
+    /// Turns the editor into the scratch buffer, restoring the last session's notes if they're kept
+    fn open_scratch(&mut self) {
+        if self.config.persist_scratch {
+            self.rows = state::load_scratch();
+            self.reset_baseline();
+        }
+        self.ephemeral = true;
+        self.set_message("Scratch buffer, Ctrl-S saves it to a file");
+    }
+
+    /// Takes the rows as they are now as the ones change marks compare against
+    fn reset_baseline(&mut self) {
+        self.baseline = changes::Baseline::new(&self.rows);
+        self.changed_rows = None;
+        self.blocks = None;
+        self.checkpoints = None;
+    }
+
+    /// Takes checkpoints along the cursor's row if it's long, unless they're still good. Drawing
+    /// it then only goes over the part of the row on screen, and a bit before it.
+    fn refresh_checkpoints(&mut self) {
+        let y = self.row_offset + self.cur_pos.y;
+        let key = self.column_key(y);
+        if self.checkpoints.as_ref().map(|(at, _)| *at) == Some(key) {
+            return;
+        }
+        self.checkpoints = self
+            .rows
+            .get(y)
+            .filter(|row| row.len() >= columns::LONG_ROW)
+            .map(|row| (key, columns::Checkpoints::new(row, self.tab_size)));
+    }
+
+    /// What checkpoints taken of row `y` now are good for: the same row, unedited, with the same
+    /// tab size
+    fn column_key(&self, y: usize) -> ColumnKey {
+        let len = self.rows.get(y).map_or(0, str::len);
+        (self.edits, y, len, self.tab_size)
+    }
+
+    /// `cx_to_rx` for row `y`, from its checkpoints when it has them
+    fn row_rx(&self, y: usize, cx: usize) -> usize {
+        let row = self.rows.get(y).unwrap_or("");
+        match &self.checkpoints {
+            Some((key, points)) if *key == self.column_key(y) => {
+                points.cx_to_rx(row, cx, self.tab_size)
+            }
+            _ => cx_to_rx(row, cx, self.tab_size),
+        }
+    }
+
+    /// `rx_to_cx` for row `y`, from its checkpoints when it has them
+    fn row_cx(&self, y: usize, rx: usize) -> usize {
+        let row = self.rows.get(y).unwrap_or("");
+        match &self.checkpoints {
+            Some((key, points)) if *key == self.column_key(y) => {
+                points.rx_to_cx(row, rx, self.tab_size)
+            }
+            _ => rx_to_cx(row, rx, self.tab_size),
+        }
+    }
+
+    /// Works out which rows changed since the baseline, unless nothing was edited since last time
+    fn refresh_changed_rows(&mut self) {
+        if self.changed_rows.as_ref().map(|(edits, _)| *edits) != Some(self.edits) {
+            self.changed_rows = Some((self.edits, self.baseline.changed(&self.rows)));
+        }
+    }
+
+    /// The highlighting rules for the open file, if its filetype has any
+    fn syntax(&self) -> Option<&'static highlight::Syntax> {
+        self.filetype
+            .and_then(|filetype| highlight::for_filetype(filetype.name))
+    }
+
+    /// Works out which block each row down to the bottom of the window starts in. Rows worked out
+    /// before are kept until an edit, any of which can open or close a block above the rest.
+    fn refresh_blocks(&mut self) {
+        let Some(syntax) = self.syntax().filter(|syntax| syntax.has_blocks()) else {
+            return;
+        };
+        if self.blocks.as_ref().map(|(edits, _)| *edits) != Some(self.edits) {
+            self.blocks = Some((self.edits, vec![None]));
+        }
+        let Some((_, blocks)) = &mut self.blocks else {
+            return;
+        };
+        let last = (self.row_offset + self.term_rows).min(self.rows.len());
+        while let Some(row) = self
+            .rows
+            .get(blocks.len() - 1)
+            .filter(|_| blocks.len() <= last)
+        {
+            let block = syntax.block_after(row, blocks[blocks.len() - 1]);
+            blocks.push(block);
+        }
+    }
+
+    fn is_changed(&self, idx: usize) -> bool {
+        self.changed_rows
+            .as_ref()
+            .is_some_and(|(_, changed)| changed.get(idx) == Some(&true))
+    }
+
+    /// Moves to the first row of the next run of changed rows, or the previous one going back,
+    /// wrapping around at the end of the buffer
+    fn goto_change(&mut self, forward: bool) {
+        self.refresh_changed_rows();
+        let y = self.row_offset + self.cur_pos.y;
+        // The first row of each run of changed rows
+        let starts: Vec<usize> = (0..self.rows.len())
+            .filter(|idx| self.is_changed(*idx) && (*idx == 0 || !self.is_changed(idx - 1)))
+            .collect();
+
+        let found = if forward {
+            starts.iter().find(|start| **start > y)
+        } else {
+            starts.iter().rev().find(|start| **start < y)
+        };
+        let around = if forward {
+            starts.first()
+        } else {
+            starts.last()
+        };
+        let Some(&start) = found.or(around) else {
+            self.set_message("No changes since the file was last saved");
+            return;
+        };
+
+        self.jump(CursorPosition { x: 0, y: start });
+        if found.is_none() {
+            self.set_message(if forward {
+                "Wrapped around to the first change"
+            } else {
+                "Wrapped around to the last change"
+            });
+        }
+    }
+
+    /// How the window's columns are shared out with it scrolled to `col_offset`, for a row going
+    /// on past its right edge when `continues`
+    fn layout(&self, col_offset: usize, continues: bool) -> Layout {
+        compute_layout(
+            self.term_cols + 1,
+            self.config.change_marks,
+            self.config.clip_markers,
+            self.wrap,
+            col_offset > 0,
+            continues,
+        )
+    }
+
+    /// Columns the gutter takes left of the text, it's there while change marks are on
+    fn gutter_width(&self) -> usize {
+        self.layout(0, false).gutter
+    }
+
+    /// Puts the gutter in front of a screen row, marked when the buffer row `idx` it shows
+    /// changed since the file was last read or saved
+    fn with_gutter(&self, line: Line, idx: Option<usize>) -> Line {
+        let width = self.gutter_width();
+        if width == 0 {
+            return line;
+        }
+
+        let changed = idx.is_some_and(|idx| self.is_changed(idx));
+        let mut text = vec![if changed { CHANGE_MARK } else { ' ' }; width];
+        text.extend(line.text);
+        let mut spans = Vec::new();
+        if changed {
+            spans.push(Span {
+                start: 0,
+                end: width,
+                style: Style::ChangeMark,
+            });
+        }
+        spans.extend(line.spans.into_iter().map(|span| Span {
+            start: span.start + width,
+            end: span.end + width,
+            ..span
+        }));
+        let combining = line
+            .combining
+            .into_iter()
+            .map(|(column, c)| (column + width, c))
+            .collect();
+        Line {
+            text,
+            spans,
+            combining,
+        }
+    }
+
+    /// Remembers the view options changed away from the config for the open file,
+    /// options that came from the command line aren't the file's and aren't remembered
+    fn remember_file_state(&self) {
+        if let Some(path) = &self.path {
+            let (tab_size, expand_tab) = self.config.indent(self.filetype);
+            let changed = FileState {
+                wrap: (self.pinned.wrap.is_none() && self.wrap != self.config.wrap)
+                    .then_some(self.wrap),
+                tab_size: (self.pinned.tab_size.is_none() && self.tab_size != tab_size)
+                    .then_some(self.tab_size),
+                expand_tab: (self.expand_tab != expand_tab).then_some(self.expand_tab),
+                show_invisibles: (self.show_invisibles != self.config.show_invisibles)
+                    .then_some(self.show_invisibles),
+                filetype: self
+                    .filetype
+                    .filter(|filetype| Some(*filetype) != filetype::detect(Path::new(path)))
+                    .map(|filetype| filetype.name),
+                position: self.config.remember_position.then(|| state::SavedPosition {
+                    y: self.row_offset + self.cur_pos.y,
+                    x: self.cur_pos.x,
+                    row_offset: self.row_offset,
+                    col_offset: self.col_offset,
+                }),
+            };
+            // Failing to remember is never worth interrupting the user for
+            let _ = state::store_file_state(Path::new(path), changed);
+        }
+    }
+
+    /// Sets the view options remembered for the open file, unless the command line set them
+    fn restore_file_state(&mut self, remembered: FileState) {
+        let view = self.pinned.or(remembered);
+        if let Some(filetype) = view.filetype.and_then(filetype::named) {
+            self.set_filetype(Some(filetype));
+        }
+        self.wrap = view.wrap.unwrap_or(self.wrap);
+        self.show_invisibles = view.show_invisibles.unwrap_or(self.show_invisibles);
+        self.tab_size = view.tab_size.unwrap_or(self.tab_size);
+        self.expand_tab = view.expand_tab.unwrap_or(self.expand_tab);
+    }
+
+    /// Makes the buffer one of `filetype`, taking its indentation unless the tab size or tabs
+    /// were changed from the old filetype's, or the tab size was given on the command line
+    fn set_filetype(&mut self, filetype: Option<&'static filetype::Filetype>) {
+        let (old_tab_size, old_expand_tab) = self.config.indent(self.filetype);
+        self.filetype = filetype;
+        self.blocks = None;
+        let (tab_size, expand_tab) = self.config.indent(self.filetype);
+        if self.pinned.tab_size.is_none() && self.tab_size == old_tab_size {
+            self.tab_size = tab_size;
+        }
+        if self.expand_tab == old_expand_tab {
+            self.expand_tab = expand_tab;
+        }
+    }
+
+    /// Puts the view options back to the ones from the config and command line, so the open file
+    /// has none of its own left to remember. Where the cursor was is still remembered.
+    fn forget_file_options(&mut self) {
+        let Some(path) = self.path.clone() else {
+            self.set_message("No file to forget the options of");
+            return;
+        };
+        if self.wrap != self.pinned.wrap.unwrap_or(self.config.wrap) {
+            self.toggle_wrap();
+        }
+        self.show_invisibles = self.config.show_invisibles;
+        self.filetype = filetype::detect(Path::new(&path));
+        self.blocks = None;
+        let (tab_size, expand_tab) = self.config.indent(self.filetype);
+        self.tab_size = self.pinned.tab_size.unwrap_or(tab_size);
+        self.expand_tab = expand_tab;
+        self.remember_file_state();
+        self.set_message("View options for this file forgotten");
+    }
+
+    /// Runs on quit, keeping the scratch buffer and the files' state for next time, the other
+    /// pane's buffer's too
+    fn remember_buffers(&mut self) {
+        self.persist_scratch();
+        self.remember_file_state();
+        if self
+            .split
+            .as_ref()
+            .is_some_and(|split| split.buffer.is_some())
+        {
+            self.switch_pane();
+            self.persist_scratch();
+            self.remember_file_state();
+        }
+    }
+
+    /// Runs on quit, keeping the scratch buffer around for next time
+    fn persist_scratch(&self) {
+        if self.ephemeral && self.config.persist_scratch {
+            // There's nowhere left to report a failure, the editor is exiting
+            let _ = state::store_scratch(&self.rows);
+        }
+    }
+
+    /// Writes the buffer out, returning how many lines were trimmed on the way.
+    /// `None` when the user backed out of saving, the status bar already says why.
+    fn save(&mut self) -> io::Result<Option<usize>> {
+        if self.locked_out && !self.reclaim() {
+            return Ok(None);
+        }
+        if self.readonly {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                "Buffer is read-only",
+            ));
+        }
+        if self.loader.is_some() {
+            return Err(Error::new(
+                ErrorKind::WouldBlock,
+                "the file is still being read",
+            ));
+        }
+
+        if !self.confirm_overwrite()? {
+            return Ok(None);
+        }
+        if !self.confirm_lossy_save() {
+            self.set_message("Save cancelled");
+            return Ok(None);
+        }
+
+        if let Err(err) = self.write_backup() {
+            let question = format!("Couldn't write a backup ({err}), save anyway?");
+            let choices = vec![
+                Choice {
+                    key: 'y',
+                    label: "save without a backup",
+                },
+                Choice {
+                    key: 'n',
+                    label: "don't save",
+                },
+            ];
+            if !matches!(self.confirm(&question, choices), Ok(Some('y'))) {
+                self.set_message("Save cancelled, no backup was written");
+                return Ok(None);
+            }
+        }
+
+        let mut trimmed = 0;
+        if self.trim_on_save && self.file.is_some() {
+            trimmed = self.trim_trailing_whitespace();
+        }
+
+        // TODO: Move all system message handeling from main loop to this function
+        if let (Some(file), Some(path)) = (&self.file, self.path.clone()) {
+            let (file, content) = (file.try_clone()?, self.contents());
+            let (sender, receiver) = mpsc::channel();
+            let target = path.clone();
+            thread::spawn(move || {
+                let _ = sender.send(write_atomically(Path::new(&target), &file, &content));
+            });
+            // Saving leaves the buffer as it is, what's typed meanwhile is handled once it's written
+            let thawed = self.freeze("Saving", Freeze::Replayed, |wait| {
+                match receiver.recv_timeout(wait) {
+                    Ok(written) => Some(written),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        Some(Err(Error::other("the saving thread stopped")))
+                    }
+                }
+            });
+            let written = thawed
+                .result
+                .unwrap_or_else(|| Err(Error::other("the save was cancelled")));
+            // The old handle points at the replaced inode, later saves need the new one
+            self.file = Some(written?);
+            if let (Some(lock), Some(file)) = (&mut self.lock, &self.file) {
+                lock.follow(file);
+            }
+            self.disk_stamp = DiskStamp::read(Path::new(&path));
+            self.dirty_flag = false;
+            self.reset_baseline();
+        } else {
+            let input = self.path_prompt("Save to: ").unwrap_or_default();
+            let input = input.trim();
+            if input.is_empty() {
+                self.set_message("Save cancelled");
+                return Ok(None);
+            }
+            // The same as saving as, asking before replacing a file and not touching it until then
+            return Ok(self.write_to_path(input, true).then_some(trimmed));
+        }
+
+        Ok(Some(trimmed))
+    }
+
+    /// Checks again whether the file opened read-only because it was being edited still is, and
+    /// takes the lock and makes it editable when it's been let go. False, saying why, while it's
+    /// still held.
+    fn reclaim(&mut self) -> bool {
+        let (Some(file), Some(path)) = (&self.file, self.path.clone()) else {
+            return true;
+        };
+        let owner = match lock::holder(Path::new(&path), file) {
+            lock::Holder::Other(owner) => owner,
+            lock::Holder::Nobody | lock::Holder::Stale(_) => {
+                self.lock = lock::Lock::acquire(Path::new(&path), file).ok();
+                self.locked_out = false;
+                self.readonly = false;
+                return true;
+            }
+        };
+        let who = owner.map_or_else(
+            || String::from("another program"),
+            |owner| owner.to_string(),
+        );
+        self.set_message(&format!(
+            "Not saved, {path} is still being edited by {who}, it's open read-only"
+        ));
+        false
+    }
+
+    /// The buffer as it's written to a file, with the file's line endings
+    fn contents(&self) -> Vec<u8> {
+        let mut content = Vec::new();
+        let newline = self.line_ending.as_str();
+        for (idx, row) in self.rows.iter().enumerate() {
+            content.extend(row.as_bytes());
+            if idx + 1 < self.rows.len() || self.final_newline {
+                content.extend(newline.as_bytes());
+            }
+        }
+        content
+    }
+
+    /// Asks for a path and writes the buffer there, confirming before replacing a file that's
+    /// already there. With `switch` the buffer becomes that file, otherwise what's being edited
+    /// stays the same and the copy is left alone. Every outcome is reported in the status bar.
+    fn write_to(&mut self, switch: bool) {
+        let prefix = if switch { "Save as:" } else { "Write to:" };
+        let Ok(input) = self.path_prompt(prefix) else {
+            self.set_message("Write cancelled");
+            return;
+        };
+        let input = input.trim();
+        if input.is_empty() {
+            self.set_message("Write cancelled");
+            return;
+        }
+        self.write_to_path(input, switch);
+    }
+
+    /// Writes the buffer to `input`, the way `write_to` does once it has the path. False when
+    /// nothing was written.
+    fn write_to_path(&mut self, input: &str, switch: bool) -> bool {
+        let path = Path::new(input);
+
+        if path.is_dir() {
+            self.set_message(&format!("Error writing {input}: it's a directory"));
+            return false;
+        }
+        // The flock this editor holds on its own file looks like anyone else's through another handle
+        let own = self.lock.as_ref().is_some_and(|lock| lock.covers(path));
+        if let Some(existing) = File::open(path).ok().filter(|_| !own) {
+            if let lock::Holder::Other(owner) = lock::holder(path, &existing) {
+                let who = owner.map_or_else(
+                    || String::from("another program"),
+                    |owner| owner.to_string(),
+                );
+                self.set_message(&format!("Not written, {input} is being edited by {who}"));
+                return false;
+            }
+        }
+        if path.exists() {
+            let question = format!("{input} already exists, overwrite it?");
+            let choices = vec![
+                Choice {
+                    key: 'y',
+                    label: "overwrite",
+                },
+                Choice {
+                    key: 'n',
+                    label: "cancel",
+                },
+            ];
+            if !matches!(self.confirm(&question, choices), Ok(Some('y'))) {
+                self.set_message("Write cancelled");
+                return false;
+            }
+        }
+
+        let file = match write_new_or_replace(path, &self.contents()) {
+            Ok(file) => file,
+            Err(err) => {
+                self.set_message(&format!("Error writing {input}: {err}"));
+                return false;
+            }
+        };
+
+        let lines = self.rows.len();
+        if own {
+            // Written over the file being edited, which is saving it under another name
+            if let Some(lock) = &mut self.lock {
+                lock.follow(&file);
+            }
+            self.file = Some(file);
+            self.disk_stamp = DiskStamp::read(path);
+            self.dirty_flag = false;
+            self.reset_baseline();
+            self.set_message(&format!("Wrote {lines} lines to {input}"));
+        } else if switch {
+            self.lock = lock::Lock::acquire(path, &file).ok();
+            // Read-only for being locked out of the old file doesn't carry over to this one
+            self.readonly &= !self.locked_out;
+            self.locked_out = false;
+            self.file = Some(file);
+            self.path = Some(String::from(input));
+            self.listing = None;
+            self.set_filetype(filetype::detect(path));
+            self.disk_stamp = DiskStamp::read(path);
+            self.dirty_flag = false;
+            self.reset_baseline();
+            self.ephemeral = false;
+            self.remember_file_state();
+            self.set_message(&format!("Saved {lines} lines as {input}, now editing it"));
+        } else {
+            self.set_message(&format!("Wrote {lines} lines to {input}"));
+        }
+        true
+    }
+
+    /// Asks before saving a file that wasn't valid UTF-8, which replaces its bad bytes for good.
+    /// False unless saving should go on.
+    fn confirm_lossy_save(&mut self) -> bool {
+        if !self.lossy {
+            return true;
+        }
+
+        let question = "The file wasn't valid UTF-8, save it with the bad bytes replaced?";
+        let choices = vec![
+            Choice {
+                key: 'y',
+                label: "save as UTF-8",
+            },
+            Choice {
+                key: 'n',
+                label: "don't save",
+            },
+        ];
+        // Asked once, the file on disk is UTF-8 from then on
+        self.lossy = !matches!(self.confirm(question, choices), Ok(Some('y')));
+        !self.lossy
+    }
+
+    /// Checks the file hasn't changed on disk since it was read, and if it has asks whether to
+    /// overwrite it, reload it and drop the edits, or not save. False unless saving should go on.
+    fn confirm_overwrite(&mut self) -> io::Result<bool> {
+        let (Some(stamp), Some(path)) = (self.disk_stamp, self.path.clone()) else {
+            return Ok(true);
+        };
+        let current = DiskStamp::read(Path::new(&path));
+        if current == Some(stamp) {
+            return Ok(true);
+        }
+
+        let (question, choices) = if current.is_some() {
+            let question = format!("{path} was changed by another program, overwrite it?");
+            let choices = vec![
+                Choice {
+                    key: 'o',
+                    label: "overwrite",
+                },
+                Choice {
+                    key: 'r',
+                    label: "reload, dropping your changes",
+                },
+                Choice {
+                    key: 'c',
+                    label: "cancel",
+                },
+            ];
+            (question, choices)
+        } else {
+            let question = format!("{path} was deleted by another program, save it again?");
+            let choices = vec![
+                Choice {
+                    key: 'o',
+                    label: "recreate it",
+                },
+                Choice {
+                    key: 'c',
+                    label: "cancel",
+                },
+            ];
+            (question, choices)
+        };
+
+        match self.confirm(&question, choices) {
+            Ok(Some('o')) => Ok(true),
+            Ok(Some('r')) => {
+                self.reload()?;
+                self.set_message("Reloaded from disk, your changes were dropped");
+                Ok(false)
+            }
+            _ => {
+                self.set_message("Save cancelled");
+                Ok(false)
+            }
+        }
+    }
+
+    /// Reads the file again, throwing away the buffer. The cursor stays where it was, as far as the
+    /// new text allows.
+    fn reload(&mut self) -> io::Result<()> {
+        let Some(path) = self.path.clone() else {
+            return Ok(());
+        };
+        if !Path::new(&path).is_file() {
+            return Err(Error::new(ErrorKind::NotFound, "the file is gone"));
+        }
+
+        self.open(&path, None)?;
+        self.dirty_flag = false;
+        self.mark = None;
+        self.search = None;
+        self.clamp_cursor();
+        Ok(())
+    }
+
+    /// Throws the edits away and reads the file from disk again, asking first when there are
+    /// edits to lose. A file that's gone leaves the buffer as it is.
+    fn revert(&mut self) {
+        let Some(path) = self.path.clone() else {
+            self.set_message("There's no file to revert to");
+            return;
+        };
+        if self.dirty_flag {
+            let question = format!("Drop the changes to {path} and read it from disk again?");
+            let choices = vec![
+                Choice {
+                    key: 'y',
+                    label: "revert",
+                },
+                Choice {
+                    key: 'n',
+                    label: "keep editing",
+                },
+            ];
+            if !matches!(self.confirm(&question, choices), Ok(Some('y'))) {
+                return;
+            }
+        }
+
+        match self.reload() {
+            Ok(()) => self.set_message(&format!("Reverted to {path} as it is on disk")),
+            Err(err) => self.set_message(&format!("Error reverting {path}: {err}")),
+        }
+    }
+
+    /// Saves for the Save key, reporting how it went in the status bar
+    fn save_and_report(&mut self) {
+        // The scratch buffer is never dirty, but saving it is how it becomes a file
+        if !self.dirty_flag && !self.ephemeral {
+            self.set_message("No Changes Made!");
+            return;
+        }
+
+        let message = match self.save() {
+            Ok(Some(0)) => String::from("File saved successfully!"),
+            Ok(Some(trimmed)) => {
+                format!("File saved, trailing whitespace trimmed from {trimmed} lines")
+            }
+            // Backed out, save already said so
+            Ok(None) => return,
+            Err(err) => {
+                self.set_message(&format!("Error saving file: {err}"));
+                return;
+            }
+        };
+        self.remember_file_state();
+        self.set_message(&message);
+    }
+
+    /// Copies the file as it is on disk to its backup, when backups are on. A file with nothing in
+    /// it yet has nothing worth keeping.
+    fn write_backup(&self) -> io::Result<()> {
+        let (true, Some(file), Some(path)) = (self.backup, &self.file, &self.path) else {
+            return Ok(());
+        };
+        // Deleted by another program and about to be recreated, there's nothing left to keep
+        if file.metadata()?.len() == 0 || !Path::new(path).exists() {
+            return Ok(());
+        }
+
+        let path = fs::canonicalize(path)?;
+        let backup = backup_path(
+            &path,
+            &self.config.backup_suffix,
+            self.config.backup_dir.as_deref(),
+        )?;
+        fs::copy(&path, backup)?;
+        Ok(())
+    }
+
+    /// Strips trailing spaces and tabs from every row and drops blank rows at the end of the file,
+    /// returning how many lines changed. The cursor is pulled back if what it sat on is gone.
+    fn trim_trailing_whitespace(&mut self) -> usize {
+        let mut trimmed = self.rows.rewrite(0..self.rows.len(), |row| {
+            let trimmed = row.trim_end_matches([' ', '\t']);
+            (trimmed.len() != row.len()).then(|| trimmed.to_string())
+        });
+        while self.rows.len() > 1 && self.rows.last().is_some_and(str::is_empty) {
+            self.rows.pop();
+            trimmed += 1;
+        }
+
+        self.clamp_cursor();
+        trimmed
+    }
+
+    /// Pulls the cursor back inside the buffer after rows were removed or shortened under it
+    fn clamp_cursor(&mut self) {
+        let y = (self.row_offset + self.cur_pos.y).min(self.rows.len().saturating_sub(1));
+        self.scroll_to_row(y);
+        let row = self.rows.get(y).unwrap_or("");
+        self.cur_pos.x = split_point(row, self.cur_pos.x).unwrap_or_else(|x| x);
+        self.scroll_horizontal();
+    }
+
+    /// Switches the buffer between LF and CRLF line endings, mixed endings are made uniform LF
+    fn convert_line_endings(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+
+        self.line_ending = match self.line_ending {
+            LineEnding::Lf => LineEnding::Crlf,
+            LineEnding::Crlf => LineEnding::Lf,
+            LineEnding::Mixed => {
+                self.rows.rewrite(0..self.rows.len(), |row| {
+                    row.strip_suffix('\r').map(String::from)
+                });
+                LineEnding::Lf
+            }
+        };
+        self.mark_edited();
+        let message = format!("Line endings converted to {}", self.line_ending.name());
+        self.set_message(&message);
+    }
+
+    fn toggle_invisibles(&mut self) {
+        self.show_invisibles = !self.show_invisibles;
+        self.set_message(if self.show_invisibles {
+            "Showing tabs, trailing spaces and line ends"
+        } else {
+            "Hiding tabs, trailing spaces and line ends"
+        });
+    }
+
+    fn cycle_search_case(&mut self) {
+        self.search_case = self.search_case.next();
+        self.set_message(self.search_case.describe());
+    }
+
+    fn toggle_trim_on_save(&mut self) {
+        self.trim_on_save = !self.trim_on_save;
+        self.set_message(if self.trim_on_save {
+            "Trailing whitespace is trimmed on save"
+        } else {
+            "Trailing whitespace is kept on save"
+        });
+    }
+
+    /// Searches from the cursor on, wrapping around to the top when nothing is below it
+    fn find(&mut self) -> io::Result<CursorPosition> {
+        self.search = None;
+        let Ok(query) = self.search_prompt() else {
+            return Err(Error::other("Find: no search term was found"));
+        };
+        let Some(((y, x), wrapped)) = self.nearest_match(&query, true, true) else {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("Pattern not found: {query}"),
+            ));
+        };
+
+        if wrapped {
+            self.set_message("Search wrapped");
+        }
+        self.last_query = Some(query.clone());
+        self.search = Some(Search {
+            query,
+            current: (y, x),
+        });
+        Ok(CursorPosition { x, y })
+    }
+
+    /// Asks for a line, or a line and a column as `LINE:COLUMN`, and goes there
+    fn goto_line(&mut self) {
+        let Ok(input) = self.prompt("Go to line:") else {
+            return;
+        };
+        let (line, column) = input.trim().split_once(':').unwrap_or((input.trim(), "1"));
+        match (line.parse(), column.parse()) {
+            (Ok(line), Ok(column)) => self.goto(line, column),
+            _ => self.set_message(&format!("Not a line number: {}", input.trim())),
+        }
+    }
+
+    /// Moves to the match of the last search after the cursor, or before it going back. Past the
+    /// last match it wraps around to the first, and past the first to the last.
+    fn find_next(&mut self, forward: bool) {
+        let Some(query) = self.last_query.clone() else {
+            self.set_message("Nothing was searched for yet");
+            return;
+        };
+        let Some(((y, x), wrapped)) = self.nearest_match(&query, forward, false) else {
+            self.set_message(&format!("Pattern not found: {query}"));
+            return;
+        };
+
+        self.jump(CursorPosition { x, y });
+        if wrapped {
+            self.set_message("Search wrapped");
+        }
+        self.search = Some(Search {
+            query,
+            current: (y, x),
+        });
+    }
+
+    /// The first match of `query` after the cursor, or the last one before it going back, as a
+    /// row and index and whether the ends of the buffer were wrapped around to reach it. A match
+    /// right at the cursor only counts going forward with `here`. Each match is looked at once, so
+    /// a query found only at the cursor comes back to it rather than searching forever.
+    fn nearest_match(
+        &self,
+        query: &str,
+        forward: bool,
+        here: bool,
+    ) -> Option<((usize, usize), bool)> {
+        let cursor = (self.row_offset + self.cur_pos.y, self.cur_pos.x);
+        let case = self.search_case;
+        let matches: Vec<(usize, usize)> = self
+            .rows
+            .iter()
+            .enumerate()
+            .flat_map(|(y, row)| {
+                search::matches(row, query, case)
+                    .into_iter()
+                    .map(move |found| (y, found.start))
+            })
+            .collect();
+
+        let found = if forward {
+            matches
+                .iter()
+                .find(|found| **found > cursor || (here && **found == cursor))
+        } else {
+            matches.iter().rev().find(|found| **found < cursor)
+        };
+        let around = if forward {
+            matches.first()
+        } else {
+            matches.last()
+        };
+        found
+            .map(|found| (*found, false))
+            .or_else(|| around.map(|found| (*found, true)))
+    }
+
+    /// Draws out the current state held in the editor to the terminal
+    fn draw(&mut self) {
+        if matches!(self.refresh_size(), Geometry::Unavailable) {
+            return;
+        }
+
+        let frame = self.compose_frame();
+        self.screen.set_view((self.row_offset, self.col_offset));
+        let changed = frame.render(&self.theme(), &mut self.screen);
+        if changed.is_empty() {
+            send_esc_seq(CtrlSeq::MoveCursor(frame.cursor));
+            return;
+        }
+
+        send_esc_seq(CtrlSeq::HideCursor);
+        stdout_write(changed);
+        send_esc_seq(CtrlSeq::MoveCursor(frame.cursor));
+        send_esc_seq(CtrlSeq::ShowCursor);
+    }
+
+    /// Stacks the layers making up the screen: the text rows and the status bar under them
+    fn compose_frame(&mut self) -> Frame {
+        let (mut lines, mut cursor) = self.pane_layer(true);
+        if self.shown_split().is_some_and(|split| split.top_active) {
+            // The other pane is drawn by switching to it for a moment
+            self.switch_pane();
+            let (below, _) = self.pane_layer(false);
+            self.switch_pane();
+            lines.extend(below);
+        } else if self.shown_split().is_some() {
+            self.switch_pane();
+            let (mut above, _) = self.pane_layer(false);
+            self.switch_pane();
+            cursor.y += above.len();
+            above.extend(lines);
+            lines = above;
+        }
+
+        if let Some(confirm) = &self.confirm {
+            let covering = confirm.lines(self.term_cols);
+            let top = lines.len().saturating_sub(covering.len());
+            cursor = CursorPosition {
+                x: covering[0].text.len().min(self.term_cols),
+                y: top,
+            };
+            lines.truncate(top);
+            lines.extend(covering);
+        } else if let Some(overlay) = &self.overlay {
+            let top = lines.len().saturating_sub(overlay.len());
+            lines.truncate(top);
+            lines.extend(overlay.iter().cloned());
+            cursor = CursorPosition {
+                x: lines.last().map_or(0, |line| line.text.len()),
+                y: lines.len() - 1,
+            };
+        }
+
+        // A window of one row only has room for the status bar
+        let extra = lines.len().saturating_sub(self.text_height + 1);
+        lines.drain(..extra);
+        cursor.y = cursor.y.saturating_sub(extra);
+
+        Frame { lines, cursor }
+    }
+
+    /// The pane being edited: its text rows with its status line under them, and where its cursor
+    /// goes relative to the pane's top
+    fn pane_layer(&mut self, active: bool) -> (Vec<Line>, CursorPosition) {
+        if self.config.change_marks {
+            self.refresh_changed_rows();
+        }
+        self.refresh_checkpoints();
+        self.refresh_blocks();
+        let mut lines = if self.wrap {
+            self.scroll_wrapped();
+            self.wrapped_text_layer()
+        } else {
+            self.scroll_horizontal();
+            self.text_layer()
+        };
+        if self.rows.is_empty() && self.path.is_none() && !self.ephemeral && self.listing.is_none()
+        {
+            self.welcome_layer(&mut lines);
+        }
+        lines.push(self.status_bar_layer(active));
+
+        let text_cursor = self.screen_cursor();
+        let layout = self.layout(self.col_offset, false);
+        debug_assert!(
+            text_cursor.y <= self.term_rows && text_cursor.x <= layout.last_column(),
+            "cursor at {text_cursor:?} is outside the {}x{} text area",
+            layout.last_column() + 1,
+            self.term_rows + 1,
+        );
+        // Only a question or a list drawn over the status bar puts the cursor on it
+        let cursor = CursorPosition {
+            x: text_cursor.x.min(layout.last_column()) + layout.gutter,
+            y: text_cursor.y.min(self.term_rows),
+        };
+        (lines, cursor)
+    }
+
+    /// Puts a banner a third of the way down the empty rows of a fresh editor, with a hint of what
+    /// to do under it. Typing anything or opening a file makes the rows non-empty and it's gone.
+    fn welcome_layer(&self, lines: &mut [Line]) {
+        let key = |action| {
+            self.key_name(action)
+                .unwrap_or_else(|| String::from(describe(action)))
+        };
+        let banner = format!(
+            "rilo - a tiny editor - version {}",
+            env!("CARGO_PKG_VERSION")
+        );
+        let hint = format!(
+            "{} saves, {} quits, F1 lists the keys",
+            key(Action::Save),
+            key(Action::Quit)
+        );
+
+        let top = lines.len() / 3;
+        for (idx, text) in [banner, hint].iter().enumerate() {
+            let Some(line) = lines.get_mut(top + idx * 2) else {
+                break;
+            };
+            // The '~' stays in the first column like on every other empty row
+            let padding = self.term_cols.saturating_sub(text.len()) / 2;
+            let gutter = " ".repeat(self.gutter_width());
+            let mut centered = format!("{gutter}~{}{text}", " ".repeat(padding.saturating_sub(1)));
+            centered.truncate(self.term_cols);
+            line.text = centered.chars().collect();
+        }
+    }
+
+    /// The visible part of each row on screen, rows past the end of the file are drawn as '~'. With
+    /// clip markers on, a '<' in the first column and a '>' in the last tell a row goes on past the
+    /// window, the text is drawn between them.
+    fn text_layer(&self) -> Vec<Line> {
+        let brackets = self.bracket_cells();
+        let layout = self.layout(self.col_offset, false);
+        let width = layout.text;
+        (self.row_offset..=self.term_rows + self.row_offset)
+            .map(|idx| match self.rows.get(idx) {
+                Some(line) => {
+                    // Only the row from the char at the window's left edge is rendered, up to a
+                    // column past the screen so the end mark knows if it fits. However far into a
+                    // long row the window is scrolled, that's a screen's worth of work after
+                    // finding the edge.
+                    let edge = self.col_offset;
+                    let from = self.row_cx(idx, edge);
+                    let start = self.row_rx(idx, from);
+                    // Columns of a tab or wide char the edge cuts through, left of the window
+                    let cut = edge.saturating_sub(start);
+                    let (mut rendered, _) = render_row(
+                        &line[from..],
+                        self.tab_size,
+                        self.show_invisibles,
+                        start,
+                        cut + width + 1,
+                    );
+                    let row_layout = self.layout(edge, rendered.text.len() > cut + width);
+                    let clipped = row_layout.right_marker > 0;
+                    let end = rendered.text.len().min(cut + row_layout.text);
+                    let mut shown = rendered.columns(cut, end);
+                    // The line end mark takes the column the cursor has at the end of the row
+                    if self.show_invisibles && end == rendered.text.len() {
+                        let mark = eol_mark(&mut shown.text);
+                        rendered.spans.push(Span {
+                            start: mark.start + cut,
+                            end: mark.end + cut,
+                            ..mark
+                        });
+                    }
+                    // Up to the cursor's column past the text, so a selected line break shows
+                    let last = if clipped { end - cut } else { width + 1 };
+                    shown.spans = clip_spans(&rendered.spans, cut, cut + last);
+                    let decorations = self.row_spans(idx, &brackets);
+                    shown
+                        .spans
+                        .extend(clip_spans(&decorations, edge, edge + last));
+                    shown
+                        .spans
+                        .extend(clip_spans(&self.guide_spans(), edge, edge + last));
+                    if clipped {
+                        shown.spans.push(Span {
+                            start: end,
+                            end: end + 1,
+                            style: Style::StatusBar,
+                        });
+                        shown.text.push('>');
+                    }
+                    if layout.left_marker > 0 {
+                        shown = with_left_marker(shown, from > 0 || cut > 0);
+                    }
+                    self.with_gutter(shown, Some(idx))
+                }
+                None => self.with_gutter(filler_line(), None),
+            })
+            .collect()
+    }
+
+    /// Soft wrap version of `text_layer`, every row takes as many screen rows as it needs
+    fn wrapped_text_layer(&self) -> Vec<Line> {
+        let width = self.wrap_width();
+        let mut rows = self.rows.iter().enumerate().skip(self.row_offset);
+        let mut chunks: Vec<Line> = Vec::new();
+        let mut lines = Vec::new();
+        let mut skip = self.wrap_skip;
+        let brackets = self.bracket_cells();
+        // The buffer row the chunks are of
+        let mut chunks_of = None;
+
+        for _ in 0..=self.term_rows {
+            if chunks.is_empty() {
+                chunks_of = None;
+                if let Some((idx, row)) = rows.next() {
+                    chunks_of = Some(idx);
+                    // Only the screen rows left to fill are rendered
+                    let limit = (skip + self.term_rows + 1 - lines.len()) * width;
+                    let (mut rendered, whole) =
+                        render_row(row, self.tab_size, self.show_invisibles, 0, limit);
+                    rendered.spans.extend(self.row_spans(idx, &brackets));
+                    // Past the window's width the guide would land on the next screen row
+                    let guides = self.guide_spans();
+                    rendered
+                        .spans
+                        .extend(guides.into_iter().filter(|guide| guide.end <= width));
+                    // A wide char split over two screen rows shows as a '>' and a '<'
+                    let screen_rows = rendered.text.len().div_ceil(width).max(1);
+                    chunks = (0..screen_rows)
+                        .map(|n| Line {
+                            spans: clip_spans(&rendered.spans, n * width, (n + 1) * width),
+                            ..rendered.columns(n * width, (n + 1) * width)
+                        })
+                        .rev()
+                        .collect();
+                    // The mark goes on the row's last screen row even when that's full, in the
+                    // column the cursor takes there, so it never adds a screen row
+                    if self.show_invisibles && whole {
+                        let last = &mut chunks[0];
+                        let mark = eol_mark(&mut last.text);
+                        last.spans.push(mark);
+                    }
+                    // The chunks are in reverse, the top row's first screen rows are at the end
+                    let hidden = skip.min(chunks.len() - 1);
+                    chunks.truncate(chunks.len() - hidden);
+                    skip = 0;
+                }
+            }
+
+            let line = chunks.pop().unwrap_or_else(filler_line);
+            lines.push(self.with_gutter(line, chunks_of));
+        }
+
+        lines
+    }
+
+    /// The line length guide, as spans over a rendered row
+    fn guide_spans(&self) -> Vec<Span> {
+        self.color_column
+            .iter()
+            .map(|column| Span {
+                start: column - 1,
+                end: *column,
+                style: Style::ColorColumn,
+            })
+            .collect()
+    }
+
+    /// The decorations of a row, as spans over the whole rendered row. `brackets` are the
+    /// highlighted brackets as `bracket_cells` found them, looked for once for the whole frame.
+    fn row_spans(&self, idx: usize, brackets: &[(usize, usize, Style)]) -> Vec<Span> {
+        let mut spans = self.syntax_spans(idx);
+        spans.extend(self.match_spans(idx));
+        if let Some(row) = self.rows.get(idx) {
+            spans.extend(
+                brackets
+                    .iter()
+                    .filter(|(y, _, _)| *y == idx)
+                    .map(|(_, x, style)| Span {
+                        start: cx_to_rx(row, *x, self.tab_size),
+                        end: cx_to_rx(row, *x + 1, self.tab_size),
+                        style: *style,
+                    }),
+            );
+        }
+        spans.extend(self.whitespace_spans(idx));
+        spans.extend(self.selection_span(idx));
+        spans
+    }
+
+    /// The warnings for a row's blanks: its trailing spaces and tabs, and the first column when
+    /// its indent mixes tabs and spaces. Blanks the cursor is in at the end of its row are left
+    /// alone, they're most likely being typed.
+    fn whitespace_spans(&self, idx: usize) -> Vec<Span> {
+        let mut spans = Vec::new();
+        let Some(row) = self
+            .rows
+            .get(idx)
+            .filter(|_| self.config.whitespace_warnings)
+        else {
+            return spans;
+        };
+        let indent = &row[..row.len() - row.trim_start_matches([' ', '\t']).len()];
+        if indent.contains(' ') && indent.contains('\t') {
+            spans.push(Span {
+                start: 0,
+                end: 1,
+                style: Style::MixedIndent,
+            });
+        }
+        let trailing = row.trim_end_matches([' ', '\t']).len();
+        let typing = idx == self.row_offset + self.cur_pos.y && self.cur_pos.x >= trailing;
+        if trailing < row.len() && !typing {
+            spans.push(Span {
+                start: self.row_rx(idx, trailing),
+                end: self.row_rx(idx, row.len()),
+                style: Style::TrailingWhitespace,
+            });
+        }
+        spans
+    }
+
+    /// The bracket matching the one the cursor is on or right after, as its row, index into the
+    /// row and style. A bracket without a match is flagged itself instead. Taken afresh on every
+    /// frame, so it follows each move and edit.
+    fn bracket_cells(&self) -> Vec<(usize, usize, Style)> {
+        let y = self.row_offset + self.cur_pos.y;
+        let Some(x) = self
+            .rows
+            .get(y)
+            .and_then(|row| brackets::near(row, self.cur_pos.x))
+        else {
+            return Vec::new();
+        };
+
+        match brackets::partner(&self.rows, y, x) {
+            Some(brackets::Partner::Found(row, column)) => {
+                vec![(row, column, Style::MatchingBracket)]
+            }
+            Some(brackets::Partner::Unmatched) => vec![(y, x, Style::UnmatchedBracket)],
+            Some(brackets::Partner::TooFar) | None => Vec::new(),
+        }
+    }
+
+    /// The selected part of a row. A row the selection continues past has its line break
+    /// selected too, drawn as one more column after its text.
+    fn selection_span(&self, idx: usize) -> Option<Span> {
+        let ((start_y, start_x), (end_y, end_x)) = self.selection()?;
+        let row = self
+            .rows
+            .get(idx)
+            .filter(|_| (start_y..=end_y).contains(&idx))?;
+        let start = if idx == start_y {
+            cx_to_rx(row, start_x, self.tab_size)
+        } else {
+            0
+        };
+        let end = if idx == end_y {
+            cx_to_rx(row, end_x, self.tab_size)
+        } else {
+            cx_to_rx(row, row.len(), self.tab_size) + 1
+        };
+        Some(Span {
+            start,
+            end,
+            style: Style::Selection,
+        })
+    }
+
+    /// The parts of a row its filetype's rules pick out, as spans over the whole rendered row.
+    /// Rows too long to go over on every draw are left plain.
+    fn syntax_spans(&self, idx: usize) -> Vec<Span> {
+        let (Some(syntax), Some(row)) = (self.syntax(), self.rows.get(idx)) else {
+            return Vec::new();
+        };
+        if row.len() >= columns::LONG_ROW {
+            return Vec::new();
+        }
+        let block = self
+            .blocks
+            .as_ref()
+            .filter(|(edits, _)| *edits == self.edits)
+            .and_then(|(_, blocks)| blocks.get(idx).copied())
+            .flatten();
+
+        // The parts are in order, so the row is measured once from one to the next
+        let (mut cx, mut rx) = (0, 0);
+        let mut spans = Vec::new();
+        for part in syntax.parts(row, block) {
+            let start = columns::advance(&row[cx..part.start], rx, self.tab_size);
+            rx = columns::advance(&row[part.start..part.end], start, self.tab_size);
+            cx = part.end;
+            spans.push(Span {
+                start,
+                end: rx,
+                style: Style::Syntax(part.kind),
+            });
+        }
+        spans
+    }
+
+    /// The last search's matches in a row, as spans over the whole rendered row. Only rows that
+    /// are drawn are looked at, so a search in a big file costs no more than one in a small one.
+    fn match_spans(&self, idx: usize) -> Vec<Span> {
+        let (Some(search), Some(row)) = (&self.search, self.rows.get(idx)) else {
+            return Vec::new();
+        };
+        search::matches(row, &search.query, self.search_case)
+            .into_iter()
+            .map(|found| Span {
+                start: cx_to_rx(row, found.start, self.tab_size),
+                end: cx_to_rx(row, found.end, self.tab_size),
+                style: if search.current == (idx, found.start) {
+                    Style::CurrentMatch
+                } else {
+                    Style::SearchMatch
+                },
+            })
+            .collect()
+    }
+
+    fn current_line(&self) -> Option<&str> {
+        let current_line_idx = self.row_offset + self.cur_pos.y;
+        self.rows.get(current_line_idx)
+    }
+
+    /// The rendered column in the row Up and Down aim for, where the cursor is unless one of them
+    /// was the last key
+    fn goal_column(&mut self) -> usize {
+        if let Some(goal) = self.goal_column {
+            return goal;
+        }
+        let x = self.cur_pos.x;
+        let goal = self
+            .current_line()
+            .map_or(0, |line| cx_to_rx(line, x, self.tab_size));
+        *self.goal_column.insert(goal)
+    }
+
+    /// Puts the cursor on the char covering the rendered column `goal` of the current row, or at
+    /// its end when it's narrower. `scroll_horizontal` brings it into view.
+    fn move_to_column(&mut self, goal: usize) {
+        if let Some(line) = self.current_line() {
+            self.cur_pos.x = rx_to_cx(line, goal, self.tab_size);
+        }
+    }
+
+    /// The cursor's rendered column relative to the left edge of the window
+    fn rx(&self) -> usize {
+        let y = self.row_offset + self.cur_pos.y;
+        self.current_line().map_or(0, |_| {
+            self.row_rx(y, self.cur_pos.x)
+                .saturating_sub(self.col_offset)
+        })
+    }
+
+    /// Moves `col_offset` so the cursor's rendered column fits in the window, tabs can make a row
+    /// wider on screen than it is long
+    fn scroll_horizontal(&mut self) {
+        if self.wrap {
+            return;
+        }
+
+        let x = self.cur_pos.x;
+        let y = self.row_offset + self.cur_pos.y;
+        if let Some(line) = self.current_line() {
+            let x = x.min(line.len());
+            let rx = self.row_rx(y, x);
+            let fits = |col_offset| rx - col_offset <= self.cursor_room(y, col_offset);
+
+            let mut col_offset = self.col_offset.min(rx);
+            if !fits(col_offset) {
+                // Once scrolled the left marker is there, and the right one takes more room from
+                // the cursor if the row still goes on past the window
+                col_offset = rx.saturating_sub(self.layout(1, false).cursor_room());
+                if !fits(col_offset) {
+                    col_offset = rx.saturating_sub(self.layout(1, true).cursor_room());
+                }
+            }
+            self.col_offset = col_offset;
+            self.cur_pos.x = x;
+        }
+    }
+
+    /// Columns the '<' takes left of the text, there while the window is scrolled right and clip
+    /// markers are on
+    fn left_marker_width(&self) -> usize {
+        self.layout(self.col_offset, false).left_marker
+    }
+
+    /// How many columns right of the window's left edge the cursor can be on row `y` with the
+    /// window scrolled to `col_offset`, see `Layout::cursor_room`
+    fn cursor_room(&self, y: usize, col_offset: usize) -> usize {
+        let text = self.layout(col_offset, false).text;
+        let row_width = self.rows.get(y).map_or(0, |row| self.row_rx(y, row.len()));
+        let continues = row_width.saturating_sub(col_offset) > text;
+        self.layout(col_offset, continues).cursor_room()
+    }
+
+    /// The status line under the pane being edited. Only the bottom one shows messages, and the
+    /// one under the pane that isn't `active` is dimmed.
+    fn status_bar_layer(&self, active: bool) -> Line {
+        let bottom = self.shown_split().is_none_or(|split| !split.top_active);
+        // Stays up until the config is fixed, unlike the message that first reported it
+        let mut flags = match self.config_errors.len() {
+            0 => String::new(),
+            1 => String::from(" [1 config error]"),
+            n => format!(" [{n} config errors]"),
+        };
+        if let Some(loader) = &self.loader {
+            flags.insert_str(0, &format!(" [loading\u{2026} {}%]", loader.percent()));
+        }
+        if self.overwrite {
+            flags.insert_str(0, " [OVR]");
+        }
+        if self.input.recording() {
+            flags.insert_str(0, " [REC]");
+        }
+        if self.readonly {
+            flags.insert_str(0, " [RO]");
+        }
+        if self.dirty_flag {
+            flags.insert_str(0, " [+]");
+        }
+
+        let name = match (&self.path, self.file.is_some()) {
+            (Some(path), true) => sanitize(path, MAX_MESSAGE_LEN),
+            _ if self.ephemeral => String::from("[Scratch]"),
+            _ => match &self.listing {
+                Some(listing) => {
+                    let dir = listing.dir.to_string_lossy();
+                    sanitize(&format!("{}/", dir.trim_end_matches('/')), MAX_MESSAGE_LEN)
+                }
+                None => String::from("[No open file]"),
+            },
+        };
+        // Without a file there's nothing else to say, so the help stays up until it's replaced
+        let message = self
+            .message
+            .message
+            .as_deref()
+            .filter(|_| bottom)
+            .filter(|_| {
+                self.file.is_none()
+                    || self.prompt_active
+                    || self.message.time.elapsed() < MESSAGE_DURATION
+            })
+            .unwrap_or_default();
+
+        let line_count = self.rows.len();
+        let y = (self.row_offset + self.cur_pos.y).min(line_count.saturating_sub(1));
+        let percent = ((y + 1) * 100).checked_div(line_count).unwrap_or(0);
+        // Columns count as they're drawn, the index into the row is added once tabs make them differ
+        let cx = self.cur_pos.x;
+        let rx = self.current_line().map_or(cx, |_| self.row_rx(y, cx));
+        let column = if cx == rx {
+            format!("{}", rx + 1)
+        } else {
+            format!("{} (char {})", rx + 1, cx + 1)
+        };
+        let filetype = self
+            .filetype
+            .map(|filetype| format!("{}  ", filetype.name))
+            .unwrap_or_default();
+        // Until a big file is all read neither its line count nor its line endings are known
+        let position = if self.loader.is_some() {
+            format!("{filetype}Ln {}, Col {column}  {line_count}+ lines", y + 1)
+        } else {
+            format!(
+                "{filetype}{}  Ln {}, Col {column}  {line_count} lines  {percent}%",
+                self.line_ending.name(),
+                y + 1,
+            )
+        };
+
+        // An open prompt's question and input matter more than anything else on the bar
+        let text = if self.prompt_active {
+            String::from(truncate_end(message, self.term_cols))
+        } else {
+            status_line(&name, &flags, message, &position, self.term_cols)
+        };
+
+        // Makes it plain which pane has focus when several are side by side in a multiplexer
+        let style = if !active || (!self.focused && self.config.dim_unfocused) {
+            Style::StatusBarUnfocused
+        } else {
+            Style::StatusBar
+        };
+        Line {
+            text: text.chars().collect(),
+            spans: vec![Span {
+                start: 0,
+                end: self.term_cols,
+                style,
+            }],
+            combining: Vec::new(),
+        }
+    }
+
+    /// Reads a line of input on the message line. While a prompt is open it consumes every key,
+    /// messages raised in the meantime are held back until it closes and a second prompt is refused.
+    fn prompt(&mut self, prompt_prefix: &str) -> io::Result<String> {
+        self.open_prompt(prompt_prefix, PromptKind::Line)
+    }
+
+    /// The prompt for a search, showing how it treats case. Alt-C changes that while it's open.
+    fn search_prompt(&mut self) -> io::Result<String> {
+        self.open_prompt("Search", PromptKind::Search)
+    }
+
+    /// The prompt for a file's path, Tab completes the name being typed as far as it can
+    fn path_prompt(&mut self, prompt_prefix: &str) -> io::Result<String> {
+        self.open_prompt(prompt_prefix, PromptKind::Path)
+    }
+
+    fn open_prompt(&mut self, prompt_prefix: &str, kind: PromptKind) -> io::Result<String> {
+        if self.prompt_active {
+            return Err(Error::other("prompt: another prompt is already open"));
+        }
+
+        self.prompt_active = true;
+        let input = self.read_prompt_input(prompt_prefix, kind);
+        self.prompt_active = false;
+        self.release_queued_message();
+        input
+    }
+
+    fn read_prompt_input(&mut self, prompt_prefix: &str, kind: PromptKind) -> io::Result<String> {
+        let mut input = String::new();
+        loop {
+            let prefix = if kind == PromptKind::Search {
+                format!("{prompt_prefix} [{}]:", self.search_case.flag())
+            } else {
+                String::from(prompt_prefix)
+            };
+            self.message = SystemMessage::new(&format!("{prefix} {input}"));
+            self.draw();
+            match self.input.next(self.wait_time())? {
+                None => {}
+                // A lone Escape cancels, other sequences don't end up in the input
+                Some(Event::Escape(seq)) => match seq {
+                    EscapeSequence::Bare => {
+                        return Err(Error::other("prompt: action cancelled"));
+                    }
+                    EscapeSequence::Focus(focused) => self.set_focus(focused),
+                    EscapeSequence::CycleSearchCase if kind == PromptKind::Search => {
+                        self.search_case = self.search_case.next();
+                    }
+                    _ => {}
+                },
+                Some(Event::Char(c)) => input.push(c),
+                Some(Event::Byte(byte)) => match self.action_for(byte) {
+                    Action::Cancel => {
+                        return Err(Error::other("prompt: action cancelled"));
+                    }
+                    Action::Enter => return Ok(input),
+                    Action::Delete => {
+                        input.pop();
+                    }
+                    Action::Tab if kind == PromptKind::Path => input = browser::complete(&input),
+                    Action::Input(c) if !c.is_ascii_control() => input.push(c),
+                    // Every other key is swallowed while the prompt is open
+                    _ => {}
+                },
+            }
+        }
+    }
+
+    /// Asks a question answered by one of `choices`, `None` when it's cancelled.
+    /// Shares the prompt's rules: it consumes every key and can't be opened while a prompt is.
+    fn confirm(&mut self, question: &str, choices: Vec<Choice>) -> io::Result<Option<char>> {
+        if self.prompt_active {
+            return Err(Error::other("confirm: a prompt is already open"));
+        }
+
+        self.prompt_active = true;
+        self.confirm = Some(Confirm::new(question, choices));
+        let answer = self.read_confirm_input();
+        // Whatever the question covered is drawn again on the next frame
+        self.confirm = None;
+        self.prompt_active = false;
+        self.release_queued_message();
+        answer
+    }
+
+    fn read_confirm_input(&mut self) -> io::Result<Option<char>> {
+        loop {
+            self.draw();
+            let Some(event) = self.input.next(self.wait_time())? else {
+                continue;
+            };
+
+            let choice = |c| self.confirm.as_ref().and_then(|confirm| confirm.choice(c));
+            let answer = match event {
+                // A lone Escape cancels, a whole sequence (an arrow key) is just an unknown key
+                Event::Escape(EscapeSequence::Bare) => return Ok(None),
+                Event::Escape(EscapeSequence::Focus(focused)) => {
+                    self.set_focus(focused);
+                    continue;
+                }
+                Event::Escape(_) => None,
+                Event::Char(c) => choice(c),
+                Event::Byte(byte) => match self.action_for(byte) {
+                    Action::Cancel => return Ok(None),
+                    Action::Input(c) => choice(c),
+                    _ => None,
+                },
+            };
+
+            if answer.is_some() {
+                return Ok(answer);
+            }
+
+            send_esc_seq(CtrlSeq::Bell);
+            if let Some(confirm) = &mut self.confirm {
+                confirm.flash = true;
+            }
+        }
+    }
+
+    /// Asks for a command: an action by the name `bind` lines use, for actions without a key of
+    /// their own, or one of the command line's. Those are done right here, actions are handed back.
+    fn read_command(&mut self) -> Option<Action> {
+        let line = self.prompt(":").ok()?;
+        let line = line.trim();
+        if line.is_empty() {
+            return None;
+        }
+
+        if let Some(binding) = BINDINGS.iter().find(|binding| binding.name == line) {
+            return Some(binding.action);
+        }
+        match ex::parse(line) {
+            Ok(command) => self.run_command(command),
+            Err(message) => {
+                self.set_message(&message);
+                None
+            }
+        }
+    }
+
+    /// Does what a command line command asks, handing back the action for those that are one
+    fn run_command(&mut self, command: ex::Command) -> Option<Action> {
+        if matches!(
+            command,
+            ex::Command::Write(Some(_)) | ex::Command::Goto(_) | ex::Command::Filter(_)
+        ) {
+            self.finish_loading();
+        }
+        match command {
+            ex::Command::Write(None) => return Some(Action::Save),
+            ex::Command::Write(Some(path)) => {
+                self.write_to_path(&path, false);
+            }
+            ex::Command::Quit { force: false } => return Some(Action::Quit),
+            ex::Command::Quit { force: true } => return Some(Action::ForceQuit),
+            ex::Command::Edit { path, force } => self.edit_file(&path, force),
+            ex::Command::Revert => return Some(Action::Revert),
+            ex::Command::Set(ex::Setting::TabSize(size)) => {
+                self.tab_size = size;
+                self.scroll_horizontal();
+                self.set_message(&format!("Tab size {size}"));
+            }
+            ex::Command::Set(ex::Setting::ExpandTab(expand_tab)) => {
+                self.expand_tab = expand_tab;
+                self.set_message(if expand_tab {
+                    "Tab inserts spaces"
+                } else {
+                    "Tab inserts a tab character"
+                });
+            }
+            ex::Command::Set(ex::Setting::Filetype(filetype)) => {
+                self.set_filetype(Some(filetype));
+                self.set_message(&format!("Filetype {}", filetype.name));
+            }
+            ex::Command::Set(ex::Setting::ColorColumn(columns)) => {
+                let listed: Vec<String> = columns.iter().map(usize::to_string).collect();
+                self.set_message(&match listed.as_slice() {
+                    [] => String::from("Line length guide off"),
+                    [column] => format!("Line length guide at column {column}"),
+                    listed => format!("Line length guide at columns {}", listed.join(", ")),
+                });
+                self.color_column = columns;
+            }
+            ex::Command::Goto(line) => self.goto(line, 1),
+            ex::Command::Filter(command) => self.filter_rows(&command),
+        }
+        None
+    }
+
+    /// Runs the lines the selection touches through `command`, or every line without a selection,
+    /// and puts what it prints in their place. When the command fails the buffer stays as it was,
+    /// and output no different from the lines leaves it unmodified.
+    fn filter_rows(&mut self, command: &str) {
+        if !self.check_writable() {
+            return;
+        }
+
+        let (y, x) = self.edit_point();
+        let (first, end) = match self.selection() {
+            // A selection ending at the start of a line doesn't take that line along
+            Some(((start_y, _), (end_y, 0))) if end_y > start_y => (start_y, end_y),
+            Some(((start_y, _), (end_y, _))) => (start_y, end_y + 1),
+            None => (0, self.rows.len()),
+        };
+        let end = end.min(self.rows.len());
+        let running = match filter::start(command, rows_to_text(self.rows.range(first..end))) {
+            Ok(running) => running,
+            Err(message) => {
+                self.set_message(&message);
+                return;
+            }
+        };
+        // What it prints replaces the lines it was given, edits made meanwhile would be lost or
+        // land in the wrong place
+        let thawed = self.freeze(&format!("Running {command}"), Freeze::Rejected, |wait| {
+            running.wait(wait)
+        });
+        let rows = match &thawed.result {
+            None => {
+                running.cancel();
+                let message = format!("Cancelled {command}, the buffer is as it was");
+                self.set_message(&thawed.report(&message));
+                return;
+            }
+            Some(Err(message)) => {
+                self.set_message(&thawed.report(message));
+                return;
+            }
+            Some(Ok(rows)) => rows.clone(),
+        };
+        if self.rows.range(first..end).eq(&rows) {
+            self.set_message(&thawed.report(&format!("{command} changed nothing")));
+            return;
+        }
+
+        let count = rows.len();
+        self.rows.splice(first..end, rows);
+        self.mark_edited();
+        // The cursor stays on its line, moving along with the lines after the filtered ones, and goes
+        // to the last of the output if there aren't as many lines anymore
+        let y = if y >= end {
+            y - (end - first) + count
+        } else if y < first + count {
+            y
+        } else {
+            (first + count).saturating_sub(1)
+        };
+        let y = y.min(self.rows.len().saturating_sub(1));
+        let x = self
+            .rows
+            .get(y)
+            .map_or(0, |row| split_point(row, x).unwrap_or_else(|x| x));
+        self.place_cursor(y, x);
+        self.set_message(&thawed.report(&format!(
+            "Ran {} lines through {command}, got {count} back",
+            end - first
+        )));
+    }
+
+    /// Edits the file at `path` instead, refusing while there are unsaved changes unless `force`
+    /// says to drop them. A file that can't be opened leaves the buffer as it was.
+    fn edit_file(&mut self, path: &str, force: bool) {
+        // With both panes over the buffer it stays open in the other one, this one gets the file
+        let keep = self
+            .split
+            .as_ref()
+            .is_some_and(|split| split.buffer.is_none());
+        if !Path::new(path).is_file() && !Path::new(path).is_dir() {
+            self.set_message(&format!("Error opening {path}: no such file"));
+            return;
+        }
+        let other_path = match &self.split {
+            Some(Split {
+                buffer: Some(buffer),
+                ..
+            }) => buffer.path.as_deref(),
+            Some(_) => self.path.as_deref(),
+            None => None,
+        };
+        let canonical = |path: &str| Path::new(path).canonicalize().ok();
+        if other_path.is_some_and(|other| canonical(other) == canonical(path)) {
+            self.set_message(&format!("{path} is open in the other pane"));
+            return;
+        }
+        if self.dirty_flag && !self.ephemeral && !force && !keep {
+            self.set_message("There are unsaved changes, e! drops them");
+            return;
+        }
+
+        if keep {
+            let mut buffer = self.new_buffer();
+            self.swap_buffer(&mut buffer);
+            if let Some(split) = &mut self.split {
+                split.buffer = Some(Box::new(buffer));
+            }
+        } else {
+            self.remember_file_state();
+        }
+        let said = self.message.time;
+        if let Err(err) = self.open(path, None) {
+            self.set_message(&format!("Error opening {path}: {err}"));
+            // Back to both panes over the buffer they had
+            if keep {
+                if let Some(mut buffer) = self.split.as_mut().and_then(|split| split.buffer.take())
+                {
+                    self.swap_buffer(&mut buffer);
+                }
+            }
+            return;
+        }
+        self.dirty_flag = false;
+        self.ephemeral = false;
+        self.mark = None;
+        self.search = None;
+        self.goal_column = None;
+        // A listing has its own message, and what opening the file said about it matters more
+        if self.listing.is_none() && self.message.time == said {
+            self.set_message(&format!("Editing {path}, {} lines", self.rows.len()));
+        }
+    }
+
+    /// Lists the files under the working directory, narrowed down to the paths with the typed
+    /// letters in them, and opens the one picked the way `e path` would
+    fn find_file(&mut self) {
+        let files = finder::walk(Path::new("."));
+        if files.is_empty() {
+            self.set_message("There are no files here");
+            return;
+        }
+        match self.pick_file(&files) {
+            Ok(Some(path)) => self.edit_file(&path, false),
+            Ok(None) => {}
+            Err(err) => self.set_message(&err.to_string()),
+        }
+    }
+
+    /// Like `pick_from_list`, with the list filtered by what's typed
+    fn pick_file(&mut self, files: &[String]) -> io::Result<Option<String>> {
+        if self.prompt_active {
+            return Err(Error::other("list: a prompt is already open"));
+        }
+
+        // The title and the footer take two of the screen's `text_height + 1` rows
+        let room = self.text_height.saturating_sub(1).max(1);
+        let mut query = String::new();
+        let mut matches = finder::filter(files, &query);
+        let (mut selected, mut top): (usize, usize) = (0, 0);
+        self.prompt_active = true;
+        let picked = loop {
+            top = top.min(selected).max((selected + 1).saturating_sub(room));
+            let title = format!("Open file: {query}");
+            let mut lines = vec![list_line(&title, self.term_cols, Style::StatusBar)];
+            for (idx, path) in matches.iter().enumerate().skip(top).take(room) {
+                let style = if idx == selected {
+                    Style::StatusBar
+                } else {
+                    Style::Normal
+                };
+                lines.push(list_line(path, self.term_cols, style));
+            }
+            let footer = format!(
+                "{}/{} files  Enter opens, Escape closes",
+                matches.len(),
+                files.len()
+            );
+            lines.push(list_line(&footer, self.term_cols, Style::StatusBar));
+            self.overlay = Some(lines);
+            self.draw();
+
+            let event = match self.input.next(self.wait_time()) {
+                Ok(Some(event)) => event,
+                Ok(None) => continue,
+                Err(err) => break Err(err),
+            };
+            let last = matches.len().saturating_sub(1);
+            let typed = match event {
+                Event::Escape(EscapeSequence::Navigation(NavigationKey::Up)) => {
+                    selected = selected.saturating_sub(1);
+                    continue;
+                }
+                Event::Escape(EscapeSequence::Navigation(NavigationKey::Down)) => {
+                    selected = (selected + 1).min(last);
+                    continue;
+                }
+                Event::Escape(EscapeSequence::Navigation(NavigationKey::PageUp)) => {
+                    selected = selected.saturating_sub(room);
+                    continue;
+                }
+                Event::Escape(EscapeSequence::Navigation(NavigationKey::PageDown)) => {
+                    selected = (selected + room).min(last);
+                    continue;
+                }
+                Event::Escape(EscapeSequence::Bare) => break Ok(None),
+                Event::Escape(EscapeSequence::Focus(focused)) => {
+                    self.set_focus(focused);
+                    continue;
+                }
+                Event::Escape(_) => continue,
+                Event::Char(c) => Some(c),
+                Event::Byte(byte) => match self.action_for(byte) {
+                    Action::Enter => break Ok(matches.get(selected).map(|path| (*path).clone())),
+                    Action::Cancel => break Ok(None),
+                    Action::Delete => None,
+                    Action::Input(c) => Some(c),
+                    _ => continue,
+                },
+            };
+
+            match typed {
+                Some(c) => query.push(c),
+                None => {
+                    query.pop();
+                }
+            }
+            matches = finder::filter(files, &query);
+            (selected, top) = (0, 0);
+        };
+
+        self.overlay = None;
+        self.prompt_active = false;
+        self.release_queued_message();
+        picked
+    }
+
+    /// Shows `items` under a title over the bottom of the screen until a key is pressed
+    fn show_list(&mut self, title: &str, items: &[String]) -> io::Result<()> {
+        let line = |text: &str, style| list_line(text, self.term_cols, style);
+        // The screen has `text_height + 1` rows, the title and the footer take two of them
+        let room = self.text_height.saturating_sub(1).max(1);
+        let mut lines = vec![line(title, Style::StatusBar)];
+        if items.len() > room {
+            let more = format!("... and {} more", items.len() - room + 1);
+            lines.extend(
+                items[..room - 1]
+                    .iter()
+                    .map(|item| line(item, Style::Normal)),
+            );
+            lines.push(line(&more, Style::Normal));
+        } else {
+            lines.extend(items.iter().map(|item| line(item, Style::Normal)));
+        }
+        lines.push(line("Press any key to close", Style::StatusBar));
+        self.show_overlay(lines)
+    }
+
+    /// Draws `lines` over the bottom of the screen until a key is pressed. Nothing under them is
+    /// touched, so the view comes back exactly as it was.
+    fn show_overlay(&mut self, lines: Vec<Line>) -> io::Result<()> {
+        if self.prompt_active {
+            return Err(Error::other("list: a prompt is already open"));
+        }
+
+        self.prompt_active = true;
+        self.overlay = Some(lines);
+        self.draw();
+        let read = loop {
+            match self.input.next(self.wait_time()) {
+                Ok(None) => {}
+                // A focus change isn't a key press and leaves the list up
+                Ok(Some(Event::Escape(EscapeSequence::Focus(focused)))) => {
+                    self.set_focus(focused);
+                    self.draw();
+                }
+                Ok(Some(_)) => break Ok(()),
+                Err(err) => break Err(err),
+            }
+        };
+        self.overlay = None;
+        self.prompt_active = false;
+        self.release_queued_message();
+        read
+    }
+
+    /// Shows `items` under a title over the bottom of the screen and lets the user pick one with
+    /// the arrow keys and Enter. `None` when the list is closed without picking anything.
+    /// A `numbered` list shows numbers by its first nine items, typing one picks that item.
+    fn pick_from_list(
+        &mut self,
+        title: &str,
+        items: &[String],
+        numbered: bool,
+    ) -> io::Result<Option<usize>> {
+        if self.prompt_active {
+            return Err(Error::other("list: a prompt is already open"));
+        }
+        if items.is_empty() {
+            return Ok(None);
+        }
+
+        // The title and the footer take two of the screen's `text_height + 1` rows
+        let room = self.text_height.saturating_sub(1).max(1);
+        let (mut selected, mut top): (usize, usize) = (0, 0);
+        self.prompt_active = true;
+        let picked = loop {
+            top = top.min(selected).max((selected + 1).saturating_sub(room));
+            let mut lines = vec![list_line(title, self.term_cols, Style::StatusBar)];
+            for (idx, item) in items.iter().enumerate().skip(top).take(room) {
+                let style = if idx == selected {
+                    Style::StatusBar
+                } else {
+                    Style::Normal
+                };
+                let item = match idx {
+                    0..9 if numbered => format!("{}  {item}", idx + 1),
+                    _ if numbered => format!("   {item}"),
+                    _ => item.clone(),
+                };
+                lines.push(list_line(&item, self.term_cols, style));
+            }
+            let footer = format!(
+                "{}/{}  Enter picks, Escape closes",
+                selected + 1,
+                items.len()
+            );
+            lines.push(list_line(&footer, self.term_cols, Style::StatusBar));
+            self.overlay = Some(lines);
+            self.draw();
+
+            let event = match self.input.next(self.wait_time()) {
+                Ok(Some(event)) => event,
+                Ok(None) => continue,
+                Err(err) => break Err(err),
+            };
+            match event {
+                Event::Escape(EscapeSequence::Navigation(NavigationKey::Up)) => {
+                    selected = selected.saturating_sub(1);
+                }
+                Event::Escape(EscapeSequence::Navigation(NavigationKey::Down)) => {
+                    selected = (selected + 1).min(items.len() - 1);
+                }
+                Event::Escape(EscapeSequence::Navigation(NavigationKey::PageUp)) => {
+                    selected = selected.saturating_sub(room);
+                }
+                Event::Escape(EscapeSequence::Navigation(NavigationKey::PageDown)) => {
+                    selected = (selected + room).min(items.len() - 1);
+                }
+                Event::Escape(EscapeSequence::Bare) => break Ok(None),
+                Event::Escape(EscapeSequence::Focus(focused)) => self.set_focus(focused),
+                Event::Byte(digit @ b'1'..=b'9')
+                    if numbered && usize::from(digit - b'1') < items.len() =>
+                {
+                    break Ok(Some(usize::from(digit - b'1')));
+                }
+                Event::Byte(byte) => match self.action_for(byte) {
+                    Action::Enter => break Ok(Some(selected)),
+                    Action::Cancel => break Ok(None),
+                    _ => {}
+                },
+                _ => {}
+            }
+        };
+
+        self.overlay = None;
+        self.prompt_active = false;
+        self.release_queued_message();
+        picked
+    }
+
+    /// Asks for a file and lists how the buffer differs from it as unified diff hunks, the buffer
+    /// being the old side. Picking a line of the diff jumps to where it is in the buffer.
+    fn compare_file(&mut self) {
+        let Ok(input) = self.path_prompt("Compare with:") else {
+            return;
+        };
+        let input = input.trim();
+        if input.is_empty() {
+            return;
+        }
+
+        let other = match fs::metadata(input) {
+            Ok(metadata) if metadata.is_dir() => Err(String::from("it's a directory")),
+            Ok(metadata) if metadata.len() > MAX_COMPARE_SIZE => {
+                Err(format!("it's over {} MB", MAX_COMPARE_SIZE / (1024 * 1024)))
+            }
+            Ok(_) => fs::read(input)
+                .and_then(|bytes| read_lines(&bytes[..]))
+                .map(|text| text.rows)
+                .map_err(|err| err.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        let other = match other {
+            Ok(other) => other,
+            Err(err) => {
+                self.set_message(&format!("Couldn't compare with {input}: {err}"));
+                return;
+            }
+        };
+
+        let rows: Vec<&str> = self.rows.iter().collect();
+        let other: Vec<&str> = other.iter().collect();
+        let edits = diff::diff(&rows, &other);
+        let lines = diff::unified(&edits, &rows, &other);
+        if lines.is_empty() {
+            self.set_message(&format!("No differences from {input}"));
+            return;
+        }
+
+        let tab = " ".repeat(usize::from(self.tab_size));
+        let items: Vec<String> = lines
+            .iter()
+            .map(|line| line.text.replace('\t', &tab))
+            .collect();
+        let title = format!("- this buffer, + {input}");
+        match self.pick_from_list(&title, &items, false) {
+            Ok(Some(idx)) => self.jump(CursorPosition {
+                x: 0,
+                y: lines[idx].row,
+            }),
+            Ok(None) => {}
+            Err(err) => self.set_message(&err.to_string()),
+        }
+    }
+
+    /// Lists the files edited lately that are still there, and edits the one picked
+    fn recent_files(&mut self) {
+        let current = self
+            .path
+            .as_ref()
+            .and_then(|path| Path::new(path).canonicalize().ok());
+        let files: Vec<PathBuf> = state::recent_files()
+            .into_iter()
+            .filter(|path| path.is_file() && current.as_ref() != Some(path))
+            .take(MAX_RECENT_FILES)
+            .collect();
+        if files.is_empty() {
+            self.set_message("No recent files");
+            return;
+        }
+
+        let items: Vec<String> = files
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+        match self.pick_from_list("Recent files", &items, true) {
+            Ok(Some(idx)) => self.edit_file(&items[idx], false),
+            Ok(None) => {}
+            Err(err) => self.set_message(&err.to_string()),
+        }
+    }
+
+    fn list_annotations(&mut self) {
+        let annotations = annotations::scan(&self.rows, &self.config.annotation_markers);
+        if annotations.is_empty() {
+            self.set_message("No annotations in this file");
+            return;
+        }
+
+        let digits = self.rows.len().to_string().len();
+        let items: Vec<String> = annotations
+            .iter()
+            .map(|note| format!("  {:>digits$}  {} {}", note.row + 1, note.marker, note.text))
+            .collect();
+        let title = format!("{} annotations", annotations.len());
+        match self.pick_from_list(&title, &items, false) {
+            Ok(Some(idx)) => {
+                let note = &annotations[idx];
+                self.jump(CursorPosition {
+                    x: note.column,
+                    y: note.row,
+                });
+            }
+            Ok(None) => {}
+            Err(err) => self.set_message(&err.to_string()),
+        }
+    }
+
+    /// Moves to the closest annotation after (or before) the cursor, wrapping around the file
+    fn goto_annotation(&mut self, forward: bool) {
+        let annotations = annotations::scan(&self.rows, &self.config.annotation_markers);
+        let here = (self.row_offset + self.cur_pos.y, self.cur_pos.x);
+        let position = |note: &annotations::Annotation| (note.row, note.column);
+
+        let found = if forward {
+            annotations.iter().find(|note| position(note) > here)
+        } else {
+            annotations.iter().rev().find(|note| position(note) < here)
+        };
+        let wrapped = found.is_none();
+        let around = if forward {
+            annotations.first()
+        } else {
+            annotations.last()
+        };
+        let Some(note) = found.or(around) else {
+            self.set_message("No annotations in this file");
+            return;
+        };
+
+        let message = if wrapped {
+            format!("{} {} (wrapped around)", note.marker, note.text)
+        } else {
+            format!("{} {}", note.marker, note.text)
+        };
+        self.jump(CursorPosition {
+            x: note.column,
+            y: note.row,
+        });
+        self.set_message(&message);
+    }
+
+    /// Fills the screen with every action and the keys bound to it, taken from the live keymap.
+    /// Actions without a key are listed by the name Alt-X runs them by.
+    fn show_help(&mut self) {
+        let entries: Vec<(String, &str)> = BINDINGS
+            .iter()
+            .map(|binding| {
+                let keys: Vec<String> = self
+                    .keymap
+                    .iter()
+                    .filter(|(_, action)| *action == binding.action)
+                    .map(|(key, _)| key.to_string())
+                    .collect();
+                let keys = if keys.is_empty() {
+                    format!("alt-x {}", binding.name)
+                } else {
+                    keys.join(", ")
+                };
+                (keys, binding.description)
+            })
+            .collect();
+
+        let key_width = entries
+            .iter()
+            .map(|(keys, _)| keys.len())
+            .max()
+            .unwrap_or(0);
+        let mut items: Vec<String> = entries
+            .iter()
+            .map(|(keys, description)| format!("{keys:>key_width$}  {description}"))
+            .collect();
+        // The screen has `text_height + 1` rows, the title and the footer take two of them
+        let room = self.text_height.saturating_sub(1).max(1);
+        if items.len() > room {
+            let more = items.len() - room + 1;
+            items.truncate(room - 1);
+            items.push(format!("... and {more} more"));
+        }
+
+        let width = items.iter().map(String::len).max().unwrap_or(0);
+        let left = " ".repeat(self.term_cols.saturating_sub(width) / 2);
+        let above = (room - items.len()) / 2;
+        let mut lines = vec![list_line("Keys", self.term_cols, Style::StatusBar)];
+        lines.extend((0..above).map(|_| Line::default()));
+        lines.extend(
+            items
+                .iter()
+                .map(|item| list_line(&format!("{left}{item}"), self.term_cols, Style::Normal)),
+        );
+        lines.resize(room + 1, Line::default());
+        lines.push(list_line(
+            "Press any key to return",
+            self.term_cols,
+            Style::StatusBar,
+        ));
+
+        if let Err(err) = self.show_overlay(lines) {
+            self.set_message(&err.to_string());
+        }
+    }
+
+    /// Lists the messages set lately, newest first. Picking one shows it on the status bar again,
+    /// for reading one the list cut short.
+    fn show_message_history(&mut self) {
+        let items: Vec<String> = self
+            .message_log
+            .iter()
+            .rev()
+            .map(|(time, message)| format!("{:>7}  {message}", format_age(time.elapsed())))
+            .collect();
+        match self.pick_from_list("Messages, newest first", &items, false) {
+            Ok(Some(idx)) => {
+                let (_, message) = &self.message_log[self.message_log.len() - 1 - idx];
+                self.message = SystemMessage::new(message);
+            }
+            Ok(None) => {}
+            Err(err) => self.set_message(&err.to_string()),
+        }
+    }
+
+    fn show_config_errors(&mut self) {
+        if self.config_errors.is_empty() {
+            self.set_message("The config file has no problems");
+            return;
+        }
+
+        let title = match config::path() {
+            Some(path) => format!("Problems in {}", path.display()),
+            None => String::from("Problems in the config file"),
+        };
+        let items: Vec<String> = self
+            .config_errors
+            .iter()
+            .map(|error| match error.location {
+                Some((line, column)) => {
+                    format!("  line {line}, column {column}: {}", error.message)
+                }
+                None => format!("  {}", error.message),
+            })
+            .collect();
+        if let Err(err) = self.show_list(&title, &items) {
+            self.set_message(&err.to_string());
+        }
+    }
+
+    /// Reads the config file again and applies it. Settings changed in this session, or pinned on
+    /// the command line, are kept, everything still at the old config's value follows the new one.
+    fn reload_config(&mut self) {
+        let config = Config::load();
+        let (keymap, errors) = build_keymap(&config);
+
+        if self.pinned.wrap.is_none() && self.wrap == self.config.wrap && config.wrap != self.wrap {
+            self.toggle_wrap();
+        }
+        let (old_tab_size, old_expand_tab) = self.config.indent(self.filetype);
+        let (tab_size, expand_tab) = config.indent(self.filetype);
+        if self.pinned.tab_size.is_none() && self.tab_size == old_tab_size {
+            self.tab_size = tab_size;
+        }
+        if self.expand_tab == old_expand_tab {
+            self.expand_tab = expand_tab;
+        }
+        if self.trim_on_save == self.config.trim_trailing_whitespace {
+            self.trim_on_save = config.trim_trailing_whitespace;
+        }
+        if self.backup == self.config.backup {
+            self.backup = config.backup;
+        }
+        if self.scroll_off == self.config.scroll_off {
+            self.scroll_off = config.scroll_off;
+        }
+        if self.show_invisibles == self.config.show_invisibles {
+            self.show_invisibles = config.show_invisibles;
+        }
+        if self.color_column == self.config.color_column {
+            self.color_column.clone_from(&config.color_column);
+        }
+        if self.search_case == self.config.search_case {
+            self.search_case = config.search_case;
+        }
+
+        self.keymap = keymap;
+        self.config = config;
+        self.config_errors = errors;
+        self.apply_cursor_shape();
+        match self.config_errors.len() {
+            0 => self.set_message("Config reloaded"),
+            1 => self.set_message(&format!("Config reloaded, {}", self.config_errors[0])),
+            n => self.set_message(&format!(
+                "Config reloaded with {n} problems, Alt-X show-config-errors lists them"
+            )),
+        }
+    }
+
+    /// Clears the topmost piece of transient UI, one per bare Escape so mashing it always ends on a
+    /// clean screen: a list drawn over the screen first, then a replay count being typed, the
+    /// selection, the search highlight and the status message. Prompts and questions own the
+    /// keyboard while they're open and close on Escape themselves. The buffer is never touched.
+    /// False when there was nothing left to clear.
+    fn dismiss_topmost(&mut self) -> bool {
+        if self.overlay.take().is_some()
+            || self.replay_count.take().is_some()
+            || self.mark.take().is_some()
+            || self.search.take().is_some()
+        {
+            return true;
+        }
+        self.message.message.take().is_some()
+    }
+
+    /// Whether quitting can go ahead, asking what to do with unsaved changes first
+    fn confirm_quit(&mut self) -> bool {
+        if !self.confirm_close() {
+            return false;
+        }
+        // The other pane's own buffer is asked about with it showing
+        let other_dirty = self
+            .split
+            .as_ref()
+            .and_then(|split| split.buffer.as_ref())
+            .is_some_and(|buffer| buffer.dirty_flag && !buffer.ephemeral);
+        if other_dirty {
+            self.switch_pane();
+            return self.confirm_close();
+        }
+        true
+    }
+
+    /// Whether the buffer being edited can be closed, asking what to do with unsaved changes first
+    fn confirm_close(&mut self) -> bool {
+        if !self.dirty_flag || self.ephemeral || self.print_on_exit {
+            return true;
+        }
+
+        let name = self
+            .path
+            .clone()
+            .unwrap_or_else(|| String::from("the buffer"));
+        let lines = self.rows.len();
+        let question = format!("{name} ({lines} lines) has unsaved changes, save before quitting?");
+        let choices = vec![
+            Choice {
+                key: 'y',
+                label: "save and quit",
+            },
+            Choice {
+                key: 'n',
+                label: "quit without saving",
+            },
+            Choice {
+                key: 'c',
+                label: "keep editing",
+            },
+        ];
+
+        match self.confirm(&question, choices) {
+            Ok(Some('y')) => match self.save() {
+                Ok(saved) => saved.is_some(),
+                Err(err) => {
+                    self.set_message(&format!("Error saving file: {err}"));
+                    false
+                }
+            },
+            Ok(Some('n')) => true,
+            _ => false,
+        }
+    }
+
+    /// Shows the message held back while a prompt was open, its time on screen starts now
+    fn release_queued_message(&mut self) {
+        self.message = self.queued_message.take().unwrap_or_default();
+        self.message.time = Instant::now();
+    }
+
+    /// How long to wait for input before the editor has something to do on its own: take another
+    /// look at a size the terminal couldn't report, or drop the message. `None` to wait for as long
+    /// as it takes, resizes interrupt the wait anyway.
+    fn wait_time(&self) -> Option<Duration> {
+        if !self.fixed_size && self.terminal.size().is_err() {
+            return Some(SIZE_POLL_INTERVAL);
+        }
+        let wait = self
+            .message_expires()
+            .then(|| MESSAGE_DURATION.saturating_sub(self.message.time.elapsed()));
+        if self.loader.is_some() {
+            return Some(wait.map_or(LOAD_POLL_INTERVAL, |wait| wait.min(LOAD_POLL_INTERVAL)));
+        }
+        wait
+    }
+
+    /// Takes in the rows read in the background so far. True when there were any, or reading
+    /// ended, and the screen needs drawing again.
+    fn receive_rows(&mut self) -> bool {
+        self.take_rows(|_| false)
+    }
+
+    /// Waits for rows read in the background until there are at least `rows` of them or the file
+    /// is all read
+    fn load_until(&mut self, rows: usize) {
+        self.take_rows(|editor| editor.rows.len() < rows);
+    }
+
+    /// The buffer as `--print-on-exit` writes it to stdout, all of it even when still being read
+    fn printed_text(&mut self) -> String {
+        self.finish_loading();
+        rows_to_text(&self.rows)
+    }
+
+    /// Waits for the whole of a file being read in the background, for what looks through all of it
+    fn finish_loading(&mut self) {
+        self.take_rows(|_| true);
+    }
+
+    fn take_rows(&mut self, wait: impl Fn(&Self) -> bool) -> bool {
+        let mut received = false;
+        loop {
+            let waiting = wait(self);
+            let Some(loader) = &mut self.loader else {
+                return received;
+            };
+            let Some(chunk) = loader.next(waiting) else {
+                return received;
+            };
+            received = true;
+            match chunk {
+                loader::Chunk::Rows(rows, _) => self.rows.append(rows),
+                loader::Chunk::Done(lines) => {
+                    self.loader = None;
+                    self.line_ending = lines.line_ending(&mut self.rows);
+                    self.final_newline = lines.final_newline;
+                    self.lossy = lines.lossy;
+                    self.rows.shrink_to_fit();
+                    self.reset_baseline();
+                    if self.lossy {
+                        self.set_message(
+                            "Not valid UTF-8, bad bytes are shown as \u{fffd} and saved that way",
+                        );
+                    }
+                }
+                loader::Chunk::Failed(err) => {
+                    // Saving what was read would cut the file short
+                    self.loader = None;
+                    self.readonly = true;
+                    self.reset_baseline();
+                    self.set_message(&format!(
+                        "Couldn't read all of the file, showing the start read-only: {err}"
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Waits for work in the background that needs the buffer as it is, `poll` waiting up to the
+    /// time it's given for what the work came to. The status bar says what's `doing` once it takes
+    /// a while. Every key typed meanwhile, pasted or replayed from a macro is held back instead of
+    /// handled, `freeze` says what becomes of them.
+    fn freeze<T>(
+        &mut self,
+        doing: &str,
+        freeze: Freeze,
+        mut poll: impl FnMut(Duration) -> Option<T>,
+    ) -> Thawed<T> {
+        let started = Instant::now();
+        let mut noticed = false;
+        let mut held = Held::default();
+        loop {
+            let result = poll(FREEZE_POLL_INTERVAL);
+            // Whatever's come in by now, up to the work finishing, is held back. A read that
+            // fails is the end of the keys, and the work is waited for all the same.
+            while let Ok(Some(event)) = self.input.next(Some(Duration::ZERO)) {
+                if matches!(
+                    (freeze, event),
+                    (Freeze::Rejected, Event::Escape(EscapeSequence::Bare))
+                ) {
+                    return Thawed {
+                        result: None,
+                        dropped: held.len(),
+                    };
+                }
+                self.input.hold(&mut held);
+            }
+            if let Some(result) = result {
+                return match freeze {
+                    Freeze::Replayed => {
+                        self.input.put_back(held);
+                        Thawed {
+                            result: Some(result),
+                            dropped: 0,
+                        }
+                    }
+                    Freeze::Rejected => Thawed {
+                        result: Some(result),
+                        dropped: held.len(),
+                    },
+                };
+            }
+
+            let resized = matches!(self.refresh_size(), Geometry::Resized);
+            if !noticed && started.elapsed() >= FREEZE_NOTICE_AFTER {
+                noticed = true;
+                self.set_message(&match freeze {
+                    Freeze::Replayed => format!("{doing}\u{2026} keys wait until it's done"),
+                    Freeze::Rejected => format!("{doing}\u{2026} Escape cancels"),
+                });
+                self.draw();
+            } else if resized {
+                self.draw();
+            }
+        }
+    }
+
+    /// Whether the message goes away with time. Without a file the help message is all the bar has
+    /// to say, so it stays up.
+    fn message_expires(&self) -> bool {
+        self.file.is_some() && !self.prompt_active && self.message.message.is_some()
+    }
+
+    /// What's done when no input came in time: take in rows read so far, follow a resize and drop
+    /// an expired message. Only what changed is drawn, for an expired message that's the status
+    /// bar. True when it drew.
+    fn idle(&mut self) -> bool {
+        let received = self.receive_rows();
+        if matches!(self.refresh_size(), Geometry::Resized) || self.expire_message() || received {
+            self.draw();
+            return true;
+        }
+        false
+    }
+
+    /// Drops the message once its time is up. True when it was still showing, and the status bar
+    /// needs drawing again.
+    fn expire_message(&mut self) -> bool {
+        if !self.message_expires() || self.message.time.elapsed() < MESSAGE_DURATION {
+            return false;
+        }
+
+        self.message.message = None;
+        true
+    }
+
+    /// Shows a message in the status bar, or holds it back until the open prompt closes
+    fn set_message(&mut self, message: &str) {
+        let message = SystemMessage::new(message);
+        if self.message_log.len() == MAX_LOGGED_MESSAGES {
+            self.message_log.pop_front();
+        }
+        self.message_log
+            .push_back((message.time, message.message.clone().unwrap_or_default()));
+        if self.prompt_active {
+            self.queued_message = Some(message);
+        } else {
+            self.message = message;
+        }
+    }
+
+    /// Notes that the rows changed, the highlighted matches and the selection may not be there
+    /// anymore
+    fn mark_edited(&mut self) {
+        self.dirty_flag = true;
+        self.edits += 1;
+        self.search = None;
+        self.mark = None;
+    }
+
+    /// Whether the buffer can be edited, telling the user why not when it can't
+    fn check_writable(&mut self) -> bool {
+        if self.listing.is_some() {
+            self.set_message("A directory listing can't be edited, Enter opens an entry");
+            return false;
+        }
+        if let Some(loader) = &self.loader {
+            let percent = loader.percent();
+            self.set_message(&format!(
+                "Still reading the file ({percent}%), it can be edited once it's all read"
+            ));
+            return false;
+        }
+        if self.readonly {
+            self.set_message("Buffer is read-only");
+        }
+        !self.readonly
+    }
+
+    fn toggle_overwrite(&mut self) {
+        self.overwrite = !self.overwrite;
+        self.apply_cursor_shape();
+        if self.overwrite {
+            self.set_message("Overwrite mode, typing replaces text");
+        } else {
+            self.set_message("Insert mode");
+        }
+    }
+
+    /// Draws the cursor in the configured shape while inserting, and as a block while overwriting,
+    /// an underline if the configured shape is already a block so the modes still look different
+    fn apply_cursor_shape(&self) {
+        let shape = match (self.overwrite, self.config.cursor_shape) {
+            (false, shape) => shape,
+            (true, CursorShape::Block) => CursorShape::Underline,
+            (true, _) => CursorShape::Block,
+        };
+        send_esc_seq(CtrlSeq::CursorShape(shape, self.config.cursor_blink));
+    }
+
+    fn record_macro(&mut self) {
+        if self.input.replaying() {
+            return;
+        }
+        if let Some(recorded) = self.input.stop_recording() {
+            let message = match self.key_name(Action::ReplayMacro) {
+                Some(key) => format!("Recorded {} keys, {key} replays them", recorded.len()),
+                None => format!("Recorded {} keys", recorded.len()),
+            };
+            self.set_message(&message);
+            self.last_macro = recorded;
+        } else {
+            self.input.start_recording();
+            let message = match self.key_name(Action::RecordMacro) {
+                Some(key) => format!("Recording keys, {key} stops"),
+                None => String::from("Recording keys"),
+            };
+            self.set_message(&message);
+        }
+    }
+
+    /// Has the keys recorded last taken next, through the main loop like they were typed again,
+    /// as many times as the count typed ahead of it says. Nothing is drawn until they've all been
+    /// handled, presses of the key that came in together included.
+    fn replay_macro(&mut self) {
+        let count = self.replay_count.take().unwrap_or(1);
+        // A macro with this key in it would replay itself for ever
+        if self.input.replaying() {
+            return;
+        }
+        if self.last_macro.is_empty() {
+            let message = match self.key_name(Action::RecordMacro) {
+                Some(key) => format!("No keys recorded, {key} starts recording"),
+                None => String::from("No keys recorded"),
+            };
+            self.set_message(&message);
+            return;
+        }
+        for _ in 0..count {
+            self.input.replay(&self.last_macro);
+        }
+    }
+
+    /// Adds a digit typed with Alt to the count for the next replay
+    fn count_replays(&mut self, digit: u8) {
+        let count = self.replay_count.unwrap_or(0) * 10 + usize::from(digit);
+        let count = count.min(MAX_REPLAYS);
+        self.replay_count = Some(count);
+        let message = match self.key_name(Action::ReplayMacro) {
+            Some(key) => format!("{key} replays the macro {count} times"),
+            None => format!("The macro replays {count} times"),
+        };
+        self.set_message(&message);
+    }
+
+    fn toggle_readonly(&mut self) {
+        self.readonly = !self.readonly;
+        self.set_message(if self.readonly {
+            "Buffer is read-only"
+        } else {
+            "Buffer is editable"
+        });
+    }
+
+    fn insert_newline(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+
+        self.mark_edited();
+        let (y, x) = self.edit_point();
+        if y == self.rows.len() {
+            self.rows.push("");
+        }
+        let tail = self.rows.split_off(y, x);
+        self.rows.insert(y + 1, &tail);
+        // On the bottom row the new line scrolls into view rather than under the status bar
+        self.place_cursor(y + 1, 0);
+    }
+
+    /// Types `c` over the char under the cursor, at the end of the row it's added like in insert
+    /// mode
+    fn overwrite_char(&mut self, c: char) {
+        if !self.check_writable() {
+            return;
+        }
+
+        let (y, x) = self.edit_point();
+        let Some(replaced) = self.rows.get(y).and_then(|row| row[x..].chars().next()) else {
+            self.insert_char(c);
+            return;
+        };
+        self.mark_edited();
+        self.rows
+            .replace_range(y, x..x + replaced.len_utf8(), c.encode_utf8(&mut [0; 4]));
+        self.place_cursor(y, x + c.len_utf8());
+    }
+
+    /// Inserts the next key as it's typed, a control character is drawn in caret notation
+    fn insert_verbatim(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+
+        self.set_message("^V, the next key is inserted as it's typed");
+        self.draw();
+        let c = match self.input.next_verbatim() {
+            Ok(Event::Byte(byte)) if byte.is_ascii() => char::from(byte),
+            Ok(Event::Char(c)) => c,
+            // A byte that can't start a char, like any other invalid UTF-8
+            Ok(_) => char::REPLACEMENT_CHARACTER,
+            // Left for the main loop to run into on its next read
+            Err(_) => return,
+        };
+        self.message = SystemMessage::default();
+        if self.overwrite {
+            self.overwrite_char(c);
+        } else {
+            self.insert_char(c);
+        }
+    }
+
+    fn insert_char(&mut self, c: char) {
+        if !self.check_writable() {
+            return;
+        }
+
+        self.mark_edited();
+        let (y, x) = self.edit_point();
+        // Only an empty buffer has the cursor past its last row
+        if y == self.rows.len() {
+            self.rows.push("");
+        }
+        self.rows.insert_str(y, x, c.encode_utf8(&mut [0; 4]));
+        self.place_cursor(y, x + c.len_utf8());
+    }
+
+    /// Inserts a typed char. With `auto_close` on, an opening bracket or quote comes with its
+    /// closer and the cursor between them, and typing a closer that's already next steps over it.
+    fn type_char(&mut self, c: char) {
+        self.auto_closed = None;
+        // Nothing is typed where nothing can be, not even the closer
+        if !self.check_writable() {
+            return;
+        }
+        if !self.config.auto_close {
+            self.insert_char(c);
+            return;
+        }
+
+        let (y, x) = self.edit_point();
+        let row = self.rows.get(y).unwrap_or("");
+        if row[x..].starts_with(c) && AUTO_PAIRS.iter().any(|(_, close)| *close == c) {
+            self.place_cursor(y, x + c.len_utf8());
+            return;
+        }
+        let previous = row[..x].chars().next_back();
+        let closer = AUTO_PAIRS
+            .iter()
+            .find(|(open, _)| *open == c)
+            .map(|(_, close)| *close)
+            // A quote right after a letter or digit is most likely an apostrophe, as in "don't"
+            .filter(|close| *close != c || !previous.is_some_and(char::is_alphanumeric));
+
+        self.insert_char(c);
+        if let Some(close) = closer {
+            let x = x + c.len_utf8();
+            self.rows.insert_str(y, x, close.encode_utf8(&mut [0; 4]));
+            self.auto_closed = Some((y, x));
+        }
+    }
+
+    /// Where the cursor edits, as (row, index into it). The cursor should always be on a char
+    /// boundary of its row, or at the start of the row after the last one in an empty buffer.
+    /// A movement bug that leaves it anywhere else is logged and the cursor is pulled back, rather
+    /// than the edit panicking or landing in the wrong place.
+    fn edit_point(&mut self) -> (usize, usize) {
+        let y = self.row_offset + self.cur_pos.y;
+        let x = self.cur_pos.x;
+        let checked = match self.rows.get(y) {
+            Some(row) => split_point(row, x),
+            None if y == self.rows.len() && x == 0 => Ok(0),
+            None => Err(0),
+        };
+
+        if let Ok(x) = checked {
+            return (y, x);
+        }
+
+        let report = format!(
+            "cursor out of sync: line {} index {x}, the buffer has {} lines",
+            y + 1,
+            self.rows.len()
+        );
+        debug_assert!(false, "{}", report);
+        let _ = state::log(&report);
+        self.set_message("Cursor out of sync, repositioned");
+
+        let y = y.min(self.rows.len().saturating_sub(1));
+        let row = self.rows.get(y).unwrap_or("");
+        let x = split_point(row, x).unwrap_or_else(|clamped| clamped);
+        self.place_cursor(y, x);
+        (y, x)
+    }
+
+    /// Puts the cursor on row `y` at index `x`, scrolling the least needed to show it
+    fn place_cursor(&mut self, y: usize, x: usize) {
+        self.scroll_to_row(y);
+        self.cur_pos.x = x;
+        self.scroll_horizontal();
+    }
+
+    /// Inserts pasted text at the cursor, leaving the cursor at its end. Big pastes are confirmed
+    /// first, and the rows are built in chunks between which progress is shown and Escape aborts.
+    /// The buffer is only touched once every row is built, so an aborted paste leaves it as it was.
+    fn paste(&mut self, paste: &[u8]) {
+        if !self.check_writable() {
+            return;
+        }
+
+        let limit = self.config.paste_limit_mb.saturating_mul(1024 * 1024);
+        if paste.len() > limit {
+            let question = format!("Paste {} MB?", paste.len().div_ceil(1024 * 1024));
+            let choices = vec![
+                Choice {
+                    key: 'y',
+                    label: "paste it",
+                },
+                Choice {
+                    key: 'n',
+                    label: "drop it",
+                },
+            ];
+            if !matches!(self.confirm(&question, choices), Ok(Some('y'))) {
+                self.set_message("Paste dropped");
+                return;
+            }
+        }
+
+        // Terminals send newlines in pastes as '\r', other control characters can't be typed either
+        let text = String::from_utf8_lossy(paste)
+            .replace("\r\n", "\n")
+            .replace('\r', "\n");
+        let text: String = text
+            .chars()
+            .filter(|c| !c.is_control() || *c == '\n' || *c == '\t')
+            .collect();
+        let lines: Vec<&str> = text.split('\n').collect();
+
+        let mut pasted: Vec<Row> = Vec::with_capacity(lines.len());
+        for (idx, chunk) in lines.chunks(PASTE_CHUNK_LINES).enumerate() {
+            if idx != 0 {
+                if self.input.escape_pressed() {
+                    self.set_message("Paste cancelled");
+                    return;
+                }
+                let done = idx * PASTE_CHUNK_LINES * 100 / lines.len();
+                self.set_message(&format!("Pasting... {done}% (Escape cancels)"));
+                self.draw();
+            }
+            pasted.extend(chunk.iter().map(|line| Row::from(*line)));
+        }
+
+        let y = (self.row_offset + self.cur_pos.y).min(self.rows.len());
+        let row = self.rows.get(y).unwrap_or("");
+        let x = self.cur_pos.x.min(row.len());
+        let (head, tail) = row.split_at(x);
+
+        pasted[0].insert_str(0, head);
+        let last = pasted.last_mut().unwrap();
+        let end = last.len();
+        last.push_str(tail);
+
+        let replaced = y..(y + 1).min(self.rows.len());
+        let last_row = y + pasted.len() - 1;
+        self.rows.splice(replaced, pasted);
+        self.mark_edited();
+        self.scroll_to_row(last_row);
+        self.col_offset = 0;
+        self.cur_pos.x = end;
+        self.set_message("");
+    }
+
+    /// Inserts a tab, or `tab_size` spaces when tabs are expanded
+    fn insert_tab(&mut self) {
+        if self.expand_tab {
+            for _ in 0..self.tab_size {
+                self.insert_char(' ');
+            }
+        } else {
+            self.insert_char('\t');
+        }
+    }
+
+    /// Shifts the lines of the selection, or the cursor's line without one, right or left by an
+    /// indent: a tab, or `tab_size` spaces when tabs are expanded. Shifting left takes off at most
+    /// that much of the leading whitespace, shifting right leaves empty lines alone. The selection
+    /// and the cursor move along with their lines, so the lines can be shifted again.
+    fn shift_lines(&mut self, right: bool) {
+        if !self.check_writable() {
+            return;
+        }
+
+        let cursor = self.edit_point();
+        let (first, last) = match self.selection() {
+            // A selection ending at the start of a line doesn't take that line along
+            Some(((start_y, _), (end_y, 0))) if end_y > start_y => (start_y, end_y - 1),
+            Some(((start_y, _), (end_y, _))) => (start_y, end_y),
+            None => (cursor.0, cursor.0),
+        };
+        let indent = if self.expand_tab {
+            " ".repeat(usize::from(self.tab_size))
+        } else {
+            String::from("\t")
+        };
+
+        // How many bytes each line grew or shrank by at its start
+        let mut shifted = Vec::new();
+        let tab_size = self.tab_size;
+        self.rows.rewrite(first..last + 1, |row| {
+            let (bytes, shifted_row) = if row.is_empty() {
+                (0, None)
+            } else if right {
+                (indent.len(), Some(format!("{indent}{row}")))
+            } else {
+                let end = indent_end(row, tab_size);
+                (end, (end != 0).then(|| row[end..].to_string()))
+            };
+            shifted.push(bytes);
+            shifted_row
+        });
+        if shifted.iter().all(|bytes| *bytes == 0) {
+            return;
+        }
+
+        // A point at the start of its line stays there, so a selection from there takes in the indent
+        let moved = |(y, x): (usize, usize)| {
+            let bytes = y
+                .checked_sub(first)
+                .and_then(|idx| shifted.get(idx))
+                .copied()
+                .unwrap_or(0);
+            match x {
+                0 => (y, 0),
+                x if right => (y, x + bytes),
+                x => (y, x.saturating_sub(bytes)),
+            }
+        };
+        let mark = self.mark.map(moved);
+        self.mark_edited();
+        self.mark = mark;
+        let (y, x) = moved(cursor);
+        self.place_cursor(y, x);
+    }
+
+    fn remove_char(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+
+        let (y, x) = self.edit_point();
+        if self.auto_closed.take() == Some((y, x)) {
+            let closer = self.rows[y][x..].chars().next().map_or(0, char::len_utf8);
+            self.rows.replace_range(y, x..x + closer, "");
+        }
+        if x == 0 {
+            // Joins the row onto the one above, the cursor lands where they meet
+            if y == 0 || y >= self.rows.len() {
+                return;
+            }
+            let row = self.rows.remove(y);
+            let join = self.rows[y - 1].len();
+            self.rows.push_str(y - 1, &row);
+            self.mark_edited();
+            self.place_cursor(y - 1, join);
+        } else {
+            let removed = self.rows[y][..x]
+                .chars()
+                .next_back()
+                .map_or(1, char::len_utf8);
+            self.rows.replace_range(y, x - removed..x, "");
+            self.mark_edited();
+            self.place_cursor(y, x - removed);
+        }
+    }
+
+    /// Removes the char under the cursor, at the end of a row that's joining the next row onto it
+    fn remove_char_forward(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+
+        let (y, x) = self.edit_point();
+        let Some(row) = self.rows.get(y) else {
+            return;
+        };
+        if let Some(c) = row[x..].chars().next() {
+            self.rows.replace_range(y, x..x + c.len_utf8(), "");
+        } else if y + 1 < self.rows.len() {
+            let next = self.rows.remove(y + 1);
+            self.rows.push_str(y, &next);
+        } else {
+            return;
+        }
+        self.mark_edited();
+        self.place_cursor(y, x);
+    }
+
+    /// Moves to the partner of the bracket under the cursor, or of the first bracket after it on
+    /// the row, like vim's `%`. Without one the cursor stays put.
+    fn goto_matching_bracket(&mut self) {
+        let (y, x) = self.edit_point();
+        let partner = self
+            .rows
+            .get(y)
+            .and_then(|row| brackets::next_in_row(row, x))
+            .and_then(|x| brackets::partner(&self.rows, y, x));
+
+        match partner {
+            Some(brackets::Partner::Found(row, column)) => self.place_cursor(row, column),
+            Some(brackets::Partner::TooFar) => {
+                self.set_message("No matching bracket close enough to find");
+            }
+            _ => self.set_message("No matching bracket"),
+        }
+    }
+
+    /// Sets the mark at the cursor, the selection grows from there as the cursor moves
+    fn set_mark(&mut self) {
+        self.mark = Some(self.edit_point());
+        self.set_message("Mark set");
+    }
+
+    /// Counts like `wc` does, of the selection if there is one and otherwise of the whole buffer.
+    /// Bytes are what saving would write, line endings included, so they differ from characters
+    /// for anything beyond ASCII as well as for CRLF files.
+    fn word_count(&mut self) {
+        let Some(last) = self.rows.len().checked_sub(1) else {
+            self.set_message("0 lines, 0 words, 0 characters, 0 bytes");
+            return;
+        };
+        let selection = self.selection();
+        let ((start_y, start_x), (end_y, end_x)) =
+            selection.unwrap_or(((0, 0), (last, self.rows[last].len())));
+
+        let (mut words, mut chars, mut bytes) = (0, 0, 0);
+        for y in start_y..=end_y {
+            let row = &self.rows[y];
+            let from = if y == start_y { start_x } else { 0 };
+            let to = if y == end_y { end_x } else { row.len() };
+            let part = &row[from..to];
+            // A line break always ends a word, so counting row by row splits them the same
+            words += part.split_whitespace().count();
+            chars += part.chars().count();
+            bytes += part.len();
+        }
+        let newline = self.line_ending.as_str();
+        let breaks = end_y - start_y + usize::from(selection.is_none() && self.final_newline);
+        chars += breaks * newline.chars().count();
+        bytes += breaks * newline.len();
+
+        let lines = end_y - start_y + 1;
+        let counts = format!("{lines} lines, {words} words, {chars} characters, {bytes} bytes");
+        if selection.is_some() {
+            self.set_message(&format!("Selection: {counts}"));
+        } else {
+            self.set_message(&counts);
+        }
+    }
+
+    /// The selection's start and end, in the order they're in the buffer. `None` without a mark,
+    /// or when the cursor is back on it and there's nothing between them.
+    fn selection(&self) -> Option<((usize, usize), (usize, usize))> {
+        let mark = self.mark?;
+        let cursor = (self.row_offset + self.cur_pos.y, self.cur_pos.x);
+        match mark.cmp(&cursor) {
+            Ordering::Less => Some((mark, cursor)),
+            Ordering::Greater => Some((cursor, mark)),
+            Ordering::Equal => None,
+        }
+    }
+
+    /// The selected text, a row for each line it touches
+    fn selected_rows(&self) -> Option<Vec<Row>> {
+        let ((start_y, start_x), (end_y, end_x)) = self.selection()?;
+        if start_y == end_y {
+            return Some(vec![Row::from(&self.rows[start_y][start_x..end_x])]);
+        }
+
+        let mut rows = vec![Row::from(&self.rows[start_y][start_x..])];
+        rows.extend(self.rows.range(start_y + 1..end_y).map(Row::from));
+        rows.push(Row::from(&self.rows[end_y][..end_x]));
+        Some(rows)
+    }
+
+    /// Puts the selection in the kill buffer and on the clipboard
+    fn copy_selection(&mut self) {
+        self.edit_point();
+        let Some(rows) = self.selected_rows() else {
+            self.set_message("Nothing selected");
+            return;
+        };
+
+        self.copy_to_clipboard(&rows.join("\n"));
+        self.set_message(&format!("Copied {} lines", rows.len()));
+        self.kill_buffer = rows;
+    }
+
+    /// Copies the selection and deletes it
+    fn cut_selection(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+        self.copy_selection();
+        self.delete_selection();
+    }
+
+    /// Removes the selected text, joining what's left of its first and last rows. The cursor lands
+    /// where the selection started.
+    fn delete_selection(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+
+        self.edit_point();
+        let Some(((start_y, start_x), (end_y, end_x))) = self.selection() else {
+            return;
+        };
+        let tail = self.rows.split_off(end_y, end_x);
+        self.rows.splice(start_y + 1..end_y + 1, [""; 0]);
+        self.rows.truncate(start_y, start_x);
+        self.rows.push_str(start_y, &tail);
+
+        self.mark_edited();
+        self.place_cursor(start_y, start_x);
+    }
+
+    /// Cuts the current row into the kill buffer, consecutive kills are collected into one block
+    fn kill_line(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+
+        let y = self.row_offset + self.cur_pos.y;
+        if y >= self.rows.len() {
+            return;
+        }
+
+        if !self.appending_kill {
+            self.kill_buffer.clear();
+        }
+        self.kill_buffer.push(self.rows.remove(y));
+        self.appending_kill = true;
+        self.mark_edited();
+        self.copy_to_clipboard(&rows_to_text(&self.kill_buffer));
+
+        self.scroll_to_row(y.min(self.rows.len().saturating_sub(1)));
+        self.cur_pos.x = 0;
+        self.col_offset = 0;
+    }
+
+    /// Cuts back to the start of the word before the cursor, whitespace in between included like
+    /// readline's Ctrl-W, or on to the end of the word after it. Words are what word moves go by,
+    /// and neither goes past the row: joining rows is what Backspace and Delete are for.
+    fn kill_word(&mut self, forward: bool) {
+        if !self.check_writable() {
+            return;
+        }
+
+        let (y, x) = self.edit_point();
+        let Some(row) = self.rows.get(y) else {
+            return;
+        };
+        let (start, end) = if forward {
+            (x, next_word_boundary(row, x))
+        } else {
+            (previous_word_boundary(row, x), x)
+        };
+        if start == end {
+            return;
+        }
+
+        let killed = String::from(&row[start..end]);
+        self.rows.replace_range(y, start..end, "");
+        self.mark_edited();
+        self.copy_to_clipboard(&killed);
+        self.kill_buffer = vec![killed];
+        self.place_cursor(y, start);
+    }
+
+    /// Inserts a copy of the current row under it and moves the cursor onto the copy
+    fn duplicate_line(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+
+        let y = self.row_offset + self.cur_pos.y;
+        if let Some(row) = self.rows.get(y).map(Row::from) {
+            self.rows.insert(y + 1, &row);
+            self.mark_edited();
+            self.scroll_to_row(y + 1);
+        }
+    }
+
+    /// Appends the next row to the current one, its indentation collapsed into a single space.
+    /// The cursor lands on the join so repeated presses keep gluing lines on.
+    fn join_lines(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+
+        let y = self.row_offset + self.cur_pos.y;
+        if y + 1 >= self.rows.len() {
+            self.set_message("No line below to join");
+            return;
+        }
+
+        let next = self.rows.remove(y + 1);
+        let row = &self.rows[y];
+        let join = row.trim_end().len();
+        let next = next.trim_start();
+        let space = if join != 0 && !next.is_empty() {
+            " "
+        } else {
+            ""
+        };
+        self.rows
+            .replace_range(y, join..row.len(), &format!("{space}{next}"));
+
+        self.mark_edited();
+        self.col_offset = 0;
+        self.cur_pos.x = join;
+    }
+
+    /// Swaps the current row with the one above (or below) it, the cursor moves along with the row
+    fn move_line(&mut self, up: bool) {
+        if !self.check_writable() {
+            return;
+        }
+
+        let y = self.row_offset + self.cur_pos.y;
+        let target = if up {
+            y.checked_sub(1)
+        } else {
+            Some(y + 1).filter(|target| *target < self.rows.len())
+        };
+
+        if let Some(target) = target.filter(|_| y < self.rows.len()) {
+            self.rows.swap(y, target);
+            self.mark_edited();
+            self.scroll_to_row(target);
+        }
+    }
+
+    /// Pastes the system clipboard, or the kill buffer when it can't be read, above the current row,
+    /// leaving the cursor on the row after it
+    fn yank(&mut self) {
+        if !self.check_writable() {
+            return;
+        }
+
+        let pasted: Vec<Row> = match self.read_clipboard() {
+            Some(text) => text.lines().map(String::from).collect(),
+            None => self.kill_buffer.clone(),
+        };
+        if pasted.is_empty() {
+            return;
+        }
+
+        let y = (self.row_offset + self.cur_pos.y).min(self.rows.len());
+        let count = pasted.len();
+        self.rows.splice(y..y, pasted);
+        self.mark_edited();
+
+        self.scroll_to_row(y + count);
+        self.cur_pos.x = 0;
+        self.col_offset = 0;
+    }
+
+    /// Hands copied text to the terminal's clipboard, unless it's disabled or too big for it
+    fn copy_to_clipboard(&mut self, text: &str) {
+        if !self.config.osc52 {
+            return;
+        }
+
+        let encoded = clipboard::base64_encode(text.as_bytes());
+        if encoded.len() > clipboard::MAX_PAYLOAD {
+            self.set_message("Too large for the system clipboard, only kept in rilo");
+        } else {
+            send_esc_seq(CtrlSeq::SetClipboard(encoded));
+        }
+    }
+
+    fn read_clipboard(&mut self) -> Option<String> {
+        if self.config.osc52 && self.config.osc52_paste {
+            clipboard::read(&mut self.input)
+        } else {
+            None
+        }
+    }
+
+    fn handle_mouse(&mut self, event: &MouseEvent) {
+        match *event {
+            MouseEvent::Click { x, y } => self.click(x, y),
+            MouseEvent::WheelUp => self.scroll_view(-3),
+            MouseEvent::WheelDown => self.scroll_view(3),
+        }
+    }
+
+    /// Moves the cursor to the character drawn at a screen cell, clicks outside the text are ignored
+    fn click(&mut self, x: usize, y: usize) {
+        let mut y = y;
+        if let Some(split) = self.shown_split() {
+            // A click in the other pane moves there first, the status line between them is skipped
+            let (top, _) = self.pane_heights();
+            if y == top {
+                return;
+            }
+            if (y < top) != split.top_active {
+                self.switch_pane();
+            }
+            if y > top {
+                y -= top + 1;
+            }
+        }
+
+        // A click on the gutter goes to the start of the row
+        let x = x.saturating_sub(self.gutter_width());
+        if y > self.term_rows || self.rows.is_empty() {
+            return;
+        }
+
+        let (row, rx) = if self.wrap {
+            // Walk down the wrapped rows to find the one covering the clicked screen row
+            let mut row = self.row_offset;
+            let mut remaining = y + self.wrap_skip;
+            while row + 1 < self.rows.len() && remaining >= self.visual_rows(&self.rows[row]) {
+                remaining -= self.visual_rows(&self.rows[row]);
+                row += 1;
+            }
+            (row, remaining * self.wrap_width() + x)
+        } else {
+            let row = (self.row_offset + y).min(self.rows.len() - 1);
+            // The columns of the clip markers belong to the text next to them
+            let x = x.saturating_sub(self.left_marker_width());
+            (row, self.col_offset + x)
+        };
+
+        let cx = rx_to_cx(&self.rows[row], rx, self.tab_size);
+        self.place_cursor(row, cx);
+    }
+
+    /// Scrolls the window by `delta` rows, the cursor stays on its row unless that row leaves the window
+    fn scroll_view(&mut self, delta: isize) {
+        let y = self.row_offset + self.cur_pos.y;
+        let rx = self.row_rx(y, self.cur_pos.x);
+        let last_offset = self.rows.len().saturating_sub(self.term_rows + 1);
+        self.row_offset = self
+            .row_offset
+            .saturating_add_signed(delta)
+            .min(last_offset.max(self.row_offset));
+
+        let y = y.clamp(self.row_offset, self.row_offset + self.term_rows);
+        self.cur_pos.y = y - self.row_offset;
+        if self.wrap {
+            while self.cur_pos.y != 0 && self.screen_cursor().y > self.term_rows {
+                self.cur_pos.y -= 1;
+            }
+        }
+
+        // The cursor keeps its column on screen, landing on the char there on a row it's moved to
+        self.cur_pos.x = self.row_cx(self.row_offset + self.cur_pos.y, rx);
+        self.scroll_horizontal();
+    }
+
+    /// Puts the cursor on row `y` of the file, scrolling the least needed to keep it on screen
+    /// along with the margin around it. Near the start and end of the file the margin shrinks to
+    /// the rows there are.
+    fn scroll_to_row(&mut self, y: usize) {
+        let margin = self.scroll_margin();
+        let top = y.saturating_sub(margin);
+        let bottom = (y + margin).min(self.rows.len().saturating_sub(1)).max(y);
+        if top < self.row_offset {
+            self.row_offset = top;
+        } else if bottom > self.row_offset + self.term_rows {
+            self.row_offset = bottom - self.term_rows;
+        }
+        self.cur_pos.y = y - self.row_offset;
+    }
+
+    /// Scrolls the cursor's line to the middle of the window, and on the next presses to the top
+    /// and the bottom, as far as the file goes. The cursor stays on its line.
+    fn recenter(&mut self) {
+        let y = self.row_offset + self.cur_pos.y;
+        let above = match self.recenters % 3 {
+            0 => self.term_rows / 2,
+            1 => self.scroll_margin(),
+            _ => self.term_rows - self.scroll_margin(),
+        };
+        self.recenters += 1;
+
+        if !self.wrap {
+            let last_offset = self.rows.len().saturating_sub(self.term_rows + 1);
+            self.row_offset = y.saturating_sub(above).min(last_offset);
+            self.cur_pos.y = y - self.row_offset;
+            return;
+        }
+
+        // Only the cursor's own row can be partly scrolled off the top, the rows above it are
+        // shown whole or not at all
+        let (visual_row, _) = self
+            .rows
+            .get(y)
+            .map_or((0, 0), |line| self.visual_position(line, self.cur_pos.x));
+        self.row_offset = y;
+        self.wrap_skip = visual_row.saturating_sub(above);
+        let mut remaining = above.saturating_sub(visual_row);
+        while self.row_offset > 0 {
+            let rows = self.visual_rows(&self.rows[self.row_offset - 1]);
+            if rows > remaining {
+                break;
+            }
+            remaining -= rows;
+            self.row_offset -= 1;
+        }
+        if self.row_offset != y {
+            self.wrap_skip = 0;
+        }
+        self.cur_pos.y = y - self.row_offset;
+    }
+
+    /// Checks the terminal's size and colors and paints the whole screen again on the next draw,
+    /// for a screen that got mangled. Resizes are normally picked up on their own.
+    fn redraw(&mut self) {
+        self.refresh_size();
+        self.colors = color_depth(self.color_mode);
+        send_esc_seq(CtrlSeq::ClearScreen);
+        self.screen.invalidate();
+    }
+
+    /// The rows kept above and below the cursor, at most half the window so the cursor can move
+    fn scroll_margin(&self) -> usize {
+        self.scroll_off.min(self.term_rows / 2)
+    }
+
+    /// Puts the cursor on a 1-based line and character column with the line in the middle of the
+    /// window. A line past the end goes to the last one and says so, a column past the end of the
+    /// line to its end.
+    fn goto(&mut self, line: usize, column: usize) {
+        let last = self.rows.len().max(1);
+        if line > last {
+            self.set_message(&format!("Line {line} is past the end, went to line {last}"));
+        }
+        let y = line.clamp(1, last) - 1;
+        let row = self.rows.get(y).unwrap_or("");
+        let x = row
+            .char_indices()
+            .nth(column.saturating_sub(1))
+            .map_or(row.len(), |(x, _)| x);
+
+        self.row_offset = y.saturating_sub(self.term_rows / 2);
+        self.scroll_to_row(y);
+        self.col_offset = 0;
+        self.cur_pos.x = x;
+        self.scroll_horizontal();
+    }
+
+    fn jump(&mut self, cp: CursorPosition) {
+        if cp.y > self.term_rows {
+            self.row_offset = cp.y;
+            self.cur_pos.y = 0;
+        } else {
+            self.row_offset = 0;
+            self.cur_pos.y = cp.y;
+        }
+        self.scroll_to_row(cp.y);
+
+        // `cp.x` is an index into the row, the window is scrolled by where it's rendered
+        self.col_offset = 0;
+        self.cur_pos.x = cp.x;
+        self.scroll_horizontal();
+    }
+
+    /// Does what an event read from the input asks, the way the main loop does for every key.
+    /// False when it quit.
+    fn handle_event(&mut self, event: Event) -> bool {
+        // Keys moving around the screen can go a screen past it, those need to be there
+        if self.loader.is_some() {
+            self.receive_rows();
+            self.load_until(self.row_offset + 3 * (self.term_rows + 1));
+        }
+
+        // Up and Down keep aiming for the column the first of them started from, anything else
+        // sets a new one
+        if !matches!(
+            event,
+            Event::Escape(EscapeSequence::Navigation(
+                NavigationKey::Up | NavigationKey::Down
+            ))
+        ) {
+            self.goal_column = None;
+        }
+
+        // A sequence bound in the keymap does what it's bound to instead of what it's decoded as
+        let bound = match event {
+            Event::Escape(_) => self.bound_action(self.input.last_bytes()),
+            _ => None,
+        };
+        let action = match event {
+            _ if bound.is_some() => bound,
+            // Delete and Insert are actions like any other key, only they're sent as sequences
+            Event::Escape(EscapeSequence::Delete) => Some(Action::DeleteForward),
+            Event::Escape(EscapeSequence::Insert) => Some(Action::ToggleOverwrite),
+            Event::Escape(EscapeSequence::BackTab) => Some(Action::Dedent),
+            // Alt and a digit counts the replays of the next macro
+            Event::Escape(EscapeSequence::Unknown)
+                if matches!(self.input.last_bytes(), [0x1b, b'0'..=b'9']) =>
+            {
+                let digit = self.input.last_bytes()[1] - b'0';
+                self.count_replays(digit);
+                return true;
+            }
+            Event::Escape(seq) => {
+                self.appending_kill = false;
+                self.recenters = 0;
+                self.auto_closed = None;
+                // A bare Escape clears the count itself, when it's the topmost thing to clear
+                if !matches!(seq, EscapeSequence::Bare) {
+                    self.replay_count = None;
+                }
+                return perform_escape(self, seq);
+            }
+            Event::Byte(byte) => Some(self.action_for(byte)),
+            Event::Char(c) => Some(Action::Input(c)),
+        };
+        if let Some(action) = action {
+            // Any other key ends a run of Ctrl-K presses
+            if !matches!(action, Action::KillLine) {
+                self.appending_kill = false;
+            }
+            if !matches!(action, Action::Recenter) {
+                self.recenters = 0;
+            }
+            if !matches!(action, Action::Input(_) | Action::Delete) {
+                self.auto_closed = None;
+            }
+            if !matches!(action, Action::ReplayMacro) {
+                self.replay_count = None;
+            }
+
+            if !perform(self, action) {
+                return false;
+            }
+            // Moving the cursor keeps the selection going, keys that use it end it themselves and
+            // any other key just ends it. Shifting lines keeps it so they can be shifted again.
+            if !matches!(action, Action::SetMark | Action::Tab | Action::Dedent) {
+                self.mark = None;
+            }
+        }
+        true
+    }
+}
+
+/// Runs rilo the way the command line asks, the whole program short of the binary's `main`
+pub fn main() -> io::Result<()> {
+    // Everything that can exit early has to happen before the terminal is switched to raw mode
+    let mut options = match cli::parse(std::env::args().skip(1)) {
+        Ok(cli::Invocation::Edit(options)) => options,
+        Ok(cli::Invocation::Help) => {
+            print!("{}", cli::USAGE);
+            return Ok(());
+        }
+        Ok(cli::Invocation::Version) => {
+            println!("rilo {}", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        }
+        Err(message) => {
+            eprint!("rilo: {message}\n\n{}", cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    // Piped text has to be read before the terminal takes stdin over. With nothing else to open,
+    // text piped in is edited as if '-' was given.
+    let stdin_piped = !isatty(io::stdin().as_raw_fd()).unwrap_or(true);
+    let nothing_to_open = options.path.is_none() && !options.scratch && !options.tutor;
+    let piped = if options.path.as_deref() == Some("-") || (nothing_to_open && stdin_piped) {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes)?;
+        // Binary junk is better shown mangled than refused outright
+        let text = String::from_utf8_lossy(&bytes);
+        Some(text.lines().map(Row::from).collect())
+    } else {
+        None
+    };
+    // Kept aside so the buffer can be written there once the terminal is restored
+    let output = if options.print_on_exit {
+        let fd = dup(io::stdout().as_raw_fd()).map_err(Error::other)?;
+        Some(unsafe { File::from_raw_fd(fd) })
+    } else {
+        None
+    };
+    // Whether stdout is a terminal can only be told before it's pointed at one
+    if options.color == cli::ColorMode::Auto && !isatty(io::stdout().as_raw_fd()).unwrap_or(true) {
+        options.color = cli::ColorMode::Never;
+    }
+    if let Err(err) = attach_terminal() {
+        exit_with(&error::Error::Setup(err));
+    }
+
+    let mut e = match Editor::new(&options, Config::load()) {
+        Ok(e) => e,
+        Err(err) => exit_with(&error::Error::Setup(err)),
+    };
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore_cursor();
+        leave_alt_screen();
+        default_hook(info);
+    }));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| run(&mut e, &options, piped)));
+    // Only a pipeline gets the text, it's not put together for nothing on every quit
+    let text = output.as_ref().map(|_| e.printed_text());
+    // Dropping the editor restores the terminal, nothing may be printed before that
+    drop(e);
+    if let (Some(mut output), Some(text)) = (output, text) {
+        output.write_all(text.as_bytes())?;
+    }
+
+    match result {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(err)) => exit_with(&err),
+        Err(panic) => panic::resume_unwind(panic),
+    }
+}
+
+/// Reports an error that ended the session, the terminal has to be restored already
+fn exit_with(err: &error::Error) -> ! {
+    eprintln!("rilo: {err}");
+    std::process::exit(1);
+}
+
+/// Edits until the user quits. Failures the editor can carry on from are shown in the status bar
+/// where they happen, only the ones it can't come back here.
+pub fn run(
+    e: &mut Editor,
+    options: &cli::Options,
+    piped: Option<Vec<Row>>,
+) -> Result<(), error::Error> {
+    // Clear the screen
+    send_esc_seq(CtrlSeq::HideCursor);
+    send_esc_seq(CtrlSeq::ClearScreen);
+    send_esc_seq(CtrlSeq::ShowCursor);
+    e.apply_cursor_shape();
+
+    if let Some(rows) = piped {
+        e.open_piped(rows);
+    } else if options.tutor {
+        let path = std::env::temp_dir().join(format!("rilo-tutor-{}.txt", std::process::id()));
+        std::fs::write(&path, tutor::generate(e)).map_err(error::Error::Open)?;
+        e.open(&path, None).map_err(error::Error::Open)?;
+    } else if options.scratch {
+        e.open_scratch();
+    } else if let Some(filename) = &options.path {
+        e.open(filename, options.position)
+            .map_err(error::Error::Open)?;
+    } else if e.config.browse_on_start {
+        e.open(".", None).map_err(error::Error::Open)?;
+    }
+
+    e.draw();
+
+    loop {
+        // A terminal that stopped taking output is gone, drawing into it any longer is pointless
+        if let Some(err) = output_error() {
+            return Err(error::Error::Output(err));
+        }
+        // `kill -TSTP` from elsewhere, Ctrl-Z itself comes in as a key
+        if input::take_suspend_request() {
+            e.suspend();
+            e.draw();
+        }
+        // The wait ends early for a resize, when the message is due to go, or to take in more of
+        // a file being read
+        let Some(event) = e.input.next(e.wait_time()).map_err(error::Error::Input)? else {
+            e.idle();
+            continue;
+        };
+        if !e.handle_event(event) {
+            break;
+        }
+        // Keys that came in together, a paste without bracketed paste or a held key, are drawn once
+        if !e.input.pending() {
+            e.draw();
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether an action looks past the rows around the cursor, so a file still being read in the
+/// background has to be read whole first
+fn reads_whole_buffer(action: Action) -> bool {
+    matches!(
+        action,
+        Action::Find
+            | Action::FindNext
+            | Action::FindPrevious
+            | Action::GotoLine
+            | Action::WordCount
+            | Action::WriteTo
+            | Action::SaveAs
+            | Action::ListAnnotations
+            | Action::NextChange
+            | Action::PreviousChange
+            | Action::NextAnnotation
+            | Action::PreviousAnnotation
+            | Action::CompareFile
+            | Action::MatchingBracket
+    )
+}
+
+/// Does what an escape sequence asks, false when it ran a command that quit
+fn perform_escape(e: &mut Editor, seq: EscapeSequence) -> bool {
+    match seq {
+        EscapeSequence::Navigation(ak) => {
+            // The next blank line can be anywhere further down
+            if matches!(ak, NavigationKey::ParagraphDown) {
+                e.finish_loading();
+            }
+            e.move_cursor(&ak);
+        }
+        EscapeSequence::Delete => return perform(e, Action::DeleteForward),
+        EscapeSequence::Insert => return perform(e, Action::ToggleOverwrite),
+        EscapeSequence::BackTab => return perform(e, Action::Dedent),
+        EscapeSequence::Mouse(event) => e.handle_mouse(&event),
+        EscapeSequence::MoveLineUp => e.move_line(true),
+        EscapeSequence::MoveLineDown => e.move_line(false),
+        EscapeSequence::Paste(paste) => e.paste(&paste),
+        EscapeSequence::RunCommand => {
+            if let Some(action) = e.read_command() {
+                return perform(e, action);
+            }
+        }
+        EscapeSequence::Help => e.show_help(),
+        EscapeSequence::FindNext => return perform(e, Action::FindNext),
+        EscapeSequence::FindPrevious => return perform(e, Action::FindPrevious),
+        EscapeSequence::CycleSearchCase => e.cycle_search_case(),
+        EscapeSequence::Bare => {
+            e.dismiss_topmost();
+        }
+        EscapeSequence::Focus(focused) => e.set_focus(focused),
+        EscapeSequence::Unknown => {}
+    }
+    true
+}
+
+/// Does what an action asks, false when it was quitting and the editor should exit
+fn perform(e: &mut Editor, action: Action) -> bool {
+    if reads_whole_buffer(action) {
+        e.finish_loading();
+    }
+    match action {
+        Action::Quit if !e.confirm_quit() => {}
+        Action::Quit | Action::ForceQuit => {
+            e.remember_buffers();
+            // The alternate screen goes away with the editor on it, the shell's own screen doesn't
+            if !e.terminal.alt_screen() {
+                send_esc_seq(CtrlSeq::ClearScreen);
+                send_esc_seq(CtrlSeq::GotoStart);
+            }
+            return false;
+        }
+        Action::Save if !e.check_writable() => {}
+        // A directory listing takes Enter, Backspace and '.' for browsing
+        Action::Enter if e.listing.is_some() => e.open_entry(),
+        Action::Delete if e.listing.is_some() => e.browse_parent(),
+        Action::Input('.') if e.listing.is_some() => e.toggle_dotfiles(),
+        Action::Save => e.save_and_report(),
+        Action::FindNext => e.find_next(true),
+        Action::FindPrevious => e.find_next(false),
+        Action::GotoLine => e.goto_line(),
+        Action::FindFile => e.find_file(),
+        Action::Find => match e.find() {
+            Ok(cp) => e.jump(cp),
+            Err(err) => e.set_message(&err.to_string()),
+        },
+        Action::Delete | Action::DeleteForward if e.selection().is_some() => e.delete_selection(),
+        Action::Delete => {
+            e.remove_char();
+        }
+        Action::DeleteForward => e.remove_char_forward(),
+        Action::Enter => e.insert_newline(),
+        Action::Input(c) if !c.is_ascii_control() && e.selection().is_some() => {
+            e.delete_selection();
+            e.type_char(c);
+        }
+        Action::Input(c) if !c.is_ascii_control() && e.overwrite => e.overwrite_char(c),
+        Action::Input(c) => {
+            if !c.is_ascii_control() {
+                e.type_char(c);
+            }
+        }
+        Action::ToggleWrap => e.toggle_wrap(),
+        Action::ToggleReadonly => e.toggle_readonly(),
+        Action::ToggleOverwrite => e.toggle_overwrite(),
+        Action::RecordMacro => e.record_macro(),
+        Action::ReplayMacro => e.replay_macro(),
+        Action::InsertVerbatim => e.insert_verbatim(),
+        Action::Tab if e.selection().is_some() => e.shift_lines(true),
+        Action::Tab => e.insert_tab(),
+        Action::Dedent => e.shift_lines(false),
+        Action::KillLine => e.kill_line(),
+        Action::KillPreviousWord => e.kill_word(false),
+        Action::KillNextWord => e.kill_word(true),
+        Action::Yank => e.yank(),
+        Action::Home => e.move_cursor(&NavigationKey::Home),
+        Action::DuplicateLine => e.duplicate_line(),
+        Action::JoinLines => e.join_lines(),
+        Action::ToggleTrim => e.toggle_trim_on_save(),
+        Action::ConvertLineEndings => e.convert_line_endings(),
+        Action::Suspend => e.suspend(),
+        Action::Recenter => {
+            e.recenter();
+            e.redraw();
+        }
+        Action::Redraw => e.redraw(),
+        Action::Help => e.show_help(),
+        Action::WordCount => e.word_count(),
+        Action::ShowConfigErrors => e.show_config_errors(),
+        Action::MessageHistory => e.show_message_history(),
+        Action::ReloadConfig => e.reload_config(),
+        Action::ForgetFileOptions => e.forget_file_options(),
+        Action::WriteTo => e.write_to(false),
+        Action::SaveAs => e.write_to(true),
+        Action::CompareFile => e.compare_file(),
+        Action::CycleSearchCase => e.cycle_search_case(),
+        Action::MatchingBracket => e.goto_matching_bracket(),
+        Action::ScrollDown => e.scroll_view(1),
+        Action::ScrollUp => e.scroll_view(-1),
+        Action::ToggleInvisibles => e.toggle_invisibles(),
+        Action::SetMark => e.set_mark(),
+        Action::Cancel | Action::Copy if e.mark.is_some() => e.copy_selection(),
+        Action::Copy => e.set_message("Nothing selected"),
+        Action::Cut => e.cut_selection(),
+        Action::OtherPane => e.other_pane(),
+        Action::ClosePane => e.close_pane(),
+        Action::ListAnnotations => e.list_annotations(),
+        Action::RecentFiles => e.recent_files(),
+        Action::NextChange => e.goto_change(true),
+        Action::PreviousChange => e.goto_change(false),
+        Action::Revert => e.revert(),
+        Action::NextAnnotation => e.goto_annotation(true),
+        Action::PreviousAnnotation => e.goto_annotation(false),
+        Action::Cancel => {}
+    }
+    true
+}
+
+/// Lays out a status line `width` columns wide: the name, its flags and the message on the left,
+/// the position on the right. What doesn't fit goes in order: the message, then the start of the
+/// name, which is shown from the left as `...path/file.rs`.
+fn status_line(name: &str, flags: &str, message: &str, position: &str, width: usize) -> String {
+    // At least one space between the two sides
+    let room = width.saturating_sub(position.len() + 1);
+    let name = truncate_start(name, room.saturating_sub(flags.len()));
+    let mut left = format!("{name}{flags}");
+    if !message.is_empty() && left.len() + 2 < room {
+        let message = truncate_end(message, room - left.len() - 2);
+        left = format!("{left}  {message}");
+    }
+
+    let line = format!("{left:<room$} {position}");
+    String::from(truncate_end(&line, width))
+}
+
+/// The longest start of `text` at most `width` bytes long, cut on a char boundary
+fn truncate_end(text: &str, width: usize) -> &str {
+    let mut end = width.min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// `text` cut down to at most `width` bytes by dropping its start, marked with `...`
+fn truncate_start(text: &str, width: usize) -> String {
+    if text.len() <= width {
+        return String::from(text);
+    }
+    if width < 3 {
+        return String::new();
+    }
+
+    let mut start = text.len() - (width - 3);
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    format!("...{}", &text[start..])
+}
+
+/// How a screen row's columns are shared out, left to right. The widths always add up to the
+/// terminal's.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Layout {
+    /// The change mark gutter
+    gutter: usize,
+    /// The '<' of a row the window is scrolled sideways on
+    left_marker: usize,
+    /// The row's text
+    text: usize,
+    /// The '>' of a row going on past the window, it takes the text's last column
+    right_marker: usize,
+    /// The terminal's last column, never written to so the terminal doesn't wrap. The cursor sits
+    /// in it past the end of a row that fills the text's columns.
+    margin: usize,
+}
+
+impl Layout {
+    /// The last column the cursor can take, counted from the text's left edge. On a row going on
+    /// past the window, the char under the cursor stays left of the '>'.
+    fn cursor_room(&self) -> usize {
+        if self.right_marker > 0 {
+            self.text.saturating_sub(1)
+        } else {
+            self.text
+        }
+    }
+
+    /// The last column right of the gutter, the cursor never goes past it
+    fn last_column(&self) -> usize {
+        self.left_marker + self.text + self.right_marker + self.margin - 1
+    }
+}
+
+/// Shares out the `width` columns of a screen row. `scrolled` is for a window scrolled sideways,
+/// `continues` for a row going on past the window's right edge, neither matters when soft wrap is
+/// on. Each decoration gets its column while there's one left.
+#[allow(clippy::fn_params_excessive_bools)]
+fn compute_layout(
+    width: usize,
+    change_marks: bool,
+    clip_markers: bool,
+    wrap: bool,
+    scrolled: bool,
+    continues: bool,
+) -> Layout {
+    let mut left = width;
+    let mut take = |wanted: bool| {
+        let taken = usize::from(wanted && left > 0);
+        left -= taken;
+        taken
+    };
+    let margin = take(true);
+    let gutter = take(change_marks);
+    let left_marker = take(clip_markers && !wrap && scrolled);
+    let right_marker = take(clip_markers && !wrap && continues);
+    let layout = Layout {
+        gutter,
+        left_marker,
+        text: left,
+        right_marker,
+        margin,
+    };
+    debug_assert_eq!(
+        layout.gutter + layout.left_marker + layout.text + layout.right_marker + layout.margin,
+        width,
+        "{layout:?} doesn't add up to the terminal's width"
+    );
+    layout
+}
+
+/// Puts the clip marker column in front of a screen row, with a '<' when the row has text left of
+/// the window
+fn with_left_marker(line: Line, clipped: bool) -> Line {
+    let mut text = vec![if clipped { '<' } else { ' ' }];
+    text.extend(line.text);
+    let mut spans = Vec::new();
+    if clipped {
+        spans.push(Span {
+            start: 0,
+            end: 1,
+            style: Style::StatusBar,
+        });
+    }
+    spans.extend(line.spans.into_iter().map(|span| Span {
+        start: span.start + 1,
+        end: span.end + 1,
+        ..span
+    }));
+    let combining = line
+        .combining
+        .into_iter()
+        .map(|(column, c)| (column + 1, c))
+        .collect();
+    Line {
+        text,
+        spans,
+        combining,
+    }
+}
+
+/// A row past the end of the file
+fn filler_line() -> Line {
+    Line {
+        text: vec!['~'],
+        spans: vec![Span {
+            start: 0,
+            end: 1,
+            style: Style::Filler,
+        }],
+        combining: Vec::new(),
+    }
+}
+
+/// The parts of `spans` between the rendered columns `start` and `end`, moved to start from 0
+fn clip_spans(spans: &[Span], start: usize, end: usize) -> Vec<Span> {
+    spans
+        .iter()
+        .filter(|span| span.start < end && span.end > start)
+        .map(|span| Span {
+            start: span.start.max(start) - start,
+            end: span.end.min(end) - start,
+            style: span.style,
+        })
+        .collect()
+}
+
+/// A line of a list drawn over the screen, filling the screen's width in `style`
+fn list_line(text: &str, width: usize, style: Style) -> Line {
+    let mut text: Vec<char> = sanitize(text, width).chars().collect();
+    text.truncate(width);
+    Line {
+        text,
+        spans: vec![Span {
+            start: 0,
+            end: width,
+            style,
+        }],
+        combining: Vec::new(),
+    }
+}
+
+/// Replaces the file at `path` with `content` without ever leaving it half written: the content goes
+/// to a temporary file next to it, which is synced and then renamed over it. `original` is the open
+/// file being replaced, its permissions carry over. Returns the replacing file, opened for writing.
+fn write_atomically(path: &Path, original: &File, content: &[u8]) -> io::Result<File> {
+    fn context(stage: &'static str) -> impl Fn(Error) -> Error {
+        move |err| Error::new(err.kind(), format!("{stage}: {err}"))
+    }
+
+    // Renaming over a symlink would replace the link rather than the file it points to
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{name}.rilo-{}.tmp", std::process::id()));
+    let permissions = original
+        .metadata()
+        .map_err(context("couldn't read the file's permissions"))?
+        .permissions();
+
+    let written = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp)
+        .and_then(|mut file| {
+            file.write_all(content)?;
+            file.set_permissions(permissions)?;
+            file.sync_all()
+        })
+        .map_err(context("couldn't write a temporary copy"))
+        .and_then(|()| fs::rename(&temp, &path).map_err(context("couldn't replace the file")));
+    if let Err(err) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(err);
+    }
+
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&path)
+        .map_err(context("saved, but couldn't reopen the file"))
+}
+
+/// Writes `content` to `path`, replacing the file there atomically or creating it.
+/// Returns the written file, opened for reading and writing.
+fn write_new_or_replace(path: &Path, content: &[u8]) -> io::Result<File> {
+    match File::open(path) {
+        Ok(existing) => write_atomically(path, &existing, content),
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(path)?;
+            file.write_all(content)?;
+            Ok(file)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// What a file looked like on disk, a different stamp means it was written in between
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct DiskStamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+impl DiskStamp {
+    /// `None` when the file doesn't exist, or its modification time isn't available
+    fn read(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(DiskStamp {
+            modified: metadata.modified().ok()?,
+            len: metadata.len(),
+        })
+    }
+}
+
+/// Where the backup of `path`, an absolute path, goes. In a backup directory the whole path is
+/// flattened into the name, `/home/me/notes.txt` becomes `!home!me!notes.txt~` like emacs does,
+/// so files with the same name in different places don't overwrite each other's backups.
+fn backup_path(path: &Path, suffix: &str, dir: Option<&Path>) -> io::Result<PathBuf> {
+    if let Some(dir) = dir {
+        fs::create_dir_all(dir)?;
+        let flat = path.to_string_lossy().replace('/', "!");
+        Ok(dir.join(format!("{flat}{suffix}")))
+    } else {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        Ok(path.with_file_name(format!("{name}{suffix}")))
+    }
+}
+
+/// How a file ends its lines
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum LineEnding {
+    Lf,
+    Crlf,
+    /// Both kinds, the rows ending in CRLF keep their '\r' so every line is written back as it was
+    Mixed,
+}
+
+impl LineEnding {
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf | LineEnding::Mixed => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::Crlf => "CRLF",
+            LineEnding::Mixed => "Mixed",
+        }
+    }
+}
+
+/// Whether a file starting with `sample` is more likely binary than text: it has a NUL byte, or
+/// more than one byte in ten is a control character text doesn't use. Bytes past ASCII never
+/// count, UTF-8 (its BOM too) and the older encodings write text with them.
+fn looks_binary(sample: &[u8]) -> bool {
+    if sample.contains(&0) {
+        return true;
+    }
+    let controls = sample
+        .iter()
+        .filter(|b| b.is_ascii_control() && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b))
+        .count();
+    controls * 10 > sample.len()
+}
+
+/// Text read into rows, with what it takes to write it back the same way
+struct ReadText {
+    rows: Rows,
+    line_ending: LineEnding,
+    final_newline: bool,
+    /// Some of it wasn't valid UTF-8, those bytes were replaced with U+FFFD
+    lossy: bool,
+}
+
+/// Reads text into rows, detecting its line endings and whether it ends with one. Bytes that
+/// aren't valid UTF-8 are replaced, so any file can be read. Only the line being read is held
+/// besides the rows, a big file never has to fit in memory twice.
+fn read_lines(reader: impl BufRead) -> io::Result<ReadText> {
+    let mut lines = LineReader::new(reader);
+    let mut rows = lines.read_rows(usize::MAX)?;
+    rows.shrink_to_fit();
+    let line_ending = lines.line_ending(&mut rows);
+    Ok(ReadText {
+        rows,
+        line_ending,
+        final_newline: lines.final_newline,
+        lossy: lines.lossy,
+    })
+}
+
+/// Reads text into rows a batch at a time, keeping track of what `read_lines` works out about the
+/// whole of it. Big files are read this way in the background while the start of them is shown.
+pub(crate) struct LineReader<R> {
+    reader: R,
+    line: Vec<u8>,
+    final_newline: bool,
+    lossy: bool,
+    /// Rows that had a newline to end them, which are all but maybe the last one, and how many of
+    /// those newlines were CRLF
+    ended: usize,
+    crlf: usize,
+    /// Bytes read so far
+    pub(crate) read: u64,
+}
+
+impl<R: BufRead> LineReader<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: Vec::new(),
+            final_newline: true,
+            lossy: false,
+            ended: 0,
+            crlf: 0,
+            read: 0,
+        }
+    }
+
+    /// Reads up to `limit` more rows, fewer only at the end of the text
+    pub(crate) fn read_rows(&mut self, limit: usize) -> io::Result<Rows> {
+        let mut rows = Rows::new();
+        while rows.len() < limit {
+            self.line.clear();
+            let read = self.reader.read_until(b'\n', &mut self.line)?;
+            if read == 0 {
+                break;
+            }
+            self.read += read as u64;
+            self.final_newline = self.line.ends_with(b"\n");
+            if self.final_newline {
+                self.line.pop();
+                self.ended += 1;
+                self.crlf += usize::from(self.line.ends_with(b"\r"));
+            }
+            let row = String::from_utf8_lossy(&self.line);
+            self.lossy |= matches!(row, Cow::Owned(_));
+            rows.push(&row);
+        }
+        Ok(rows)
+    }
+
+    /// The line endings of everything read, which are all in `rows`. When every line ends in
+    /// CRLF the '\r' is taken off the rows that had one.
+    fn line_ending(&self, rows: &mut Rows) -> LineEnding {
+        if self.crlf == 0 {
+            LineEnding::Lf
+        } else if self.crlf == self.ended {
+            rows.rewrite(0..self.ended, |row| Some(row[..row.len() - 1].to_string()));
+            LineEnding::Crlf
+        } else {
+            LineEnding::Mixed
+        }
+    }
+}
+
+/// Joins rows back into text the way they'd be written to a file
+fn rows_to_text(rows: impl IntoIterator<Item = impl AsRef<str>>) -> String {
+    rows.into_iter().fold(String::new(), |mut text, row| {
+        text.push_str(row.as_ref());
+        text.push('\n');
+        text
+    })
+}
+
+/// Checks `x` is somewhere a row can be split or inserted into: at most its length, and on a char
+/// boundary. When it isn't, the error holds the closest such index before it.
+fn split_point(row: &str, x: usize) -> Result<usize, usize> {
+    if row.is_char_boundary(x) {
+        Ok(x)
+    } else {
+        let mut clamped = x.min(row.len());
+        while !row.is_char_boundary(clamped) {
+            clamped -= 1;
+        }
+        Err(clamped)
+    }
+}
+
+/// The start of the char before `x`, 0 at the start of the line
+fn previous_char_boundary(line: &str, x: usize) -> usize {
+    line[..x.min(line.len())]
+        .char_indices()
+        .next_back()
+        .map_or(0, |(idx, _)| idx)
+}
+
+/// The start of the char after the one at `x`, the line's length at its end
+fn next_char_boundary(line: &str, x: usize) -> usize {
+    line.get(x..)
+        .and_then(|rest| rest.chars().next())
+        .map_or(line.len(), |c| x + c.len_utf8())
+}
+
+/// Letters, digits and underscores make up words, anything else separates them
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+/// The start of the word before `x`, skipping any separators in between
+fn previous_word_boundary(line: &str, x: usize) -> usize {
+    let bytes = &line.as_bytes()[..x.min(line.len())];
+    let end = bytes
+        .iter()
+        .rposition(|b| is_word_byte(*b))
+        .map_or(0, |idx| idx + 1);
+    bytes[..end]
+        .iter()
+        .rposition(|b| !is_word_byte(*b))
+        .map_or(0, |idx| idx + 1)
+}
+
+/// The row vim's `}` goes to from row `y`: the first blank row past the next paragraph, or the
+/// last row when there's none. Going back, like `{`, the last blank row before the previous
+/// paragraph or the first row. Blank rows next to `y` are skipped, so it goes a paragraph at a time.
+fn paragraph_boundary(rows: &Rows, y: usize, forward: bool) -> usize {
+    let blank = |y: &usize| rows[*y].trim().is_empty();
+    if forward {
+        (y + 1..rows.len())
+            .skip_while(blank)
+            .find(blank)
+            .unwrap_or(rows.len().saturating_sub(1))
+    } else {
+        (0..y.min(rows.len()))
+            .rev()
+            .skip_while(blank)
+            .find(blank)
+            .unwrap_or(0)
+    }
+}
+
+/// The end of the leading whitespace making up the first indent of `line`, a tab stop's worth
+fn indent_end(line: &str, tab_size: u8) -> usize {
+    let mut width = 0;
+    for (idx, c) in line.char_indices() {
+        if !matches!(c, ' ' | '\t') || width >= usize::from(tab_size) {
+            return idx;
+        }
+        width += render_width(c, width, tab_size);
+    }
+    line.len()
+}
+
+/// The end of the word after `x`, skipping any separators in between
+fn next_word_boundary(line: &str, x: usize) -> usize {
+    let bytes = line.as_bytes();
+    let x = x.min(bytes.len());
+    let start = bytes[x..]
+        .iter()
+        .position(|b| is_word_byte(*b))
+        .map_or(bytes.len(), |idx| x + idx);
+    bytes[start..]
+        .iter()
+        .position(|b| !is_word_byte(*b))
+        .map_or(bytes.len(), |idx| start + idx)
+}
+
+/// Where Home goes from `x`: the first non-blank character, or the start of the line when already
+/// there or when the line is all blank
+fn home_column(line: &str, x: usize) -> usize {
+    match line.find(|c: char| !c.is_whitespace()) {
+        Some(indent) if indent != x => indent,
+        _ => 0,
+    }
+}
+
+/// How many columns `c` takes when rendered starting at `column`, tabs advance to the next tab stop
+fn render_width(c: char, column: usize, tab_size: u8) -> usize {
+    if c == '\t' {
+        let tab_size = usize::from(tab_size.max(1));
+        tab_size - column % tab_size
+    } else if c.is_ascii_control() {
+        // Drawn in caret notation
+        2
+    } else {
+        width::of(c)
+    }
+}
+
+/// What checkpoints along a row were taken for: the count of edits, the row, its length and the tab
+/// size
+type ColumnKey = (u64, usize, usize, u8);
+
+/// Converts an index into a row to the column it's rendered at. Both are 0-based, indexes past
+/// the end of the row are clamped to it and ones inside a char to its start, the column offset is
+/// shared by every row. Positions are stored as indexes and only converted when they're shown to
+/// or taken from the user.
+fn cx_to_rx(row: &str, cx: usize, tab_size: u8) -> usize {
+    let cx = split_point(row, cx).unwrap_or_else(|clamped| clamped);
+    columns::advance(&row[..cx], 0, tab_size)
+}
+
+/// Converts a rendered column back to an index into the row, landing on the character covering it.
+/// Columns inside a tab land on the tab, columns past the end of the row land on its end.
+fn rx_to_cx(row: &str, rx: usize, tab_size: u8) -> usize {
+    columns::index_at(row, 0, rx, tab_size)
+}
+
+/// A row as it's drawn, from its start up to at least the column `limit`, so however long the row
+/// only about a screen of it is rendered. With `invisibles` tabs are drawn as an arrow and dots up
+/// to the next tab stop and trailing spaces as dots, in the same columns they take as blanks,
+/// along with the spans styling those marks. The bool is whether the whole row was rendered.
+///
+/// `row` can be the end of a longer one starting at screen column `column`, which only tab stops
+/// depend on. The rendered columns still count from 0.
+pub fn render_row(
+    row: &str,
+    tab_size: u8,
+    invisibles: bool,
+    column: usize,
+    limit: usize,
+) -> (Line, bool) {
+    let mut rendered = Line {
+        text: Vec::with_capacity(row.len().min(limit)),
+        ..Line::default()
+    };
+    let text = &mut rendered.text;
+    let trailing = row.trim_end_matches(' ').len();
+    for (idx, c) in row.char_indices() {
+        let start = text.len();
+        if start >= limit {
+            return (rendered, false);
+        }
+        match c {
+            '\t' if invisibles => {
+                text.push('→');
+                text.resize(start + render_width(c, column + start, tab_size), '·');
+            }
+            '\t' => text.resize(start + render_width(c, column + start, tab_size), ' '),
+            ' ' if invisibles && idx >= trailing => text.push('·'),
+            // Sent as is these would move the terminal's cursor, like the '\r' kept in mixed files,
+            // so they're drawn like ^L, with DEL as ^?
+            _ if c.is_ascii_control() => {
+                text.extend(['^', char::from(c as u8 ^ 0x40)]);
+                rendered.spans.push(Span {
+                    start,
+                    end: text.len(),
+                    style: Style::Invisible,
+                });
+            }
+            _ => match width::of(c) {
+                // Drawn over the char before, one at the start of the row has nothing to go on
+                0 => {
+                    let base = text.iter().rposition(|c| *c != WIDE_TAIL);
+                    rendered.combining.extend(base.map(|column| (column, c)));
+                }
+                2 => text.extend([c, WIDE_TAIL]),
+                _ => text.push(c),
+            },
+        }
+        if invisibles && (c == '\t' || (c == ' ' && idx >= trailing)) {
+            rendered.spans.push(Span {
+                start,
+                end: text.len(),
+                style: Style::Invisible,
+            });
+        }
+    }
+
+    (rendered, true)
+}
+
+/// Adds the line end mark to a rendered row, returning the span styling it
+fn eol_mark(rendered: &mut Vec<char>) -> Span {
+    rendered.push('↲');
+    Span {
+        start: rendered.len() - 1,
+        end: rendered.len(),
+        style: Style::Invisible,
+    }
+}
+
+/// Send an escape sequence to the actual terminal
+fn send_esc_seq(ctrl: CtrlSeq) {
+    stdout_write(Vec::from(ctrl));
+}
+
+/// The terminal's size on startup. Multiplexers can report a zero size for a moment while
+/// attaching, so the kernel is asked a few times before measuring with the cursor instead.
+fn get_window_size() -> TermSize {
+    let mut size = ioctl_window_size();
+    for _ in 0..SIZE_RETRIES {
+        if size.is_ok() {
+            break;
+        }
+        std::thread::sleep(SIZE_RETRY_DELAY);
+        size = ioctl_window_size();
+    }
+    match size {
+        Ok((rows, cols)) => TermSize::Reported(rows, cols),
+        Err(_) => match measure_window_size() {
+            Some((rows, cols)) => TermSize::Measured(rows, cols),
+            None => TermSize::Unknown,
+        },
+    }
+}
+
+/// Moves the cursor to the bottom right corner, terminals stop it at the edges, and asks where it
+/// is. `None` when the terminal doesn't answer in time.
+fn measure_window_size() -> Option<(usize, usize)> {
+    send_esc_seq(CtrlSeq::MoveCursor(CursorPosition { x: 998, y: 998 }));
+    send_esc_seq(CtrlSeq::QueryCursorPosition);
+
+    // Raw mode's VTIME makes each read give up after a tenth of a second without input
+    let deadline = Instant::now() + CURSOR_REPORT_TIMEOUT;
+    let mut response = Vec::new();
+    let mut buff = [0; 32];
+    while Instant::now() < deadline && !response.ends_with(b"R") {
+        let read = io::stdin().lock().read(&mut buff).ok()?;
+        response.extend(&buff[..read]);
+    }
+    parse_cursor_report(&response)
+}
+
+/// Pulls (rows, cols) out of a `ESC [ rows ; cols R` answer
+fn parse_cursor_report(response: &[u8]) -> Option<(usize, usize)> {
+    let response = std::str::from_utf8(response).ok()?;
+    let start = response.rfind("\x1b[")?;
+    let (rows, cols) = response[start + 2..].strip_suffix('R')?.split_once(';')?;
+    let (rows, cols) = (rows.parse::<usize>().ok()?, cols.parse::<usize>().ok()?);
+    if rows == 0 || cols == 0 {
+        return None;
+    }
+    Some((rows.min(MAX_WINDOW_SIZE), cols.min(MAX_WINDOW_SIZE)))
+}
+
+/// Gets terminal size as (rows, cols) tuple. **Note:** libc returns a value in the  [1..N] range, so we do the same.
+/// Sizes are clamped to `MAX_WINDOW_SIZE`, a zero size is an error.
+fn ioctl_window_size() -> io::Result<(usize, usize)> {
+    let fd = io::stdin().as_raw_fd();
+    let mut winsize = WindowSize::default();
+
+    let return_code = unsafe { ioctl(fd, TIOCGWINSZ, std::ptr::addr_of_mut!(winsize)) };
+    let rows = usize::try_from(winsize.ws_row).unwrap_or_default();
+    let cols = usize::try_from(winsize.ws_col).unwrap_or_default();
+    if (return_code == -1) || rows == 0 || cols == 0 {
+        Err(Error::other(
+            "ioctl_window_size: ioctl failed or returned invalid value",
+        ))
+    } else {
+        Ok((rows.min(MAX_WINDOW_SIZE), cols.min(MAX_WINDOW_SIZE)))
+    }
+}
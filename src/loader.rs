@@ -0,0 +1,90 @@
+//! Reading the rest of a big file in the background. The first screenful is read before the file
+//! is shown, a thread reads the rest and sends it over in batches the editor takes in between
+//! keys. Dropping the `Loader`, like opening another file, stops the thread at its next batch.
+
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use crate::rows::Rows;
+use crate::LineReader;
+
+/// Rows the thread reads before sending them over
+const BATCH_ROWS: usize = 16 * 1024;
+
+/// What the reading thread sends
+pub enum Chunk {
+    /// More rows, and how many bytes of the file have been read with them
+    Rows(Rows, u64),
+    /// The file has been read to the end, the reader has what it found out about the whole of it
+    Done(LineReader<BufReader<File>>),
+    Failed(io::Error),
+}
+
+/// A file being read in the background
+pub struct Loader {
+    receiver: Receiver<Chunk>,
+    /// Bytes read, out of `size`
+    read: u64,
+    size: u64,
+}
+
+impl Loader {
+    /// Reads the rest of the file `lines` has been reading in another thread, `size` bytes of it
+    pub fn start(mut lines: LineReader<BufReader<File>>, size: u64) -> Self {
+        let read = lines.read;
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || loop {
+            let chunk = match lines.read_rows(BATCH_ROWS) {
+                Ok(rows) if rows.is_empty() => {
+                    let _ = sender.send(Chunk::Done(lines));
+                    return;
+                }
+                Ok(rows) => Chunk::Rows(rows, lines.read),
+                Err(err) => {
+                    let _ = sender.send(Chunk::Failed(err));
+                    return;
+                }
+            };
+            // Nobody's listening anymore, the file was closed
+            if sender.send(chunk).is_err() {
+                return;
+            }
+        });
+        Self {
+            receiver,
+            read,
+            size,
+        }
+    }
+
+    /// The next thing the thread sent, waiting for it with `wait`. None when there's nothing yet.
+    pub fn next(&mut self, wait: bool) -> Option<Chunk> {
+        let chunk = if wait {
+            self.receiver.recv().map_err(|_| TryRecvError::Disconnected)
+        } else {
+            self.receiver.try_recv()
+        };
+        match chunk {
+            Ok(chunk) => {
+                if let Chunk::Rows(_, read) = &chunk {
+                    self.read = *read;
+                }
+                Some(chunk)
+            }
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Chunk::Failed(io::Error::other(
+                "the reading thread stopped",
+            ))),
+        }
+    }
+
+    /// How much of the file has been read, as a percentage
+    pub fn percent(&self) -> u64 {
+        (self.read * 100)
+            .checked_div(self.size)
+            .unwrap_or(100)
+            .min(100)
+    }
+}
@@ -0,0 +1,142 @@
+//! Keeping two rilos from editing the same file at once. The editing one holds a `flock` on the
+//! open file and leaves a `.name.rilo-lock` file next to it saying which process it is, so the
+//! other one can say who has it. The flock goes away with the process however it ends; the lock
+//! file is removed when the editor is done with the file, and one left by a process that's gone
+//! is noticed and ignored.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use nix::errno::Errno;
+use nix::fcntl::{flock, FlockArg};
+use nix::sys::signal::kill;
+use nix::unistd::{gethostname, Pid};
+
+/// The process a lock file names
+#[derive(Debug)]
+pub struct Owner {
+    pub pid: u32,
+    pub host: String,
+}
+
+impl fmt::Display for Owner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host == hostname() {
+            write!(f, "pid {}", self.pid)
+        } else {
+            write!(f, "pid {} on {}", self.pid, self.host)
+        }
+    }
+}
+
+/// Who has a file, as far as can be told
+pub enum Holder {
+    Nobody,
+    /// A lock file was left by a process that's gone
+    Stale(Owner),
+    /// Another process holds it, the one its lock file names if there is one
+    Other(Option<Owner>),
+}
+
+/// The lock on a file being edited, given up when it's dropped
+#[derive(Debug)]
+pub struct Lock {
+    /// What the flock is held on, the same open file as the editor's
+    file: File,
+    sentinel: PathBuf,
+}
+
+impl Lock {
+    /// Locks `file`, the file at `path`, for this process. Fails when another one has it locked.
+    pub fn acquire(path: &Path, file: &File) -> io::Result<Self> {
+        let file = file.try_clone()?;
+        flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock).map_err(io::Error::other)?;
+        let sentinel = sentinel_path(path);
+        fs::write(
+            &sentinel,
+            format!("{}\n{}\n", std::process::id(), hostname()),
+        )?;
+        Ok(Self { file, sentinel })
+    }
+
+    /// Whether this is the lock for the file at `path`, however it's reached
+    pub fn covers(&self, path: &Path) -> bool {
+        sentinel_path(path) == self.sentinel
+    }
+
+    /// Moves the flock to `file`, the file saving replaced the locked one with
+    pub fn follow(&mut self, file: &File) {
+        if let Ok(file) = file.try_clone() {
+            if flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock).is_ok() {
+                self.file = file;
+            }
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.sentinel);
+    }
+}
+
+/// Who has the file at `path`, which `file` is open on
+pub fn holder(path: &Path, file: &File) -> Holder {
+    let owner = fs::read_to_string(sentinel_path(path))
+        .ok()
+        .and_then(|text| parse_owner(&text));
+    let locked = file.try_clone().is_ok_and(|file| {
+        let free = flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock).is_ok();
+        let _ = flock(file.as_raw_fd(), FlockArg::UnlockNonblock);
+        !free
+    });
+
+    match owner {
+        _ if locked => Holder::Other(owner),
+        None => Holder::Nobody,
+        // Whether a process on another machine is still there can't be told from here
+        Some(owner) if owner.host != hostname() => Holder::Other(Some(owner)),
+        Some(owner) if owner.pid == std::process::id() => Holder::Nobody,
+        Some(owner) if is_running(owner.pid) => Holder::Other(Some(owner)),
+        Some(owner) => Holder::Stale(owner),
+    }
+}
+
+/// The lock file for the file at `path`, next to it
+fn sentinel_path(path: &Path) -> PathBuf {
+    // The same file reached through a symlink or another relative path has the same lock file
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{name}.rilo-lock"))
+}
+
+/// Reads a lock file: the pid on the first line, the host on the second
+fn parse_owner(text: &str) -> Option<Owner> {
+    let mut lines = text.lines();
+    let pid = lines.next()?.trim().parse().ok()?;
+    let host = lines.next().unwrap_or_default().trim().to_string();
+    Some(Owner { pid, host })
+}
+
+fn is_running(pid: u32) -> bool {
+    let Ok(pid) = i32::try_from(pid) else {
+        return false;
+    };
+    // A process owned by another user can't be signalled, but it's there
+    kill(Pid::from_raw(pid), None)
+        .err()
+        .and_then(nix::Error::as_errno)
+        != Some(Errno::ESRCH)
+}
+
+fn hostname() -> String {
+    let mut buffer = [0; 256];
+    gethostname(&mut buffer).map_or_else(
+        |_| String::new(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
@@ -0,0 +1,685 @@
+//! Frames are put together from layers: the text of the visible rows, decoration spans over it,
+//! and the status bar under it. Each layer only produces `Line`s and `Span`s, the escape sequences
+//! that style them are only decided here when a line is composed, from the user's `Theme`.
+
+use std::ops::RangeInclusive;
+
+use crate::highlight::{Kind, KINDS};
+use crate::{CtrlSeq, CursorPosition};
+
+/// What a cell is decorated as. When spans overlap, the style that comes later in this list wins,
+/// this ordering is the one place where precedence between decorations is decided.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Style {
+    Normal,
+    /// A part of a row its filetype's rules picked out
+    Syntax(Kind),
+    /// A column of the line length guide
+    ColorColumn,
+    /// Spaces and tabs at the end of a row
+    TrailingWhitespace,
+    /// The first column of a row indented with both tabs and spaces
+    MixedIndent,
+    /// The '~' marking rows past the end of the file
+    Filler,
+    /// A match of the last search
+    SearchMatch,
+    /// The match the last search jumped to
+    CurrentMatch,
+    /// The bracket matching the one at the cursor
+    MatchingBracket,
+    /// The bracket at the cursor when nothing matches it
+    UnmatchedBracket,
+    /// The marks drawn for tabs, trailing spaces and line ends while invisibles are shown, and
+    /// control characters in caret notation
+    Invisible,
+    /// The text between the mark and the cursor
+    Selection,
+    StatusBar,
+    /// The status bar while the terminal window doesn't have focus
+    StatusBarUnfocused,
+    /// The key that picks an answer in a question
+    Key,
+    /// The gutter's mark by a row changed since the file was last read or saved
+    ChangeMark,
+}
+
+impl Style {
+    /// Every sequence starts from a reset, so nothing of the style before it carries over
+    fn escape_sequence(self, theme: &Theme) -> Vec<u8> {
+        let reset = Vec::from(CtrlSeq::NormalColor);
+        let depth = theme.depth;
+        match self {
+            Style::Normal => reset,
+            Style::Syntax(kind) => {
+                [reset, theme.syntax[kind as usize].escape_sequence(depth)].concat()
+            }
+            Style::ColorColumn => [reset, theme.color_column.escape_sequence(depth)].concat(),
+            Style::TrailingWhitespace => {
+                [reset, theme.trailing_whitespace.escape_sequence(depth)].concat()
+            }
+            Style::MixedIndent => [reset, theme.mixed_indent.escape_sequence(depth)].concat(),
+            Style::Filler => [reset, theme.filler.escape_sequence(depth)].concat(),
+            Style::SearchMatch => [reset, theme.search_match.escape_sequence(depth)].concat(),
+            Style::CurrentMatch => [reset, theme.current_match.escape_sequence(depth)].concat(),
+            Style::MatchingBracket => {
+                [reset, theme.matching_bracket.escape_sequence(depth)].concat()
+            }
+            Style::UnmatchedBracket => {
+                [reset, theme.unmatched_bracket.escape_sequence(depth)].concat()
+            }
+            Style::Invisible => [reset, theme.invisible.escape_sequence(depth)].concat(),
+            Style::Selection => [reset, theme.selection.escape_sequence(depth)].concat(),
+            Style::ChangeMark => [reset, theme.change_mark.escape_sequence(depth)].concat(),
+            Style::StatusBar => [reset, theme.status_bar.escape_sequence(depth)].concat(),
+            Style::StatusBarUnfocused => [
+                reset,
+                theme.status_bar.escape_sequence(depth),
+                CtrlSeq::Dim.into(),
+            ]
+            .concat(),
+            // Shown the other way around from the inverted bar around it
+            Style::Key => [reset, Vec::from(CtrlSeq::Bold)].concat(),
+        }
+    }
+}
+
+/// A color as it's configured, brought down to one the terminal can show when it's drawn
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Color {
+    /// One of the terminal's 256 colors: 0 to 7 are the standard colors, 8 to 15 their bright
+    /// versions, and the rest the 6x6x6 cube and grays of xterm's palette
+    Palette(u8),
+    /// Any 24-bit color, written `#rrggbb`
+    Rgb(u8, u8, u8),
+}
+
+/// How many colors the terminal can show
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ColorDepth {
+    /// The 8 standard colors and their bright versions, what every color terminal has
+    #[default]
+    Basic,
+    /// xterm's palette of 256
+    Palette,
+    /// Any 24-bit color
+    TrueColor,
+}
+
+impl ColorDepth {
+    /// What the terminal says it can show. Terminals that do 24-bit color mostly say so in
+    /// `COLORTERM`, a `TERM` like `xterm-256color` or `xterm-direct` says it otherwise.
+    pub fn detect(colorterm: Option<&str>, term: Option<&str>) -> Self {
+        let term = term.unwrap_or_default();
+        if matches!(colorterm, Some("truecolor" | "24bit")) || term.ends_with("-direct") {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") {
+            ColorDepth::Palette
+        } else {
+            ColorDepth::Basic
+        }
+    }
+}
+
+/// The standard and bright colors as xterm shows them unless it's told otherwise
+const BASIC_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// The levels each of red, green and blue steps through in the palette's color cube
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl Color {
+    /// The color the terminal shows closest to this one. The 16 standard colors are left alone
+    /// whatever the terminal, they're the ones the user's terminal theme decides the look of.
+    pub fn reduce(self, depth: ColorDepth) -> Color {
+        match (self, depth) {
+            (_, ColorDepth::TrueColor)
+            | (Color::Palette(0..=15), _)
+            | (Color::Palette(_), ColorDepth::Palette) => self,
+            (Color::Rgb(..), ColorDepth::Palette) => Color::Palette(nearest(self.rgb(), 16..=255)),
+            (_, ColorDepth::Basic) => Color::Palette(nearest(self.rgb(), 0..=15)),
+        }
+    }
+
+    fn rgb(self) -> (u8, u8, u8) {
+        match self {
+            Color::Rgb(red, green, blue) => (red, green, blue),
+            Color::Palette(idx @ 0..=15) => BASIC_RGB[usize::from(idx)],
+            Color::Palette(idx @ 16..=231) => {
+                let idx = usize::from(idx - 16);
+                (
+                    CUBE_LEVELS[idx / 36],
+                    CUBE_LEVELS[idx / 6 % 6],
+                    CUBE_LEVELS[idx % 6],
+                )
+            }
+            Color::Palette(gray) => {
+                let level = 8 + (gray - 232) * 10;
+                (level, level, level)
+            }
+        }
+    }
+}
+
+/// The palette color out of `candidates` that looks closest to `rgb`
+fn nearest(rgb: (u8, u8, u8), candidates: RangeInclusive<u8>) -> u8 {
+    let distance = |(red, green, blue): (u8, u8, u8)| {
+        let square = |a: u8, b: u8| u32::from(a.abs_diff(b)).pow(2);
+        square(red, rgb.0) + square(green, rgb.1) + square(blue, rgb.2)
+    };
+    candidates
+        .min_by_key(|idx| distance(Color::Palette(*idx).rgb()))
+        .unwrap_or(0)
+}
+
+/// Names for the 16 standard colors, the bright ones are `bright-` and the name
+const COLOR_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+impl std::str::FromStr for Color {
+    type Err = ();
+
+    /// A color's name, its number in the palette, or `#rrggbb`
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        if let Some(hex) = name.strip_prefix('#') {
+            let channel = |at: usize| {
+                hex.get(at..at + 2)
+                    .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                    .ok_or(())
+            };
+            if hex.len() != 6 {
+                return Err(());
+            }
+            return Ok(Color::Rgb(channel(0)?, channel(2)?, channel(4)?));
+        }
+        let (bright, base) = match name.strip_prefix("bright-") {
+            Some(base) => (8, base),
+            None => (0, name),
+        };
+        if let Some((idx, _)) = (0..).zip(COLOR_NAMES).find(|(_, color)| *color == base) {
+            return Ok(Color::Palette(bright + idx));
+        }
+        name.parse().map(Color::Palette).map_err(|_| ())
+    }
+}
+
+/// How a part of the screen is drawn, as the user configured it
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct TextStyle {
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+impl TextStyle {
+    pub const PLAIN: TextStyle = TextStyle {
+        foreground: None,
+        background: None,
+        bold: false,
+        dim: false,
+        underline: false,
+        reverse: false,
+    };
+
+    pub const REVERSE: TextStyle = TextStyle {
+        foreground: None,
+        background: None,
+        bold: false,
+        dim: false,
+        underline: false,
+        reverse: true,
+    };
+
+    /// Bold and underlined, standing out without covering the cell
+    pub const BOLD_UNDERLINE: TextStyle = TextStyle {
+        foreground: None,
+        background: None,
+        bold: true,
+        dim: false,
+        underline: true,
+        reverse: false,
+    };
+
+    pub const BOLD: TextStyle = TextStyle {
+        foreground: None,
+        background: None,
+        bold: true,
+        dim: false,
+        underline: false,
+        reverse: false,
+    };
+
+    pub const UNDERLINE: TextStyle = TextStyle {
+        underline: true,
+        ..TextStyle::PLAIN
+    };
+
+    /// Faint, for what's drawn but isn't text
+    pub const DIM: TextStyle = TextStyle {
+        foreground: None,
+        background: None,
+        bold: false,
+        dim: true,
+        underline: false,
+        reverse: false,
+    };
+
+    /// Reverse video underlined, to tell one reversed span from the others
+    pub const REVERSE_UNDERLINE: TextStyle = TextStyle {
+        underline: true,
+        ..TextStyle::REVERSE
+    };
+
+    /// Colored text on a colored background
+    const fn colored(foreground: Color, background: Color) -> TextStyle {
+        TextStyle {
+            foreground: Some(foreground),
+            background: Some(background),
+            ..TextStyle::PLAIN
+        }
+    }
+
+    /// Colored text on the terminal's background
+    const fn foreground(foreground: Color) -> TextStyle {
+        TextStyle {
+            foreground: Some(foreground),
+            ..TextStyle::PLAIN
+        }
+    }
+
+    /// Bold colored text on the terminal's background
+    const fn bold(foreground: Color) -> TextStyle {
+        TextStyle {
+            bold: true,
+            ..TextStyle::foreground(foreground)
+        }
+    }
+
+    /// Reads a style written as words, like `bold fg:white bg:4`. Colors are named, numbered or
+    /// written `#rrggbb`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut style = TextStyle::default();
+        for word in spec.split_whitespace() {
+            match word {
+                "plain" => style = TextStyle::default(),
+                "bold" => style.bold = true,
+                "dim" => style.dim = true,
+                "underline" => style.underline = true,
+                "reverse" => style.reverse = true,
+                _ => {
+                    let (slot, color) = if let Some(color) = word.strip_prefix("fg:") {
+                        (&mut style.foreground, color)
+                    } else if let Some(color) = word.strip_prefix("bg:") {
+                        (&mut style.background, color)
+                    } else {
+                        return Err(format!("unknown style word '{word}'"));
+                    };
+                    *slot = Some(
+                        color
+                            .parse()
+                            .map_err(|()| format!("unknown color '{color}'"))?,
+                    );
+                }
+            }
+        }
+        Ok(style)
+    }
+
+    fn escape_sequence(self, depth: ColorDepth) -> Vec<u8> {
+        let mut seq = Vec::new();
+        if self.reverse {
+            seq.extend(Vec::from(CtrlSeq::InverteColor));
+        }
+        if self.bold {
+            seq.extend(Vec::from(CtrlSeq::Bold));
+        }
+        if self.dim {
+            seq.extend(Vec::from(CtrlSeq::Dim));
+        }
+        if self.underline {
+            seq.extend(Vec::from(CtrlSeq::Underline));
+        }
+        if let Some(color) = self.foreground {
+            seq.extend(Vec::from(CtrlSeq::Foreground(color.reduce(depth))));
+        }
+        if let Some(color) = self.background {
+            seq.extend(Vec::from(CtrlSeq::Background(color.reduce(depth))));
+        }
+        seq
+    }
+}
+
+/// The configurable styles, the rest of the screen is drawn in the terminal's own colors
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Theme {
+    /// What the colors are brought down to when they're drawn
+    pub depth: ColorDepth,
+    pub status_bar: TextStyle,
+    pub filler: TextStyle,
+    pub search_match: TextStyle,
+    pub current_match: TextStyle,
+    pub matching_bracket: TextStyle,
+    pub unmatched_bracket: TextStyle,
+    pub invisible: TextStyle,
+    pub selection: TextStyle,
+    pub change_mark: TextStyle,
+    pub color_column: TextStyle,
+    pub trailing_whitespace: TextStyle,
+    pub mixed_indent: TextStyle,
+    /// How each kind of highlighted part looks, in the order the kinds are declared
+    pub syntax: [TextStyle; KINDS],
+}
+
+impl Default for Theme {
+    /// Plain reverse video, what rilo looks like without a config and with `NO_COLOR`
+    fn default() -> Self {
+        Theme::PLAIN
+    }
+}
+
+/// The themes the config file can pick with `theme = NAME`, its style settings still win
+const THEMES: &[(&str, Theme)] = &[
+    ("plain", Theme::PLAIN),
+    ("ocean", Theme::OCEAN),
+    ("gruvbox", Theme::GRUVBOX),
+];
+
+impl Theme {
+    const PLAIN: Theme = Theme {
+        depth: ColorDepth::Basic,
+        status_bar: TextStyle::REVERSE,
+        filler: TextStyle::PLAIN,
+        search_match: TextStyle::REVERSE,
+        current_match: TextStyle::REVERSE_UNDERLINE,
+        matching_bracket: TextStyle::BOLD_UNDERLINE,
+        unmatched_bracket: TextStyle::REVERSE,
+        invisible: TextStyle::DIM,
+        selection: TextStyle::REVERSE,
+        change_mark: TextStyle::BOLD,
+        color_column: TextStyle::UNDERLINE,
+        trailing_whitespace: TextStyle::REVERSE,
+        mixed_indent: TextStyle::UNDERLINE,
+        // Only what's worth telling apart without colors stands out
+        syntax: [
+            TextStyle::BOLD,
+            TextStyle::BOLD,
+            TextStyle::UNDERLINE,
+            TextStyle::PLAIN,
+            TextStyle::BOLD,
+            TextStyle::PLAIN,
+            TextStyle::PLAIN,
+            TextStyle::DIM,
+            TextStyle::PLAIN,
+        ],
+    };
+
+    /// The 16 standard colors only, so it follows the terminal's own idea of them
+    const OCEAN: Theme = Theme {
+        status_bar: TextStyle::colored(Color::Palette(15), Color::Palette(4)),
+        filler: TextStyle::foreground(Color::Palette(4)),
+        search_match: TextStyle::colored(Color::Palette(0), Color::Palette(6)),
+        current_match: TextStyle::colored(Color::Palette(0), Color::Palette(14)),
+        unmatched_bracket: TextStyle::colored(Color::Palette(15), Color::Palette(1)),
+        invisible: TextStyle::foreground(Color::Palette(8)),
+        selection: TextStyle::colored(Color::Palette(15), Color::Palette(12)),
+        change_mark: TextStyle::foreground(Color::Palette(3)),
+        color_column: TextStyle {
+            background: Some(Color::Palette(8)),
+            ..TextStyle::PLAIN
+        },
+        trailing_whitespace: TextStyle {
+            background: Some(Color::Palette(1)),
+            ..TextStyle::PLAIN
+        },
+        mixed_indent: TextStyle {
+            background: Some(Color::Palette(3)),
+            ..TextStyle::PLAIN
+        },
+        syntax: [
+            TextStyle::bold(Color::Palette(12)),
+            TextStyle::BOLD,
+            TextStyle::UNDERLINE,
+            TextStyle::foreground(Color::Palette(6)),
+            TextStyle::foreground(Color::Palette(3)),
+            TextStyle::foreground(Color::Palette(4)),
+            TextStyle::foreground(Color::Palette(2)),
+            TextStyle::foreground(Color::Palette(8)),
+            TextStyle::foreground(Color::Palette(5)),
+        ],
+        ..Theme::PLAIN
+    };
+
+    /// Warm 24-bit colors meant for a dark background, brought down on terminals without them
+    const GRUVBOX: Theme = Theme {
+        status_bar: TextStyle::colored(Color::Rgb(0xeb, 0xdb, 0xb2), Color::Rgb(0x50, 0x49, 0x45)),
+        filler: TextStyle::foreground(Color::Rgb(0x66, 0x5c, 0x54)),
+        search_match: TextStyle::colored(
+            Color::Rgb(0x28, 0x28, 0x28),
+            Color::Rgb(0xd7, 0x99, 0x21),
+        ),
+        current_match: TextStyle {
+            bold: true,
+            ..TextStyle::colored(Color::Rgb(0x28, 0x28, 0x28), Color::Rgb(0xfa, 0xbd, 0x2f))
+        },
+        matching_bracket: TextStyle {
+            bold: true,
+            underline: true,
+            ..TextStyle::foreground(Color::Rgb(0xfe, 0x80, 0x19))
+        },
+        unmatched_bracket: TextStyle::colored(
+            Color::Rgb(0xeb, 0xdb, 0xb2),
+            Color::Rgb(0xcc, 0x24, 0x1d),
+        ),
+        invisible: TextStyle::foreground(Color::Rgb(0x66, 0x5c, 0x54)),
+        selection: TextStyle::colored(Color::Rgb(0xeb, 0xdb, 0xb2), Color::Rgb(0x66, 0x5c, 0x54)),
+        change_mark: TextStyle::foreground(Color::Rgb(0xb8, 0xbb, 0x26)),
+        color_column: TextStyle {
+            background: Some(Color::Rgb(0x3c, 0x38, 0x36)),
+            ..TextStyle::PLAIN
+        },
+        trailing_whitespace: TextStyle {
+            background: Some(Color::Rgb(0xcc, 0x24, 0x1d)),
+            ..TextStyle::PLAIN
+        },
+        mixed_indent: TextStyle {
+            background: Some(Color::Rgb(0x79, 0x74, 0x0e)),
+            ..TextStyle::PLAIN
+        },
+        syntax: [
+            TextStyle::bold(Color::Rgb(0xfa, 0xbd, 0x2f)),
+            TextStyle::bold(Color::Rgb(0xeb, 0xdb, 0xb2)),
+            TextStyle {
+                underline: true,
+                ..TextStyle::foreground(Color::Rgb(0xd3, 0x86, 0x9b))
+            },
+            TextStyle::foreground(Color::Rgb(0x8e, 0xc0, 0x7c)),
+            TextStyle::foreground(Color::Rgb(0xfe, 0x80, 0x19)),
+            TextStyle::foreground(Color::Rgb(0x83, 0xa5, 0x98)),
+            TextStyle::foreground(Color::Rgb(0xb8, 0xbb, 0x26)),
+            TextStyle::foreground(Color::Rgb(0x92, 0x83, 0x74)),
+            TextStyle::foreground(Color::Rgb(0xd3, 0x86, 0x9b)),
+        ],
+        ..Theme::PLAIN
+    };
+
+    /// The built-in theme called `name`
+    pub fn named(name: &str) -> Option<Theme> {
+        THEMES
+            .iter()
+            .find(|(theme, _)| *theme == name)
+            .map(|(_, theme)| *theme)
+    }
+}
+
+/// A styled range of screen columns, `end` is exclusive
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub style: Style,
+}
+
+/// Fills the column taken by the second half of a wide char, it's drawn along with the first
+pub const WIDE_TAIL: char = '\0';
+
+/// A single screen row, its text as it's drawn and the spans decorating it
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Line {
+    /// A char for each screen column, a wide char is followed by a `WIDE_TAIL`
+    pub text: Vec<char>,
+    pub spans: Vec<Span>,
+    /// Chars that take no column, like combining accents, as the column of the char they're
+    /// drawn over. In the order they're drawn.
+    pub combining: Vec<(usize, char)>,
+}
+
+impl Line {
+    /// The style that wins at a column
+    pub(crate) fn style_at(&self, column: usize) -> Style {
+        self.spans
+            .iter()
+            .filter(|span| (span.start..span.end).contains(&column))
+            .map(|span| span.style)
+            .max()
+            .unwrap_or(Style::Normal)
+    }
+
+    /// The text of the columns from `start` up to `end`, moved to start from 0, without the spans.
+    /// A wide char cut in two by either edge is drawn as a '<' or '>' instead of half of it.
+    pub fn columns(&self, start: usize, end: usize) -> Line {
+        let end = end.min(self.text.len());
+        let start = start.min(end);
+        let mut text = self.text[start..end].to_vec();
+        let mut combining: Vec<(usize, char)> = self
+            .combining
+            .iter()
+            .filter(|(column, _)| (start..end).contains(column))
+            .map(|&(column, c)| (column - start, c))
+            .collect();
+
+        if let Some(first) = text.first_mut().filter(|c| **c == WIDE_TAIL) {
+            *first = '<';
+        }
+        if self.text.get(end) == Some(&WIDE_TAIL) {
+            if let Some(last) = text.last_mut() {
+                *last = '>';
+                let column = text.len() - 1;
+                combining.retain(|(at, _)| *at != column);
+            }
+        }
+
+        Line {
+            text,
+            spans: Vec::new(),
+            combining,
+        }
+    }
+
+    /// Merges the spans into the text. A span reaching past the text pads it with spaces,
+    /// and the line always ends with the style reset so nothing leaks into the next row.
+    pub fn compose(&self, theme: &Theme) -> Vec<u8> {
+        let width = self
+            .spans
+            .iter()
+            .map(|span| span.end)
+            .fold(self.text.len(), usize::max);
+
+        let mut composed = Vec::with_capacity(width);
+        let mut current = Style::Normal;
+        let mut combining = self.combining.iter().peekable();
+        for column in 0..width {
+            let style = self.style_at(column);
+            if style != current {
+                composed.extend(style.escape_sequence(theme));
+                current = style;
+            }
+            let c = self.text.get(column).copied().unwrap_or(' ');
+            if c != WIDE_TAIL {
+                composed.extend(c.encode_utf8(&mut [0; 4]).as_bytes());
+            }
+            while let Some((_, c)) = combining.next_if(|(at, _)| *at == column) {
+                composed.extend(c.encode_utf8(&mut [0; 4]).as_bytes());
+            }
+        }
+
+        if current != Style::Normal {
+            composed.extend(Style::Normal.escape_sequence(theme));
+        }
+
+        composed
+    }
+}
+
+/// Everything that ends up on the terminal for one draw
+pub struct Frame {
+    pub lines: Vec<Line>,
+    pub cursor: CursorPosition,
+}
+
+impl Frame {
+    /// The bytes that paint the rows that differ from what `screen` shows, which is updated to
+    /// this frame
+    pub fn render(&self, theme: &Theme, screen: &mut Screen) -> Vec<u8> {
+        screen.rows.resize(self.lines.len(), None);
+        let mut rendered = Vec::new();
+        for (idx, (line, shown)) in self.lines.iter().zip(&mut screen.rows).enumerate() {
+            let composed = line.compose(theme);
+            if shown.as_ref() == Some(&composed) {
+                continue;
+            }
+
+            rendered.append(&mut CtrlSeq::MoveCursor(CursorPosition { x: 0, y: idx }).into());
+            rendered.append(&mut CtrlSeq::ClearLine.into());
+            rendered.extend(&composed);
+            *shown = Some(composed);
+        }
+
+        rendered
+    }
+}
+
+/// What the terminal shows, as the bytes that painted each of its rows, so a draw only has to
+/// send the rows that changed
+#[derive(Default)]
+pub struct Screen {
+    rows: Vec<Option<Vec<u8>>>,
+    /// The (row, column) offsets of the view the rows were drawn at
+    view: (usize, usize),
+}
+
+impl Screen {
+    /// Forgets what the terminal shows, the next draw paints every row
+    pub fn invalidate(&mut self) {
+        self.rows.clear();
+    }
+
+    /// Notes the view the next frame is drawn at. A view that scrolled is painted whole, nearly
+    /// every row changed anyway.
+    pub fn set_view(&mut self, view: (usize, usize)) {
+        if view != self.view {
+            self.invalidate();
+            self.view = view;
+        }
+    }
+}
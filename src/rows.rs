@@ -0,0 +1,660 @@
+//! The buffer's rows, packed into blocks of text. A row isn't a `String` of its own: the rows of a
+//! block are one string, with where each of them ends kept next to it, so the rows of a big file
+//! take little more memory than the file. Editing a row or adding and removing rows moves the rest
+//! of its block, never the rows past it, and the block a row is in is found by binary search.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::iter::FromIterator;
+use std::ops::{Index, Range};
+
+/// Bytes of rows a block is filled with before the next row starts a new one
+const BLOCK_BYTES: usize = 16 * 1024;
+
+/// Most rows in a block, so a run of empty rows doesn't make one block of thousands
+const BLOCK_ROWS: usize = 1024;
+
+#[derive(Clone, Default)]
+pub struct Rows {
+    blocks: Vec<Block>,
+    /// The index of each block's first row, then the number of rows
+    starts: Vec<usize>,
+}
+
+#[derive(Clone, Default)]
+struct Block {
+    /// The rows one after another
+    text: String,
+    /// Where each of the rows ends in `text`
+    ends: Vec<u32>,
+}
+
+impl Block {
+    fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    /// Where row `row` of the block is in `text`
+    fn span(&self, row: usize) -> Range<usize> {
+        let start = row
+            .checked_sub(1)
+            .map_or(0, |above| self.ends[above] as usize);
+        start..self.ends[row] as usize
+    }
+
+    fn row(&self, row: usize) -> &str {
+        &self.text[self.span(row)]
+    }
+
+    /// Whether a row of `len` bytes added at the end should start a new block instead
+    fn is_full(&self, len: usize) -> bool {
+        !self.ends.is_empty()
+            && (self.text.len() + len > BLOCK_BYTES || self.ends.len() >= BLOCK_ROWS)
+    }
+
+    fn push(&mut self, row: &str) {
+        self.text.push_str(row);
+        self.ends.push(offset(self.text.len()));
+    }
+
+    /// Moves the ends of the rows from `row` on for `grown` bytes added before them and `shrunk`
+    /// taken away
+    fn shift(&mut self, row: usize, grown: usize, shrunk: usize) {
+        for end in &mut self.ends[row..] {
+            *end = offset(*end as usize + grown - shrunk);
+        }
+    }
+
+    /// Gives back the memory a block filled up row by row kept spare
+    fn seal(&mut self) {
+        self.text.shrink_to_fit();
+        self.ends.shrink_to_fit();
+    }
+}
+
+/// An offset into a block's text. Rows are limited to 4 GiB, more than a block ever holds of the
+/// rows around a long one.
+fn offset(len: usize) -> u32 {
+    u32::try_from(len).expect("a row is longer than 4 GiB")
+}
+
+impl Rows {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.starts.last().copied().unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&str> {
+        if idx >= self.len() {
+            return None;
+        }
+        let (block, row) = self.locate(idx);
+        Some(self.blocks[block].row(row))
+    }
+
+    pub fn last(&self) -> Option<&str> {
+        self.get(self.len().checked_sub(1)?)
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        self.range(0..self.len())
+    }
+
+    /// The rows in `range`, which is clamped to the rows there are
+    pub fn range(&self, range: Range<usize>) -> Iter<'_> {
+        let end = range.end.min(self.len());
+        Iter {
+            rows: self,
+            front: range.start.min(end),
+            back: end,
+        }
+    }
+
+    /// Adds `row` at the end
+    pub fn push(&mut self, row: &str) {
+        if self
+            .blocks
+            .last()
+            .is_none_or(|block| block.is_full(row.len()))
+        {
+            if let Some(block) = self.blocks.last_mut() {
+                block.seal();
+            }
+            let mut block = Block::default();
+            block.text.reserve_exact(row.len().max(BLOCK_BYTES));
+            self.blocks.push(block);
+            if self.starts.is_empty() {
+                self.starts.push(0);
+            }
+            self.starts.push(self.len());
+        }
+        let last = self.blocks.len() - 1;
+        self.blocks[last].push(row);
+        self.starts[last + 1] += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<String> {
+        let last = self.len().checked_sub(1)?;
+        Some(self.remove(last))
+    }
+
+    pub fn insert(&mut self, idx: usize, row: &str) {
+        self.splice(idx..idx, [row]);
+    }
+
+    pub fn remove(&mut self, idx: usize) -> String {
+        let row = self[idx].to_string();
+        self.splice(idx..idx + 1, [""; 0]);
+        row
+    }
+
+    /// Replaces the rows in `range` with `rows`
+    pub fn splice<T: AsRef<str>>(
+        &mut self,
+        range: Range<usize>,
+        rows: impl IntoIterator<Item = T>,
+    ) {
+        assert!(
+            range.start <= range.end && range.end <= self.len(),
+            "rows {:?} are out of the {} there are",
+            range,
+            self.len()
+        );
+        // A single row added or removed is done in its block, anything else replaces blocks
+        let mut rows = rows.into_iter();
+        let first = rows.next();
+        let second = first.as_ref().and_then(|_| rows.next());
+        match (range.len(), first, second) {
+            (0, Some(row), None) if !self.is_empty() => {
+                let (block_idx, at) = self.locate_end(range.start);
+                let block = &mut self.blocks[block_idx];
+                let start = at
+                    .checked_sub(1)
+                    .map_or(0, |above| block.ends[above] as usize);
+                let row = row.as_ref();
+                block.text.insert_str(start, row);
+                block.ends.insert(at, offset(start));
+                block.shift(at, row.len(), 0);
+                self.balance(block_idx);
+            }
+            (1, None, None) => {
+                let (block, at) = self.locate(range.start);
+                let span = self.blocks[block].span(at);
+                let emptied = {
+                    let block = &mut self.blocks[block];
+                    block.text.replace_range(span.clone(), "");
+                    block.ends.remove(at);
+                    block.shift(at, 0, span.len());
+                    block.ends.is_empty()
+                };
+                if emptied {
+                    self.blocks.remove(block);
+                }
+                self.recount();
+            }
+            (_, first, second) => {
+                let added: Vec<T> = first.into_iter().chain(second).chain(rows).collect();
+                self.replace_blocks(range, &added);
+            }
+        }
+    }
+
+    /// Adds `rows` at the end
+    pub fn extend<T: AsRef<str>>(&mut self, rows: impl IntoIterator<Item = T>) {
+        for row in rows {
+            self.push(row.as_ref());
+        }
+    }
+
+    /// Moves the rows of `other` to the end, a block at a time
+    pub fn append(&mut self, other: Rows) {
+        if let Some(block) = self.blocks.last_mut() {
+            block.seal();
+        }
+        self.blocks.extend(other.blocks);
+        self.recount();
+    }
+
+    pub fn swap(&mut self, a: usize, b: usize) {
+        if a == b {
+            return;
+        }
+        let (row_a, row_b) = (self[a].to_string(), self[b].to_string());
+        self.replace_range(a, 0..row_a.len(), &row_b);
+        self.replace_range(b, 0..row_b.len(), &row_a);
+    }
+
+    /// Gives back the memory kept spare for rows added at the end
+    pub fn shrink_to_fit(&mut self) {
+        if let Some(block) = self.blocks.last_mut() {
+            block.seal();
+        }
+        self.blocks.shrink_to_fit();
+        self.starts.shrink_to_fit();
+    }
+
+    /// Replaces the bytes in `range` of row `idx` with `with`, like `String::replace_range`
+    pub fn replace_range(&mut self, idx: usize, range: Range<usize>, with: &str) {
+        let (block_idx, row) = self.locate(idx);
+        let block = &mut self.blocks[block_idx];
+        let span = block.span(row);
+        assert!(
+            range.start <= range.end
+                && range.end <= span.len()
+                && block.row(row).is_char_boundary(range.start)
+                && block.row(row).is_char_boundary(range.end),
+            "{:?} isn't a range of chars in row {} of {} bytes",
+            range,
+            idx,
+            span.len()
+        );
+        let removed = range.len();
+        block
+            .text
+            .replace_range(span.start + range.start..span.start + range.end, with);
+        block.shift(row, with.len(), removed);
+        self.balance(block_idx);
+    }
+
+    pub fn insert_str(&mut self, idx: usize, at: usize, text: &str) {
+        self.replace_range(idx, at..at, text);
+    }
+
+    pub fn push_str(&mut self, idx: usize, text: &str) {
+        let len = self[idx].len();
+        self.replace_range(idx, len..len, text);
+    }
+
+    pub fn truncate(&mut self, idx: usize, len: usize) {
+        let end = self[idx].len();
+        if len < end {
+            self.replace_range(idx, len..end, "");
+        }
+    }
+
+    /// Cuts row `idx` short at `at`, giving back what was after it
+    pub fn split_off(&mut self, idx: usize, at: usize) -> String {
+        let tail = self[idx][at..].to_string();
+        self.truncate(idx, at);
+        tail
+    }
+
+    /// Rewrites the rows in `range` a block at a time, to what `edit` makes of each of them. It
+    /// gives `None` for a row that stays as it is. Returns how many rows were rewritten.
+    pub fn rewrite(
+        &mut self,
+        range: Range<usize>,
+        mut edit: impl FnMut(&str) -> Option<String>,
+    ) -> usize {
+        let mut rewritten = 0;
+        let mut grown = Vec::new();
+        for (idx, (block, first)) in self.blocks.iter_mut().zip(&self.starts).enumerate() {
+            let rows = range.start.saturating_sub(*first)..range.end.saturating_sub(*first);
+            let rows = rows.start..rows.end.min(block.len());
+            if rows.is_empty() {
+                continue;
+            }
+            let mut text = String::with_capacity(block.text.len());
+            let mut ends = Vec::with_capacity(block.len());
+            let mut changed = false;
+            for row in 0..block.len() {
+                let old = block.row(row);
+                let new = if rows.contains(&row) { edit(old) } else { None };
+                if new.is_some() {
+                    changed = true;
+                    rewritten += 1;
+                }
+                text.push_str(new.as_deref().unwrap_or(old));
+                ends.push(offset(text.len()));
+            }
+            if changed {
+                grown.extend((text.len() > block.text.len()).then_some(idx));
+                block.text = text;
+                block.ends = ends;
+            }
+        }
+        // From the last block back, so splitting one doesn't move the ones still to go
+        for idx in grown.into_iter().rev() {
+            self.balance(idx);
+        }
+        rewritten
+    }
+
+    /// The block row `idx` is in, and which of its rows it is
+    fn locate(&self, idx: usize) -> (usize, usize) {
+        let block = self.starts.partition_point(|start| *start <= idx) - 1;
+        (block, idx - self.starts[block])
+    }
+
+    /// Like `locate`, but the row past the end of a block is in that block, so a row can be
+    /// added there. The buffer can't be empty.
+    fn locate_end(&self, idx: usize) -> (usize, usize) {
+        let block = self.starts.partition_point(|start| *start < idx).max(1) - 1;
+        (block, idx - self.starts[block])
+    }
+
+    /// Splits block `idx` in two once rows added to it or grown in it make it twice the size blocks
+    /// are filled to, so editing in it stays quick
+    fn balance(&mut self, idx: usize) {
+        let block = &mut self.blocks[idx];
+        if block.len() < 2 || (block.text.len() <= 2 * BLOCK_BYTES && block.len() <= 2 * BLOCK_ROWS)
+        {
+            self.recount();
+            return;
+        }
+        let half = block.len() / 2;
+        let cut = block.span(half).start;
+        let tail = Block {
+            text: block.text[cut..].to_string(),
+            ends: block.ends[half..]
+                .iter()
+                .map(|end| end - offset(cut))
+                .collect(),
+        };
+        block.text.truncate(cut);
+        block.ends.truncate(half);
+        block.seal();
+        self.blocks.insert(idx + 1, tail);
+        self.recount();
+    }
+
+    /// Counts up where each block starts again, after rows were added or removed
+    fn recount(&mut self) {
+        self.starts.clear();
+        let mut start = 0;
+        for block in &self.blocks {
+            self.starts.push(start);
+            start += block.len();
+        }
+        self.starts.push(start);
+    }
+
+    /// Replaces the rows in `range` with `rows`, rebuilding the blocks they touch
+    fn replace_blocks<T: AsRef<str>>(&mut self, range: Range<usize>, rows: &[T]) {
+        // The blocks the range starts and ends in, which include the rows around it
+        let first = if self.is_empty() {
+            0
+        } else {
+            self.locate_end(range.start).0
+        };
+        let last = if range.end < self.len() {
+            self.locate(range.end).0 + 1
+        } else {
+            self.blocks.len()
+        };
+        let mut rebuilt = Rows::new();
+        let kept = self.starts.get(first).copied().unwrap_or(0);
+        rebuilt.extend(self.range(kept..range.start));
+        rebuilt.extend(rows);
+        let end = self.starts.get(last).copied().unwrap_or(kept);
+        rebuilt.extend(self.range(range.end..end));
+        rebuilt.shrink_to_fit();
+        self.blocks.splice(first..last, rebuilt.blocks);
+        self.recount();
+    }
+}
+
+impl Index<usize> for Rows {
+    type Output = str;
+
+    fn index(&self, idx: usize) -> &str {
+        match self.get(idx) {
+            Some(row) => row,
+            None => panic!("row {} is past the {} there are", idx, self.len()),
+        }
+    }
+}
+
+impl fmt::Debug for Rows {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: AsRef<str>> PartialEq<[T]> for Rows {
+    fn eq(&self, other: &[T]) -> bool {
+        self.len() == other.len() && self.iter().zip(other).all(|(a, b)| a == b.as_ref())
+    }
+}
+
+impl<T: AsRef<str>, const N: usize> PartialEq<[T; N]> for Rows {
+    fn eq(&self, other: &[T; N]) -> bool {
+        *self == other[..]
+    }
+}
+
+impl<T: AsRef<str>> PartialEq<Vec<T>> for Rows {
+    fn eq(&self, other: &Vec<T>) -> bool {
+        *self == other[..]
+    }
+}
+
+impl<T: AsRef<str>> FromIterator<T> for Rows {
+    fn from_iter<I: IntoIterator<Item = T>>(rows: I) -> Self {
+        let mut packed = Rows::new();
+        packed.extend(rows);
+        packed.shrink_to_fit();
+        packed
+    }
+}
+
+impl<T: AsRef<str>> From<Vec<T>> for Rows {
+    fn from(rows: Vec<T>) -> Self {
+        rows.into_iter().collect()
+    }
+}
+
+impl<'a> IntoIterator for &'a Rows {
+    type Item = &'a str;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+/// The rows from `front` up to `back`
+#[derive(Clone)]
+pub struct Iter<'a> {
+    rows: &'a Rows,
+    front: usize,
+    back: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        if self.front == self.back {
+            return None;
+        }
+        self.front += 1;
+        self.rows.get(self.front - 1)
+    }
+
+    fn nth(&mut self, n: usize) -> Option<&'a str> {
+        self.front = self.front.saturating_add(n).min(self.back);
+        self.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.back - self.front;
+        (len, Some(len))
+    }
+}
+
+impl DoubleEndedIterator for Iter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        self.rows.get(self.back)
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small deterministic generator, so a failing run can be repeated
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 = self
+                .0
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            (self.0 >> 33) as usize % n.max(1)
+        }
+
+        /// A row mostly of a few dozen bytes, now and then one long enough to fill a block
+        fn row(&mut self) -> String {
+            let len = match self.below(20) {
+                0 => self.below(3 * BLOCK_BYTES),
+                1 => 0,
+                _ => self.below(80),
+            };
+            let c = char::from(b'a' + u8::try_from(self.below(26)).unwrap());
+            c.to_string().repeat(len)
+        }
+    }
+
+    /// Checks `rows` holds what `model` does, and that its blocks add up
+    fn check(rows: &Rows, model: &[String]) {
+        assert_eq!(rows.len(), model.len());
+        assert!(rows.iter().eq(model.iter().map(String::as_str)));
+        assert!(rows.iter().rev().eq(model.iter().rev().map(String::as_str)));
+        assert!(rows.blocks.iter().all(|block| !block.ends.is_empty()));
+        let mut start = 0;
+        for (block, first) in rows.blocks.iter().zip(&rows.starts) {
+            assert_eq!(*first, start);
+            assert_eq!(
+                block.ends.last().map(|end| *end as usize),
+                Some(block.text.len())
+            );
+            start += block.len();
+        }
+        assert_eq!(
+            rows.starts.len(),
+            rows.blocks.len() + usize::from(!rows.blocks.is_empty())
+        );
+    }
+
+    #[test]
+    fn edits_match_a_vec_of_strings() {
+        let mut lcg = Lcg(7);
+        let mut rows = Rows::new();
+        let mut model: Vec<String> = Vec::new();
+        for _ in 0..5000 {
+            let len = model.len();
+            let idx = lcg.below(len);
+            match lcg.below(12) {
+                0 | 1 => {
+                    let row = lcg.row();
+                    rows.push(&row);
+                    model.push(row);
+                }
+                2 => {
+                    let idx = lcg.below(len + 1);
+                    let row = lcg.row();
+                    rows.insert(idx, &row);
+                    model.insert(idx, row);
+                }
+                3 if len > 0 => assert_eq!(rows.remove(idx), model.remove(idx)),
+                4 => {
+                    let start = lcg.below(len + 1);
+                    let end = start + lcg.below(len - start + 1).min(50);
+                    let added: Vec<String> = (0..lcg.below(60)).map(|_| lcg.row()).collect();
+                    rows.splice(start..end, &added);
+                    model.splice(start..end, added);
+                }
+                5 if len > 0 => {
+                    let at = lcg.below(model[idx].len() + 1);
+                    let end = at + lcg.below(model[idx].len() - at + 1);
+                    let with = lcg.row();
+                    rows.replace_range(idx, at..end, &with);
+                    model[idx].replace_range(at..end, &with);
+                }
+                6 if len > 0 => {
+                    let text = lcg.row();
+                    rows.push_str(idx, &text);
+                    model[idx].push_str(&text);
+                }
+                7 if len > 0 => {
+                    let at = lcg.below(model[idx].len() + 1);
+                    assert_eq!(rows.split_off(idx, at), model[idx].split_off(at));
+                }
+                8 if len > 0 => {
+                    let other = lcg.below(len);
+                    rows.swap(idx, other);
+                    model.swap(idx, other);
+                }
+                9 => {
+                    let start = lcg.below(len + 1);
+                    let end = start + lcg.below(len - start + 1);
+                    let rewritten = rows.rewrite(start..end, |row| {
+                        (row.len() % 3 != 0).then(|| format!("-{row}"))
+                    });
+                    let mut expected = 0;
+                    for row in &mut model[start..end] {
+                        if row.len() % 3 != 0 {
+                            row.insert(0, '-');
+                            expected += 1;
+                        }
+                    }
+                    assert_eq!(rewritten, expected);
+                }
+                10 => assert_eq!(rows.pop(), model.pop()),
+                _ => {
+                    let other: Rows = (0..lcg.below(40)).map(|_| lcg.row()).collect();
+                    model.extend(other.iter().map(String::from));
+                    rows.append(other);
+                }
+            }
+            check(&rows, &model);
+        }
+    }
+
+    #[test]
+    fn ranges_are_clamped() {
+        let rows = Rows::from(vec!["a", "b", "c"]);
+        assert!(rows.range(1..10).eq(["b", "c"]));
+        assert!(rows.range(5..10).eq([""; 0]));
+        assert_eq!(rows.range(0..3).nth(2), Some("c"));
+        assert_eq!(rows.range(0..3).nth(3), None);
+    }
+
+    #[test]
+    fn a_big_file_takes_little_more_than_its_text() {
+        let mut rows = Rows::new();
+        let mut bytes = 0;
+        for n in 0..200_000 {
+            let row = format!("{n:>8} a line of a log of the kind that gets opened");
+            bytes += row.len() + 1;
+            rows.push(&row);
+        }
+        rows.shrink_to_fit();
+        let held: usize = rows
+            .blocks
+            .iter()
+            .map(|block| block.text.capacity() + 4 * block.ends.capacity())
+            .sum();
+        assert!(
+            held * 10 <= bytes * 12,
+            "{} bytes of rows for {} of text",
+            held,
+            bytes
+        );
+    }
+}
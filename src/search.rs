@@ -0,0 +1,106 @@
+//! Finding a query in a row, with or without telling upper and lower case apart. Case is folded
+//! one char at a time with `char::to_lowercase`, so a match always starts and ends on a char
+//! boundary, however many bytes the chars folded to or from.
+
+use std::ops::Range;
+
+/// Whether a search tells upper and lower case apart
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CaseMode {
+    Sensitive,
+    Insensitive,
+    /// Insensitive unless the query has an upper case letter in it
+    Smart,
+}
+
+impl std::str::FromStr for CaseMode {
+    type Err = ();
+
+    /// The names the config file uses
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "sensitive" => Ok(CaseMode::Sensitive),
+            "insensitive" => Ok(CaseMode::Insensitive),
+            "smart" => Ok(CaseMode::Smart),
+            _ => Err(()),
+        }
+    }
+}
+
+impl CaseMode {
+    /// The mode after this one, for the key cycling through them
+    pub fn next(self) -> Self {
+        match self {
+            CaseMode::Sensitive => CaseMode::Insensitive,
+            CaseMode::Insensitive => CaseMode::Smart,
+            CaseMode::Smart => CaseMode::Sensitive,
+        }
+    }
+
+    /// The letter shown in the search prompt
+    pub fn flag(self) -> char {
+        match self {
+            CaseMode::Sensitive => 'C',
+            CaseMode::Insensitive => 'I',
+            CaseMode::Smart => 'S',
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            CaseMode::Sensitive => "Search matches case",
+            CaseMode::Insensitive => "Search ignores case",
+            CaseMode::Smart => "Search ignores case unless the query has capitals",
+        }
+    }
+
+    fn ignores_case(self, query: &str) -> bool {
+        match self {
+            CaseMode::Sensitive => false,
+            CaseMode::Insensitive => true,
+            CaseMode::Smart => !query.chars().any(char::is_uppercase),
+        }
+    }
+}
+
+/// Where `query` appears in `line`, as byte ranges that don't overlap, from left to right.
+/// An empty query matches nothing.
+pub fn matches(line: &str, query: &str, mode: CaseMode) -> Vec<Range<usize>> {
+    if query.is_empty() {
+        return Vec::new();
+    }
+    if !mode.ignores_case(query) {
+        return line
+            .match_indices(query)
+            .map(|(start, found)| start..start + found.len())
+            .collect();
+    }
+
+    let query: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+    let mut found = Vec::new();
+    let mut from = 0;
+    for (start, _) in line.char_indices() {
+        if start < from {
+            continue;
+        }
+        if let Some(end) = folded_match(&line[start..], &query) {
+            found.push(start..start + end);
+            from = start + end;
+        }
+    }
+    found
+}
+
+/// The length of the start of `text` that folds to `query`, if it does
+fn folded_match(text: &str, query: &[char]) -> Option<usize> {
+    let mut rest = query;
+    for (idx, c) in text.char_indices() {
+        if rest.is_empty() {
+            return Some(idx);
+        }
+        for folded in c.to_lowercase() {
+            rest = rest.strip_prefix(&[folded])?;
+        }
+    }
+    rest.is_empty().then_some(text.len())
+}
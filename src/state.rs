@@ -0,0 +1,321 @@
+//! Files rilo keeps for itself between sessions
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::filetype;
+use crate::rows::Rows;
+
+/// The directory rilo keeps its state in, following the XDG base directory spec
+pub fn state_dir() -> Option<PathBuf> {
+    env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .map(|dir| dir.join("rilo"))
+}
+
+/// Appends a line to rilo's log, for problems worth finding the cause of later but not worth
+/// interrupting the user for
+pub fn log(line: &str) -> io::Result<()> {
+    let dir = state_dir().ok_or_else(|| io::Error::other("No state directory"))?;
+    fs::create_dir_all(&dir)?;
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join("log"))?;
+    writeln!(file, "{seconds} {line}")
+}
+
+/// Where the scratch buffer is kept between sessions
+fn scratch_path() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("scratch"))
+}
+
+/// Reads back the rows of the last scratch buffer, a missing or unreadable file is just an empty scratch
+pub fn load_scratch() -> Rows {
+    scratch_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|content| content.lines().collect())
+        .unwrap_or_default()
+}
+
+pub fn store_scratch(rows: &Rows) -> io::Result<()> {
+    let path = scratch_path().ok_or_else(|| io::Error::other("No state directory"))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut content = rows.iter().collect::<Vec<_>>().join("\n");
+    content.push('\n');
+    fs::write(path, content)
+}
+
+/// Most files remembered in the per-file state, older ones are forgotten first
+const MAX_FILE_RECORDS: usize = 500;
+
+/// What's remembered about a single file, fields left as `None` fall back to the global config
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct FileState {
+    pub wrap: Option<bool>,
+    pub tab_size: Option<u8>,
+    pub expand_tab: Option<bool>,
+    pub show_invisibles: Option<bool>,
+    /// The filetype's name, when it was set by hand rather than told by the file's name
+    pub filetype: Option<&'static str>,
+    /// Where the cursor was left, `None` starts at the top
+    pub position: Option<SavedPosition>,
+}
+
+/// The cursor and the window over the file, as a row and an index into it and the offsets the
+/// window was scrolled by
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SavedPosition {
+    pub y: usize,
+    pub x: usize,
+    pub row_offset: usize,
+    pub col_offset: usize,
+}
+
+impl SavedPosition {
+    fn parse(value: &str) -> Option<SavedPosition> {
+        let mut numbers = value.split(',').map(|number| number.parse().ok());
+        let position = SavedPosition {
+            y: numbers.next()??,
+            x: numbers.next()??,
+            row_offset: numbers.next()??,
+            col_offset: numbers.next()??,
+        };
+        numbers.next().is_none().then_some(position)
+    }
+}
+
+impl FileState {
+    fn is_empty(self) -> bool {
+        self == FileState::default()
+    }
+
+    /// Fills in whatever `self` doesn't set from `fallback`
+    pub fn or(self, fallback: FileState) -> FileState {
+        FileState {
+            wrap: self.wrap.or(fallback.wrap),
+            tab_size: self.tab_size.or(fallback.tab_size),
+            expand_tab: self.expand_tab.or(fallback.expand_tab),
+            show_invisibles: self.show_invisibles.or(fallback.show_invisibles),
+            filetype: self.filetype.or(fallback.filetype),
+            position: self.position.or(fallback.position),
+        }
+    }
+
+    fn to_fields(self) -> Vec<(&'static str, String)> {
+        let mut fields = Vec::new();
+        if let Some(wrap) = self.wrap {
+            fields.push(("wrap", wrap.to_string()));
+        }
+        if let Some(tab_size) = self.tab_size {
+            fields.push(("tab_size", tab_size.to_string()));
+        }
+        if let Some(expand_tab) = self.expand_tab {
+            fields.push(("expand_tab", expand_tab.to_string()));
+        }
+        if let Some(show_invisibles) = self.show_invisibles {
+            fields.push(("show_invisibles", show_invisibles.to_string()));
+        }
+        if let Some(filetype) = self.filetype {
+            fields.push(("filetype", filetype.to_string()));
+        }
+        if let Some(position) = self.position {
+            let SavedPosition {
+                y,
+                x,
+                row_offset,
+                col_offset,
+            } = position;
+            fields.push(("position", format!("{y},{x},{row_offset},{col_offset}")));
+        }
+        fields
+    }
+
+    /// Fields that fail to parse are dropped rather than failing the whole record
+    fn from_fields<'a>(fields: impl Iterator<Item = (&'a str, &'a str)>) -> FileState {
+        let mut state = FileState::default();
+        for (key, value) in fields {
+            match key {
+                "wrap" => state.wrap = value.parse().ok(),
+                "tab_size" => state.tab_size = value.parse().ok().filter(|size| *size != 0),
+                "expand_tab" => state.expand_tab = value.parse().ok(),
+                "show_invisibles" => state.show_invisibles = value.parse().ok(),
+                "filetype" => state.filetype = filetype::named(value).map(|filetype| filetype.name),
+                "position" => state.position = SavedPosition::parse(value),
+                _ => {}
+            }
+        }
+        state
+    }
+}
+
+/// One line per file, most recently used first: the canonical path, then tab separated `key=value` fields
+fn files_path() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("files"))
+}
+
+/// Reads every record, skipping any line that doesn't parse so a corrupt file only loses those lines
+fn read_file_records() -> Vec<(PathBuf, FileState)> {
+    let content = files_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .unwrap_or_default();
+
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split('\t');
+            let path = parts.next().filter(|path| path.starts_with('/'))?;
+            let fields = parts.filter_map(|field| field.split_once('='));
+            Some((PathBuf::from(path), FileState::from_fields(fields)))
+        })
+        .take(MAX_FILE_RECORDS)
+        .collect()
+}
+
+/// Every file with something remembered about it, most recently used first
+pub fn recent_files() -> Vec<PathBuf> {
+    read_file_records()
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect()
+}
+
+pub fn load_file_state(path: &Path) -> Option<FileState> {
+    let path = path.canonicalize().ok()?;
+    read_file_records()
+        .into_iter()
+        .find(|(record_path, _)| *record_path == path)
+        .map(|(_, state)| state)
+}
+
+/// Remembers `state` for `path`, or forgets the path when there's nothing worth remembering
+pub fn store_file_state(path: &Path, state: FileState) -> io::Result<()> {
+    let path = path.canonicalize()?;
+    if path
+        .to_str()
+        .is_none_or(|path| path.contains(['\t', '\n', '\r']))
+    {
+        return Err(io::Error::other("Path can't be stored in the state file"));
+    }
+
+    let mut records = read_file_records();
+    records.retain(|(record_path, _)| *record_path != path);
+    if !state.is_empty() {
+        records.insert(0, (path.clone(), state));
+    }
+    records.truncate(MAX_FILE_RECORDS);
+
+    let mut content = String::new();
+    for (record_path, record) in &records {
+        // Every path was either read back from the file as text or checked above
+        content.push_str(&record_path.to_string_lossy());
+        for (key, value) in record.to_fields() {
+            content.push('\t');
+            content.push_str(key);
+            content.push('=');
+            content.push_str(&value);
+        }
+        content.push('\n');
+    }
+
+    let files_path = files_path().ok_or_else(|| io::Error::other("No state directory"))?;
+    if let Some(dir) = files_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(files_path, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `state` written out as a record's fields and read back
+    fn round_trip(state: FileState) -> FileState {
+        let fields = state.to_fields();
+        let text: Vec<String> = fields
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect();
+        FileState::from_fields(text.iter().filter_map(|field| field.split_once('=')))
+    }
+
+    #[test]
+    fn every_option_round_trips() {
+        let position = SavedPosition {
+            y: 120,
+            x: 7,
+            row_offset: 100,
+            col_offset: 3,
+        };
+        for wrap in [None, Some(false), Some(true)] {
+            for tab_size in [None, Some(1), Some(8), Some(u8::MAX)] {
+                for expand_tab in [None, Some(false), Some(true)] {
+                    for show_invisibles in [None, Some(false), Some(true)] {
+                        for filetype in [None, Some("make"), Some("yaml")] {
+                            for position in [None, Some(position)] {
+                                let state = FileState {
+                                    wrap,
+                                    tab_size,
+                                    expand_tab,
+                                    show_invisibles,
+                                    filetype,
+                                    position,
+                                };
+                                assert_eq!(round_trip(state), state);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_bad_field_loses_only_itself() {
+        let fields = [
+            ("wrap", "maybe"),
+            ("tab_size", "0"),
+            ("expand_tab", "true"),
+            ("show_invisibles", "1"),
+            ("filetype", "no-such-type"),
+            ("position", "1,2,3"),
+            ("unknown", "x"),
+        ];
+        let state = FileState::from_fields(fields.iter().copied());
+        assert_eq!(
+            state,
+            FileState {
+                expand_tab: Some(true),
+                ..FileState::default()
+            }
+        );
+    }
+
+    #[test]
+    fn saved_fields_fill_in_for_missing_ones() {
+        let saved = FileState {
+            wrap: Some(true),
+            ..FileState::default()
+        };
+        let config = FileState {
+            wrap: Some(false),
+            tab_size: Some(4),
+            ..FileState::default()
+        };
+        let state = saved.or(config);
+        assert_eq!(state.wrap, Some(true));
+        assert_eq!(state.tab_size, Some(4));
+        assert_eq!(state.expand_tab, None);
+    }
+}
@@ -0,0 +1,1781 @@
+//! Runs of the whole editor without a terminal: keys come from `input::Keys`, the window is a
+//! `FixedTerminal` and what's drawn is caught by `set_output`. Everything drawn goes through one
+//! sink for the whole process, so the tests driving an editor take turns.
+
+use super::*;
+use std::sync::{Arc, MutexGuard, Once, PoisonError};
+
+use render::{Frame, Screen, WIDE_TAIL};
+
+static DRAWING: Mutex<()> = Mutex::new(());
+
+/// The bytes drawn since the sink was set
+#[derive(Clone, Default)]
+pub(crate) struct Drawn(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Drawn {
+    fn write(&mut self, buff: &[u8]) -> io::Result<usize> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extend_from_slice(buff);
+        Ok(buff.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drawn {
+    /// What was drawn since the last call
+    pub(crate) fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut self.0.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+/// An editor with a window of `rows` by `cols`, what it draws and its turn at drawing
+pub(crate) struct Harness {
+    pub(crate) e: Editor,
+    pub(crate) drawn: Drawn,
+    _turn: MutexGuard<'static, ()>,
+}
+
+impl Harness {
+    pub(crate) fn new(rows: usize, cols: usize) -> Self {
+        Self::with_config(rows, cols, Config::default())
+    }
+
+    pub(crate) fn with_config(rows: usize, cols: usize, config: Config) -> Self {
+        let turn = DRAWING.lock().unwrap_or_else(PoisonError::into_inner);
+        keep_state_apart();
+        let drawn = Drawn::default();
+        set_output(Box::new(drawn.clone()));
+        let e = Editor::with_terminal(
+            &cli::Options::default(),
+            config,
+            Box::new(FixedTerminal { rows, cols }),
+            Input::new(Box::new(input::Keys::new(&[]))),
+        );
+        Harness {
+            e,
+            drawn,
+            _turn: turn,
+        }
+    }
+
+    /// Runs the main loop from the start, opening `path`, until `keys` run out
+    pub(crate) fn run(&mut self, path: &Path, keys: &[u8]) {
+        let options = cli::Options {
+            path: Some(path.to_string_lossy().into_owned()),
+            ..cli::Options::default()
+        };
+        self.run_with(&options, keys);
+    }
+
+    /// Runs the main loop from the start, as started with `options`, until `keys` run out
+    pub(crate) fn run_with(&mut self, options: &cli::Options, keys: &[u8]) {
+        self.e.input = Input::new(Box::new(input::Keys::new(keys)));
+        let ended = run(&mut self.e, options, None);
+        assert!(
+            matches!(ended, Err(error::Error::Input(ref err)) if err.kind() == ErrorKind::UnexpectedEof),
+            "the keys should have run out, the loop ended with {:?}",
+            ended
+        );
+    }
+
+    /// Handles `keys` like the main loop does, returning how many times it drew
+    pub(crate) fn keys(&mut self, keys: &[u8]) -> usize {
+        self.e.input = Input::new(Box::new(input::Keys::new(keys)));
+        let mut draws = 0;
+        // The keys running out ends it
+        while let Ok(Some(event)) = self.e.input.next(Some(Duration::ZERO)) {
+            assert!(self.e.handle_event(event), "the editor quit");
+            if !self.e.input.pending() {
+                self.e.draw();
+                draws += 1;
+            }
+        }
+        draws
+    }
+
+    /// Makes the window `rows` by `cols` from now on, the editor finds out when it next looks
+    pub(crate) fn resize(&mut self, rows: usize, cols: usize) {
+        self.e.terminal = Box::new(FixedTerminal { rows, cols });
+    }
+
+    pub(crate) fn frame(&mut self) -> Frame {
+        self.e.compose_frame()
+    }
+
+    /// The frame's rows as they'd read on the screen, without trailing blanks
+    pub(crate) fn screen_text(&mut self) -> Vec<String> {
+        self.frame()
+            .lines
+            .iter()
+            .map(|line| {
+                let text: String = line.text.iter().filter(|c| **c != WIDE_TAIL).collect();
+                text.trim_end().to_string()
+            })
+            .collect()
+    }
+
+    /// The cursor as (row index, index into the row)
+    pub(crate) fn cursor(&self) -> (usize, usize) {
+        (self.e.row_offset + self.e.cur_pos.y, self.e.cur_pos.x)
+    }
+}
+
+/// Per-file state and the log go to a directory of their own, nothing from the user's sessions
+/// gets in and nothing from the tests gets out
+fn keep_state_apart() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| {
+        let dir = env::temp_dir().join(format!("rilo-test-state-{}", std::process::id()));
+        env::set_var("XDG_STATE_HOME", dir);
+    });
+}
+
+/// A directory for a test's files, removed with them when it's dropped
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    pub(crate) fn new(name: &str) -> Self {
+        let dir = env::temp_dir().join(format!("rilo-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("the temp directory can be created");
+        TempDir(dir)
+    }
+
+    /// A file `name` in the directory holding `text`
+    pub(crate) fn file(&self, name: &str, text: &str) -> PathBuf {
+        let path = self.0.join(name);
+        fs::write(&path, text).expect("the file can be written");
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+const UP: &[u8] = b"\x1b[A";
+const DOWN: &[u8] = b"\x1b[B";
+const END: &[u8] = b"\x1b[F";
+const HOME: &[u8] = b"\x1b[H";
+const RIGHT: &[u8] = b"\x1b[C";
+const BACKSPACE: &[u8] = b"\x7f";
+const CTRL_S: u8 = 0x13;
+const ENTER: &[u8] = b"\r";
+const ALT_R: &[u8] = b"\x1br";
+const CTRL_BACKSLASH: &[u8] = b"\x1c";
+
+#[test]
+fn open_navigate_edit_save() {
+    let dir = TempDir::new("edit");
+    let path = dir.file("notes.txt", "one\ntwo\nthree\n");
+    let mut h = Harness::new(6, 120);
+
+    h.run(&path, &[DOWN, END, b"!", &[CTRL_S]].concat());
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "one\ntwo!\nthree\n");
+    assert_eq!(h.cursor(), (1, 4));
+    assert!(!h.e.dirty_flag);
+    let screen = h.screen_text();
+    assert_eq!(screen[..5], ["one", "two!", "three", "~", "~"]);
+    assert!(
+        screen[5].contains("File saved successfully!"),
+        "status bar: {:?}",
+        screen[5]
+    );
+    assert!(
+        screen[5].contains("Ln 2, Col 5"),
+        "status bar: {:?}",
+        screen[5]
+    );
+
+    // The first frame paints every row, from the top left
+    let drawn = h.drawn.take();
+    let first_row = b"\x1b[1;1H\x1b[Kone";
+    assert!(
+        drawn.windows(first_row.len()).any(|w| w == first_row),
+        "the first row wasn't painted: {}",
+        String::from_utf8_lossy(&drawn)
+    );
+    // and the cursor ends up after the '!'
+    assert!(
+        drawn.ends_with(b"\x1b[2;5H\x1b[?25h"),
+        "{}",
+        String::from_utf8_lossy(&drawn)
+    );
+}
+
+#[test]
+fn frame_bytes_of_a_small_window() {
+    let dir = TempDir::new("frame");
+    let path = dir.file("a.txt", "ab\n");
+    let mut h = Harness::new(3, 20);
+    h.run(&path, &[]);
+
+    let frame = h.frame();
+    let painted = frame.render(&h.e.theme(), &mut Screen::default());
+    let text = String::from_utf8_lossy(&painted);
+    // Every row from the top left, the filler row and the status bar in their styles, and a
+    // status bar too narrow for the file name leaves it out
+    assert_eq!(
+        text,
+        "\x1b[1;1H\x1b[Kab\
+         \x1b[2;1H\x1b[K\x1b[m~\x1b[m\
+         \x1b[3;1H\x1b[K\x1b[m\x1b[7m LF  Ln 1, Col 1  1\x1b[m"
+    );
+    assert_eq!((frame.cursor.x, frame.cursor.y), (0, 0));
+}
+
+#[test]
+fn a_listing_takes_no_typing() {
+    let dir = TempDir::new("listing");
+    dir.file("a.txt", "");
+    let config = Config {
+        auto_close: true,
+        ..Config::default()
+    };
+    let mut h = Harness::with_config(6, 120, config);
+
+    h.run(&dir.0, b"(");
+
+    assert!(h.e.listing.is_some());
+    assert!(
+        h.e.rows.iter().all(|row| !row.contains(['(', ')'])),
+        "{:?}",
+        h.e.rows
+    );
+    assert!(!h.e.dirty_flag);
+    let screen = h.screen_text();
+    assert!(
+        screen[5].contains("A directory listing can't be edited"),
+        "status bar: {:?}",
+        screen[5]
+    );
+}
+
+#[test]
+fn a_file_still_being_read_takes_no_typing() {
+    let dir = TempDir::new("loading");
+    let path = dir.file("big.txt", "first\n");
+    let config = Config {
+        auto_close: true,
+        ..Config::default()
+    };
+    let mut h = Harness::with_config(6, 120, config);
+    h.run(&path, &[]);
+    // As if the file were too big to read before showing it, and the rest were still coming
+    let file = File::open(&path).unwrap();
+    let lines = LineReader::new(io::BufReader::new(file));
+    h.e.loader = Some(loader::Loader::start(lines, 1 << 40));
+
+    h.e.type_char('(');
+
+    assert_eq!(h.e.rows, ["first"]);
+    assert!(!h.e.dirty_flag);
+    let screen = h.screen_text();
+    assert!(
+        screen[5].contains("Still reading the file"),
+        "status bar: {:?}",
+        screen[5]
+    );
+}
+
+#[test]
+fn a_file_with_bad_utf8_shows_replacements_and_isnt_saved_unless_asked() {
+    let dir = TempDir::new("lossy");
+    let path = dir.file("bad.txt", "");
+    let bytes = b"caf\xe9 ok\n\xff\xfe\n";
+    fs::write(&path, bytes).unwrap();
+    let mut h = Harness::new(6, 40);
+
+    h.run(&path, &[b"x", &[CTRL_S][..], b"n"].concat());
+
+    assert!(h.e.lossy);
+    assert_eq!(h.e.rows, ["xcaf\u{fffd} ok", "\u{fffd}\u{fffd}"]);
+    assert_eq!(fs::read(&path).unwrap(), bytes);
+    assert!(h.e.dirty_flag);
+
+    // Once it's agreed to, the file is written as UTF-8
+    h.keys(&[CTRL_S, b'y']);
+    assert!(!h.e.lossy);
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "xcaf\u{fffd} ok\n\u{fffd}\u{fffd}\n"
+    );
+}
+
+#[test]
+fn reading_bad_utf8_replaces_only_the_bad_bytes() {
+    let text = read_lines(&b"a\xff\r\n\xe2\x82\r\n\xe2\x82\xac\r\n"[..]).unwrap();
+    assert!(text.lossy);
+    assert_eq!(text.rows, ["a\u{fffd}", "\u{fffd}", "\u{20ac}"]);
+    assert!(matches!(text.line_ending, LineEnding::Crlf));
+    assert!(text.final_newline);
+
+    let text = read_lines(&b"plain\n"[..]).unwrap();
+    assert!(!text.lossy);
+}
+
+#[test]
+fn saving_a_new_buffer_over_a_file_asks_first() {
+    let dir = TempDir::new("save-to");
+    let path = dir.file("kept.txt", "kept\n");
+    let keys = |answer: &[u8]| {
+        let name = path.to_str().unwrap().as_bytes();
+        [b"new", &[CTRL_S][..], name, ENTER, answer].concat()
+    };
+
+    let mut h = Harness::new(6, 120);
+    h.run_with(&cli::Options::default(), &keys(b"n"));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "kept\n");
+    assert!(h.e.path.is_none());
+    assert!(h.e.dirty_flag);
+    drop(h);
+
+    let mut h = Harness::new(6, 120);
+    h.run_with(&cli::Options::default(), &keys(b"y"));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+    assert_eq!(h.e.path.as_deref(), path.to_str());
+    assert!(!h.e.dirty_flag);
+}
+
+#[test]
+fn path_prompts_complete_with_tab() {
+    let dir = TempDir::new("complete");
+    dir.file("alpha.txt", "");
+    dir.file("alphabet.txt", "");
+    dir.file(".alps", "");
+    fs::create_dir(dir.0.join("beta")).unwrap();
+    let base = format!("{}/", dir.0.display());
+    let complete = |typed: &str| browser::complete(&format!("{base}{typed}"));
+
+    assert_eq!(complete("al"), format!("{base}alpha"));
+    assert_eq!(complete("alphab"), format!("{base}alphabet.txt"));
+    assert_eq!(complete("b"), format!("{base}beta/"));
+    assert_eq!(complete(".a"), format!("{base}.alps"));
+    assert_eq!(complete("x"), format!("{base}x"));
+    assert_eq!(complete("nowhere/a"), format!("{base}nowhere/a"));
+
+    // Tab in the prompt completes, elsewhere it's swallowed
+    let path = dir.file("mine.txt", "mine\n");
+    let mut h = Harness::new(6, 120);
+    h.run(&path, &[]);
+    let keys = [format!("{base}alphab").as_bytes(), b"\t", ENTER].concat();
+    h.e.input = Input::new(Box::new(input::Keys::new(&keys)));
+    assert_eq!(
+        h.e.path_prompt("Compare with:").ok(),
+        Some(format!("{base}alphabet.txt"))
+    );
+    h.e.input = Input::new(Box::new(input::Keys::new(&[b"al\t", ENTER].concat())));
+    assert_eq!(h.e.prompt("Go to line:").ok().as_deref(), Some("al"));
+}
+
+#[test]
+fn saving_as_an_existing_file_asks_first() {
+    let dir = TempDir::new("save-as");
+    let path = dir.file("mine.txt", "mine\n");
+    let other = dir.file("theirs.txt", "theirs\n");
+    let mut h = Harness::new(6, 120);
+    h.run(&path, &[]);
+    let answering = |answer: &[u8]| {
+        let keys = [other.to_str().unwrap().as_bytes(), ENTER, answer].concat();
+        Input::new(Box::new(input::Keys::new(&keys)))
+    };
+
+    h.e.input = answering(b"n");
+    perform(&mut h.e, Action::SaveAs);
+    assert_eq!(fs::read_to_string(&other).unwrap(), "theirs\n");
+    assert_eq!(h.e.path.as_deref(), path.to_str());
+
+    h.e.input = answering(b"y");
+    perform(&mut h.e, Action::SaveAs);
+    assert_eq!(fs::read_to_string(&other).unwrap(), "mine\n");
+    assert_eq!(h.e.path.as_deref(), other.to_str());
+}
+
+#[test]
+fn saving_as_takes_the_lock_and_respects_others() {
+    let dir = TempDir::new("save-as-lock");
+    let path = dir.file("mine.txt", "mine\n");
+    let held = dir.file("held.txt", "held\n");
+    let free = dir.file("free.txt", "free\n");
+    // Another session editing held.txt
+    let theirs = lock::Lock::acquire(&held, &File::open(&held).unwrap()).unwrap();
+    let mut h = Harness::new(6, 120);
+    h.run(&path, &[]);
+    let answering = |path: &Path| {
+        let keys = [path.to_str().unwrap().as_bytes(), ENTER, b"y"].concat();
+        Input::new(Box::new(input::Keys::new(&keys)))
+    };
+
+    h.e.input = answering(&held);
+    perform(&mut h.e, Action::SaveAs);
+    assert_eq!(fs::read_to_string(&held).unwrap(), "held\n");
+    assert_eq!(h.e.path.as_deref(), path.to_str());
+    let said = &h.e.message_log.back().unwrap().1;
+    assert!(said.contains("is being edited by"), "{:?}", said);
+
+    h.e.input = answering(&free);
+    perform(&mut h.e, Action::SaveAs);
+    assert_eq!(fs::read_to_string(&free).unwrap(), "mine\n");
+    assert!(h.e.lock.as_ref().is_some_and(|lock| lock.covers(&free)));
+    drop(theirs);
+}
+
+#[test]
+fn saving_as_another_filetype_takes_its_indent() {
+    let dir = TempDir::new("save-as-indent");
+    let path = dir.file("build.yaml", "all:\n");
+    let mut h = Harness::new(6, 120);
+    h.run(&path, &[]);
+    assert_eq!((h.e.tab_size, h.e.expand_tab), (2, true));
+    let save_as = |name: &str| {
+        let keys = [dir.0.join(name).to_str().unwrap().as_bytes(), ENTER].concat();
+        Input::new(Box::new(input::Keys::new(&keys)))
+    };
+
+    h.e.input = save_as("Makefile");
+    perform(&mut h.e, Action::SaveAs);
+    h.keys(&[END, ENTER, b"\t"].concat());
+    assert_eq!(h.e.rows, ["all:", "\t"]);
+    assert_eq!(h.e.tab_size, Config::default().tab_size);
+
+    // A tab size set by hand stays, the tabs still follow the filetype
+    h.keys(&[ALT_X, b"set ts=3", ENTER].concat());
+    h.e.input = save_as("build.yml");
+    perform(&mut h.e, Action::SaveAs);
+    assert_eq!((h.e.tab_size, h.e.expand_tab), (3, true));
+}
+
+#[test]
+fn saving_as_the_file_being_edited_writes_it() {
+    let dir = TempDir::new("save-as-own");
+    let path = dir.file("mine.txt", "mine\n");
+    let mut h = Harness::new(6, 120);
+    h.run(&path, b"x");
+    let keys = [path.to_str().unwrap().as_bytes(), ENTER, b"y"].concat();
+    h.e.input = Input::new(Box::new(input::Keys::new(&keys)));
+    perform(&mut h.e, Action::SaveAs);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "xmine\n");
+    assert!(!h.e.dirty_flag);
+    assert!(h.e.lock.as_ref().is_some_and(|lock| lock.covers(&path)));
+
+    // Saving again doesn't take the write for another program's
+    h.keys(&[b'y', CTRL_S]);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "xymine\n");
+}
+
+#[test]
+fn saving_a_locked_out_file_as_another_makes_it_editable() {
+    let dir = TempDir::new("save-as-locked-out");
+    let held = dir.file("held.txt", "held\n");
+    let theirs = lock::Lock::acquire(&held, &File::open(&held).unwrap()).unwrap();
+    let mut h = Harness::new(6, 120);
+    h.run(&held, b"r");
+    assert!(h.e.readonly);
+
+    let copy = dir.0.join("copy.txt");
+    let keys = [copy.to_str().unwrap().as_bytes(), ENTER].concat();
+    h.e.input = Input::new(Box::new(input::Keys::new(&keys)));
+    perform(&mut h.e, Action::SaveAs);
+    assert_eq!(h.e.path.as_deref(), copy.to_str());
+    assert!(!h.e.readonly);
+    h.keys(b"x");
+    assert_eq!(h.e.rows, ["xheld"]);
+    drop(theirs);
+}
+
+#[test]
+fn a_counted_replay_runs_the_macro_that_many_times_and_draws_once() {
+    let dir = TempDir::new("macro");
+    let path = dir.file("m.txt", "\n");
+    let mut h = Harness::new(6, 120);
+    h.run(&path, &[]);
+
+    h.keys(&[ALT_R, b"ab", ALT_R].concat());
+    assert_eq!(h.e.rows, ["ab"]);
+    let said = &h.e.message_log.back().unwrap().1;
+    assert!(said.ends_with(" replays them"), "{:?}", said);
+
+    let draws = h.keys(&[b"\x1b1\x1b2", CTRL_BACKSLASH].concat());
+    assert_eq!(h.e.rows, [String::from("ab").repeat(13)]);
+    // Drawn once, when all the replays are done
+    assert_eq!(draws, 1);
+
+    // The count is used up, a plain press replays once
+    h.keys(CTRL_BACKSLASH);
+    assert_eq!(h.e.rows, [String::from("ab").repeat(14)]);
+}
+
+#[test]
+fn matching_bracket_is_back_on_ctrl_close_bracket() {
+    let dir = TempDir::new("bracket");
+    let path = dir.file("b.txt", "(a\nb)\n");
+    let mut h = Harness::new(6, 120);
+    h.run(&path, b"\x1d");
+    assert_eq!(h.cursor(), (1, 1));
+}
+
+/// Every key a prompt could be sent: the bound ones and the sequences it has to read past
+fn every_key() -> Vec<Vec<u8>> {
+    let mut keys: Vec<Vec<u8>> = BINDINGS
+        .iter()
+        .filter_map(|binding| binding.key.as_ref().map(Key::encode))
+        .collect();
+    keys.extend(
+        [
+            &b"\x1b[A"[..],
+            b"\x1b[3~",
+            b"\x1b[2~",
+            b"\x1b[Z",
+            b"\x1bx",
+            b"\x1bOP",
+            b"\x1b[<0;3;2M",
+            b"\x1b[200~pasted\x1b[201~",
+            b"\x1b[I",
+            b"\x1b[O",
+            b"\x1b1",
+        ]
+        .iter()
+        .map(|key| key.to_vec()),
+    );
+    keys
+}
+
+#[test]
+fn prompts_take_every_key_without_touching_the_buffer() {
+    let dir = TempDir::new("prompt-matrix");
+    let path = dir.file("p.txt", "one\ntwo\n");
+    let mut h = Harness::new(6, 120);
+    h.run(&path, &[]);
+    let feed = |e: &mut Editor, keys: &[&[u8]]| {
+        e.input = Input::new(Box::new(input::Keys::new(&keys.concat())));
+    };
+
+    for key in every_key() {
+        let cancels = matches!(h.e.bound_action(&key), Some(Action::Cancel));
+        let expected_text = if cancels {
+            None
+        } else {
+            Some(String::from("abc"))
+        };
+
+        feed(&mut h.e, &[&key, b"abc", ENTER]);
+        let text = h.e.prompt("Go to:").ok();
+        assert_eq!(text, expected_text, "prompt, after {key:?}");
+
+        feed(&mut h.e, &[&key, b"abc", ENTER]);
+        let text = h.e.search_prompt().ok();
+        assert_eq!(text, expected_text, "search prompt, after {key:?}");
+
+        feed(&mut h.e, &[&key, b"n"]);
+        let choices = vec![
+            Choice {
+                key: 'y',
+                label: "yes",
+            },
+            Choice {
+                key: 'n',
+                label: "no",
+            },
+        ];
+        let answer = h.e.confirm("Sure?", choices).unwrap();
+        assert_eq!(answer, (!cancels).then_some('n'), "question, after {key:?}");
+
+        assert_eq!(h.e.rows, ["one", "two"], "after {key:?}");
+        assert!(!h.e.dirty_flag, "after {:?}", key);
+        assert!(
+            !h.e.prompt_active && h.e.confirm.is_none(),
+            "after {:?}",
+            key
+        );
+    }
+}
+
+#[test]
+fn focus_changes_are_ignored_by_prompts_and_the_buffer() {
+    let (focus_in, focus_out): (&[u8], &[u8]) = (b"\x1b[I", b"\x1b[O");
+    let dir = TempDir::new("focus");
+    let path = dir.file("f.txt", "one\n");
+    let mut h = Harness::new(6, 40);
+    h.run(&path, &[focus_out, focus_in].concat());
+    assert_eq!(h.e.rows, ["one"]);
+    assert!(!h.e.dirty_flag);
+
+    h.e.input = Input::new(Box::new(input::Keys::new(
+        &[b"a", focus_out, b"b", focus_in, ENTER].concat(),
+    )));
+    assert_eq!(h.e.prompt("Go to:").ok().as_deref(), Some("ab"));
+
+    h.e.input = Input::new(Box::new(input::Keys::new(
+        &[focus_out, focus_in, b"n"].concat(),
+    )));
+    let choices = vec![
+        Choice {
+            key: 'y',
+            label: "yes",
+        },
+        Choice {
+            key: 'n',
+            label: "no",
+        },
+    ];
+    assert_eq!(h.e.confirm("Sure?", choices).unwrap(), Some('n'));
+
+    h.keys(&[focus_out, b"x", focus_in].concat());
+    assert_eq!(h.e.rows, ["xone"]);
+}
+
+#[test]
+fn messages_wait_for_the_prompt_and_prompts_dont_nest() {
+    let mut h = Harness::new(6, 120);
+    h.e.prompt_active = true;
+    h.e.set_message("raised meanwhile");
+    assert!(h.e.prompt("Again:").is_err());
+    assert!(h.e.confirm("Again?", Vec::new()).is_err());
+    assert_ne!(h.e.message.message.as_deref(), Some("raised meanwhile"));
+    h.e.prompt_active = false;
+
+    h.e.release_queued_message();
+    assert_eq!(h.e.message.message.as_deref(), Some("raised meanwhile"));
+}
+
+#[test]
+fn every_ctrl_letter() {
+    // The letter, what its byte is taken as on its own, and what the default keymap makes it
+    let table = [
+        ('a', Action::Input('\x01'), Action::Home),
+        ('b', Action::Input('\x02'), Action::ToggleTrim),
+        ('c', Action::Input('\x03'), Action::Cancel),
+        ('d', Action::Input('\x04'), Action::DuplicateLine),
+        ('e', Action::Input('\x05'), Action::ScrollDown),
+        ('f', Action::Input('\x06'), Action::Find),
+        ('g', Action::Input('\x07'), Action::ToggleInvisibles),
+        ('h', Action::Delete, Action::Delete),
+        ('i', Action::Tab, Action::Tab),
+        ('j', Action::Input('\n'), Action::JoinLines),
+        ('k', Action::Input('\x0b'), Action::KillLine),
+        ('l', Action::Input('\x0c'), Action::Recenter),
+        ('m', Action::Enter, Action::Enter),
+        ('n', Action::Input('\x0e'), Action::ConvertLineEndings),
+        ('o', Action::Input('\x0f'), Action::WriteTo),
+        ('p', Action::Input('\x10'), Action::Input('\x10')),
+        ('q', Action::Input('\x11'), Action::Quit),
+        ('r', Action::Input('\x12'), Action::ToggleReadonly),
+        ('s', Action::Input('\x13'), Action::Save),
+        ('t', Action::Input('\x14'), Action::ToggleWrap),
+        ('u', Action::Input('\x15'), Action::Yank),
+        ('v', Action::Input('\x16'), Action::InsertVerbatim),
+        ('w', Action::Input('\x17'), Action::KillPreviousWord),
+        ('x', Action::Input('\x18'), Action::Cut),
+        ('y', Action::Input('\x19'), Action::ScrollUp),
+        ('z', Action::Input('\x1a'), Action::Suspend),
+    ];
+    let h = Harness::new(6, 80);
+
+    for (letter, alone, bound) in table {
+        let byte = letter as u8 & 0x1f;
+        assert_eq!(Action::from(byte), alone, "ctrl-{letter}");
+        assert_eq!(h.e.action_for(byte), bound, "ctrl-{letter}");
+        // The ones sending a named key's byte can't be bound, the rest can
+        let reserved = matches!(letter, 'h' | 'i' | 'm');
+        let parsed = format!("ctrl-{letter}").parse::<Key>();
+        assert_eq!(parsed.is_err(), reserved, "ctrl-{letter}: {parsed:?}");
+    }
+}
+
+/// Checks the cursor is somewhere an edit can happen: on a row, or at the start of the row after
+/// the last, and on a char boundary
+fn assert_sane_cursor(h: &mut Harness, after: &str) {
+    let (y, x) = h.cursor();
+    let row = h.e.rows.get(y);
+    assert!(
+        row.map_or(y == h.e.rows.len() && x == 0, |row| row.is_char_boundary(x)),
+        "the cursor is at {:?} after {}, with {} rows",
+        (y, x),
+        after,
+        h.e.rows.len()
+    );
+    let cursor = h.e.screen_cursor();
+    assert!(cursor.y <= h.e.term_rows, "{:?} after {}", cursor, after);
+}
+
+#[test]
+fn every_action_and_move_on_an_empty_buffer() {
+    let moves = [
+        NavigationKey::Left,
+        NavigationKey::Right,
+        NavigationKey::Up,
+        NavigationKey::Down,
+        NavigationKey::Home,
+        NavigationKey::End,
+        NavigationKey::PageUp,
+        NavigationKey::PageDown,
+        NavigationKey::WordLeft,
+        NavigationKey::WordRight,
+        NavigationKey::ParagraphUp,
+        NavigationKey::ParagraphDown,
+    ];
+    let actions = BINDINGS
+        .iter()
+        .map(|binding| binding.action)
+        // Stopping the process would stop the tests
+        .filter(|action| *action != Action::Suspend)
+        .chain([Action::Input('x'), Action::Input('é'), Action::Input('\t')]);
+
+    for action in actions {
+        for wrap in [false, true] {
+            let mut h = Harness::new(6, 40);
+            h.e.wrap = wrap;
+            // Whatever the action asks gets the input running out, like Escape
+            perform(&mut h.e, action);
+            h.e.draw();
+            let after = format!("{action:?}");
+            assert_sane_cursor(&mut h, &after);
+            for key in &moves {
+                h.e.move_cursor(key);
+                h.e.draw();
+                assert_sane_cursor(&mut h, &format!("{after} then {key:?}"));
+            }
+        }
+    }
+}
+
+#[test]
+fn sanitize_caps_huge_text_quickly() {
+    let huge = "é".repeat(8 * 1024 * 1024);
+    let started = Instant::now();
+    let shown = sanitize(&huge, MAX_MESSAGE_LEN);
+    assert!(started.elapsed() < Duration::from_millis(100));
+    assert_eq!(shown.chars().count(), MAX_MESSAGE_LEN + 3);
+    assert!(shown.ends_with("é..."));
+
+    // Text exactly as long as the cap is kept whole
+    let fits = "x".repeat(MAX_MESSAGE_LEN);
+    assert_eq!(sanitize(&fits, MAX_MESSAGE_LEN), fits);
+}
+
+#[test]
+fn sanitize_escapes_control_chars() {
+    let cases = [
+        ("plain", "plain"),
+        ("\x1b[2Jgone", "^[[2Jgone"),
+        ("tab\there", "tab^Ihere"),
+        ("line\nbreak\r", "line^Jbreak^M"),
+        ("nul\0del\x7f", "nul^@del^?"),
+        ("c1\u{9b}31m", "c1\\u{9b}31m"),
+        ("wide 漢字 stays", "wide 漢字 stays"),
+    ];
+    for (text, shown) in cases {
+        assert_eq!(sanitize(text, MAX_MESSAGE_LEN), shown, "{text:?}");
+    }
+    // An escaped char counts once against the cap, however many chars it's shown as
+    assert_eq!(sanitize("\x01\x02\x03", 2), "^A^B...");
+}
+
+// Movement that once left the cursor off its row: an edit right after it mustn't trip the
+// out-of-sync check in `edit_point`, which panics in tests
+
+#[test]
+fn end_on_a_long_line_scrolled_sideways() {
+    let dir = TempDir::new("long-end");
+    let long = "0123456789".repeat(6);
+    let path = dir.file("l.txt", &format!("short\n{long}\n"));
+    let mut h = Harness::new(6, 20);
+
+    h.run(&path, &[DOWN, END, b"!", END, b"?"].concat());
+
+    assert_eq!(h.e.rows[1], format!("{long}!?"));
+    assert_eq!(h.cursor(), (1, 62));
+    assert!(h.e.col_offset > 0);
+    // Up onto the short row lands at its end, inside it
+    h.keys(&[UP, b"."].concat());
+    assert_eq!(&h.e.rows[0], "short.");
+    assert_eq!(h.cursor(), (0, 6));
+}
+
+#[test]
+fn the_wheel_keeps_the_cursor_on_a_char() {
+    let dir = TempDir::new("wheel");
+    let path = dir.file("w.txt", &format!("abcd\n{}", "ééééé\n".repeat(20)));
+    let mut h = Harness::new(6, 40);
+    let wheel_down = b"\x1b[<65;1;1M";
+
+    h.run(
+        &path,
+        &[RIGHT, RIGHT, RIGHT, wheel_down, wheel_down, b"x"].concat(),
+    );
+
+    // The fourth column is the fourth é, two bytes each
+    assert_eq!(h.cursor(), (6, 7));
+    assert_eq!(&h.e.rows[6], "éééxéé");
+}
+
+#[test]
+fn a_click_past_the_end_of_a_row_scrolled_by_lands_on_its_end() {
+    let dir = TempDir::new("click");
+    let long = "0123456789".repeat(6);
+    let path = dir.file("c.txt", &format!("{long}\nab\n"));
+    let mut h = Harness::new(6, 20);
+
+    h.run(&path, &[END, b"\x1b[<0;5;2M", b"x"].concat());
+
+    assert_eq!(&h.e.rows[1], "abx");
+    assert_eq!(h.cursor(), (1, 3));
+}
+
+#[test]
+fn rows_scrolled_sideways_start_at_the_same_column() {
+    let dir = TempDir::new("scroll-tabs");
+    let plain = "0123456789".repeat(5);
+    let path = dir.file("t.txt", &format!("\t\t\t{plain}\n{plain}\n"));
+    let mut h = Harness::new(6, 20);
+    h.run(&path, &[DOWN, END].concat());
+    let edge = h.e.col_offset;
+    assert!(edge > 0);
+    h.e.color_column = vec![edge + 5];
+
+    let layout = h.e.layout(edge, false);
+    let left = layout.gutter + layout.left_marker;
+    let lines = h.frame().lines;
+    for (idx, line) in lines.iter().enumerate().take(2) {
+        let (whole, _) = render_row(&h.e.rows[idx], h.e.tab_size, false, 0, usize::MAX);
+        assert_eq!(
+            line.text[left..left + 10],
+            whole.text[edge..edge + 10],
+            "row {idx}"
+        );
+        let guide = line
+            .spans
+            .iter()
+            .find(|span| span.style == Style::ColorColumn);
+        assert_eq!(guide.map(|span| span.start), Some(left + 4), "row {idx}");
+    }
+}
+
+#[test]
+fn a_row_of_ascii_cjk_and_combining_accents() {
+    // 'e' with a combining acute takes one column, each CJK char two
+    let row = "ae\u{301}漢字x";
+    let (rendered, whole) = render_row(row, 8, false, 0, usize::MAX);
+    assert!(whole);
+    assert_eq!(
+        rendered.text,
+        ['a', 'e', '漢', WIDE_TAIL, '字', WIDE_TAIL, 'x']
+    );
+    assert_eq!(rendered.combining, [(1, '\u{301}')]);
+
+    let x = row.find('x').unwrap();
+    assert_eq!(cx_to_rx(row, x, 8), 6);
+    assert_eq!(rx_to_cx(row, 6, 8), x);
+    // Both columns of a wide char are on it
+    assert_eq!(rx_to_cx(row, 3, 8), row.find('漢').unwrap());
+    assert_eq!(rx_to_cx(row, 5, 8), row.find('字').unwrap());
+
+    // A wide char cut by the right edge of the window shows a placeholder, not half of it
+    let dir = TempDir::new("mixed");
+    let path = dir.file("m.txt", &format!("{}漢\n", "a".repeat(8)));
+    let config = Config {
+        clip_markers: false,
+        ..Config::default()
+    };
+    let mut h = Harness::with_config(6, 10, config);
+    h.run(&path, &[]);
+    assert_eq!(h.screen_text()[0], "aaaaaaaa>");
+}
+
+#[test]
+fn a_ten_megabyte_line_draws_a_window_at_a_time() {
+    let dir = TempDir::new("huge-line");
+    let line = "lorem\tipsum dolor sit amet ".repeat(10 * 1024 * 1024 / 27);
+    let path = dir.file("huge.txt", &format!("{line}\n"));
+    let (rows, cols) = (6, 80);
+    let mut h = Harness::new(rows, cols);
+    h.run(&path, &[]);
+    h.drawn.take();
+
+    let started = Instant::now();
+    for key in [END, b"x", b"y", b"z"] {
+        assert_eq!(h.keys(key), 1);
+        // A full repaint of every cell with room for styles, nothing like the row
+        let drawn = h.drawn.take().len();
+        assert!(drawn < 8 * rows * cols, "{} bytes drawn for one key", drawn);
+    }
+    assert!(h.e.rows[0].ends_with(" xyz"));
+    assert_eq!(h.cursor(), (0, line.len() + 3));
+    let text = h.screen_text();
+    assert!(text[0].ends_with("amet xyz"), "{:?}", text[0]);
+    // Generous for a debug build, rendering the whole row each key would take far longer
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn paragraph_boundaries() {
+    let runs = Rows::from(vec!["a", "b", "", " \t", "c", "d", "", ""]);
+    let edges = Rows::from(vec!["", "a", "", "b", ""]);
+    let cases = [
+        // Consecutive blank rows count as one gap, whitespace is blank too
+        (&runs, 0, true, 2),
+        (&runs, 2, true, 6),
+        (&runs, 3, true, 6),
+        (&runs, 6, true, 7),
+        (&runs, 7, true, 7),
+        (&runs, 7, false, 3),
+        (&runs, 4, false, 0),
+        (&runs, 3, false, 0),
+        (&runs, 0, false, 0),
+        // A blank first and last row are boundaries themselves
+        (&edges, 0, true, 2),
+        (&edges, 2, true, 4),
+        (&edges, 4, true, 4),
+        (&edges, 4, false, 2),
+        (&edges, 2, false, 0),
+        (&edges, 1, false, 0),
+        (&edges, 0, false, 0),
+    ];
+    for (rows, y, forward, expected) in cases {
+        assert_eq!(
+            paragraph_boundary(rows, y, forward),
+            expected,
+            "{y} forward: {forward}"
+        );
+    }
+
+    let empty = Rows::from(Vec::<&str>::new());
+    assert_eq!(paragraph_boundary(&empty, 0, true), 0);
+    assert_eq!(paragraph_boundary(&empty, 0, false), 0);
+}
+
+#[test]
+fn pathological_window_sizes() {
+    let dir = TempDir::new("sizes");
+    let path = dir.file("s.txt", "one\ttwo 漢字\nthree\n");
+    let mut h = Harness::new(24, 80);
+    h.run(&path, &[]);
+
+    let sizes = [
+        (0, 0),
+        (1, 1),
+        (65535, 65535),
+        (0, 80),
+        (24, 0),
+        (1, 65535),
+        (65535, 1),
+        (2, 2),
+        (0, 0),
+        (65535, 65535),
+        (24, 80),
+    ];
+    let mut last = (24, 80);
+    for (rows, cols) in sizes {
+        h.resize(rows, cols);
+        h.e.idle();
+        h.keys(&[END, b"x", DOWN, ENTER, BACKSPACE].concat());
+        // A window of no size can't be told from one that can't say, the last size is kept
+        if rows > 0 && cols > 0 {
+            last = (rows.min(MAX_WINDOW_SIZE), cols.min(MAX_WINDOW_SIZE));
+        }
+        let (rows, cols) = last;
+        assert_eq!(h.e.text_height, rows.saturating_sub(1), "{rows}x{cols}");
+        assert_eq!(h.e.term_cols, cols.saturating_sub(1), "{rows}x{cols}");
+        let frame = h.frame();
+        assert!(
+            frame.lines.len() <= rows,
+            "{} lines in {rows}x{cols}",
+            frame.lines.len()
+        );
+        for line in &frame.lines {
+            assert!(line.text.len() <= cols, "{:?} in {rows}x{cols}", line.text);
+        }
+    }
+    assert!(
+        h.e.rows[0].starts_with("one\ttwo 漢字x"),
+        "{:?}",
+        &h.e.rows[0]
+    );
+}
+
+#[test]
+fn cursor_reports() {
+    let cases: [(&[u8], _); 9] = [
+        (b"\x1b[24;80R", Some((24, 80))),
+        (b"\x1b[1;1R", Some((1, 1))),
+        (
+            b"\x1b[65535;65535R",
+            Some((MAX_WINDOW_SIZE, MAX_WINDOW_SIZE)),
+        ),
+        (b"\x1b[99999999999999999999999;80R", None),
+        (b"\x1b[0;80R", None),
+        (b"\x1b[24;0R", None),
+        (b"junk\x1b[5;7R", Some((5, 7))),
+        (b"\x1b[24;80", None),
+        (b"\x1b[;R", None),
+    ];
+    for (response, expected) in cases {
+        assert_eq!(parse_cursor_report(response), expected, "{response:?}");
+    }
+}
+
+#[test]
+fn a_resize_between_idle_ticks_reflows_once() {
+    let dir = TempDir::new("idle-resize");
+    let path = dir.file("i.txt", &"0123456789\n".repeat(30));
+    let mut h = Harness::new(24, 80);
+    h.run(&path, &[]);
+    h.keys(&[DOWN; 20].concat());
+    h.drawn.take();
+
+    // Nothing changed, nothing drawn
+    assert!(!h.e.idle());
+    assert!(h.drawn.take().is_empty());
+
+    for (rows, cols) in [(10, 40), (10, 40), (30, 100), (6, 5), (24, 80)] {
+        let resized = (rows - 1, cols - 1) != (h.e.text_height, h.e.term_cols);
+        h.resize(rows, cols);
+        assert_eq!(h.e.idle(), resized, "{rows}x{cols}");
+        assert_eq!(!h.drawn.take().is_empty(), resized, "{rows}x{cols}");
+        assert_eq!((h.e.text_height, h.e.term_cols), (rows - 1, cols - 1));
+        // The cursor's row stays in the window
+        assert_eq!(h.cursor(), (20, 0));
+        assert!(h.e.cur_pos.y <= h.e.term_rows, "{}x{}", rows, cols);
+        assert_eq!(h.frame().lines.len(), rows);
+    }
+}
+
+#[test]
+fn redraw_looks_at_the_colors_again() {
+    let mut h = Harness::new(24, 80);
+    h.e.color_mode = cli::ColorMode::Always;
+    let saved = [
+        ("COLORTERM", env::var_os("COLORTERM")),
+        ("TERM", env::var_os("TERM")),
+    ];
+
+    env::set_var("COLORTERM", "truecolor");
+    perform(&mut h.e, Action::Redraw);
+    assert_eq!(h.e.colors, Some(ColorDepth::TrueColor));
+
+    env::remove_var("COLORTERM");
+    env::set_var("TERM", "xterm-256color");
+    perform(&mut h.e, Action::Redraw);
+    assert_eq!(h.e.colors, Some(ColorDepth::Palette));
+
+    h.e.color_mode = cli::ColorMode::Never;
+    perform(&mut h.e, Action::Redraw);
+    assert_eq!(h.e.colors, None);
+
+    for (name, value) in saved {
+        match value {
+            Some(value) => env::set_var(name, value),
+            None => env::remove_var(name),
+        }
+    }
+}
+
+#[test]
+fn print_on_exit_prints_the_edited_buffer() {
+    let mut h = Harness::new(10, 40);
+    let options = cli::Options {
+        print_on_exit: true,
+        ..cli::Options::default()
+    };
+    // Quitting doesn't ask about the changes, they go to stdout
+    let keys = [DOWN, END, b"!", ENTER, b"2.5", DOWN, BACKSPACE, b"\x11"].concat();
+    h.e = Editor::with_terminal(
+        &options,
+        Config::default(),
+        Box::new(FixedTerminal { rows: 10, cols: 40 }),
+        Input::new(Box::new(input::Keys::new(&keys))),
+    );
+    let piped = vec![Row::from("one"), Row::from("two"), Row::from("three")];
+    let ended = run(&mut h.e, &options, Some(piped));
+    assert!(ended.is_ok(), "{:?}", ended);
+
+    assert_eq!(h.e.rows, ["one", "two!", "2.5", "thee"]);
+    assert_eq!(h.e.printed_text(), "one\ntwo!\n2.5\nthee\n");
+}
+
+#[test]
+fn pasting_ten_million_lines_takes_linear_time() {
+    let config = Config {
+        paste_limit_mb: 1024,
+        ..Config::default()
+    };
+    let mut h = Harness::with_config(10, 40, config);
+    let mut paste = |lines: usize| {
+        let text = (0..lines).fold(String::new(), |mut text, n| {
+            text.push_str(&n.to_string());
+            text.push_str("\tpasted\n");
+            text
+        });
+        h.e.rows = Rows::from(vec!["ab"]);
+        h.e.cur_pos = CursorPosition { x: 1, y: 0 };
+        h.e.row_offset = 0;
+        let started = Instant::now();
+        h.e.paste(text.as_bytes());
+        let took = started.elapsed();
+        assert_eq!(h.e.rows.len(), lines + 1);
+        assert_eq!(&h.e.rows[0], "a0\tpasted");
+        assert_eq!(&h.e.rows[lines], "b");
+        assert_eq!(h.cursor(), (lines, 0));
+        took
+    };
+
+    let small = paste(1_000_000);
+    let large = paste(10_000_000);
+    // Ten times the lines, allowing plenty for allocators and caches but nothing like quadratic
+    assert!(
+        large < small * 30,
+        "{:?} for 1M lines, {:?} for 10M",
+        small,
+        large
+    );
+}
+
+#[test]
+fn index_and_column_conversions() {
+    // 'a', a tab, 'b', a wide char and an 'e' with a combining accent
+    let row = "a\tb漢e\u{301}";
+    let to_columns = [
+        (0, 4, 0),
+        (1, 4, 1),
+        (2, 4, 4),
+        (2, 8, 8),
+        (3, 4, 5),
+        // Inside the wide char and the accent, counted from their start
+        (4, 4, 5),
+        (5, 4, 5),
+        (6, 4, 7),
+        (7, 4, 8),
+        (8, 4, 8),
+        (9, 4, 8),
+        // Past the end
+        (10, 4, 8),
+        (usize::MAX, 4, 8),
+    ];
+    for (cx, tab_size, rx) in to_columns {
+        assert_eq!(
+            cx_to_rx(row, cx, tab_size),
+            rx,
+            "{cx} with tabs of {tab_size}"
+        );
+    }
+
+    let to_indexes = [
+        (0, 4, 0),
+        // Every column of the tab lands on it
+        (1, 4, 1),
+        (3, 4, 1),
+        (7, 8, 1),
+        (4, 4, 2),
+        (8, 8, 2),
+        // Both columns of the wide char land on it
+        (5, 4, 3),
+        (6, 4, 3),
+        (7, 4, 6),
+        // Past the end
+        (8, 4, 9),
+        (100, 4, 9),
+    ];
+    for (rx, tab_size, cx) in to_indexes {
+        assert_eq!(
+            rx_to_cx(row, rx, tab_size),
+            cx,
+            "{rx} with tabs of {tab_size}"
+        );
+    }
+
+    // A tab after text only reaches the next stop
+    assert_eq!(cx_to_rx("ab\tc", 3, 4), 4);
+    assert_eq!(rx_to_cx("ab\tc", 3, 4), 2);
+    assert_eq!(cx_to_rx("", 5, 4), 0);
+    assert_eq!(rx_to_cx("", 5, 4), 0);
+}
+
+#[test]
+fn selection_wins_over_search_over_syntax() {
+    let dir = TempDir::new("layers");
+    let path = dir.file("l.toml", "value = \"value\" # value\n");
+    let mut h = Harness::new(6, 60);
+    h.run(&path, &[]);
+    h.e.search = Some(Search {
+        query: String::from("value"),
+        current: (0, 0),
+    });
+    h.e.mark = Some((0, 0));
+    h.e.cur_pos.x = 11;
+
+    let lines = h.e.text_layer();
+    assert_eq!(lines.len(), 5);
+    let row = &lines[0];
+    let gutter = h.e.layout(0, false).gutter;
+    let cases = [
+        // The selection covers the key, the current match and a match in a string
+        (1, Style::Selection),
+        (6, Style::Selection),
+        (10, Style::Selection),
+        // Past it a match shows over the string, and over the comment
+        (12, Style::SearchMatch),
+        (14, Style::Syntax(highlight::Kind::String)),
+        (16, Style::Syntax(highlight::Kind::Comment)),
+        (19, Style::SearchMatch),
+    ];
+    for (column, style) in cases {
+        assert_eq!(row.style_at(gutter + column), style, "column {column}");
+    }
+
+    // Without the selection the matches show, without the search the syntax
+    h.e.mark = None;
+    let row = &h.e.text_layer()[0];
+    assert_eq!(row.style_at(gutter + 1), Style::CurrentMatch);
+    assert_eq!(row.style_at(gutter + 10), Style::SearchMatch);
+    h.e.search = None;
+    let row = &h.e.text_layer()[0];
+    assert_eq!(
+        row.style_at(gutter + 1),
+        Style::Syntax(highlight::Kind::Key)
+    );
+    assert_eq!(
+        row.style_at(gutter + 10),
+        Style::Syntax(highlight::Kind::String)
+    );
+    assert_eq!(
+        row.style_at(gutter + 19),
+        Style::Syntax(highlight::Kind::Comment)
+    );
+}
+
+#[test]
+fn the_status_bar_layer() {
+    let dir = TempDir::new("status-layer");
+    let path = dir.file("s.txt", "\tone\nthree\n");
+    let mut h = Harness::new(6, 60);
+    h.run(&path, &[DOWN, b"x"].concat());
+    h.e.message.message = None;
+
+    let bar = h.e.status_bar_layer(true);
+    let text: String = bar.text.iter().collect();
+    // A long path gives way from its start
+    assert!(text.contains("status-layer/s.txt [+]"), "{:?}", text);
+    assert!(
+        text.ends_with("LF  Ln 2, Col 2  2 lines  100%"),
+        "{:?}",
+        text
+    );
+    assert_eq!(bar.style_at(0), Style::StatusBar);
+    assert_eq!(bar.style_at(h.e.term_cols - 1), Style::StatusBar);
+
+    // A tab makes the drawn column and the index into the row differ
+    h.e.tab_size = 4;
+    h.keys(&[UP, END].concat());
+    let text: String = h.e.status_bar_layer(true).text.iter().collect();
+    assert!(text.contains("Ln 1, Col 8 (char 5)"), "{:?}", text);
+
+    // The pane that isn't being edited, and a window without focus, are dimmed
+    assert_eq!(
+        h.e.status_bar_layer(false).style_at(0),
+        Style::StatusBarUnfocused
+    );
+    h.e.focused = false;
+    h.e.config.dim_unfocused = true;
+    assert_eq!(
+        h.e.status_bar_layer(true).style_at(0),
+        Style::StatusBarUnfocused
+    );
+}
+
+#[test]
+fn the_pane_layer_is_text_rows_and_a_status_bar() {
+    let dir = TempDir::new("pane-layer");
+    let path = dir.file("p.txt", &"row\n".repeat(20));
+    let mut h = Harness::new(6, 40);
+    h.run(&path, &[DOWN, DOWN, END].concat());
+
+    let (lines, cursor) = h.e.pane_layer(true);
+    assert_eq!(lines.len(), 6);
+    assert_eq!(lines[..5], h.e.text_layer()[..]);
+    assert_eq!(lines[5], h.e.status_bar_layer(true));
+    let gutter = h.e.layout(0, false).gutter;
+    assert_eq!((cursor.y, cursor.x), (2, gutter + 3));
+}
+
+#[test]
+fn view_options_are_remembered_until_forgotten() {
+    let dir = TempDir::new("file-options");
+    let path = dir.file("notes.txt", "a\n");
+    let mut h = Harness::new(6, 60);
+    // Invisibles shown and YAML's filetype, which indents with two spaces
+    h.run(&path, &[b"\x07", ALT_X, b"set ft=yaml", ENTER].concat());
+    assert!(!perform(&mut h.e, Action::Quit));
+    drop(h);
+
+    let mut h = Harness::new(6, 60);
+    h.run(&path, b"\t");
+    assert!(h.e.show_invisibles);
+    assert_eq!(h.e.filetype.map(|filetype| filetype.name), Some("yaml"));
+    assert_eq!(h.e.rows, ["  a"]);
+    h.keys(&[ALT_X, b"forget-file-options", ENTER].concat());
+    assert!(!h.e.show_invisibles);
+    assert_eq!(h.e.filetype, None);
+    assert!(!perform(&mut h.e, Action::ForceQuit));
+    drop(h);
+
+    let mut h = Harness::new(6, 60);
+    h.run(&path, b"\t");
+    assert!(!h.e.show_invisibles);
+    assert_eq!(h.e.filetype, None);
+    // Where the cursor was is still remembered, at the end of the row
+    assert_eq!(h.e.rows, ["a\t"]);
+}
+
+const ALT_W: &[u8] = b"\x1bw";
+
+/// Keys that open `path` with `e path` at the Alt-X prompt
+fn edit_keys(path: &Path) -> Vec<u8> {
+    [ALT_X, b"e ", path.to_str().unwrap().as_bytes(), ENTER].concat()
+}
+
+#[test]
+fn two_panes_over_two_files() {
+    let dir = TempDir::new("split-files");
+    let alpha = dir.file("alpha.txt", "alpha\n");
+    let beta = dir.file("beta.txt", "beta\n");
+    let mut h = Harness::new(12, 40);
+    let binary = dir.file("binary.dat", "\0\0\0");
+    h.run(&alpha, &[]);
+    perform(&mut h.e, Action::OtherPane);
+    // A file that can't be opened leaves both panes on alpha.txt
+    h.keys(&edit_keys(&binary));
+    assert_eq!(h.e.rows, ["alpha"]);
+    assert!(h
+        .e
+        .split
+        .as_ref()
+        .is_some_and(|split| split.buffer.is_none()));
+    // The pane being edited gets the file, the other one keeps alpha.txt
+    h.keys(&[&edit_keys(&beta)[..], b"x"].concat());
+    assert_eq!(h.e.rows, ["xbeta"]);
+    assert_eq!(h.e.path.as_deref(), beta.to_str());
+    let text = h.screen_text();
+    let top = text.iter().position(|line| line == "alpha").unwrap();
+    let bottom = text.iter().position(|line| line == "xbeta").unwrap();
+    assert!(top < bottom, "{:?}", text);
+
+    h.keys(&[ALT_W, b"y"].concat());
+    assert_eq!(h.e.rows, ["yalpha"]);
+    assert_eq!(h.e.path.as_deref(), alpha.to_str());
+    assert!(h.screen_text().contains(&String::from("xbeta")));
+    h.keys(ALT_W);
+    assert_eq!(h.e.rows, ["xbeta"]);
+
+    // Neither file is open twice
+    h.keys(&edit_keys(&alpha));
+    assert_eq!(h.e.path.as_deref(), beta.to_str());
+    let said = &h.e.message_log.back().unwrap().1;
+    assert!(said.ends_with("is open in the other pane"), "{:?}", said);
+
+    // Closing the other pane doesn't lose its changes
+    perform(&mut h.e, Action::ClosePane);
+    assert!(h.e.split.is_some());
+
+    // Quitting asks about both files
+    h.e.input = Input::new(Box::new(input::Keys::new(b"nn")));
+    assert!(!perform(&mut h.e, Action::Quit));
+    assert_eq!(fs::read_to_string(&alpha).unwrap(), "alpha\n");
+    assert_eq!(fs::read_to_string(&beta).unwrap(), "beta\n");
+}
+
+#[test]
+fn closing_a_pane_over_another_file() {
+    let dir = TempDir::new("split-close");
+    let alpha = dir.file("alpha.txt", "alpha\n");
+    let beta = dir.file("beta.txt", "beta\n");
+    let mut h = Harness::new(12, 40);
+    h.run(&alpha, &[]);
+    perform(&mut h.e, Action::OtherPane);
+    h.keys(&[&edit_keys(&beta)[..], ALT_W, b"x", &[CTRL_S]].concat());
+    assert_eq!(fs::read_to_string(&alpha).unwrap(), "xalpha\n");
+
+    h.keys(ALT_W);
+    perform(&mut h.e, Action::ClosePane);
+    assert!(h.e.split.is_none());
+    assert_eq!(h.e.rows, ["beta"]);
+    // Its lock went with it, alpha.txt can be opened again
+    h.keys(&edit_keys(&alpha));
+    assert_eq!(h.e.rows, ["xalpha"]);
+    assert!(!h.e.readonly);
+}
+
+#[test]
+fn a_small_window_hides_the_other_file() {
+    let dir = TempDir::new("split-small");
+    let alpha = dir.file("alpha.txt", "alpha\n");
+    let beta = dir.file("beta.txt", "beta\n");
+    let mut h = Harness::new(12, 40);
+    h.run(&alpha, &[]);
+    perform(&mut h.e, Action::OtherPane);
+    h.keys(&edit_keys(&beta));
+
+    h.resize(3, 40);
+    h.e.idle();
+    assert!(h.e.split.is_some());
+    assert_eq!(h.frame().lines.len(), 3);
+    assert_eq!(h.screen_text()[0], "beta");
+    h.keys(ALT_W);
+    assert_eq!(h.screen_text()[0], "alpha");
+
+    h.resize(12, 40);
+    h.e.idle();
+    let text = h.screen_text();
+    assert!(text.contains(&String::from("alpha")), "{:?}", text);
+    assert!(text.contains(&String::from("beta")), "{:?}", text);
+}
+
+#[test]
+fn right_at_the_end_of_the_file() {
+    let dir = TempDir::new("right-eof");
+    let path = dir.file("r.txt", "ab\ncd\n");
+    let mut h = Harness::new(6, 40);
+
+    h.run(&path, &[DOWN, END, RIGHT, RIGHT, RIGHT, b"e"].concat());
+
+    assert_eq!(h.e.rows, ["ab", "cde"]);
+    assert_eq!(h.cursor(), (1, 3));
+}
+
+#[test]
+fn backspace_after_joining_lines() {
+    let dir = TempDir::new("merge");
+    let path = dir.file("m.txt", "ab\ncd\n");
+    let mut h = Harness::new(6, 40);
+
+    h.run(&path, &[DOWN, HOME, BACKSPACE].concat());
+    assert_eq!(h.e.rows, ["abcd"]);
+    assert_eq!(h.cursor(), (0, 2));
+
+    h.keys(BACKSPACE);
+    assert_eq!(h.e.rows, ["acd"]);
+    assert_eq!(h.cursor(), (0, 1));
+}
+
+#[test]
+fn backspace_and_join_at_the_top_of_the_file() {
+    let dir = TempDir::new("top");
+    let path = dir.file("t.txt", "ab\ncd\n");
+    let mut h = Harness::new(6, 40);
+
+    // At the very start there's nothing to delete
+    h.run(&path, BACKSPACE);
+    assert_eq!(h.e.rows, ["ab", "cd"]);
+    assert_eq!(h.cursor(), (0, 0));
+    assert!(!h.e.dirty_flag);
+
+    // On the first char it deletes just that
+    h.keys(&[RIGHT, BACKSPACE].concat());
+    assert_eq!(h.e.rows, ["b", "cd"]);
+    assert_eq!(h.cursor(), (0, 0));
+
+    // Joining the first line keeps the cursor on it
+    h.keys(b"\x0a");
+    assert_eq!(h.e.rows.len(), 1);
+    assert!(h.e.rows[0].starts_with('b') && h.e.rows[0].ends_with("cd"));
+    assert_eq!(h.cursor().0, 0);
+}
+
+#[test]
+fn backspace_joins_onto_a_row_above_the_window() {
+    let dir = TempDir::new("join-above");
+    let rows: Vec<String> = (0..10).map(|n| format!("row{n}")).collect();
+    let text = rows.join("\n") + "\n";
+    let path = dir.file("j.txt", &text);
+    let mut h = Harness::new(4, 40);
+    h.run(&path, &[]);
+    // The cursor at the start of the window's top row, with the row before it scrolled away
+    h.e.row_offset = 5;
+    h.e.cur_pos = CursorPosition { x: 0, y: 0 };
+
+    h.keys(BACKSPACE);
+
+    assert_eq!(&h.e.rows[4], "row4row5");
+    assert_eq!(h.cursor(), (4, 4));
+    assert_eq!(h.e.row_offset, 4);
+}
+
+/// Where the last frame left the terminal's cursor, as the 1-based row and column it was sent
+fn drawn_cursor(drawn: &[u8]) -> (usize, usize) {
+    let text = String::from_utf8_lossy(drawn);
+    let moves: Vec<(usize, usize)> = text
+        .split("\x1b[")
+        .filter_map(|seq| {
+            let (row, rest) = seq.split_once(';')?;
+            let (column, _) = rest.split_once('H')?;
+            Some((row.parse().ok()?, column.parse().ok()?))
+        })
+        .collect();
+    *moves.last().expect("the cursor was moved")
+}
+
+#[test]
+fn the_cursor_is_never_sent_below_the_text() {
+    const PAGE_DOWN: &[u8] = b"\x1b[6~";
+    let dir = TempDir::new("bottom");
+    let rows: Vec<String> = (0..20).map(|n| format!("line {n}")).collect();
+    let path = dir.file("b.txt", &(rows.join("\n") + "\n"));
+    let mut h = Harness::new(6, 40);
+    h.run(&path, &[]);
+    let text_rows = h.e.term_rows + 1;
+    assert_eq!(text_rows, 5);
+
+    let cases: [(&[u8], (usize, usize)); 5] = [
+        (&DOWN.repeat(4), (5, 1)),
+        (DOWN, (5, 1)),
+        (PAGE_DOWN, (5, 1)),
+        (&[PAGE_DOWN, PAGE_DOWN, PAGE_DOWN].concat(), (5, 1)),
+        (&[DOWN, END].concat(), (5, 7)),
+    ];
+    for (keys, expected) in cases {
+        h.drawn.take();
+        h.keys(keys);
+        let (row, column) = drawn_cursor(&h.drawn.take());
+        assert!(row <= text_rows, "row {} after {:?}", row, keys);
+        assert_eq!((row, column), expected, "after {keys:?}");
+    }
+    assert_eq!(h.cursor(), (6, 6));
+}
+
+#[test]
+fn escape_clears_one_thing_at_a_time() {
+    let dir = TempDir::new("escape");
+    let path = dir.file("e.txt", "one\ntwo\n");
+    let mut h = Harness::new(6, 80);
+    // Searching leaves the match highlighted and says where it is
+    h.run(&path, &[&[0x06], &b"two"[..], ENTER].concat());
+    h.e.mark = Some((0, 0));
+    h.keys(b"\x1b3");
+    h.e.overlay = Some(Vec::new());
+    assert!(h.e.search.is_some() && h.e.message.message.is_some());
+
+    let escape = |h: &mut Harness| {
+        assert!(h.e.handle_event(Event::Escape(EscapeSequence::Bare)));
+    };
+    escape(&mut h);
+    assert!(h.e.overlay.is_none() && h.e.replay_count.is_some());
+    escape(&mut h);
+    assert!(h.e.replay_count.is_none() && h.e.mark.is_some());
+    escape(&mut h);
+    assert!(h.e.mark.is_none() && h.e.search.is_some());
+    escape(&mut h);
+    assert!(h.e.search.is_none() && h.e.message.message.is_some());
+    escape(&mut h);
+    assert!(h.e.message.message.is_none());
+    assert!(!h.e.dismiss_topmost());
+
+    assert_eq!(h.e.rows, ["one", "two"]);
+    assert!(!h.e.dirty_flag);
+}
+
+#[test]
+fn typing_repaints_its_row_and_the_status_bar() {
+    let dir = TempDir::new("repaint");
+    let path = dir.file("r.txt", "one\ntwo\nthree\n");
+    let mut h = Harness::new(6, 40);
+    h.run(&path, DOWN);
+    h.drawn.take();
+
+    h.keys(b"x");
+    let drawn = h.drawn.take();
+    let text = String::from_utf8_lossy(&drawn);
+    assert_eq!(text.matches("\x1b[K").count(), 2, "{text:?}");
+    assert!(text.contains("\x1b[2;1H\x1b[Kxtwo"), "{:?}", text);
+    assert!(text.contains("\x1b[6;1H\x1b[K"), "{:?}", text);
+
+    // Moving the cursor repaints only the status bar, for the position it shows
+    h.keys(END);
+    let drawn = h.drawn.take();
+    let text = String::from_utf8_lossy(&drawn);
+    assert_eq!(text.matches("\x1b[K").count(), 1, "{text:?}");
+    assert!(text.contains("\x1b[6;1H\x1b[K"), "{:?}", text);
+    assert!(text.ends_with("\x1b[2;5H\x1b[?25h"), "{:?}", text);
+
+    // and when nothing on screen changes, all that's sent is the cursor
+    h.keys(END);
+    assert_eq!(h.drawn.take(), b"\x1b[2;5H");
+}
+
+#[test]
+fn layouts_add_up_at_every_width() {
+    for width in 20..=200 {
+        for decorations in 0..32_u8 {
+            let [change_marks, clip_markers, wrap, scrolled, continues] =
+                [0, 1, 2, 3, 4].map(|bit| decorations & 1 << bit != 0);
+            let layout =
+                compute_layout(width, change_marks, clip_markers, wrap, scrolled, continues);
+
+            let clipping = clip_markers && !wrap;
+            let expected = Layout {
+                gutter: usize::from(change_marks),
+                left_marker: usize::from(clipping && scrolled),
+                text: width
+                    - 1
+                    - usize::from(change_marks)
+                    - usize::from(clipping && scrolled)
+                    - usize::from(clipping && continues),
+                right_marker: usize::from(clipping && continues),
+                margin: 1,
+            };
+            assert_eq!(
+                layout, expected,
+                "{width} columns, decorations {decorations:05b}"
+            );
+            assert_eq!(layout.gutter + layout.last_column() + 1, width);
+            // The cursor stays in the text area, and left of a '>'
+            assert!(layout.left_marker + layout.cursor_room() <= layout.last_column());
+            if layout.right_marker > 0 {
+                assert!(layout.cursor_room() < layout.text);
+            }
+        }
+    }
+}
+
+#[test]
+fn rows_fit_the_window_at_every_width() {
+    let dir = TempDir::new("layout");
+    for width in 20..=200 {
+        let long = format!("\t{}z", "x".repeat(width * 2));
+        let path = dir.file("l.txt", &format!("short\n{long}\n"));
+        for decorations in 0..8_u8 {
+            let [change_marks, clip_markers, wrap] =
+                [0, 1, 2].map(|bit| decorations & 1 << bit != 0);
+            let config = Config {
+                wrap,
+                clip_markers,
+                change_marks,
+                ..Config::default()
+            };
+            let mut h = Harness::with_config(6, width, config);
+            let context = format!("{width} columns, decorations {decorations:03b}");
+
+            h.run(&path, &[b"!", DOWN, END].concat());
+            let frame = h.frame();
+            for line in &frame.lines {
+                assert!(line.text.len() < width, "{}: {:?}", context, line.text);
+            }
+            assert!(frame.cursor.x < width, "{}", context);
+            // The cursor is past the end of the long row, right of its last char
+            let row = &frame.lines[frame.cursor.y].text;
+            assert_eq!(row[frame.cursor.x - 1], 'z', "{context}: {row:?}");
+            if change_marks {
+                assert_eq!(frame.lines[0].text[0], CHANGE_MARK, "{context}");
+            }
+
+            h.keys(HOME);
+            let frame = h.frame();
+            let row = &frame.lines[1].text;
+            if clip_markers && !wrap {
+                assert_eq!(row.len(), width - 1, "{context}");
+                assert_eq!(row.last(), Some(&'>'), "{context}");
+            } else {
+                assert!(!row.contains(&'>'), "{}", context);
+            }
+        }
+    }
+}
+
+// Work in the background freezes the buffer: what's typed meanwhile waits for a save, which
+// leaves the buffer as it was, and is dropped for a filter, which doesn't
+
+const ALT_X: &[u8] = b"\x1bx";
+
+#[test]
+fn keys_typed_while_a_filter_runs_are_dropped() {
+    let dir = TempDir::new("filter-freeze");
+    let path = dir.file("f.txt", "b\na\n");
+    let mut h = Harness::new(6, 120);
+    h.run(&path, &[ALT_R, b"m", ALT_R, BACKSPACE].concat());
+    assert_eq!(h.e.rows, ["b", "a"]);
+
+    // Typing, a paste and a macro, none of which get to the buffer
+    h.keys(
+        &[
+            ALT_X,
+            b"|sleep 0.3; sort",
+            ENTER,
+            b"xy",
+            b"\x1b[200~pasted\x1b[201~",
+            CTRL_BACKSLASH,
+            ENTER,
+        ]
+        .concat(),
+    );
+
+    assert_eq!(h.e.rows, ["a", "b"]);
+    // The status bar said what was going on while it did
+    let said = |message: &str| h.e.message_log.iter().any(|(_, logged)| logged == message);
+    assert!(said("Running sleep 0.3; sort\u{2026} Escape cancels"));
+    let message = &h.e.message_log.back().unwrap().1;
+    assert_eq!(
+        message,
+        "Ran 2 lines through sleep 0.3; sort, got 2 back, 5 keys typed meanwhile were dropped"
+    );
+    // Keys after the filter's done are handled as usual
+    h.keys(b"z");
+    assert_eq!(h.e.rows, ["za", "b"]);
+}
+
+#[test]
+fn escape_cancels_a_filter() {
+    let dir = TempDir::new("filter-cancel");
+    let path = dir.file("f.txt", "b\na\n");
+    let mut h = Harness::new(6, 120);
+    h.run(&path, b"");
+
+    let started = Instant::now();
+    h.keys(&[ALT_X, b"|sleep 5; sort", ENTER, b"x\x1b\x1b"].concat());
+
+    assert!(started.elapsed() < Duration::from_secs(4));
+    assert_eq!(h.e.rows, ["b", "a"]);
+    assert!(!h.e.dirty_flag);
+    let message = &h.e.message_log.back().unwrap().1;
+    assert_eq!(
+        message,
+        "Cancelled sleep 5; sort, the buffer is as it was, a key typed meanwhile was dropped"
+    );
+}
+
+#[test]
+fn keys_typed_while_saving_are_handled_once_it_is_written() {
+    let dir = TempDir::new("save-freeze");
+    let path = dir.file("s.txt", "one\n");
+    let mut h = Harness::new(6, 120);
+    h.run(&path, b"x");
+
+    // What's typed behind the save is held back and recorded once, after it's handled
+    h.keys(&[ALT_R, &[CTRL_S], b"!", ALT_R].concat());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "xone\n");
+    assert_eq!(h.e.rows, ["x!one"]);
+    assert_eq!(h.e.last_macro.len(), 2);
+
+    // Typing, a paste and a macro that saves again, which holds back the rest of the macro and
+    // what's typed after it
+    h.keys(
+        &[
+            &[CTRL_S][..],
+            b"a",
+            b"\x1b[200~b\x1b[201~",
+            CTRL_BACKSLASH,
+            b"c",
+        ]
+        .concat(),
+    );
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "x!abone\n");
+    assert_eq!(h.e.rows, ["x!ab!cone"]);
+    assert!(h.e.dirty_flag);
+}
@@ -0,0 +1,150 @@
+//! The text of `rilo --tutor`, a walk through the editor done in the editor itself.
+//! Key names come from the live keymap, so the lessons name the keys as the user has bound them.
+//! Lines stay within 80 columns so the tutorial reads well on a default sized terminal.
+
+use crate::{describe, Action, Editor};
+
+/// The widest a line gets, in columns
+const WIDTH: usize = 80;
+
+pub fn generate(editor: &Editor) -> String {
+    let key = |action| {
+        editor
+            .key_name(action)
+            .unwrap_or_else(|| format!("{action:?}"))
+    };
+
+    let sections = [
+        String::from(
+            "Welcome to rilo!\n\
+             \n\
+             This is a copy of the tutorial, change it as much as you like.\n\
+             Every lesson below asks you to do something, the text to work on is\n\
+             right under it.",
+        ),
+        String::from(
+            "Lesson 1: moving around\n\
+             \n\
+             Use the arrow keys to move the cursor, Home and End to jump to the\n\
+             start and end of a line, and Page Up and Page Down to move a screen\n\
+             at a time. Move the cursor onto the X below.\n\
+             \n\
+             \x20   -->  X  <--",
+        ),
+        String::from(
+            "Lesson 2: deleting\n\
+             \n\
+             Backspace deletes the character before the cursor. Remove the\n\
+             repeated word so the line reads correctly.\n\
+             \n\
+             \x20   The quick brown brown fox.",
+        ),
+        format!(
+            "Lesson 3: searching\n\
+             \n\
+             Press {find}, type needle and press Enter to jump to it.\n\
+             \n\
+             \x20   hay hay hay hay hay needle hay hay",
+            find = key(Action::Find),
+        ),
+        format!(
+            "Lesson 4: cutting and pasting lines\n\
+             \n\
+             Put the cursor on the line marked 2 and press {kill} to cut it,\n\
+             then move below the line marked 3 and press {yank} to paste it back.\n\
+             \n\
+             \x20   2. second\n\
+             \x20   1. first\n\
+             \x20   3. third",
+            kill = key(Action::KillLine),
+            yank = key(Action::Yank),
+        ),
+        format!(
+            "Lesson 5: long lines\n\
+             \n\
+             Lines wider than the window scroll sideways. Make the window narrower\n\
+             than this paragraph, then press {wrap} to toggle soft wrap and see\n\
+             the lines folded to fit. Press it again to go back to scrolling.",
+            wrap = key(Action::ToggleWrap),
+        ),
+        format!(
+            "Lesson 6: read-only mode\n\
+             \n\
+             Press {readonly} to make the buffer read-only, try typing, then press\n\
+             it again to allow changes.",
+            readonly = key(Action::ToggleReadonly),
+        ),
+        format!(
+            "Lesson 7: saving and quitting\n\
+             \n\
+             Press {save} to save your changes to the tutorial file, and {quit} to\n\
+             quit. {cancel} backs out of any prompt along the way.",
+            save = key(Action::Save),
+            quit = key(Action::Quit),
+            cancel = key(Action::Cancel),
+        ),
+    ];
+
+    let mut text = sections.join("\n\n\n");
+    text.push_str("\n\n\nQuick reference\n\n");
+    for (key, action) in &editor.keymap {
+        let mut line = format!("    {:<11} ", key.to_string());
+        for word in describe(*action).split(' ') {
+            // A long description goes on under itself, clear of the column of keys
+            if line.len() + word.len() > WIDTH {
+                text.push_str(line.trim_end());
+                text.push('\n');
+                line = " ".repeat(16);
+            }
+            line.push_str(word);
+            line.push(' ');
+        }
+        text.push_str(line.trim_end());
+        text.push('\n');
+    }
+    text.push_str("    alt-x       Run a command by name, like reload-config\n");
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::Key;
+    use crate::tests::Harness;
+    use crate::width;
+
+    #[test]
+    fn names_every_binding() {
+        let h = Harness::new(24, 80);
+        let text = generate(&h.e);
+        let reference = &text[text.find("Quick reference").unwrap()..];
+        // Each entry with the lines its description goes on to joined up
+        let entries = reference.replace(&format!("\n{}", " ".repeat(16)), " ");
+        for (key, action) in &h.e.keymap {
+            let line = format!("    {:<11} {}", key.to_string(), describe(*action));
+            assert!(entries.lines().any(|l| l == line), "missing {:?}", line);
+        }
+    }
+
+    #[test]
+    fn lessons_follow_remapped_keys() {
+        let mut h = Harness::new(24, 80);
+        let f5: Key = "f5".parse().unwrap();
+        for (key, action) in &mut h.e.keymap {
+            if *action == Action::Save {
+                *key = f5.clone();
+            }
+        }
+        let text = generate(&h.e);
+        assert!(text.contains("Press f5 to save your changes"), "{}", text);
+    }
+
+    #[test]
+    fn fits_in_80_columns() {
+        let h = Harness::new(24, 80);
+        for line in generate(&h.e).lines() {
+            let columns: usize = line.chars().map(width::of).sum();
+            assert!(columns <= WIDTH, "{} columns: {:?}", columns, line);
+        }
+    }
+}